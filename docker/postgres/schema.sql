@@ -1,7 +1,230 @@
 create table accounts (
     id varchar(64) not null primary key,
     email varchar(320) not null unique,
-    password_hash varchar(255) not null,
+    password_hash varchar(255),
     display_name varchar(255),
-    created_at timestamp with time zone
+    created_at timestamp with time zone,
+    email_verified_at timestamp with time zone,
+    pending_email varchar(320),
+    status varchar(16) not null default 'active'
+);
+
+create table sessions (
+    id varchar(64) not null primary key,
+    account_id varchar(64) not null references accounts(id) on delete cascade,
+    created_at timestamp with time zone not null,
+    expires_at timestamp with time zone not null
+);
+create index sessions_account_id_idx on sessions(account_id);
+
+create table signing_keys (
+    kid varchar(64) not null primary key,
+    private_key bytea not null,
+    created_at timestamp with time zone not null,
+    retired_at timestamp with time zone
+);
+
+create table revoked_access_tokens (
+    token_hash varchar(64) not null primary key,
+    expires_at timestamp with time zone not null
+);
+
+create index revoked_access_tokens_expires_at_idx on revoked_access_tokens(expires_at);
+
+create table refresh_tokens (
+    token_hash varchar(64) not null primary key,
+    session_id varchar(64) not null references sessions(id) on delete cascade,
+    created_at timestamp with time zone not null,
+    expires_at timestamp with time zone not null,
+    used_at timestamp with time zone
+);
+create index refresh_tokens_session_id_idx on refresh_tokens(session_id);
+
+create table authentication_failures (
+    account_id varchar(64) not null primary key references accounts(id) on delete cascade,
+    failed_attempts integer not null,
+    first_failed_at timestamp with time zone not null,
+    locked_until timestamp with time zone not null
+);
+
+create table password_history (
+    id varchar(64) not null primary key,
+    account_id varchar(64) not null references accounts(id) on delete cascade,
+    password_hash varchar(255) not null,
+    replaced_at timestamp with time zone not null
+);
+create index password_history_account_id_idx on password_history(account_id);
+
+create table password_reset_tokens (
+    token_hash varchar(64) not null primary key,
+    account_id varchar(64) not null references accounts(id) on delete cascade,
+    created_at timestamp with time zone not null,
+    expires_at timestamp with time zone not null
+);
+create index password_reset_tokens_account_id_idx on password_reset_tokens(account_id);
+
+create table email_verification_tokens (
+    token_hash varchar(64) not null primary key,
+    account_id varchar(64) not null references accounts(id) on delete cascade,
+    email varchar(320) not null,
+    created_at timestamp with time zone not null,
+    expires_at timestamp with time zone not null
+);
+create index email_verification_tokens_account_id_idx on email_verification_tokens(account_id);
+
+create table sign_in_tokens (
+    token_hash varchar(64) not null primary key,
+    -- not a reference to accounts, as the email may not be registered yet
+    email varchar(320) not null,
+    code_hash varchar(255) not null,
+    created_at timestamp with time zone not null,
+    expires_at timestamp with time zone not null,
+    failed_attempts integer not null default 0
+);
+create index sign_in_tokens_email_idx on sign_in_tokens(email);
+
+create table audit_events (
+    id varchar(64) not null primary key,
+    account_id varchar(64) not null references accounts(id),
+    kind varchar(32) not null,
+    actor varchar(64),
+    source_ip varchar(64),
+    user_agent varchar(512),
+    occurred_at timestamp with time zone not null
+);
+create index audit_events_account_id_idx on audit_events(account_id, occurred_at desc, id desc);
+-- the audit log is append-only, except that the source IP address and user
+-- agent are erased when the account is deleted
+create rule audit_events_no_update as on update to audit_events
+    where new.id <> old.id or new.account_id <> old.account_id or new.kind <> old.kind
+        or new.actor is distinct from old.actor or new.occurred_at <> old.occurred_at
+        or new.source_ip is not null or new.user_agent is not null
+    do instead nothing;
+create rule audit_events_no_delete as on delete to audit_events do instead nothing;
+
+create table totp_factors (
+    account_id varchar(64) not null primary key references accounts(id) on delete cascade,
+    encrypted_secret bytea not null,
+    created_at timestamp with time zone not null,
+    confirmed_at timestamp with time zone,
+    last_used_step bigint
+);
+
+create table mfa_challenges (
+    token_hash varchar(64) not null primary key,
+    account_id varchar(64) not null references accounts(id) on delete cascade,
+    created_at timestamp with time zone not null,
+    expires_at timestamp with time zone not null,
+    failed_attempts integer not null default 0
+);
+create index mfa_challenges_account_id_idx on mfa_challenges(account_id);
+
+create table recovery_codes (
+    id varchar(64) not null primary key,
+    account_id varchar(64) not null references accounts(id) on delete cascade,
+    code_hash varchar(255) not null,
+    created_at timestamp with time zone not null,
+    used_at timestamp with time zone
+);
+create index recovery_codes_account_id_idx on recovery_codes(account_id);
+
+create table passkeys (
+    id varchar(64) not null primary key,
+    account_id varchar(64) not null references accounts(id) on delete cascade,
+    name varchar(64) not null,
+    credential_id bytea not null unique,
+    public_key bytea not null,
+    sign_count bigint not null,
+    aaguid bytea not null,
+    attestation_format varchar(32) not null,
+    created_at timestamp with time zone not null,
+    last_used_at timestamp with time zone
+);
+create index passkeys_account_id_idx on passkeys(account_id);
+
+create table webauthn_challenges (
+    token_hash varchar(64) not null primary key,
+    ceremony varchar(16) not null,
+    account_id varchar(64) references accounts(id) on delete cascade,
+    created_at timestamp with time zone not null,
+    expires_at timestamp with time zone not null
+);
+
+create table oauth_clients (
+    id varchar(64) not null primary key,
+    name varchar(64) not null,
+    redirect_uris text[] not null,
+    scopes text[] not null,
+    created_at timestamp with time zone not null
+);
+
+create table oauth_authorization_codes (
+    code_hash varchar(64) not null primary key,
+    client_id varchar(64) not null references oauth_clients(id) on delete cascade,
+    account_id varchar(64) not null references accounts(id) on delete cascade,
+    redirect_uri text not null,
+    scopes text[] not null,
+    code_challenge varchar(64) not null,
+    nonce varchar(255),
+    created_at timestamp with time zone not null,
+    expires_at timestamp with time zone not null,
+    used_at timestamp with time zone
+);
+
+create table oauth_grants (
+    account_id varchar(64) not null references accounts(id) on delete cascade,
+    client_id varchar(64) not null references oauth_clients(id) on delete cascade,
+    scopes text[] not null,
+    created_at timestamp with time zone not null,
+    updated_at timestamp with time zone not null,
+    primary key (account_id, client_id)
+);
+
+create table oauth_refresh_tokens (
+    token_hash varchar(64) not null primary key,
+    account_id varchar(64) not null,
+    client_id varchar(64) not null,
+    scopes text[] not null,
+    created_at timestamp with time zone not null,
+    expires_at timestamp with time zone not null,
+    used_at timestamp with time zone,
+    foreign key (account_id, client_id) references oauth_grants(account_id, client_id) on delete cascade
+);
+create index oauth_refresh_tokens_grant_idx on oauth_refresh_tokens(account_id, client_id);
+
+create table service_accounts (
+    id varchar(64) not null primary key,
+    name varchar(64) not null,
+    scopes text[] not null,
+    created_at timestamp with time zone not null
+);
+
+create table service_account_secrets (
+    id varchar(64) not null primary key,
+    service_account_id varchar(64) not null references service_accounts(id) on delete cascade,
+    secret_hash varchar(255) not null,
+    created_at timestamp with time zone not null
+);
+create index service_account_secrets_service_account_idx on service_account_secrets(service_account_id);
+
+create table linked_identities (
+    id varchar(64) not null primary key,
+    account_id varchar(64) not null references accounts(id) on delete cascade,
+    provider varchar(64) not null,
+    subject varchar(255) not null,
+    email varchar(320),
+    created_at timestamp with time zone not null,
+    last_used_at timestamp with time zone,
+    unique (provider, subject)
+);
+create index linked_identities_account_id_idx on linked_identities(account_id);
+
+create table federated_authorizations (
+    state_hash varchar(64) not null primary key,
+    provider varchar(64) not null,
+    nonce varchar(64) not null,
+    code_verifier varchar(128) not null,
+    account_id varchar(64) references accounts(id) on delete cascade,
+    created_at timestamp with time zone not null,
+    expires_at timestamp with time zone not null
 );
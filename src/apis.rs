@@ -4,5 +4,7 @@
 
 pub mod converters;
 pub mod error;
+pub mod extractors;
 pub mod models;
+pub mod rate_limit;
 pub mod rest;
@@ -4,12 +4,50 @@
 //! Keeping the model types separate allows the API to evolve independently from
 //! the services.
 
-use crate::services::account::models::{
-    Account, AccountCredentials, NewAccount, NewAccountCredentials,
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use secrecy::Secret;
+
+use crate::services::{
+    account::models::{
+        Account, AccountCredentials, AccountStatus, NewAccount, NewAccountCredentials, Password,
+        PasswordReset, RecoveryCodeSummary, SignInRequest,
+    },
+    audit::models::{AuditEvent, AuditEventPage},
+    credential::models::{
+        AssertionCredential, AuthenticationOptions, Passkey, RegistrationCredential,
+        RegistrationOptions,
+    },
+    federation::models::{
+        FederatedAuthorizationUrl, FederatedCallback, IdentityProvider, LinkedIdentity,
+    },
+    mfa::models::{IssuedMfaChallenge, TotpEnrollment},
+    oauth::{
+        error::OAuthServiceError,
+        models::{
+            AuthorizationRequest, ClientCredentialsExchange, CodeExchange, IssuedSecret,
+            NewOAuthClient, NewServiceAccount, OAuthClient, OAuthGrant, OAuthRefreshToken,
+            RefreshExchange, ServiceAccount, ServiceAccountSecret,
+        },
+    },
+    session::models::Session,
+    token::{
+        models::{AccessToken, AccessTokenClaims, IdentityClaims, PublicKey},
+        SIGNING_ALGORITHM,
+    },
 };
 
 use super::models::{
-    AccountResponse, AuthenticateRequest, NewAccountRequest, NewCredentialsRequest,
+    AccessTokenResponse, AccountResponse, AccountStatusResponse, AuditEventResponse,
+    AuditEventsResponse, AuthenticateRequest, AuthorizationParams, FederatedAuthorizationResponse,
+    FederatedCallbackRequest, IdentityProviderResponse, IntrospectionResponse,
+    IssuedSecretResponse, JwkResponse, LinkedIdentityResponse, MfaChallengeResponse,
+    NewAccountRequest, NewCredentialsRequest, NewLinkedIdentityRequest, NewOAuthClientRequest,
+    NewPasskeyRequest, NewPasswordRequest, NewServiceAccountRequest, OAuthClientResponse,
+    OAuthGrantResponse, OAuthGrantsResponse, PasskeyAuthenticationOptionsResponse,
+    PasskeyRegistrationOptionsResponse, PasskeyResponse, PasskeySessionRequest,
+    SecuritySummaryResponse, ServiceAccountResponse, ServiceAccountSecretResponse,
+    ServiceAccountSecretsResponse, SessionResponse, SignInCodeRequest, TokenRequest,
+    TotpEnrollmentResponse, UserInfoResponse,
 };
 
 /// Converts the API [NewAccountRequest] model to an [Account] model.
@@ -31,6 +69,59 @@ impl From<Account> for AccountResponse {
             email: value.email,
             display_name: value.display_name,
             created_at: value.created_at,
+            email_verified_at: value.email_verified_at,
+            pending_email: value.pending_email,
+            status: value.status.into(),
+        }
+    }
+}
+
+/// Converts an [AccountStatus] to an API [AccountStatusResponse].
+impl From<AccountStatus> for AccountStatusResponse {
+    fn from(value: AccountStatus) -> Self {
+        match value {
+            AccountStatus::Active => AccountStatusResponse::Active,
+            AccountStatus::Deactivated => AccountStatusResponse::Deactivated,
+            AccountStatus::Deleted => AccountStatusResponse::Deleted,
+        }
+    }
+}
+
+/// Converts a [Session] and the [Account] that owns it to an API [SessionResponse].
+impl From<(Session, Account)> for SessionResponse {
+    fn from((session, account): (Session, Account)) -> Self {
+        SessionResponse {
+            id: session.id,
+            account: account.into(),
+            created_at: session.created_at,
+            expires_at: session.expires_at,
+            access_token: None,
+            refresh_token: None,
+        }
+    }
+}
+
+/// Converts an [AccessToken] to an API [AccessTokenResponse].
+impl From<AccessToken> for AccessTokenResponse {
+    fn from(value: AccessToken) -> Self {
+        AccessTokenResponse {
+            token: value.token,
+            token_type: "Bearer".to_string(),
+            expires_at: value.expires_at,
+        }
+    }
+}
+
+/// Converts a [PublicKey] to an API [JwkResponse].
+impl From<PublicKey> for JwkResponse {
+    fn from(value: PublicKey) -> Self {
+        JwkResponse {
+            kty: "OKP".to_string(),
+            crv: "Ed25519".to_string(),
+            x: URL_SAFE_NO_PAD.encode(value.key.as_bytes()),
+            kid: value.kid,
+            alg: SIGNING_ALGORITHM.to_string(),
+            key_use: "sig".to_string(),
         }
     }
 }
@@ -41,10 +132,18 @@ impl From<AuthenticateRequest> for AccountCredentials {
         AccountCredentials {
             email: value.email,
             password: value.password,
+            recovery_code: value.recovery_code,
         }
     }
 }
 
+/// Converts the API [SignInCodeRequest] model to a [SignInRequest] model.
+impl From<SignInCodeRequest> for SignInRequest {
+    fn from(value: SignInCodeRequest) -> Self {
+        SignInRequest { email: value.email }
+    }
+}
+
 /// Converts the API [NewCredentialsRequest] model to a
 /// serice [NewAccountCredentials] model.
 impl From<NewCredentialsRequest> for NewAccountCredentials {
@@ -55,3 +154,397 @@ impl From<NewCredentialsRequest> for NewAccountCredentials {
         }
     }
 }
+
+/// Converts a password reset token and the API [NewPasswordRequest] model
+/// to a service [PasswordReset] model.
+impl From<(String, NewPasswordRequest)> for PasswordReset {
+    fn from((token, value): (String, NewPasswordRequest)) -> Self {
+        PasswordReset {
+            token,
+            password: value.password,
+        }
+    }
+}
+
+/// Converts an [AuditEvent] to an API [AuditEventResponse].
+impl From<AuditEvent> for AuditEventResponse {
+    fn from(value: AuditEvent) -> Self {
+        AuditEventResponse {
+            id: value.id,
+            account_id: value.account_id,
+            kind: value.kind.as_str().to_string(),
+            actor: value.actor,
+            source_ip: value.source_ip,
+            user_agent: value.user_agent,
+            occurred_at: value.occurred_at,
+        }
+    }
+}
+
+/// Converts an [AuditEventPage] to an API [AuditEventsResponse].
+impl From<AuditEventPage> for AuditEventsResponse {
+    fn from(value: AuditEventPage) -> Self {
+        AuditEventsResponse {
+            events: value.events.into_iter().map(|e| e.into()).collect(),
+            next_cursor: value.next_cursor,
+        }
+    }
+}
+
+/// Converts a [TotpEnrollment] to an API [TotpEnrollmentResponse].
+impl From<TotpEnrollment> for TotpEnrollmentResponse {
+    fn from(value: TotpEnrollment) -> Self {
+        TotpEnrollmentResponse {
+            secret: value.secret,
+            provisioning_uri: value.provisioning_uri,
+        }
+    }
+}
+
+/// Converts an [IssuedMfaChallenge] to an API [MfaChallengeResponse].
+impl From<IssuedMfaChallenge> for MfaChallengeResponse {
+    fn from(value: IssuedMfaChallenge) -> Self {
+        MfaChallengeResponse {
+            mfa_challenge: value.handle,
+            expires_at: value.expires_at,
+        }
+    }
+}
+
+/// Converts an [Account], whether it has multi-factor authentication enabled,
+/// and its [RecoveryCodeSummary] to an API [SecuritySummaryResponse].
+impl From<(Account, bool, RecoveryCodeSummary)> for SecuritySummaryResponse {
+    fn from(value: (Account, bool, RecoveryCodeSummary)) -> Self {
+        let (account, mfa_enabled, recovery_codes) = value;
+        SecuritySummaryResponse {
+            email_verified: account.email_verified(),
+            mfa_enabled,
+            recovery_codes_remaining: recovery_codes.remaining,
+            recovery_codes_generated_at: recovery_codes.generated_at,
+        }
+    }
+}
+
+/// Converts service [RegistrationOptions] to an API [PasskeyRegistrationOptionsResponse].
+impl From<RegistrationOptions> for PasskeyRegistrationOptionsResponse {
+    fn from(value: RegistrationOptions) -> Self {
+        PasskeyRegistrationOptionsResponse {
+            challenge: value.challenge,
+            rp_id: value.rp_id,
+            rp_name: value.rp_name,
+            user_id: value.user_id,
+            user_name: value.user_name,
+            algorithms: value.algorithms,
+            exclude_credentials: value
+                .exclude_credentials
+                .iter()
+                .map(|id| URL_SAFE_NO_PAD.encode(id))
+                .collect(),
+            expires_at: value.expires_at,
+        }
+    }
+}
+
+/// Converts an API [NewPasskeyRequest] to a service [RegistrationCredential].
+impl From<NewPasskeyRequest> for RegistrationCredential {
+    fn from(value: NewPasskeyRequest) -> Self {
+        RegistrationCredential {
+            client_data_json: value.client_data_json,
+            attestation_object: value.attestation_object,
+        }
+    }
+}
+
+/// Converts a service [Passkey] to an API [PasskeyResponse].
+impl From<Passkey> for PasskeyResponse {
+    fn from(value: Passkey) -> Self {
+        PasskeyResponse {
+            id: value.id,
+            name: value.name,
+            created_at: value.created_at,
+            last_used_at: value.last_used_at,
+        }
+    }
+}
+
+/// Converts service [AuthenticationOptions] to an API [PasskeyAuthenticationOptionsResponse].
+impl From<AuthenticationOptions> for PasskeyAuthenticationOptionsResponse {
+    fn from(value: AuthenticationOptions) -> Self {
+        PasskeyAuthenticationOptionsResponse {
+            challenge: value.challenge,
+            rp_id: value.rp_id,
+            expires_at: value.expires_at,
+        }
+    }
+}
+
+/// Converts an API [PasskeySessionRequest] to a service [AssertionCredential].
+impl From<PasskeySessionRequest> for AssertionCredential {
+    fn from(value: PasskeySessionRequest) -> Self {
+        AssertionCredential {
+            credential_id: value.credential_id,
+            client_data_json: value.client_data_json,
+            authenticator_data: value.authenticator_data,
+            signature: value.signature,
+            user_handle: value.user_handle,
+        }
+    }
+}
+
+/// Converts an API [NewOAuthClientRequest] to a service [NewOAuthClient].
+impl From<NewOAuthClientRequest> for NewOAuthClient {
+    fn from(value: NewOAuthClientRequest) -> Self {
+        NewOAuthClient {
+            name: value.name,
+            redirect_uris: value.redirect_uris,
+            scopes: value.scopes,
+        }
+    }
+}
+
+/// Converts an [OAuthClient] to an API [OAuthClientResponse].
+impl From<OAuthClient> for OAuthClientResponse {
+    fn from(value: OAuthClient) -> Self {
+        OAuthClientResponse {
+            id: value.id,
+            name: value.name,
+            redirect_uris: value.redirect_uris,
+            scopes: value.scopes,
+            created_at: value.created_at,
+        }
+    }
+}
+
+/// Converts API [AuthorizationParams] to a service [AuthorizationRequest].
+impl From<AuthorizationParams> for AuthorizationRequest {
+    fn from(value: AuthorizationParams) -> Self {
+        AuthorizationRequest {
+            response_type: value.response_type,
+            client_id: value.client_id,
+            redirect_uri: value.redirect_uri,
+            scope: value.scope,
+            state: value.state,
+            code_challenge: value.code_challenge,
+            code_challenge_method: value.code_challenge_method,
+            nonce: value.nonce,
+        }
+    }
+}
+
+/// Converts an [OAuthGrant] to an API [OAuthGrantResponse].
+impl From<OAuthGrant> for OAuthGrantResponse {
+    fn from(value: OAuthGrant) -> Self {
+        OAuthGrantResponse {
+            client_id: value.client_id,
+            scopes: value.scopes,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+        }
+    }
+}
+
+/// Converts an account's [OAuthGrant]s to an API [OAuthGrantsResponse].
+impl From<Vec<OAuthGrant>> for OAuthGrantsResponse {
+    fn from(value: Vec<OAuthGrant>) -> Self {
+        OAuthGrantsResponse {
+            grants: value.into_iter().map(|g| g.into()).collect(),
+        }
+    }
+}
+
+/// Returns the value of a required token request parameter.
+fn required(value: Option<String>, name: &str) -> Result<String, OAuthServiceError> {
+    value.ok_or_else(|| OAuthServiceError::InvalidRequest(format!("{} is required", name)))
+}
+
+/// Converts an API [TokenRequest] to a service [CodeExchange],
+/// if it has all of the parameters that are needed.
+impl TryFrom<TokenRequest> for CodeExchange {
+    type Error = OAuthServiceError;
+
+    fn try_from(value: TokenRequest) -> Result<Self, Self::Error> {
+        Ok(CodeExchange {
+            client_id: required(value.client_id, "client_id")?,
+            code: required(value.code, "code")?,
+            redirect_uri: required(value.redirect_uri, "redirect_uri")?,
+            code_verifier: required(value.code_verifier, "code_verifier")?,
+        })
+    }
+}
+
+/// Converts an API [TokenRequest] to a service [RefreshExchange],
+/// if it has all of the parameters that are needed.
+impl TryFrom<TokenRequest> for RefreshExchange {
+    type Error = OAuthServiceError;
+
+    fn try_from(value: TokenRequest) -> Result<Self, Self::Error> {
+        Ok(RefreshExchange {
+            client_id: required(value.client_id, "client_id")?,
+            refresh_token: required(value.refresh_token, "refresh_token")?,
+            scope: value.scope,
+        })
+    }
+}
+
+/// Converts an API [TokenRequest] to a service [ClientCredentialsExchange],
+/// if it has all of the parameters that are needed.
+impl TryFrom<TokenRequest> for ClientCredentialsExchange {
+    type Error = OAuthServiceError;
+
+    fn try_from(value: TokenRequest) -> Result<Self, Self::Error> {
+        Ok(ClientCredentialsExchange {
+            client_id: required(value.client_id, "client_id")?,
+            client_secret: Secret::new(Password::new(&required(
+                value.client_secret,
+                "client_secret",
+            )?)),
+            scope: value.scope,
+        })
+    }
+}
+
+/// Converts the API [NewServiceAccountRequest] to a [NewServiceAccount].
+impl From<NewServiceAccountRequest> for NewServiceAccount {
+    fn from(value: NewServiceAccountRequest) -> Self {
+        NewServiceAccount {
+            name: value.name,
+            scopes: value.scopes,
+        }
+    }
+}
+
+/// Converts a [ServiceAccount] to an API [ServiceAccountResponse].
+impl From<ServiceAccount> for ServiceAccountResponse {
+    fn from(value: ServiceAccount) -> Self {
+        ServiceAccountResponse {
+            id: value.id,
+            name: value.name,
+            scopes: value.scopes,
+            created_at: value.created_at,
+        }
+    }
+}
+
+/// Converts an [IssuedSecret] to an API [IssuedSecretResponse].
+impl From<IssuedSecret> for IssuedSecretResponse {
+    fn from(value: IssuedSecret) -> Self {
+        IssuedSecretResponse {
+            id: value.id,
+            client_secret: value.secret,
+            created_at: value.created_at,
+        }
+    }
+}
+
+/// Converts a service account's [ServiceAccountSecret]s to an API [ServiceAccountSecretsResponse].
+impl From<Vec<ServiceAccountSecret>> for ServiceAccountSecretsResponse {
+    fn from(value: Vec<ServiceAccountSecret>) -> Self {
+        ServiceAccountSecretsResponse {
+            secrets: value
+                .into_iter()
+                .map(|secret| ServiceAccountSecretResponse {
+                    id: secret.id,
+                    created_at: secret.created_at,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Converts the claims of an active access token to an API [IntrospectionResponse].
+impl From<AccessTokenClaims> for IntrospectionResponse {
+    fn from(value: AccessTokenClaims) -> Self {
+        IntrospectionResponse {
+            active: true,
+            scope: value.scope,
+            client_id: value.client_id,
+            token_type: Some("Bearer".to_string()),
+            exp: Some(value.exp),
+            iat: Some(value.iat),
+            sub: Some(value.sub),
+            iss: None,
+        }
+    }
+}
+
+/// Converts an active [OAuthRefreshToken] to an API [IntrospectionResponse].
+impl From<OAuthRefreshToken> for IntrospectionResponse {
+    fn from(value: OAuthRefreshToken) -> Self {
+        IntrospectionResponse {
+            active: true,
+            scope: Some(value.scopes.join(" ")),
+            client_id: Some(value.client_id),
+            token_type: Some("refresh_token".to_string()),
+            exp: Some(value.expires_at.timestamp()),
+            iat: Some(value.created_at.timestamp()),
+            sub: Some(value.account_id),
+            iss: None,
+        }
+    }
+}
+
+/// Converts [IdentityClaims] to an API [UserInfoResponse].
+impl From<IdentityClaims> for UserInfoResponse {
+    fn from(value: IdentityClaims) -> Self {
+        UserInfoResponse {
+            sub: value.sub,
+            email: value.email,
+            email_verified: value.email_verified,
+            name: value.name,
+        }
+    }
+}
+
+/// Converts a service [IdentityProvider] to an API [IdentityProviderResponse].
+impl From<&IdentityProvider> for IdentityProviderResponse {
+    fn from(value: &IdentityProvider) -> Self {
+        IdentityProviderResponse {
+            name: value.name.clone(),
+            issuer: value.issuer.clone(),
+        }
+    }
+}
+
+/// Converts a service [FederatedAuthorizationUrl] to an API [FederatedAuthorizationResponse].
+impl From<FederatedAuthorizationUrl> for FederatedAuthorizationResponse {
+    fn from(value: FederatedAuthorizationUrl) -> Self {
+        FederatedAuthorizationResponse {
+            authorization_url: value.authorization_url,
+            expires_at: value.expires_at,
+        }
+    }
+}
+
+/// Converts an API [FederatedCallbackRequest] to a service [FederatedCallback].
+impl From<FederatedCallbackRequest> for FederatedCallback {
+    fn from(value: FederatedCallbackRequest) -> Self {
+        FederatedCallback {
+            state: value.state,
+            code: value.code,
+        }
+    }
+}
+
+/// Converts an API [NewLinkedIdentityRequest] to a service [FederatedCallback].
+impl From<NewLinkedIdentityRequest> for FederatedCallback {
+    fn from(value: NewLinkedIdentityRequest) -> Self {
+        FederatedCallback {
+            state: value.state,
+            code: value.code,
+        }
+    }
+}
+
+/// Converts a service [LinkedIdentity] to an API [LinkedIdentityResponse].
+impl From<LinkedIdentity> for LinkedIdentityResponse {
+    fn from(value: LinkedIdentity) -> Self {
+        LinkedIdentityResponse {
+            id: value.id,
+            provider: value.provider,
+            subject: value.subject,
+            email: value.email,
+            created_at: value.created_at,
+            last_used_at: value.last_used_at,
+        }
+    }
+}
@@ -1,11 +1,19 @@
-use axum::http::StatusCode;
+use axum::http::{
+    header::{RETRY_AFTER, WWW_AUTHENTICATE},
+    HeaderValue, StatusCode,
+};
 use axum::response::IntoResponse;
 use axum::Json;
 use thiserror::Error;
 
-use crate::services::account::error::AccountsServiceError;
+use crate::services::{
+    account::error::AccountsServiceError, audit::error::AuditServiceError,
+    credential::error::CredentialServiceError, federation::error::FederationServiceError,
+    mfa::error::MfaServiceError, oauth::error::OAuthServiceError,
+    session::error::SessionServiceError, token::error::TokenServiceError,
+};
 
-use super::models::ApiErrorResponse;
+use super::models::{ApiErrorResponse, OAuthErrorResponse};
 
 /// Represents an error returned by one of the API handlers.
 #[derive(Error, Debug)]
@@ -15,6 +23,24 @@ pub enum ApiError {
     NotYetImplemented,
     #[error("{0}")]
     ServiceError(#[from] AccountsServiceError),
+    #[error("{0}")]
+    SessionServiceError(#[from] SessionServiceError),
+    #[error("{0}")]
+    TokenServiceError(#[from] TokenServiceError),
+    #[error("{0}")]
+    AuditServiceError(#[from] AuditServiceError),
+    #[error("{0}")]
+    MfaServiceError(#[from] MfaServiceError),
+    #[error("{0}")]
+    CredentialServiceError(#[from] CredentialServiceError),
+    #[error("{0}")]
+    OAuthServiceError(#[from] OAuthServiceError),
+    #[error("{0}")]
+    FederationServiceError(#[from] FederationServiceError),
+    #[error("Too many requests, please try again in {0} seconds")]
+    TooManyRequests(u64),
+    #[error("The account's current password, a second factor or a recent session is required to make this change")]
+    ReauthenticationRequired,
 }
 
 /// Converts an [ApiError] into an [axum::response::Response].
@@ -28,16 +54,138 @@ impl IntoResponse for ApiError {
                 AccountsServiceError::NotYetImplemented => StatusCode::NOT_IMPLEMENTED,
                 AccountsServiceError::EmailAlreadyExists(_)
                 | AccountsServiceError::ValidationErrors(_)
-                | AccountsServiceError::InvalidCredentials => StatusCode::BAD_REQUEST,
+                | AccountsServiceError::InvalidCredentials
+                | AccountsServiceError::AccountNotActive
+                | AccountsServiceError::AccountLocked
+                | AccountsServiceError::InvalidPasswordResetToken
+                | AccountsServiceError::InvalidRecoveryCode
+                | AccountsServiceError::InvalidSignInToken
+                | AccountsServiceError::EmailAlreadyVerified(_)
+                | AccountsServiceError::InvalidEmailVerificationToken => StatusCode::BAD_REQUEST,
+                AccountsServiceError::AccountNotFound(_) => StatusCode::NOT_FOUND,
                 AccountsServiceError::PasswordHashingError(_)
                 | AccountsServiceError::StoreError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             },
+            Self::SessionServiceError(svc_err) => match svc_err {
+                SessionServiceError::InvalidSession => StatusCode::NOT_FOUND,
+                SessionServiceError::InvalidRefreshToken => StatusCode::BAD_REQUEST,
+                SessionServiceError::StoreError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Self::TokenServiceError(svc_err) => match svc_err {
+                TokenServiceError::InvalidAccessToken => StatusCode::UNAUTHORIZED,
+                TokenServiceError::InvalidSigningKey(_)
+                | TokenServiceError::SerializationError(_)
+                | TokenServiceError::StoreError(_)
+                | TokenServiceError::RevokedTokenStoreError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Self::AuditServiceError(svc_err) => match svc_err {
+                AuditServiceError::InvalidCursor => StatusCode::BAD_REQUEST,
+                AuditServiceError::StoreError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Self::MfaServiceError(svc_err) => match svc_err {
+                MfaServiceError::MfaAlreadyEnabled
+                | MfaServiceError::TotpNotEnrolled
+                | MfaServiceError::InvalidCode
+                | MfaServiceError::InvalidMfaChallenge => StatusCode::BAD_REQUEST,
+                MfaServiceError::InvalidEncryptionKey(_)
+                | MfaServiceError::EncryptionError
+                | MfaServiceError::StoreError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Self::CredentialServiceError(svc_err) => match svc_err {
+                CredentialServiceError::InvalidChallenge
+                | CredentialServiceError::InvalidClientData(_)
+                | CredentialServiceError::InvalidAuthenticatorData(_)
+                | CredentialServiceError::InvalidAttestation(_)
+                | CredentialServiceError::UnsupportedAttestationFormat(_)
+                | CredentialServiceError::UnsupportedPublicKey
+                | CredentialServiceError::UserNotVerified
+                | CredentialServiceError::InvalidSignature
+                | CredentialServiceError::SignCountNotIncreased
+                | CredentialServiceError::PasskeyAlreadyRegistered
+                | CredentialServiceError::InvalidPasskeyName(_) => StatusCode::BAD_REQUEST,
+                CredentialServiceError::PasskeyNotFound(_) => StatusCode::NOT_FOUND,
+                CredentialServiceError::StoreError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Self::OAuthServiceError(svc_err) => match svc_err {
+                OAuthServiceError::InvalidClient | OAuthServiceError::InvalidToken => {
+                    StatusCode::UNAUTHORIZED
+                }
+                OAuthServiceError::AccessDenied
+                | OAuthServiceError::ConsentRequired
+                | OAuthServiceError::InsufficientScope(_) => StatusCode::FORBIDDEN,
+                OAuthServiceError::ClientNotFound(_) | OAuthServiceError::SecretNotFound(_) => {
+                    StatusCode::NOT_FOUND
+                }
+                OAuthServiceError::InvalidRedirectUri
+                | OAuthServiceError::InvalidClientMetadata(_)
+                | OAuthServiceError::InvalidRequest(_)
+                | OAuthServiceError::UnsupportedResponseType(_)
+                | OAuthServiceError::UnsupportedGrantType(_)
+                | OAuthServiceError::InvalidScope(_)
+                | OAuthServiceError::InvalidGrant => StatusCode::BAD_REQUEST,
+                OAuthServiceError::StoreError(_) | OAuthServiceError::HashingError(_) => {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            },
+            Self::FederationServiceError(svc_err) => match svc_err {
+                FederationServiceError::InvalidState
+                | FederationServiceError::InvalidIdToken(_)
+                | FederationServiceError::IdentityNotLinked
+                | FederationServiceError::IdentityAlreadyLinked => StatusCode::BAD_REQUEST,
+                FederationServiceError::UnknownProvider(_)
+                | FederationServiceError::IdentityNotFound(_) => StatusCode::NOT_FOUND,
+                FederationServiceError::UpstreamError(_) => StatusCode::BAD_GATEWAY,
+                FederationServiceError::StoreError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Self::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::ReauthenticationRequired => StatusCode::BAD_REQUEST,
+        };
+        // OAuth clients expect errors in the format defined by RFC 6749 section 5.2
+        if let Self::OAuthServiceError(svc_err) = &self {
+            let body = OAuthErrorResponse {
+                error: svc_err.error_code().to_string(),
+                error_description: svc_err.to_string(),
+            };
+            let mut response = (status, Json(body)).into_response();
+            // resources protected by access tokens also say what was wrong
+            // with the token in a header (RFC 6750 section 3)
+            if matches!(
+                svc_err,
+                OAuthServiceError::InvalidToken | OAuthServiceError::InsufficientScope(_)
+            ) {
+                let challenge = format!(r#"Bearer error="{}""#, svc_err.error_code());
+                if let Ok(value) = HeaderValue::from_str(&challenge) {
+                    response.headers_mut().insert(WWW_AUTHENTICATE, value);
+                }
+            }
+            // a 401 from the token endpoint says how clients can
+            // authenticate (RFC 6749 section 5.2)
+            if matches!(svc_err, OAuthServiceError::InvalidClient) {
+                response
+                    .headers_mut()
+                    .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Basic"));
+            }
+            return response;
+        }
+        let message = match &self {
+            // Respond as if the credentials were incorrect, so that callers
+            // can't discover which accounts exist but are not active or locked.
+            Self::ServiceError(
+                AccountsServiceError::AccountNotActive | AccountsServiceError::AccountLocked,
+            ) => AccountsServiceError::InvalidCredentials.to_string(),
+            _ => self.to_string(),
         };
         let body = ApiErrorResponse {
-            message: self.to_string(),
+            message,
             status: status.as_u16(),
         };
 
-        (status, Json(body)).into_response()
+        let mut response = (status, Json(body)).into_response();
+        if let Self::TooManyRequests(retry_after) = self {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        response
     }
 }
@@ -0,0 +1,176 @@
+//! Axum extractors shared by the API handlers.
+
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{
+        header::{AUTHORIZATION, USER_AGENT},
+        request::Parts,
+        Extensions, HeaderMap,
+    },
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ipnet::IpNet;
+use thiserror::Error;
+
+use crate::services::{audit::models::AuditContext, oauth::error::OAuthServiceError};
+
+use super::error::ApiError;
+
+/// Header the API gateway can set to the ID of the authenticated caller
+/// (e.g., a support operator), which is recorded as the actor of audit events.
+pub const ACTOR_ID_HEADER: &str = "x-actor-id";
+/// Header containing the client IP address when requests come through a proxy.
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Returned when parsing [TrustedProxies] from an invalid specification.
+#[derive(Debug, Error)]
+#[error("'{0}' is not a valid IP address or CIDR range")]
+pub struct ParseTrustedProxiesError(pub String);
+
+/// The proxies (e.g., the API gateway or a load balancer) that are trusted to
+/// report the address they received a request from in the `X-Forwarded-For`
+/// header. The router adds these to each request's extensions for [client_ip].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    /// Returns true if the address belongs to a trusted proxy.
+    fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|network| network.contains(ip))
+    }
+}
+
+impl FromStr for TrustedProxies {
+    type Err = ParseTrustedProxiesError;
+
+    /// Parses a comma-separated list of IP addresses and CIDR ranges
+    /// (e.g., `10.0.0.0/8, 192.0.2.10`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| ParseTrustedProxiesError(entry.to_string()))
+            })
+            .collect::<Result<Vec<IpNet>, _>>()
+            .map(TrustedProxies)
+    }
+}
+
+/// Extracts an [AuditContext] describing who made the request and where it
+/// came from. The source IP is determined by [client_ip]. The actor header
+/// can be set by anyone, so the service should only be reachable through an
+/// API gateway that overwrites it.
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuditContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(AuditContext {
+            actor: header_value(&parts.headers, ACTOR_ID_HEADER),
+            source_ip: client_ip(&parts.headers, &parts.extensions),
+            user_agent: header_value(&parts.headers, USER_AGENT.as_str()),
+        })
+    }
+}
+
+/// An access token presented in the `Authorization` header
+/// using the `Bearer` scheme (RFC 6750 section 2.1).
+pub struct BearerToken(pub String);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for BearerToken {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        header_value(&parts.headers, AUTHORIZATION.as_str())
+            .and_then(|value| {
+                let (scheme, token) = value.split_once(' ')?;
+                let token = token.trim();
+                (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty())
+                    .then(|| BearerToken(token.to_string()))
+            })
+            .ok_or(OAuthServiceError::InvalidToken.into())
+    }
+}
+
+/// A client ID and secret presented in the `Authorization` header using
+/// the `Basic` scheme (RFC 6749 section 2.3.1), if the client used it.
+/// The ID and secret aren't form-decoded, since the ones we issue only
+/// contain URL-safe characters. Other schemes are ignored, and a malformed
+/// `Basic` header is rejected as an invalid client.
+pub struct BasicCredentials(pub Option<(String, String)>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for BasicCredentials {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(value) = header_value(&parts.headers, AUTHORIZATION.as_str()) else {
+            return Ok(BasicCredentials(None));
+        };
+        let Some((scheme, encoded)) = value.split_once(' ') else {
+            return Ok(BasicCredentials(None));
+        };
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return Ok(BasicCredentials(None));
+        }
+        STANDARD
+            .decode(encoded.trim())
+            .ok()
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .and_then(|decoded| {
+                let (id, secret) = decoded.split_once(':')?;
+                Some(BasicCredentials(Some((id.to_string(), secret.to_string()))))
+            })
+            .ok_or(OAuthServiceError::InvalidClient.into())
+    }
+}
+
+/// Returns the IP address of the client that made a request. This is the
+/// address of the connected peer, unless the peer is one of the [TrustedProxies].
+/// Each proxy appends the address it received the request from to the
+/// `X-Forwarded-For` header, so the header is read from the right, skipping
+/// the addresses of trusted proxies, and the first other address is the
+/// client's. Anything to the left of that could have been made up by the client.
+pub fn client_ip(headers: &HeaderMap, extensions: &Extensions) -> Option<String> {
+    let ConnectInfo(peer) = extensions.get::<ConnectInfo<SocketAddr>>()?;
+    let mut ip = peer.ip();
+    if let Some(trusted_proxies) = extensions.get::<TrustedProxies>() {
+        let forwarded_for: Vec<&str> = headers
+            .get_all(FORWARDED_FOR_HEADER)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .collect();
+        for hop in forwarded_for.iter().rev() {
+            if !trusted_proxies.contains(&ip) {
+                break;
+            }
+            match hop.trim().parse() {
+                Ok(hop) => ip = hop,
+                Err(_) => break,
+            }
+        }
+    }
+    Some(ip.to_string())
+}
+
+/// Returns the value of the named header, if it's present and valid text.
+fn header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
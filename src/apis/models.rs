@@ -14,6 +14,45 @@ use serde::{Deserialize, Serialize};
 
 use crate::services::account::models::Password;
 
+/// Serializes binary fields as unpadded base64url strings, which is how
+/// WebAuthn clients encode them in JSON.
+mod base64url {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        URL_SAFE_NO_PAD.decode(encoded).map_err(D::Error::custom)
+    }
+
+    /// The same, for optional fields.
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            bytes: &Option<Vec<u8>>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match bytes {
+                Some(bytes) => super::serialize(bytes, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Vec<u8>>, D::Error> {
+            Option::<String>::deserialize(deserializer)?
+                .map(|encoded| URL_SAFE_NO_PAD.decode(encoded).map_err(D::Error::custom))
+                .transpose()
+        }
+    }
+}
+
 /// Represents an API error JSON response
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct ApiErrorResponse {
@@ -45,6 +84,153 @@ pub struct AccountResponse {
     pub display_name: Option<String>,
     /// When this account was created.
     pub created_at: DateTime<Utc>,
+    /// When the account's email address was verified, if it has been.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_verified_at: Option<DateTime<Utc>>,
+    /// A new email address awaiting verification, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending_email: Option<String>,
+    /// Whether the account is active or deactivated.
+    pub status: AccountStatusResponse,
+}
+
+/// Represents the status of an account returned in an API response.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountStatusResponse {
+    Active,
+    Deactivated,
+    Deleted,
+}
+
+/// Represents the query parameters of a request for a page of audit events.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub struct AuditEventsQuery {
+    /// The `next_cursor` from the previous page, if any.
+    pub cursor: Option<String>,
+    /// The maximum number of events to return.
+    pub limit: Option<u32>,
+}
+
+/// Represents a page of audit events returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct AuditEventsResponse {
+    /// The events on this page, newest first.
+    pub events: Vec<AuditEventResponse>,
+    /// Cursor to pass to get the next page, if there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// Represents an audit event returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct AuditEventResponse {
+    /// Unique ID
+    pub id: String,
+    /// ID of the account the event is about.
+    pub account_id: String,
+    /// What happened (e.g., `authenticated`).
+    pub kind: String,
+    /// ID of the account or operator that caused the event, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    /// IP address of the request that caused the event, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_ip: Option<String>,
+    /// User agent of the request that caused the event, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// When the event happened.
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Represents a session returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct SessionResponse {
+    /// Opaque session ID.
+    pub id: String,
+    /// The account that owns this session.
+    pub account: AccountResponse,
+    /// When this session was created.
+    pub created_at: DateTime<Utc>,
+    /// When this session expires.
+    pub expires_at: DateTime<Utc>,
+    /// Signed access token, included only when the session is created or refreshed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<AccessTokenResponse>,
+    /// Single-use refresh token, included only when the session is created or refreshed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+/// Represents the response to an authentication request for an account that
+/// has multi-factor authentication enabled. The handle is presented along with
+/// a second factor to complete the sign-in.
+#[derive(Serialize, Deserialize)]
+pub struct MfaChallengeResponse {
+    /// Opaque handle identifying the challenge.
+    pub mfa_challenge: String,
+    /// When the challenge expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Represents a request to complete an MFA challenge and start a session.
+/// Either a code or a recovery code must be provided.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub struct MfaSessionRequest {
+    /// The handle returned when the password was authenticated.
+    pub mfa_challenge: String,
+    /// The current code from the account's authenticator app.
+    #[serde(default)]
+    pub code: Option<String>,
+    /// An unused recovery code, instead of a code from the authenticator app.
+    #[serde(default)]
+    pub recovery_code: Option<Secret<Password>>,
+}
+
+/// Represents a refresh session API request body.
+#[derive(Serialize, Deserialize)]
+pub struct RefreshSessionRequest {
+    /// The refresh token returned when the session was created or last refreshed.
+    pub refresh_token: String,
+}
+
+/// Represents a signed access token returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct AccessTokenResponse {
+    /// The signed JWT.
+    pub token: String,
+    /// How the token should be presented (always `Bearer`).
+    pub token_type: String,
+    /// When the token expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Represents a JSON Web Key Set (RFC 7517) returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct JwksResponse {
+    /// The public keys.
+    pub keys: Vec<JwkResponse>,
+}
+
+/// Represents a single public JSON Web Key.
+#[derive(Serialize, Deserialize)]
+pub struct JwkResponse {
+    /// Key type (always `OKP` for Ed25519 keys).
+    pub kty: String,
+    /// Curve name (always `Ed25519`).
+    pub crv: String,
+    /// Base64url-encoded public key.
+    pub x: String,
+    /// Key ID.
+    pub kid: String,
+    /// Algorithm the key is used with.
+    pub alg: String,
+    /// Intended use of the key (always `sig`).
+    #[serde(rename = "use")]
+    pub key_use: String,
 }
 
 /// Represents an authentication API request body.
@@ -55,6 +241,10 @@ pub struct AuthenticateRequest {
     pub email: String,
     /// Account password.
     pub password: Secret<Password>,
+    /// An unused recovery code, which lets an account with multi-factor
+    /// authentication sign in without its second factor (e.g., if it was lost).
+    #[serde(default)]
+    pub recovery_code: Option<Secret<Password>>,
 }
 
 /// Represents a set of new credentials (used in [UpdateCredentialsRequest]).
@@ -67,6 +257,44 @@ pub struct NewCredentialsRequest {
     pub email: Option<String>,
 }
 
+/// Represents a password reset API request body.
+#[derive(Serialize, Deserialize)]
+pub struct PasswordResetRequest {
+    /// Email address of the account to reset.
+    pub email: String,
+}
+
+/// Represents a passwordless sign-in API request body.
+#[derive(Serialize, Deserialize)]
+pub struct SignInCodeRequest {
+    /// Email address to send the sign-in code and token to.
+    pub email: String,
+}
+
+/// Represents a request to exchange an emailed sign-in code or token for a session.
+/// Either the token, or the email address and code, must be provided.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub struct SignInCodeSessionRequest {
+    /// Email address the sign-in code was sent to.
+    #[serde(default)]
+    pub email: Option<String>,
+    /// The sign-in code.
+    #[serde(default)]
+    pub code: Option<Secret<Password>>,
+    /// The sign-in token, instead of the email address and code.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Represents a new password API request body (used when completing a password reset).
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub struct NewPasswordRequest {
+    /// New password.
+    pub password: Secret<Password>,
+}
+
 /// Represents an update credentials API request body.
 #[derive(Deserialize)]
 #[cfg_attr(test, derive(Serialize))]
@@ -76,3 +304,531 @@ pub struct UpdateCredentialsRequest {
     /// The new credentials.
     pub new: NewCredentialsRequest,
 }
+
+/// Represents a TOTP enrollment returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct TotpEnrollmentResponse {
+    /// The shared secret, base32-encoded, for manual entry into an authenticator app.
+    pub secret: String,
+    /// The `otpauth://` URI to render as a QR code.
+    pub provisioning_uri: String,
+}
+
+/// Represents a request to confirm a TOTP enrollment.
+#[derive(Serialize, Deserialize)]
+pub struct TotpConfirmationRequest {
+    /// The current code from the authenticator app.
+    pub code: String,
+}
+
+/// Represents proof that the caller is the account holder, which is required
+/// before changes that would let someone else take over the account (e.g.,
+/// disabling MFA). One of these must be provided.
+#[derive(Default, Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub struct ReauthenticationRequest {
+    /// The account's current password.
+    #[serde(default)]
+    pub password: Option<Secret<Password>>,
+    /// The current code from the account's authenticator app.
+    #[serde(default)]
+    pub code: Option<String>,
+    /// An unused recovery code, which is then used up.
+    #[serde(default)]
+    pub recovery_code: Option<Secret<Password>>,
+    /// The ID of a session for the account that was started within the last
+    /// five minutes (e.g., for accounts without a password).
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+/// Represents a newly-generated set of recovery codes returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct RecoveryCodesResponse {
+    /// The recovery codes. These can't be retrieved again later.
+    pub codes: Vec<String>,
+}
+
+/// Represents a summary of an account's security settings returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct SecuritySummaryResponse {
+    /// Whether the account's email address has been verified.
+    pub email_verified: bool,
+    /// Whether signing in requires a second factor.
+    pub mfa_enabled: bool,
+    /// Number of recovery codes that have not yet been used.
+    pub recovery_codes_remaining: usize,
+    /// When the current set of recovery codes was generated, if one has been.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_codes_generated_at: Option<DateTime<Utc>>,
+}
+
+/// Represents the options for creating a passkey returned in an API response.
+/// The browser passes these to `navigator.credentials.create()`.
+#[derive(Serialize, Deserialize)]
+pub struct PasskeyRegistrationOptionsResponse {
+    /// The challenge, base64url-encoded.
+    pub challenge: String,
+    /// The relying party ID.
+    pub rp_id: String,
+    /// The relying party name.
+    pub rp_name: String,
+    /// The user handle, base64url-encoded.
+    pub user_id: String,
+    /// The account name to show (the email address).
+    pub user_name: String,
+    /// COSE algorithm identifiers of the supported public key types, in order of preference.
+    pub algorithms: Vec<i64>,
+    /// Base64url-encoded IDs of the account's existing passkeys.
+    pub exclude_credentials: Vec<String>,
+    /// When the challenge expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Represents a request to finish registering a passkey.
+#[derive(Serialize, Deserialize)]
+pub struct NewPasskeyRequest {
+    /// Name that identifies the passkey to the account holder.
+    pub name: String,
+    /// The `clientDataJSON` from the browser, base64url-encoded.
+    #[serde(with = "base64url")]
+    pub client_data_json: Vec<u8>,
+    /// The `attestationObject` from the browser, base64url-encoded.
+    #[serde(with = "base64url")]
+    pub attestation_object: Vec<u8>,
+}
+
+/// Represents a request to rename a passkey.
+#[derive(Serialize, Deserialize)]
+pub struct PasskeyNameRequest {
+    /// The new name.
+    pub name: String,
+}
+
+/// Represents a passkey returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct PasskeyResponse {
+    /// Unique ID
+    pub id: String,
+    /// Name that identifies the passkey to the account holder.
+    pub name: String,
+    /// When the passkey was registered.
+    pub created_at: DateTime<Utc>,
+    /// When the passkey was last used to sign in, if it has been.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Represents an account's passkeys returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct PasskeysResponse {
+    /// The passkeys, oldest first.
+    pub passkeys: Vec<PasskeyResponse>,
+}
+
+/// Represents the options for signing in with a passkey returned in an API
+/// response. The browser passes these to `navigator.credentials.get()`.
+#[derive(Serialize, Deserialize)]
+pub struct PasskeyAuthenticationOptionsResponse {
+    /// The challenge, base64url-encoded.
+    pub challenge: String,
+    /// The relying party ID.
+    pub rp_id: String,
+    /// When the challenge expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Represents a request to start a session using a passkey.
+#[derive(Serialize, Deserialize)]
+pub struct PasskeySessionRequest {
+    /// The ID of the passkey that was used, base64url-encoded.
+    #[serde(with = "base64url")]
+    pub credential_id: Vec<u8>,
+    /// The `clientDataJSON` from the browser, base64url-encoded.
+    #[serde(with = "base64url")]
+    pub client_data_json: Vec<u8>,
+    /// The `authenticatorData` from the browser, base64url-encoded.
+    #[serde(with = "base64url")]
+    pub authenticator_data: Vec<u8>,
+    /// The `signature` from the browser, base64url-encoded.
+    #[serde(with = "base64url")]
+    pub signature: Vec<u8>,
+    /// The `userHandle` from the browser, base64url-encoded, if it returned one.
+    #[serde(default, with = "base64url::option")]
+    pub user_handle: Option<Vec<u8>>,
+}
+
+/// Represents a request to register an OAuth client.
+#[derive(Serialize, Deserialize)]
+pub struct NewOAuthClientRequest {
+    /// Name shown to account holders when they're asked for consent.
+    pub name: String,
+    /// The URIs the client can be redirected back to.
+    pub redirect_uris: Vec<String>,
+    /// The scopes the client can request.
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+/// Represents an OAuth client returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct OAuthClientResponse {
+    /// The client's ID, which it sends as its `client_id`.
+    pub id: String,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Represents the parameters of an OAuth authorization request, which
+/// the client sends in the query of `GET /oauth/authorize`, and the login
+/// page passes back in the body of `POST /oauth/authorize`.
+#[derive(Serialize, Deserialize, Default)]
+pub struct AuthorizationParams {
+    #[serde(default)]
+    pub response_type: Option<String>,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub redirect_uri: Option<String>,
+    #[serde(default)]
+    pub scope: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub code_challenge: Option<String>,
+    #[serde(default)]
+    pub code_challenge_method: Option<String>,
+    #[serde(default)]
+    pub nonce: Option<String>,
+}
+
+/// Represents an account holder signing in and consenting to an OAuth
+/// authorization request. Like signing in to start a session, this takes
+/// either an email address and password (optionally with a recovery code),
+/// or the handle of an MFA challenge with a code or recovery code.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub struct AuthorizeRequest {
+    /// The parameters of the authorization request.
+    #[serde(flatten)]
+    pub params: AuthorizationParams,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub password: Option<Secret<Password>>,
+    /// The handle returned when the password was authenticated,
+    /// if the account has multi-factor authentication enabled.
+    #[serde(default)]
+    pub mfa_challenge: Option<String>,
+    /// The current code from the account's authenticator app.
+    #[serde(default)]
+    pub code: Option<String>,
+    /// An unused recovery code, instead of a second factor.
+    #[serde(default)]
+    pub recovery_code: Option<Secret<Password>>,
+    /// Whether the account holder consents to the client's access. Only needed
+    /// if they haven't consented before: if omitted, a `consent_required` error
+    /// is returned, and the request should be repeated with their answer.
+    #[serde(default)]
+    pub consent: Option<bool>,
+}
+
+/// Represents where the login page should send the browser
+/// at the end of an OAuth authorization request.
+#[derive(Serialize, Deserialize)]
+pub struct AuthorizeResponse {
+    /// The client's redirect URI, with either an authorization code or an error.
+    pub redirect_to: String,
+}
+
+/// Represents an OAuth token request (RFC 6749 sections 4.1.3, 4.4.2 and 6),
+/// which is form-encoded.
+#[derive(Serialize, Deserialize)]
+pub struct TokenRequest {
+    #[serde(default)]
+    pub grant_type: Option<String>,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// A service account's secret, unless it's sent using HTTP Basic authentication.
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub redirect_uri: Option<String>,
+    #[serde(default)]
+    pub code_verifier: Option<String>,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+/// Represents a successful OAuth token response (RFC 6749 section 5.1).
+#[derive(Serialize, Deserialize)]
+pub struct TokenResponse {
+    /// The signed JWT.
+    pub access_token: String,
+    /// How the token should be presented (always `Bearer`).
+    pub token_type: String,
+    /// How many seconds the access token remains valid.
+    pub expires_in: i64,
+    /// Single-use refresh token. Service accounts don't get one, since
+    /// they can authenticate again when the access token expires.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    /// Space-separated list of the scopes granted to the access token.
+    pub scope: String,
+    /// Signed OpenID Connect ID token, if the `openid` scope was granted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+}
+
+/// Represents a token introspection request (RFC 7662 section 2.1) or
+/// revocation request (RFC 7009 section 2.1), which is form-encoded.
+/// The client's credentials can also be sent using HTTP Basic authentication.
+#[derive(Serialize, Deserialize)]
+pub struct TokenHintRequest {
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Whether the token is an `access_token` or `refresh_token`. This is
+    /// only a hint, and both kinds are looked up regardless.
+    #[serde(default)]
+    pub token_type_hint: Option<String>,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub client_secret: Option<String>,
+}
+
+/// Represents a token introspection response (RFC 7662 section 2.2). Only
+/// `active` is returned for tokens that are invalid, expired or revoked.
+#[derive(Serialize, Deserialize, Default)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    /// Space-separated list of the scopes granted to the token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// ID of the OAuth client (or service account) the token was issued to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// `Bearer` for access tokens, or `refresh_token` for refresh tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+    /// When the token expires, in seconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    /// When the token was issued, in seconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    /// The ID of the account (or service account) the token was issued for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+}
+
+/// Represents an OAuth error response (RFC 6749 section 5.2).
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct OAuthErrorResponse {
+    pub error: String,
+    pub error_description: String,
+}
+
+/// Represents an account holder's consent for an OAuth client returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct OAuthGrantResponse {
+    pub client_id: String,
+    /// The scopes the account holder has consented to.
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Represents an account's OAuth grants returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct OAuthGrantsResponse {
+    /// The grants, oldest first.
+    pub grants: Vec<OAuthGrantResponse>,
+}
+
+/// Represents the OpenID Provider metadata (OpenID Connect Discovery section 3),
+/// which OpenID Connect client libraries use to configure themselves.
+#[derive(Serialize, Deserialize)]
+pub struct OpenIdConfigurationResponse {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub jwks_uri: String,
+    pub scopes_supported: Vec<String>,
+    pub response_types_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<String>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub code_challenge_methods_supported: Vec<String>,
+    pub claims_supported: Vec<String>,
+    /// The token introspection (RFC 7662) and revocation (RFC 7009)
+    /// endpoints, as defined by RFC 8414.
+    pub introspection_endpoint: String,
+    pub introspection_endpoint_auth_methods_supported: Vec<String>,
+    pub revocation_endpoint: String,
+    pub revocation_endpoint_auth_methods_supported: Vec<String>,
+}
+
+/// Represents the claims about an account holder returned
+/// from the userinfo endpoint (OpenID Connect Core section 5.3).
+#[derive(Serialize, Deserialize)]
+pub struct UserInfoResponse {
+    /// The ID of the account.
+    pub sub: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Represents a request to create a new service account.
+#[derive(Serialize, Deserialize)]
+pub struct NewServiceAccountRequest {
+    pub name: String,
+    /// The scopes the service account can request.
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+/// Represents a service account returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct ServiceAccountResponse {
+    /// The ID, which the service account sends as its `client_id`.
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Represents a newly created service account, along with its first secret.
+#[derive(Serialize, Deserialize)]
+pub struct NewServiceAccountResponse {
+    pub service_account: ServiceAccountResponse,
+    pub secret: IssuedSecretResponse,
+}
+
+/// Represents a newly issued service account secret. The
+/// secret is only returned once, so it must be kept safe.
+#[derive(Serialize, Deserialize)]
+pub struct IssuedSecretResponse {
+    pub id: String,
+    /// The secret, which the service account sends as its `client_secret`.
+    pub client_secret: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Represents a service account secret (without the secret itself) returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct ServiceAccountSecretResponse {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Represents a service account's secrets returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct ServiceAccountSecretsResponse {
+    /// The secrets, oldest first.
+    pub secrets: Vec<ServiceAccountSecretResponse>,
+}
+
+/// Represents an upstream identity provider returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct IdentityProviderResponse {
+    /// Name that identifies the provider in requests.
+    pub name: String,
+    /// The provider's issuer identifier.
+    pub issuer: String,
+}
+
+/// Represents the identity providers account holders can sign in with.
+#[derive(Serialize, Deserialize)]
+pub struct IdentityProvidersResponse {
+    pub providers: Vec<IdentityProviderResponse>,
+}
+
+/// Represents a request to start signing in with an upstream identity provider.
+#[derive(Serialize, Deserialize)]
+pub struct FederatedAuthorizationRequest {
+    /// Name of the provider.
+    pub provider: String,
+}
+
+/// Represents where to send the browser to sign in with an upstream identity provider.
+#[derive(Serialize, Deserialize)]
+pub struct FederatedAuthorizationResponse {
+    /// The provider's authorization URL, including the request's parameters.
+    pub authorization_url: String,
+    /// When the request expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Represents the parameters an upstream identity provider redirected back with.
+#[derive(Serialize, Deserialize)]
+pub struct FederatedCallbackRequest {
+    /// The `state` query parameter.
+    pub state: String,
+    /// The `code` query parameter.
+    pub code: String,
+}
+
+/// Represents a request to start linking an identity at an upstream identity
+/// provider to an account.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub struct LinkedIdentityAuthorizationRequest {
+    /// Name of the provider.
+    pub provider: String,
+    /// Proof that the caller is the account holder.
+    #[serde(default)]
+    pub reauthentication: ReauthenticationRequest,
+}
+
+/// Represents the parameters an upstream identity provider redirected back
+/// with, to finish linking the identity to an account.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub struct NewLinkedIdentityRequest {
+    /// The `state` query parameter.
+    pub state: String,
+    /// The `code` query parameter.
+    pub code: String,
+    /// Proof that the caller is the account holder.
+    #[serde(default)]
+    pub reauthentication: ReauthenticationRequest,
+}
+
+/// Represents an identity at an upstream provider linked to an account.
+#[derive(Serialize, Deserialize)]
+pub struct LinkedIdentityResponse {
+    pub id: String,
+    /// Name of the provider.
+    pub provider: String,
+    /// The provider's identifier for the account holder.
+    pub subject: String,
+    /// The email address the provider had when the identity was last used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// When the identity was last used to sign in, if it has been.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Represents an account's linked identities returned in an API response.
+#[derive(Serialize, Deserialize)]
+pub struct LinkedIdentitiesResponse {
+    /// The linked identities, oldest first.
+    pub identities: Vec<LinkedIdentityResponse>,
+}
@@ -0,0 +1,419 @@
+//! Middleware that limits how often clients can call the REST API, using
+//! token buckets keyed by the client IP address and, for routes that accept
+//! one, by the email address in the request body. This slows down attacks like
+//! credential stuffing, which try many passwords for many accounts.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequest, MatchedPath, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_prometheus::metrics::counter;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::services::{Clock, SystemClock};
+
+use super::{error::ApiError, extractors::client_ip};
+
+/// The route name used in [RateLimits] for limits that apply to every route
+/// without limits of its own.
+const DEFAULT_ROUTE: &str = "*";
+/// Name of the Prometheus counter incremented for each rejected request.
+const REJECTIONS_COUNTER: &str = "http_rate_limit_rejections_total";
+/// The most buckets kept at once. Email addresses are chosen by the client, so
+/// without a limit, a flood of made-up ones would use up memory. Beyond this,
+/// the least recently used bucket is discarded to make room for a new one.
+const MAX_BUCKETS: usize = 100_000;
+/// How often buckets that have refilled completely are discarded, since they
+/// are no different from new ones.
+const PRUNE_INTERVAL: TimeDelta = TimeDelta::minutes(1);
+
+/// Returned when parsing [RateLimits] from an invalid specification.
+#[derive(Debug, Error)]
+#[error("'{0}' is not a valid rate limit")]
+pub struct ParseRateLimitsError(pub String);
+
+/// A token bucket limit: a client can make up to `burst` requests at once,
+/// and the bucket refills at a rate of `burst` requests per `period`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quota {
+    pub burst: u32,
+    pub period: TimeDelta,
+}
+
+impl Quota {
+    /// Returns the number of requests the bucket regains each second.
+    fn refill_rate(&self) -> f64 {
+        self.burst as f64 / self.period.num_milliseconds() as f64 * 1000.0
+    }
+}
+
+impl FromStr for Quota {
+    type Err = ParseRateLimitsError;
+
+    /// Parses a quota written as `burst/seconds` (e.g., `5/60`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseRateLimitsError(s.to_string());
+        let (burst, seconds) = s.split_once('/').ok_or_else(err)?;
+        let burst: u32 = burst.parse().map_err(|_| err())?;
+        let seconds: i64 = seconds.parse().map_err(|_| err())?;
+        if burst == 0 || seconds <= 0 {
+            return Err(err());
+        }
+        Ok(Quota {
+            burst,
+            period: TimeDelta::seconds(seconds),
+        })
+    }
+}
+
+/// The limits applied to one route.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RouteLimits {
+    /// Limit for each client IP address.
+    pub ip: Option<Quota>,
+    /// Limit for each email address submitted in the request body.
+    pub email: Option<Quota>,
+}
+
+/// The limits applied to each route, keyed by route path as registered
+/// with the router (e.g., `/accounts/:id/credentials`). Each route has its
+/// own buckets, so a client's requests to one route don't count towards
+/// its limit for another.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RateLimits {
+    /// Limits for routes that don't have their own.
+    pub default: RouteLimits,
+    /// Limits for specific routes.
+    pub routes: HashMap<String, RouteLimits>,
+}
+
+impl RateLimits {
+    /// Returns the limits that apply to the route.
+    fn for_route(&self, route: &str) -> &RouteLimits {
+        self.routes.get(route).unwrap_or(&self.default)
+    }
+}
+
+impl FromStr for RateLimits {
+    type Err = ParseRateLimitsError;
+
+    /// Parses limits written as a semicolon-separated list of routes, each
+    /// followed by its `ip` and/or `email` quotas. `*` sets the limits for
+    /// routes that aren't listed. For example:
+    /// `* ip=300/60; /sessions ip=20/60 email=5/60`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rate_limits = RateLimits::default();
+        for entry in s.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let mut parts = entry.split_whitespace();
+            let route = parts.next().unwrap_or_default();
+            let mut limits = RouteLimits::default();
+            for part in parts {
+                match part.split_once('=') {
+                    Some(("ip", quota)) => limits.ip = Some(quota.parse()?),
+                    Some(("email", quota)) => limits.email = Some(quota.parse()?),
+                    _ => return Err(ParseRateLimitsError(entry.to_string())),
+                }
+            }
+            if route == DEFAULT_ROUTE {
+                rate_limits.default = limits;
+            } else {
+                rate_limits.routes.insert(route.to_string(), limits);
+            }
+        }
+        Ok(rate_limits)
+    }
+}
+
+/// A token bucket, which holds up to a quota's burst of tokens.
+/// Each request takes a token, and the bucket refills over time.
+struct Bucket {
+    tokens: f64,
+    updated_at: DateTime<Utc>,
+    /// When the bucket will be full again if no more tokens are taken.
+    full_at: DateTime<Utc>,
+    /// Orders the bucket in [Buckets::by_use].
+    last_used: u64,
+}
+
+/// The token buckets, keyed by route and client IP or email address, along
+/// with the order they were last used in, so the least recently used can be
+/// found without scanning them all.
+struct Buckets {
+    by_key: HashMap<String, Bucket>,
+    by_use: BTreeMap<u64, String>,
+    next_use: u64,
+    /// The most buckets kept at once (see [MAX_BUCKETS]).
+    capacity: usize,
+    pruned_at: DateTime<Utc>,
+}
+
+impl Buckets {
+    /// Returns the bucket for the key, marking it as the most recently used.
+    /// A new bucket starts full, and replaces the least recently used one if
+    /// there are already as many as the capacity.
+    fn get(&mut self, key: String, quota: &Quota, now: DateTime<Utc>) -> &mut Bucket {
+        let used = self.next_use;
+        self.next_use += 1;
+        match self.by_key.get(&key) {
+            Some(bucket) => {
+                self.by_use.remove(&bucket.last_used);
+            }
+            None if self.by_key.len() >= self.capacity => {
+                if let Some((_, oldest)) = self.by_use.pop_first() {
+                    self.by_key.remove(&oldest);
+                }
+            }
+            None => {}
+        }
+        self.by_use.insert(used, key.clone());
+        let bucket = self.by_key.entry(key).or_insert(Bucket {
+            tokens: quota.burst as f64,
+            updated_at: now,
+            full_at: now,
+            last_used: used,
+        });
+        bucket.last_used = used;
+        bucket
+    }
+
+    /// Discards the buckets that have refilled completely, at most once per
+    /// [PRUNE_INTERVAL], so that requests don't usually pay for scanning them.
+    fn prune(&mut self, now: DateTime<Utc>) {
+        if now - self.pruned_at < PRUNE_INTERVAL {
+            return;
+        }
+        self.pruned_at = now;
+        self.by_key.retain(|_, bucket| bucket.full_at > now);
+        let by_key = &self.by_key;
+        self.by_use.retain(|_, key| by_key.contains_key(key));
+    }
+}
+
+/// Applies [RateLimits] to requests, keeping a token bucket for each
+/// combination of route and client IP or email address.
+pub struct RateLimiter<C: Clock<Utc>> {
+    limits: RateLimits,
+    buckets: Mutex<Buckets>,
+    clock: C,
+}
+
+impl<C: Clock<Utc>> RateLimiter<C> {
+    /// Constructs a new [RateLimiter] given the [RateLimits] and [Clock] to use.
+    pub fn new_with_clock(limits: RateLimits, clock: C) -> Self {
+        Self {
+            limits,
+            buckets: Mutex::new(Buckets {
+                by_key: HashMap::new(),
+                by_use: BTreeMap::new(),
+                next_use: 0,
+                capacity: MAX_BUCKETS,
+                pruned_at: clock.now(),
+            }),
+            clock,
+        }
+    }
+
+    /// Takes a token from the bucket for the key, returning how long to
+    /// wait before trying again if the bucket is empty.
+    fn acquire(&self, key: String, quota: &Quota) -> Result<(), TimeDelta> {
+        let now = self.clock.now();
+        let rate = quota.refill_rate();
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.prune(now);
+        let bucket = buckets.get(key, quota, now);
+
+        let elapsed = (now - bucket.updated_at).num_milliseconds().max(0) as f64 / 1000.0;
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(quota.burst as f64);
+        bucket.updated_at = now;
+        let result = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / rate;
+            Err(TimeDelta::milliseconds((wait * 1000.0).ceil() as i64))
+        };
+        let until_full = (quota.burst as f64 - bucket.tokens) / rate;
+        bucket.full_at = now + TimeDelta::milliseconds((until_full * 1000.0).ceil() as i64);
+        result
+    }
+
+    /// Checks one of the route's limits for the key (an IP or email address),
+    /// counting the rejection if the limit has been reached.
+    fn check(
+        &self,
+        route: &str,
+        kind: &'static str,
+        key: &str,
+        quota: &Quota,
+    ) -> Result<(), ApiError> {
+        self.acquire(format!("{} {} {}", route, kind, key), quota)
+            .map_err(|wait| {
+                counter!(REJECTIONS_COUNTER, "route" => route.to_string(), "key" => kind)
+                    .increment(1);
+                // round up, so that clients don't retry too soon
+                let seconds = (wait.num_milliseconds() + 999) / 1000;
+                ApiError::TooManyRequests(seconds.max(1) as u64)
+            })
+    }
+}
+
+impl RateLimiter<SystemClock<Utc>> {
+    pub fn new(limits: RateLimits) -> Self {
+        Self::new_with_clock(limits, SystemClock::default())
+    }
+}
+
+/// The part of a request body that the email limit is keyed by.
+#[derive(Deserialize)]
+struct EmailField {
+    email: Option<String>,
+}
+
+/// Middleware that rejects requests exceeding the [RateLimiter]'s limits
+/// with a `429 Too Many Requests` response. Requests whose client IP can't
+/// be determined aren't limited by IP, and those without an email address in
+/// their body aren't limited by email.
+pub async fn limit_requests<C: Clock<Utc>>(
+    State(limiter): State<Arc<RateLimiter<C>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(matched_path) => matched_path.as_str().to_string(),
+        None => request.uri().path().to_string(),
+    };
+    let limits = limiter.limits.for_route(&route);
+
+    if let Some(quota) = &limits.ip {
+        if let Some(ip) = client_ip(request.headers(), request.extensions()) {
+            if let Err(err) = limiter.check(&route, "ip", &ip, quota) {
+                return err.into_response();
+            }
+        }
+    }
+
+    let Some(quota) = &limits.email else {
+        return next.run(request).await;
+    };
+    // the body has to be read to find the email address, and then put back for the handler
+    let (parts, body) = request.into_parts();
+    let bytes = match Bytes::from_request(Request::new(body), &()).await {
+        Ok(bytes) => bytes,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let email = serde_json::from_slice::<EmailField>(&bytes)
+        .ok()
+        .and_then(|field| field.email)
+        .map(|email| email.trim().to_lowercase());
+    if let Some(email) = email {
+        if let Err(err) = limiter.check(&route, "email", &email, quota) {
+            return err.into_response();
+        }
+    }
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::services::TestClock;
+
+    use super::*;
+
+    fn quota(burst: u32, seconds: i64) -> Quota {
+        Quota {
+            burst,
+            period: TimeDelta::seconds(seconds),
+        }
+    }
+
+    #[test]
+    fn parse_rate_limits() {
+        let rate_limits: RateLimits = "* ip=300/60; /sessions ip=20/60 email=5/60;"
+            .parse()
+            .unwrap();
+        assert_eq!(Some(quota(300, 60)), rate_limits.default.ip);
+        assert_eq!(None, rate_limits.default.email);
+        let sessions = rate_limits.for_route("/sessions");
+        assert_eq!(Some(quota(20, 60)), sessions.ip);
+        assert_eq!(Some(quota(5, 60)), sessions.email);
+        assert_eq!(&rate_limits.default, rate_limits.for_route("/accounts"));
+
+        assert_eq!(RateLimits::default(), "".parse().unwrap());
+        for invalid in [
+            "/sessions ip=20",
+            "/sessions ip=0/60",
+            "/sessions ip=20/0",
+            "/sessions ip=x/60",
+            "/sessions user=20/60",
+        ] {
+            assert!(invalid.parse::<RateLimits>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn token_bucket() {
+        let clock = TestClock::new(Utc::now());
+        let limiter = RateLimiter::new_with_clock(RateLimits::default(), clock.clone());
+        let quota = quota(2, 10);
+
+        // the burst is available at once, and then it refills at a steady rate
+        assert!(limiter.acquire("a".to_string(), &quota).is_ok());
+        assert!(limiter.acquire("a".to_string(), &quota).is_ok());
+        assert_eq!(
+            Err(TimeDelta::seconds(5)),
+            limiter.acquire("a".to_string(), &quota)
+        );
+        // other keys have their own buckets
+        assert!(limiter.acquire("b".to_string(), &quota).is_ok());
+
+        clock.advance(TimeDelta::seconds(4));
+        assert_eq!(
+            Err(TimeDelta::seconds(1)),
+            limiter.acquire("a".to_string(), &quota)
+        );
+        clock.advance(TimeDelta::seconds(1));
+        assert!(limiter.acquire("a".to_string(), &quota).is_ok());
+        assert!(limiter.acquire("a".to_string(), &quota).is_err());
+
+        // the bucket never holds more than the burst
+        clock.advance(TimeDelta::hours(1));
+        assert!(limiter.acquire("a".to_string(), &quota).is_ok());
+        assert!(limiter.acquire("a".to_string(), &quota).is_ok());
+        assert!(limiter.acquire("a".to_string(), &quota).is_err());
+    }
+
+    #[test]
+    fn bucket_limit() {
+        let clock = TestClock::new(Utc::now());
+        let limiter = RateLimiter::new_with_clock(RateLimits::default(), clock.clone());
+        limiter.buckets.lock().unwrap().capacity = 2;
+        let quota = quota(1, 60);
+        let bucket_count = || limiter.buckets.lock().unwrap().by_key.len();
+
+        // once there are too many buckets, the least recently used one is discarded
+        assert!(limiter.acquire("a".to_string(), &quota).is_ok());
+        assert!(limiter.acquire("b".to_string(), &quota).is_ok());
+        assert!(limiter.acquire("a".to_string(), &quota).is_err());
+        assert!(limiter.acquire("c".to_string(), &quota).is_ok());
+        assert_eq!(2, bucket_count());
+        assert!(limiter.acquire("a".to_string(), &quota).is_err());
+        assert!(limiter.acquire("b".to_string(), &quota).is_ok());
+
+        // full buckets are discarded periodically
+        clock.advance(PRUNE_INTERVAL);
+        assert!(limiter.acquire("a".to_string(), &quota).is_ok());
+        assert_eq!(1, bucket_count());
+        assert_eq!(1, limiter.buckets.lock().unwrap().by_use.len());
+    }
+}
@@ -3,13 +3,16 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::{Json, Path, State},
-    http::StatusCode,
-    routing::{get, post, put},
-    Router,
+    extract::{Form, Json, Path, Query, State},
+    http::{header::CACHE_CONTROL, StatusCode},
+    middleware,
+    response::{IntoResponse, Redirect, Response},
+    routing::{delete, get, post, put},
+    Extension, Router,
 };
 use axum_prometheus::PrometheusMetricLayer;
 use chrono::Utc;
+use secrecy::Secret;
 use tower::ServiceBuilder;
 use tower_http::trace::{DefaultMakeSpan, DefaultOnRequest, DefaultOnResponse, TraceLayer};
 use tracing::Level;
@@ -17,32 +20,136 @@ use tracing::Level;
 use crate::{
     apis::models::{AccountResponse, NewAccountRequest},
     services::{
-        account::{stores::AccountStore, AccountService},
-        Clock,
+        account::{
+            error::AccountsServiceError,
+            models::{Account, AccountCredentials, Password},
+            AccountService,
+        },
+        audit::{
+            models::{AuditContext, AuditEventKind},
+            AuditService,
+        },
+        credential::CredentialService,
+        federation::FederationService,
+        mfa::{error::MfaServiceError, MfaService},
+        oauth::{
+            error::OAuthServiceError,
+            models::{
+                AuthorizationRequest, ClientAuthentication, ClientCredentialsExchange,
+                CodeExchange, RefreshExchange,
+            },
+            OAuthService,
+        },
+        session::{error::SessionServiceError, SessionService},
+        token::{
+            error::TokenServiceError,
+            models::{IdentityClaims, EMAIL_SCOPE, OPENID_SCOPE, PROFILE_SCOPE},
+            TokenService, SIGNING_ALGORITHM,
+        },
+        Backends, Clock,
     },
 };
 
 use super::{
     error::ApiError,
-    models::{AuthenticateRequest, UpdateCredentialsRequest},
+    extractors::{BasicCredentials, BearerToken, TrustedProxies},
+    models::{
+        AuditEventsQuery, AuditEventsResponse, AuthenticateRequest, AuthorizationParams,
+        AuthorizeRequest, AuthorizeResponse, FederatedAuthorizationRequest,
+        FederatedAuthorizationResponse, FederatedCallbackRequest, IdentityProvidersResponse,
+        IntrospectionResponse, IssuedSecretResponse, JwkResponse, JwksResponse,
+        LinkedIdentitiesResponse, LinkedIdentityAuthorizationRequest, LinkedIdentityResponse,
+        MfaChallengeResponse, MfaSessionRequest, NewLinkedIdentityRequest, NewOAuthClientRequest,
+        NewPasskeyRequest, NewPasswordRequest, NewServiceAccountRequest, NewServiceAccountResponse,
+        OAuthClientResponse, OAuthGrantsResponse, OpenIdConfigurationResponse,
+        PasskeyAuthenticationOptionsResponse, PasskeyNameRequest,
+        PasskeyRegistrationOptionsResponse, PasskeyResponse, PasskeySessionRequest,
+        PasskeysResponse, PasswordResetRequest, ReauthenticationRequest, RecoveryCodesResponse,
+        RefreshSessionRequest, SecuritySummaryResponse, ServiceAccountResponse,
+        ServiceAccountSecretsResponse, SessionResponse, SignInCodeRequest,
+        SignInCodeSessionRequest, TokenHintRequest, TokenRequest, TokenResponse,
+        TotpConfirmationRequest, TotpEnrollmentResponse, UpdateCredentialsRequest,
+        UserInfoResponse,
+    },
+    rate_limit::{limit_requests, RateLimiter},
 };
 
 const ROOT_RESPONSE: &str = "Welcome to the identity service!";
 const ACCOUNTS_RESOURCE: &str = "/accounts";
+const ACCOUNT_RESOURCE: &str = "/accounts/:id";
+const ACCOUNT_DEACTIVATION_RESOURCE: &str = "/accounts/:id/deactivation";
+const ACCOUNT_LOCKOUT_RESOURCE: &str = "/accounts/:id/lockout";
 const CREDENTIALS_RESOURCE: &str = "/accounts/:id/credentials";
+const ACCOUNT_SESSIONS_RESOURCE: &str = "/accounts/:id/sessions";
+const ACCOUNT_EVENTS_RESOURCE: &str = "/accounts/:id/events";
+const ACCOUNT_TOTP_RESOURCE: &str = "/accounts/:id/mfa/totp";
+const ACCOUNT_RECOVERY_CODES_RESOURCE: &str = "/accounts/:id/recovery-codes";
+const ACCOUNT_SECURITY_RESOURCE: &str = "/accounts/:id/security";
+const ACCOUNT_PASSKEY_REGISTRATIONS_RESOURCE: &str = "/accounts/:id/passkey-registrations";
+const ACCOUNT_PASSKEYS_RESOURCE: &str = "/accounts/:id/passkeys";
+const ACCOUNT_PASSKEY_RESOURCE: &str = "/accounts/:id/passkeys/:passkey_id";
+const ACCOUNT_EMAIL_VERIFICATIONS_RESOURCE: &str = "/accounts/:id/email-verifications";
+const ACCOUNT_LINKED_IDENTITY_AUTHORIZATIONS_RESOURCE: &str =
+    "/accounts/:id/linked-identity-authorizations";
+const ACCOUNT_LINKED_IDENTITIES_RESOURCE: &str = "/accounts/:id/linked-identities";
+const ACCOUNT_LINKED_IDENTITY_RESOURCE: &str = "/accounts/:id/linked-identities/:identity_id";
+const ACCOUNT_OAUTH_GRANTS_RESOURCE: &str = "/accounts/:id/oauth-grants";
+const ACCOUNT_OAUTH_GRANT_RESOURCE: &str = "/accounts/:id/oauth-grants/:client_id";
+const EMAIL_VERIFICATION_RESOURCE: &str = "/email-verifications/:token";
 const SESSIONS_RESOURCE: &str = "/sessions";
+const SESSION_RESOURCE: &str = "/sessions/:id";
+const SESSION_REFRESH_RESOURCE: &str = "/sessions/refresh";
+const SESSION_MFA_RESOURCE: &str = "/sessions/mfa";
+const SESSION_PASSKEY_CHALLENGES_RESOURCE: &str = "/sessions/passkey-challenges";
+const SESSION_PASSKEY_RESOURCE: &str = "/sessions/passkey";
+const SESSION_FEDERATED_AUTHORIZATIONS_RESOURCE: &str = "/sessions/federated-authorizations";
+const SESSION_FEDERATED_RESOURCE: &str = "/sessions/federated";
+const SESSION_SIGN_IN_CODE_RESOURCE: &str = "/sessions/sign-in-code";
+const SIGN_IN_CODES_RESOURCE: &str = "/sign-in-codes";
+const PASSWORD_RESETS_RESOURCE: &str = "/password-resets";
+const PASSWORD_RESET_RESOURCE: &str = "/password-resets/:token";
+const IDENTITY_PROVIDERS_RESOURCE: &str = "/identity-providers";
+const JWKS_RESOURCE: &str = "/.well-known/jwks.json";
+const SIGNING_KEYS_RESOURCE: &str = "/signing-keys";
+const OAUTH_CLIENTS_RESOURCE: &str = "/oauth/clients";
+const OAUTH_CLIENT_RESOURCE: &str = "/oauth/clients/:id";
+const OAUTH_AUTHORIZE_RESOURCE: &str = "/oauth/authorize";
+const OAUTH_TOKEN_RESOURCE: &str = "/oauth/token";
+const OAUTH_INTROSPECT_RESOURCE: &str = "/oauth/introspect";
+const OAUTH_REVOKE_RESOURCE: &str = "/oauth/revoke";
+const OPENID_CONFIGURATION_RESOURCE: &str = "/.well-known/openid-configuration";
+const USERINFO_RESOURCE: &str = "/userinfo";
+const SERVICE_ACCOUNTS_RESOURCE: &str = "/service-accounts";
+const SERVICE_ACCOUNT_RESOURCE: &str = "/service-accounts/:id";
+const SERVICE_ACCOUNT_SECRETS_RESOURCE: &str = "/service-accounts/:id/secrets";
+const SERVICE_ACCOUNT_SECRET_RESOURCE: &str = "/service-accounts/:id/secrets/:secret_id";
 
 /// Application state that can be accessed by any route handler.
 /// Note that this doesn't need `#[derive(Clone)]` because we will
 /// put this into an [Arc] and [Arc] already supports [Clone].
-struct AppState<AS: AccountStore, C: Clock<Utc>> {
-    account_service: AccountService<AS, C>,
+pub struct AppState<B: Backends, C: Clock<Utc>> {
+    pub account_service:
+        AccountService<B::AccountStore, B::Notifier, B::BreachedPasswordChecker, C>,
+    pub session_service: SessionService<B::SessionStore, C>,
+    pub token_service: TokenService<B::SigningKeyStore, B::RevokedTokenStore, C>,
+    pub audit_service: AuditService<B::AuditStore, C>,
+    pub mfa_service: MfaService<B::MfaStore, C>,
+    pub credential_service: CredentialService<B::CredentialStore, C>,
+    pub federation_service: FederationService<B::LinkedIdentityStore, C>,
+    pub oauth_service:
+        OAuthService<B::OAuthClientStore, B::AuthorizationCodeStore, B::OAuthGrantStore, C>,
+    pub rate_limiter: Arc<RateLimiter<C>>,
+    pub trusted_proxies: TrustedProxies,
 }
 
 /// Returns the Axum Router for the REST API
-pub fn router<AS: AccountStore, C: Clock<Utc>>(account_service: AccountService<AS, C>) -> Router {
+pub fn router<B: Backends, C: Clock<Utc>>(app_state: AppState<B, C>) -> Router {
     // wrap the AppState in an [Arc] since it will be shared between threads
-    let shared_state = Arc::new(AppState { account_service });
+    let shared_state = Arc::new(app_state);
+    let rate_limit_layer =
+        middleware::from_fn_with_state(shared_state.rate_limiter.clone(), limit_requests);
+    // the client IP used for rate limiting and audit events depends on these
+    let trusted_proxies = shared_state.trusted_proxies.clone();
 
     // By default, TraceLayer traces at DEBUG level, which is probably too low
     // for runtime. This configures it to trace at INFO level instead.
@@ -54,13 +161,119 @@ pub fn router<AS: AccountStore, C: Clock<Utc>>(account_service: AccountService<A
     Router::new()
         .route("/", get(get_root))
         .route(ACCOUNTS_RESOURCE, post(post_accounts))
+        .route(ACCOUNT_RESOURCE, delete(delete_account))
+        .route(
+            ACCOUNT_DEACTIVATION_RESOURCE,
+            post(post_account_deactivation).delete(delete_account_deactivation),
+        )
+        .route(ACCOUNT_LOCKOUT_RESOURCE, delete(delete_account_lockout))
         .route(CREDENTIALS_RESOURCE, put(put_credentials))
-        .route(SESSIONS_RESOURCE, post(post_tokens))
+        .route(ACCOUNT_SESSIONS_RESOURCE, delete(delete_account_sessions))
+        .route(ACCOUNT_EVENTS_RESOURCE, get(get_account_events))
+        .route(
+            ACCOUNT_TOTP_RESOURCE,
+            post(post_account_totp)
+                .put(put_account_totp)
+                .delete(delete_account_totp),
+        )
+        .route(
+            ACCOUNT_RECOVERY_CODES_RESOURCE,
+            post(post_account_recovery_codes),
+        )
+        .route(ACCOUNT_SECURITY_RESOURCE, get(get_account_security))
+        .route(
+            ACCOUNT_PASSKEY_REGISTRATIONS_RESOURCE,
+            post(post_account_passkey_registrations),
+        )
+        .route(
+            ACCOUNT_PASSKEYS_RESOURCE,
+            get(get_account_passkeys).post(post_account_passkeys),
+        )
+        .route(
+            ACCOUNT_PASSKEY_RESOURCE,
+            put(put_account_passkey).delete(delete_account_passkey),
+        )
+        .route(
+            ACCOUNT_EMAIL_VERIFICATIONS_RESOURCE,
+            post(post_account_email_verifications),
+        )
+        .route(
+            ACCOUNT_LINKED_IDENTITY_AUTHORIZATIONS_RESOURCE,
+            post(post_account_linked_identity_authorizations),
+        )
+        .route(
+            ACCOUNT_LINKED_IDENTITIES_RESOURCE,
+            get(get_account_linked_identities).post(post_account_linked_identities),
+        )
+        .route(
+            ACCOUNT_LINKED_IDENTITY_RESOURCE,
+            delete(delete_account_linked_identity),
+        )
+        .route(ACCOUNT_OAUTH_GRANTS_RESOURCE, get(get_account_oauth_grants))
+        .route(
+            ACCOUNT_OAUTH_GRANT_RESOURCE,
+            delete(delete_account_oauth_grant),
+        )
+        .route(EMAIL_VERIFICATION_RESOURCE, put(put_email_verification))
+        .route(SESSIONS_RESOURCE, post(post_sessions))
+        .route(SESSION_RESOURCE, get(get_session).delete(delete_session))
+        .route(SESSION_REFRESH_RESOURCE, post(post_session_refresh))
+        .route(SESSION_MFA_RESOURCE, post(post_session_mfa))
+        .route(
+            SESSION_PASSKEY_CHALLENGES_RESOURCE,
+            post(post_session_passkey_challenges),
+        )
+        .route(SESSION_PASSKEY_RESOURCE, post(post_session_passkey))
+        .route(
+            SESSION_FEDERATED_AUTHORIZATIONS_RESOURCE,
+            post(post_session_federated_authorizations),
+        )
+        .route(SESSION_FEDERATED_RESOURCE, post(post_session_federated))
+        .route(
+            SESSION_SIGN_IN_CODE_RESOURCE,
+            post(post_session_sign_in_code),
+        )
+        .route(SIGN_IN_CODES_RESOURCE, post(post_sign_in_codes))
+        .route(PASSWORD_RESETS_RESOURCE, post(post_password_resets))
+        .route(PASSWORD_RESET_RESOURCE, put(put_password_reset))
+        .route(IDENTITY_PROVIDERS_RESOURCE, get(get_identity_providers))
+        .route(JWKS_RESOURCE, get(get_jwks))
+        .route(SIGNING_KEYS_RESOURCE, post(post_signing_keys))
+        .route(OAUTH_CLIENTS_RESOURCE, post(post_oauth_clients))
+        .route(
+            OAUTH_CLIENT_RESOURCE,
+            get(get_oauth_client).delete(delete_oauth_client),
+        )
+        .route(
+            OAUTH_AUTHORIZE_RESOURCE,
+            get(get_oauth_authorize).post(post_oauth_authorize),
+        )
+        .route(OAUTH_TOKEN_RESOURCE, post(post_oauth_token))
+        .route(OAUTH_INTROSPECT_RESOURCE, post(post_oauth_introspect))
+        .route(OAUTH_REVOKE_RESOURCE, post(post_oauth_revoke))
+        .route(OPENID_CONFIGURATION_RESOURCE, get(get_openid_configuration))
+        // OpenID Connect requires the userinfo endpoint to support both GET and POST
+        .route(USERINFO_RESOURCE, get(get_userinfo).post(get_userinfo))
+        .route(SERVICE_ACCOUNTS_RESOURCE, post(post_service_accounts))
+        .route(
+            SERVICE_ACCOUNT_RESOURCE,
+            get(get_service_account).delete(delete_service_account),
+        )
+        .route(
+            SERVICE_ACCOUNT_SECRETS_RESOURCE,
+            get(get_service_account_secrets).post(post_service_account_secrets),
+        )
+        .route(
+            SERVICE_ACCOUNT_SECRET_RESOURCE,
+            delete(delete_service_account_secret),
+        )
         .with_state(shared_state)
         .layer(
             ServiceBuilder::new()
+                .layer(Extension(trusted_proxies))
                 .layer(trace_layer)
-                .layer(PrometheusMetricLayer::new()),
+                .layer(PrometheusMetricLayer::new())
+                .layer(rate_limit_layer),
         )
 }
 
@@ -68,8 +281,9 @@ async fn get_root() -> &'static str {
     ROOT_RESPONSE
 }
 
-async fn post_accounts<AS: AccountStore, C: Clock<Utc>>(
-    State(app_state): State<Arc<AppState<AS, C>>>,
+async fn post_accounts<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    context: AuditContext,
     Json(new_account_request): Json<NewAccountRequest>,
 ) -> Result<(StatusCode, Json<AccountResponse>), ApiError> {
     // If the account service returns an Err result,
@@ -79,7 +293,7 @@ async fn post_accounts<AS: AccountStore, C: Clock<Utc>>(
     // defined in the converters.rs file.
     let account = app_state
         .account_service
-        .create_account(&new_account_request.into())
+        .create_account(&new_account_request.into(), &context)
         .await?;
 
     // This `account.into()` converts the service-level Account model
@@ -88,297 +302,4292 @@ async fn post_accounts<AS: AccountStore, C: Clock<Utc>>(
     Ok((StatusCode::CREATED, Json(account.into())))
 }
 
-async fn post_tokens<AS: AccountStore, C: Clock<Utc>>(
-    State(app_state): State<Arc<AppState<AS, C>>>,
-    Json(account_credentials): Json<AuthenticateRequest>,
+async fn delete_account<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+    context: AuditContext,
+) -> Result<StatusCode, ApiError> {
+    app_state.account_service.delete(&id, &context).await?;
+    app_state.session_service.revoke_all_sessions(&id).await?;
+    app_state.mfa_service.delete_factors(&id).await?;
+    app_state.credential_service.delete_passkeys(&id).await?;
+    app_state.oauth_service.delete_grants(&id).await?;
+    app_state.federation_service.delete_identities(&id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn post_account_deactivation<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+    context: AuditContext,
 ) -> Result<Json<AccountResponse>, ApiError> {
-    let account = app_state
-        .account_service
-        .authenticate(&account_credentials.into())
-        .await?;
+    let account = app_state.account_service.deactivate(&id, &context).await?;
+    app_state.session_service.revoke_all_sessions(&id).await?;
     Ok(Json(account.into()))
 }
 
-async fn put_credentials<AS: AccountStore, C: Clock<Utc>>(
-    State(app_state): State<Arc<AppState<AS, C>>>,
+async fn delete_account_deactivation<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
     Path(id): Path<String>,
-    Json(update_credentials): Json<UpdateCredentialsRequest>,
+    context: AuditContext,
 ) -> Result<Json<AccountResponse>, ApiError> {
+    let account = app_state.account_service.reactivate(&id, &context).await?;
+    Ok(Json(account.into()))
+}
+
+async fn delete_account_lockout<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+    context: AuditContext,
+) -> Result<StatusCode, ApiError> {
+    app_state.account_service.unlock(&id, &context).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn post_sessions<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    context: AuditContext,
+    Json(account_credentials): Json<AuthenticateRequest>,
+) -> Result<Response, ApiError> {
+    // a recovery code stands in for the second factor
+    let used_recovery_code = account_credentials.recovery_code.is_some();
     let account = app_state
         .account_service
-        .update_credentials(
-            &id,
-            &update_credentials.old.into(),
-            &update_credentials.new.into(),
-        )
+        .authenticate(&account_credentials.into(), &context)
         .await?;
-    Ok(Json(account.into()))
+    if used_recovery_code {
+        let session_response = start_session(&app_state, account).await?;
+        return Ok((StatusCode::CREATED, Json(session_response)).into_response());
+    }
+    start_session_or_mfa_challenge(&app_state, account).await
 }
 
-#[cfg(test)]
-mod tests {
-    use axum_test::TestServer;
-    use secrecy::Secret;
+async fn post_sign_in_codes<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Json(sign_in_request): Json<SignInCodeRequest>,
+) -> Result<StatusCode, ApiError> {
+    app_state
+        .account_service
+        .request_sign_in(&sign_in_request.into())
+        .await?;
+    // always accepted, whether or not the account exists
+    Ok(StatusCode::ACCEPTED)
+}
 
-    use crate::{
-        apis::models::{ApiErrorResponse, NewCredentialsRequest},
-        services::{
-            account::{models::Password, stores::fake::FakeAccountStore, AccountService},
-            SystemClock,
-        },
+async fn post_session_sign_in_code<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    context: AuditContext,
+    Json(sign_in_request): Json<SignInCodeSessionRequest>,
+) -> Result<Response, ApiError> {
+    let account = match sign_in_request {
+        SignInCodeSessionRequest {
+            token: Some(token), ..
+        } => {
+            app_state
+                .account_service
+                .sign_in_with_token(&token, &context)
+                .await?
+        }
+        SignInCodeSessionRequest {
+            email: Some(email),
+            code: Some(code),
+            ..
+        } => {
+            app_state
+                .account_service
+                .sign_in_with_code(&email, &code, &context)
+                .await?
+        }
+        _ => return Err(AccountsServiceError::InvalidSignInToken.into()),
     };
+    start_session_or_mfa_challenge(&app_state, account).await
+}
 
-    use super::*;
+/// Starts a session for an account that has just been authenticated, unless
+/// it has multi-factor authentication enabled, in which case an MFA challenge
+/// is returned instead (see [post_session_mfa]).
+async fn start_session_or_mfa_challenge<B: Backends, C: Clock<Utc>>(
+    app_state: &AppState<B, C>,
+    account: Account,
+) -> Result<Response, ApiError> {
+    if app_state.mfa_service.mfa_enabled(&account.id).await? {
+        let challenge = app_state.mfa_service.create_challenge(&account.id).await?;
+        let challenge_response: MfaChallengeResponse = challenge.into();
+        return Ok((StatusCode::ACCEPTED, Json(challenge_response)).into_response());
+    }
+    let session_response = start_session(app_state, account).await?;
+    Ok((StatusCode::CREATED, Json(session_response)).into_response())
+}
 
-    impl Default for NewAccountRequest {
-        fn default() -> Self {
-            NewAccountRequest {
-                email: "test@test.com".to_string(),
-                password: Secret::new(Password::new("test_password")),
-                display_name: Some("Tester McTester".to_string()),
+async fn post_session_mfa<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    context: AuditContext,
+    Json(mfa_request): Json<MfaSessionRequest>,
+) -> Result<(StatusCode, Json<SessionResponse>), ApiError> {
+    let account = complete_mfa_challenge(
+        &app_state,
+        &mfa_request.mfa_challenge,
+        mfa_request.code.as_deref(),
+        mfa_request.recovery_code.as_ref(),
+        &context,
+    )
+    .await?;
+    let session_response = start_session(&app_state, account).await?;
+    Ok((StatusCode::CREATED, Json(session_response)))
+}
+
+/// Completes an MFA challenge using either a code from the account's
+/// authenticator app or a recovery code, returning the account. Anyone with
+/// the password could start new challenges to keep guessing, so wrong codes
+/// also count towards locking the account, and locked accounts are refused.
+async fn complete_mfa_challenge<B: Backends, C: Clock<Utc>>(
+    app_state: &AppState<B, C>,
+    mfa_challenge: &str,
+    code: Option<&str>,
+    recovery_code: Option<&Secret<Password>>,
+    context: &AuditContext,
+) -> Result<Account, ApiError> {
+    let challenge = app_state.mfa_service.load_challenge(mfa_challenge).await?;
+    let account_id = challenge.account_id.clone();
+    app_state
+        .account_service
+        .check_can_authenticate(&account_id)
+        .await?;
+    match (code, recovery_code) {
+        (_, Some(recovery_code)) => {
+            // wrong recovery codes are counted by the account service
+            if let Err(err) = app_state
+                .account_service
+                .redeem_recovery_code(&account_id, recovery_code, context)
+                .await
+            {
+                app_state
+                    .mfa_service
+                    .record_failed_attempt(&challenge)
+                    .await?;
+                return Err(err.into());
+            }
+            app_state.mfa_service.close_challenge(&challenge).await?;
+        }
+        (Some(code), None) => {
+            if let Err(err) = app_state
+                .mfa_service
+                .complete_challenge(mfa_challenge, code)
+                .await
+            {
+                if matches!(err, MfaServiceError::InvalidCode) {
+                    app_state
+                        .account_service
+                        .record_failed_authentication(&account_id, context)
+                        .await?;
+                }
+                return Err(err.into());
             }
         }
+        (None, None) => return Err(MfaServiceError::InvalidCode.into()),
     }
-
-    /// Constructs a new [TestServer] using a fresh AccountService and FakeAccountStore.
-    fn test_server() -> TestServer {
-        TestServer::new(router(AccountService::new_with_clock(
-            FakeAccountStore::new(),
-            SystemClock::default(),
-        )))
-        .unwrap()
+    let account = app_state.account_service.get_account(&account_id).await?;
+    // the account may have been deactivated since the password was presented
+    if !account.is_active() {
+        return Err(AccountsServiceError::AccountNotActive.into());
     }
+    Ok(account)
+}
 
-    #[tokio::test]
-    async fn root_returns_correct_response() {
-        let response = test_server().get("/").await;
-        response.assert_status_ok();
-        response.assert_text(ROOT_RESPONSE);
-    }
+async fn post_session_passkey_challenges<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+) -> Result<(StatusCode, Json<PasskeyAuthenticationOptionsResponse>), ApiError> {
+    let options = app_state.credential_service.start_authentication().await?;
+    Ok((StatusCode::CREATED, Json(options.into())))
+}
 
-    #[tokio::test]
-    async fn invalid_route_returns_not_found() {
-        let response = test_server().get("/invalid").await;
-        response.assert_status_not_found();
+async fn post_session_passkey<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    context: AuditContext,
+    Json(passkey_request): Json<PasskeySessionRequest>,
+) -> Result<(StatusCode, Json<SessionResponse>), ApiError> {
+    // passkeys require user verification, so they count as multiple factors
+    let passkey = app_state
+        .credential_service
+        .finish_authentication(&passkey_request.into())
+        .await?;
+    let account = app_state
+        .account_service
+        .get_account(&passkey.account_id)
+        .await?;
+    if !account.is_active() {
+        return Err(AccountsServiceError::AccountNotActive.into());
     }
+    app_state
+        .audit_service
+        .record(
+            &account.id,
+            AuditEventKind::Authenticated,
+            &context.acting_as(&account.id),
+        )
+        .await?;
+    let session_response = start_session(&app_state, account).await?;
+    Ok((StatusCode::CREATED, Json(session_response)))
+}
 
-    #[tokio::test]
-    async fn new_account_success() {
-        let new_account_request = NewAccountRequest::default();
-        let response = test_server()
-            .post(ACCOUNTS_RESOURCE)
-            .json(&new_account_request)
-            .await;
-
-        response.assert_status(StatusCode::CREATED);
-        let response_account: AccountResponse = response.json();
-        assert!(!response_account.id.is_empty());
-        assert!(response_account.id.starts_with("acct_"));
-        assert_eq!(new_account_request.email, response_account.email);
-        assert_eq!(
-            new_account_request.display_name,
-            response_account.display_name
-        );
-    }
+async fn get_identity_providers<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+) -> Json<IdentityProvidersResponse> {
+    Json(IdentityProvidersResponse {
+        providers: app_state
+            .federation_service
+            .providers()
+            .iter()
+            .map(|provider| provider.into())
+            .collect(),
+    })
+}
 
-    #[tokio::test]
-    async fn new_account_no_display_name() {
-        let new_account_request = NewAccountRequest {
-            display_name: None,
-            ..NewAccountRequest::default()
-        };
-        let response = test_server()
-            .post(ACCOUNTS_RESOURCE)
-            .json(&new_account_request)
-            .await;
+async fn post_session_federated_authorizations<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Json(authorization_request): Json<FederatedAuthorizationRequest>,
+) -> Result<(StatusCode, Json<FederatedAuthorizationResponse>), ApiError> {
+    let authorization = app_state
+        .federation_service
+        .start_authorization(&authorization_request.provider, None)
+        .await?;
+    Ok((StatusCode::CREATED, Json(authorization.into())))
+}
 
-        response.assert_status(StatusCode::CREATED);
-        let response_account: AccountResponse = response.json();
-        assert!(!response_account.id.is_empty());
-        assert_eq!(new_account_request.email, response_account.email);
-        assert_eq!(None, response_account.display_name);
+async fn post_session_federated<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    context: AuditContext,
+    Json(callback_request): Json<FederatedCallbackRequest>,
+) -> Result<Response, ApiError> {
+    let identity = app_state
+        .federation_service
+        .finish_sign_in(&callback_request.into())
+        .await?;
+    let account = app_state
+        .account_service
+        .get_account(&identity.account_id)
+        .await?;
+    if !account.is_active() {
+        return Err(AccountsServiceError::AccountNotActive.into());
     }
+    app_state
+        .audit_service
+        .record(
+            &account.id,
+            AuditEventKind::Authenticated,
+            &context.acting_as(&account.id),
+        )
+        .await?;
+    // the provider only counts as one factor, however it signed the account holder in
+    start_session_or_mfa_challenge(&app_state, account).await
+}
 
-    #[tokio::test]
-    async fn new_account_empty_password() {
-        let new_account_request = NewAccountRequest {
-            password: Secret::new(Password::new("")),
-            ..NewAccountRequest::default()
-        };
-        let response = test_server()
-            .post(ACCOUNTS_RESOURCE)
-            .json(&new_account_request)
-            .await;
+/// Starts a new session for an account that has been fully authenticated,
+/// returning it along with a new access token and refresh token.
+async fn start_session<B: Backends, C: Clock<Utc>>(
+    app_state: &AppState<B, C>,
+    account: Account,
+) -> Result<SessionResponse, ApiError> {
+    app_state
+        .account_service
+        .clear_authentication_failures(&account.id)
+        .await?;
+    let issued = app_state
+        .session_service
+        .create_session(&account.id)
+        .await?;
+    let access_token = app_state.token_service.issue_access_token(&account).await?;
+    Ok(SessionResponse {
+        access_token: Some(access_token.into()),
+        refresh_token: Some(issued.refresh_token),
+        ..(issued.session, account).into()
+    })
+}
 
-        response.assert_status_bad_request();
-        let response_body: ApiErrorResponse = response.json();
-        assert!(response_body.message.starts_with("Validation error:"))
-    }
+async fn post_session_refresh<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Json(refresh_request): Json<RefreshSessionRequest>,
+) -> Result<Json<SessionResponse>, ApiError> {
+    let issued = app_state
+        .session_service
+        .refresh_session(&refresh_request.refresh_token)
+        .await?;
+    let account = app_state
+        .account_service
+        .get_account(&issued.session.account_id)
+        .await?;
+    let access_token = app_state.token_service.issue_access_token(&account).await?;
+    Ok(Json(SessionResponse {
+        access_token: Some(access_token.into()),
+        refresh_token: Some(issued.refresh_token),
+        ..(issued.session, account).into()
+    }))
+}
 
-    #[tokio::test]
-    async fn new_account_invalid_email() {
-        let new_account_request = NewAccountRequest {
-            email: "invalid".to_string(),
-            ..NewAccountRequest::default()
-        };
-        let response = test_server()
-            .post(ACCOUNTS_RESOURCE)
-            .json(&new_account_request)
-            .await;
+async fn get_session<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+) -> Result<Json<SessionResponse>, ApiError> {
+    let session = app_state.session_service.validate_session(&id).await?;
+    let account = app_state
+        .account_service
+        .get_account(&session.account_id)
+        .await?;
+    Ok(Json((session, account).into()))
+}
 
-        response.assert_status_bad_request();
-        let response_body: ApiErrorResponse = response.json();
-        assert!(response_body.message.starts_with("Validation error:"))
-    }
+async fn delete_session<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    app_state.session_service.revoke_session(&id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    #[tokio::test]
-    async fn new_account_duplicate_email() {
-        let new_account_request = NewAccountRequest::default();
-        let server = test_server();
+async fn delete_account_sessions<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+    context: AuditContext,
+) -> Result<StatusCode, ApiError> {
+    app_state.session_service.revoke_all_sessions(&id).await?;
+    app_state
+        .audit_service
+        .record(&id, AuditEventKind::SessionsRevoked, &context)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
 
-        // insert the account
-        server
-            .post(ACCOUNTS_RESOURCE)
-            .json(&new_account_request)
-            .await
-            .assert_status(StatusCode::CREATED);
-        // try to insert it again -- should get a duplicate email bad request
-        let response = server.post("/accounts").json(&new_account_request).await;
+async fn get_account_events<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+    Query(query): Query<AuditEventsQuery>,
+) -> Result<Json<AuditEventsResponse>, ApiError> {
+    let page = app_state
+        .audit_service
+        .list_events(&id, query.cursor.as_deref(), query.limit)
+        .await?;
+    Ok(Json(page.into()))
+}
 
-        response.assert_status_bad_request();
-        let response_body: ApiErrorResponse = response.json();
-        assert_eq!(
-            response_body.message,
-            "The email address 'test@test.com' is already registered".to_string()
-        )
-    }
+async fn post_account_totp<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+    context: AuditContext,
+    Json(proof): Json<ReauthenticationRequest>,
+) -> Result<(StatusCode, Json<TotpEnrollmentResponse>), ApiError> {
+    let account = app_state.account_service.get_account(&id).await?;
+    reauthenticate(&app_state, &id, &proof, &context).await?;
+    let enrollment = app_state
+        .mfa_service
+        .enroll_totp(&account.id, &account.email)
+        .await?;
+    Ok((StatusCode::CREATED, Json(enrollment.into())))
+}
 
-    #[tokio::test]
-    async fn authenticate_valid_credentials() {
-        let new_account_request = NewAccountRequest::default();
-        let server = test_server();
-        server
-            .post(ACCOUNTS_RESOURCE)
-            .json(&new_account_request)
-            .await
-            .assert_status(StatusCode::CREATED);
+async fn put_account_totp<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+    context: AuditContext,
+    Json(confirmation): Json<TotpConfirmationRequest>,
+) -> Result<StatusCode, ApiError> {
+    app_state
+        .mfa_service
+        .confirm_totp(&id, &confirmation.code)
+        .await?;
+    app_state
+        .audit_service
+        .record(&id, AuditEventKind::MfaEnabled, &context)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
 
-        let authenticate_request = AuthenticateRequest {
-            email: new_account_request.email.clone(),
-            password: new_account_request.password.clone(),
-        };
-        let response = server
-            .post(SESSIONS_RESOURCE)
-            .json(&authenticate_request)
-            .await;
-        response.assert_status_ok();
-        let response_account: AccountResponse = response.json();
-        assert_eq!(response_account.email, authenticate_request.email);
-        assert!(!response_account.id.is_empty());
-    }
+async fn delete_account_totp<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+    context: AuditContext,
+    Json(proof): Json<ReauthenticationRequest>,
+) -> Result<StatusCode, ApiError> {
+    reauthenticate(&app_state, &id, &proof, &context).await?;
+    app_state.mfa_service.disable_totp(&id).await?;
+    app_state
+        .audit_service
+        .record(&id, AuditEventKind::MfaDisabled, &context)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    #[tokio::test]
-    async fn authenticate_invalid_password() {
-        let new_account_request = NewAccountRequest::default();
-        let server = test_server();
-        server
-            .post(ACCOUNTS_RESOURCE)
-            .json(&new_account_request)
-            .await
-            .assert_status(StatusCode::CREATED);
+async fn post_account_recovery_codes<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+    context: AuditContext,
+    Json(proof): Json<ReauthenticationRequest>,
+) -> Result<(StatusCode, Json<RecoveryCodesResponse>), ApiError> {
+    reauthenticate(&app_state, &id, &proof, &context).await?;
+    let codes = app_state
+        .account_service
+        .generate_recovery_codes(&id, &context)
+        .await?;
+    Ok((StatusCode::CREATED, Json(RecoveryCodesResponse { codes })))
+}
 
-        let authenticate_request = AuthenticateRequest {
-            email: new_account_request.email.clone(),
-            password: Secret::new(Password::new("invalid")),
+async fn get_account_security<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+) -> Result<Json<SecuritySummaryResponse>, ApiError> {
+    let account = app_state.account_service.get_account(&id).await?;
+    let mfa_enabled = app_state.mfa_service.mfa_enabled(&id).await?;
+    let recovery_codes = app_state.account_service.recovery_code_summary(&id).await?;
+    Ok(Json((account, mfa_enabled, recovery_codes).into()))
+}
+
+async fn post_account_passkey_registrations<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+    context: AuditContext,
+    Json(proof): Json<ReauthenticationRequest>,
+) -> Result<(StatusCode, Json<PasskeyRegistrationOptionsResponse>), ApiError> {
+    let account = app_state.account_service.get_account(&id).await?;
+    // finishing the registration needs the challenge, so only this is checked
+    reauthenticate(&app_state, &id, &proof, &context).await?;
+    let options = app_state
+        .credential_service
+        .start_registration(&account.id, &account.email)
+        .await?;
+    Ok((StatusCode::CREATED, Json(options.into())))
+}
+
+async fn get_account_passkeys<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+) -> Result<Json<PasskeysResponse>, ApiError> {
+    let passkeys = app_state.credential_service.list_passkeys(&id).await?;
+    Ok(Json(PasskeysResponse {
+        passkeys: passkeys.into_iter().map(|passkey| passkey.into()).collect(),
+    }))
+}
+
+async fn post_account_passkeys<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+    context: AuditContext,
+    Json(passkey_request): Json<NewPasskeyRequest>,
+) -> Result<(StatusCode, Json<PasskeyResponse>), ApiError> {
+    let name = passkey_request.name.clone();
+    let passkey = app_state
+        .credential_service
+        .finish_registration(&id, &name, &passkey_request.into())
+        .await?;
+    app_state
+        .audit_service
+        .record(&id, AuditEventKind::PasskeyRegistered, &context)
+        .await?;
+    Ok((StatusCode::CREATED, Json(passkey.into())))
+}
+
+async fn put_account_passkey<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path((id, passkey_id)): Path<(String, String)>,
+    Json(name_request): Json<PasskeyNameRequest>,
+) -> Result<Json<PasskeyResponse>, ApiError> {
+    let passkey = app_state
+        .credential_service
+        .rename_passkey(&id, &passkey_id, &name_request.name)
+        .await?;
+    Ok(Json(passkey.into()))
+}
+
+async fn delete_account_passkey<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path((id, passkey_id)): Path<(String, String)>,
+    context: AuditContext,
+) -> Result<StatusCode, ApiError> {
+    app_state
+        .credential_service
+        .delete_passkey(&id, &passkey_id)
+        .await?;
+    app_state
+        .audit_service
+        .record(&id, AuditEventKind::PasskeyRemoved, &context)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn post_account_linked_identity_authorizations<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+    context: AuditContext,
+    Json(authorization_request): Json<LinkedIdentityAuthorizationRequest>,
+) -> Result<(StatusCode, Json<FederatedAuthorizationResponse>), ApiError> {
+    let account = app_state.account_service.get_account(&id).await?;
+    reauthenticate(
+        &app_state,
+        &id,
+        &authorization_request.reauthentication,
+        &context,
+    )
+    .await?;
+    let authorization = app_state
+        .federation_service
+        .start_authorization(&authorization_request.provider, Some(&account.id))
+        .await?;
+    Ok((StatusCode::CREATED, Json(authorization.into())))
+}
+
+async fn get_account_linked_identities<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+) -> Result<Json<LinkedIdentitiesResponse>, ApiError> {
+    let identities = app_state.federation_service.list_identities(&id).await?;
+    Ok(Json(LinkedIdentitiesResponse {
+        identities: identities
+            .into_iter()
+            .map(|identity| identity.into())
+            .collect(),
+    }))
+}
+
+async fn post_account_linked_identities<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+    context: AuditContext,
+    Json(callback_request): Json<NewLinkedIdentityRequest>,
+) -> Result<(StatusCode, Json<LinkedIdentityResponse>), ApiError> {
+    reauthenticate(
+        &app_state,
+        &id,
+        &callback_request.reauthentication,
+        &context,
+    )
+    .await?;
+    let identity = app_state
+        .federation_service
+        .link_identity(&id, &callback_request.into())
+        .await?;
+    app_state
+        .audit_service
+        .record(&id, AuditEventKind::IdentityLinked, &context)
+        .await?;
+    Ok((StatusCode::CREATED, Json(identity.into())))
+}
+
+async fn delete_account_linked_identity<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path((id, identity_id)): Path<(String, String)>,
+    context: AuditContext,
+) -> Result<StatusCode, ApiError> {
+    app_state
+        .federation_service
+        .unlink_identity(&id, &identity_id)
+        .await?;
+    app_state
+        .audit_service
+        .record(&id, AuditEventKind::IdentityUnlinked, &context)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn post_account_email_verifications<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    app_state
+        .account_service
+        .request_email_verification(&id)
+        .await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn put_email_verification<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(token): Path<String>,
+    context: AuditContext,
+) -> Result<Json<AccountResponse>, ApiError> {
+    let account = app_state
+        .account_service
+        .verify_email(&token, &context)
+        .await?;
+    Ok(Json(account.into()))
+}
+
+async fn post_password_resets<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Json(password_reset_request): Json<PasswordResetRequest>,
+) -> Result<StatusCode, ApiError> {
+    app_state
+        .account_service
+        .request_password_reset(&password_reset_request.email)
+        .await?;
+    // always accepted, whether or not the account exists
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn put_password_reset<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(token): Path<String>,
+    context: AuditContext,
+    Json(new_password_request): Json<NewPasswordRequest>,
+) -> Result<Json<AccountResponse>, ApiError> {
+    let account = app_state
+        .account_service
+        .reset_password(&(token, new_password_request).into(), &context)
+        .await?;
+    // whoever had the old password should no longer be signed in
+    app_state
+        .session_service
+        .revoke_all_sessions(&account.id)
+        .await?;
+    Ok(Json(account.into()))
+}
+
+async fn get_jwks<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+) -> Result<Json<JwksResponse>, ApiError> {
+    let public_keys = app_state.token_service.public_keys().await?;
+    Ok(Json(JwksResponse {
+        keys: public_keys.into_iter().map(|k| k.into()).collect(),
+    }))
+}
+
+async fn post_signing_keys<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+) -> Result<(StatusCode, Json<JwkResponse>), ApiError> {
+    let public_key = app_state.token_service.rotate_keys().await?;
+    Ok((StatusCode::CREATED, Json(public_key.into())))
+}
+
+async fn put_credentials<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+    context: AuditContext,
+    Json(update_credentials): Json<UpdateCredentialsRequest>,
+) -> Result<Json<AccountResponse>, ApiError> {
+    let account = app_state
+        .account_service
+        .update_credentials(
+            &id,
+            &update_credentials.old.into(),
+            &update_credentials.new.into(),
+            &context,
+        )
+        .await?;
+    Ok(Json(account.into()))
+}
+
+/// Checks that the caller has proven they are the account holder, before a
+/// change that would let someone else take over the account (e.g., disabling
+/// MFA). Like [put_credentials], this accepts the current password, but since
+/// accounts created by a passwordless sign-in may not have one, a code from
+/// the account's authenticator app, a recovery code, or a session started
+/// within the last few minutes is accepted instead.
+async fn reauthenticate<B: Backends, C: Clock<Utc>>(
+    app_state: &AppState<B, C>,
+    id: &str,
+    proof: &ReauthenticationRequest,
+    context: &AuditContext,
+) -> Result<(), ApiError> {
+    if let Some(password) = &proof.password {
+        app_state
+            .account_service
+            .verify_password(id, password, context)
+            .await?;
+    } else if let Some(code) = &proof.code {
+        // codes could otherwise be guessed, so wrong ones count towards a lockout
+        app_state.account_service.check_can_authenticate(id).await?;
+        if let Err(err) = app_state.mfa_service.verify_totp(id, code).await {
+            if matches!(err, MfaServiceError::InvalidCode) {
+                app_state
+                    .account_service
+                    .record_failed_authentication(id, context)
+                    .await?;
+            }
+            return Err(err.into());
+        }
+    } else if let Some(recovery_code) = &proof.recovery_code {
+        app_state
+            .account_service
+            .redeem_recovery_code(id, recovery_code, context)
+            .await?;
+    } else if let Some(session_id) = &proof.session_id {
+        app_state
+            .session_service
+            .verify_recent_session(session_id, id)
+            .await
+            .map_err(|err| match err {
+                SessionServiceError::InvalidSession => ApiError::ReauthenticationRequired,
+                _ => err.into(),
+            })?;
+    } else {
+        return Err(ApiError::ReauthenticationRequired);
+    }
+    Ok(())
+}
+
+async fn get_account_oauth_grants<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+) -> Result<Json<OAuthGrantsResponse>, ApiError> {
+    let grants = app_state.oauth_service.list_grants(&id).await?;
+    Ok(Json(grants.into()))
+}
+
+async fn delete_account_oauth_grant<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path((id, client_id)): Path<(String, String)>,
+    context: AuditContext,
+) -> Result<StatusCode, ApiError> {
+    app_state
+        .oauth_service
+        .revoke_grant(&id, &client_id)
+        .await?;
+    app_state
+        .audit_service
+        .record(&id, AuditEventKind::OAuthConsentRevoked, &context)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn post_oauth_clients<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Json(client_request): Json<NewOAuthClientRequest>,
+) -> Result<(StatusCode, Json<OAuthClientResponse>), ApiError> {
+    let client = app_state
+        .oauth_service
+        .create_client(&client_request.into())
+        .await?;
+    Ok((StatusCode::CREATED, Json(client.into())))
+}
+
+async fn get_oauth_client<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+) -> Result<Json<OAuthClientResponse>, ApiError> {
+    let client = app_state.oauth_service.get_client(&id).await?;
+    Ok(Json(client.into()))
+}
+
+async fn delete_oauth_client<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    app_state.oauth_service.delete_client(&id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Creates a service account, returning its first secret.
+async fn post_service_accounts<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Json(service_account_request): Json<NewServiceAccountRequest>,
+) -> Result<(StatusCode, Json<NewServiceAccountResponse>), ApiError> {
+    let (service_account, secret) = app_state
+        .oauth_service
+        .create_service_account(&service_account_request.into())
+        .await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(NewServiceAccountResponse {
+            service_account: service_account.into(),
+            secret: secret.into(),
+        }),
+    ))
+}
+
+async fn get_service_account<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+) -> Result<Json<ServiceAccountResponse>, ApiError> {
+    let service_account = app_state.oauth_service.get_service_account(&id).await?;
+    Ok(Json(service_account.into()))
+}
+
+async fn delete_service_account<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    app_state.oauth_service.delete_service_account(&id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_service_account_secrets<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+) -> Result<Json<ServiceAccountSecretsResponse>, ApiError> {
+    let secrets = app_state.oauth_service.list_secrets(&id).await?;
+    Ok(Json(secrets.into()))
+}
+
+/// Rotates a service account's secret by issuing a new one. The previous
+/// secret keeps working until it's revoked or the secret after this one is
+/// issued, so that the service can be redeployed with the new secret first.
+async fn post_service_account_secrets<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path(id): Path<String>,
+) -> Result<(StatusCode, Json<IssuedSecretResponse>), ApiError> {
+    let secret = app_state.oauth_service.rotate_secret(&id).await?;
+    Ok((StatusCode::CREATED, Json(secret.into())))
+}
+
+async fn delete_service_account_secret<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Path((id, secret_id)): Path<(String, String)>,
+) -> Result<StatusCode, ApiError> {
+    app_state
+        .oauth_service
+        .revoke_secret(&id, &secret_id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Starts an OAuth authorization request by sending the browser to the login
+/// page. Errors are sent back to the client's redirect URI, unless the client
+/// or redirect URI is invalid.
+async fn get_oauth_authorize<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    Query(params): Query<AuthorizationParams>,
+) -> Result<Redirect, ApiError> {
+    let request: AuthorizationRequest = params.into();
+    let redirect = app_state.oauth_service.check_redirect(&request).await?;
+    let location = match app_state
+        .oauth_service
+        .check_authorization(&redirect, &request)
+    {
+        Ok(_) => app_state.oauth_service.login_redirect(&request),
+        Err(err) => redirect.with_error(&err),
+    };
+    Ok(Redirect::to(&location))
+}
+
+/// Completes an OAuth authorization request once the login page has collected
+/// the account holder's credentials and consent, returning where to send the
+/// browser next. Accounts with multi-factor authentication get an MFA challenge
+/// first, like [post_sessions], and the request is then repeated with it.
+async fn post_oauth_authorize<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    context: AuditContext,
+    Json(authorize_request): Json<AuthorizeRequest>,
+) -> Result<Response, ApiError> {
+    let AuthorizeRequest {
+        params,
+        email,
+        password,
+        mfa_challenge,
+        code,
+        recovery_code,
+        consent,
+    } = authorize_request;
+    let request: AuthorizationRequest = params.into();
+    let redirect = app_state.oauth_service.check_redirect(&request).await?;
+    let authorization = match app_state
+        .oauth_service
+        .check_authorization(&redirect, &request)
+    {
+        Ok(authorization) => authorization,
+        Err(err) => return Ok(authorize_response(redirect.with_error(&err))),
+    };
+    if consent == Some(false) {
+        let redirect_to = redirect.with_error(&OAuthServiceError::AccessDenied);
+        return Ok(authorize_response(redirect_to));
+    }
+    let consent = consent == Some(true);
+
+    let account = match (mfa_challenge, email, password) {
+        (Some(mfa_challenge), _, _) => {
+            complete_mfa_challenge(
+                &app_state,
+                &mfa_challenge,
+                code.as_deref(),
+                recovery_code.as_ref(),
+                &context,
+            )
+            .await?
+        }
+        (None, Some(email), Some(password)) => {
+            // a recovery code stands in for the second factor
+            let used_recovery_code = recovery_code.is_some();
+            let credentials = AccountCredentials {
+                email,
+                password,
+                recovery_code,
+            };
+            let account = app_state
+                .account_service
+                .authenticate(&credentials, &context)
+                .await?;
+            if !used_recovery_code && app_state.mfa_service.mfa_enabled(&account.id).await? {
+                // ask for consent before the second factor, so it isn't used up for nothing
+                if !consent
+                    && app_state
+                        .oauth_service
+                        .needs_consent(&account.id, &authorization)
+                        .await?
+                {
+                    return Err(OAuthServiceError::ConsentRequired.into());
+                }
+                let challenge = app_state.mfa_service.create_challenge(&account.id).await?;
+                let challenge_response: MfaChallengeResponse = challenge.into();
+                return Ok((StatusCode::ACCEPTED, Json(challenge_response)).into_response());
+            }
+            account
+        }
+        _ => return Err(AccountsServiceError::InvalidCredentials.into()),
+    };
+
+    let needs_consent = app_state
+        .oauth_service
+        .needs_consent(&account.id, &authorization)
+        .await?;
+    let code = app_state
+        .oauth_service
+        .authorize(&account.id, &authorization, consent)
+        .await?;
+    if needs_consent {
+        app_state
+            .audit_service
+            .record(
+                &account.id,
+                AuditEventKind::OAuthConsentGranted,
+                &context.acting_as(&account.id),
+            )
+            .await?;
+    }
+    Ok(authorize_response(redirect.with_code(&code)))
+}
+
+fn authorize_response(redirect_to: String) -> Response {
+    Json(AuthorizeResponse { redirect_to }).into_response()
+}
+
+/// Exchanges an authorization code or refresh token for an access token
+/// and a new refresh token, or a service account's credentials for an
+/// access token.
+async fn post_oauth_token<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    BasicCredentials(basic): BasicCredentials,
+    Form(mut token_request): Form<TokenRequest>,
+) -> Result<Response, ApiError> {
+    (token_request.client_id, token_request.client_secret) = client_credentials(
+        basic,
+        token_request.client_id.take(),
+        token_request.client_secret.take(),
+    )?;
+    let grant = match token_request.grant_type.as_deref() {
+        Some("client_credentials") => {
+            return post_client_credentials_token(&app_state, token_request).await
+        }
+        Some("authorization_code") => {
+            let exchange: CodeExchange = token_request.try_into()?;
+            app_state.oauth_service.exchange_code(&exchange).await?
+        }
+        Some("refresh_token") => {
+            let exchange: RefreshExchange = token_request.try_into()?;
+            app_state.oauth_service.refresh(&exchange).await?
+        }
+        Some(grant_type) => {
+            return Err(OAuthServiceError::UnsupportedGrantType(grant_type.to_string()).into())
+        }
+        None => {
+            return Err(
+                OAuthServiceError::InvalidRequest("grant_type is required".to_string()).into(),
+            )
+        }
+    };
+    // the account may have been deactivated or deleted since access was granted
+    let account = match app_state
+        .account_service
+        .get_account(&grant.account_id)
+        .await
+    {
+        Ok(account) if account.is_active() => account,
+        Ok(_) | Err(AccountsServiceError::AccountNotFound(_)) => {
+            return Err(OAuthServiceError::InvalidGrant.into())
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let access_token = app_state
+        .token_service
+        .issue_client_access_token(&account, &grant.client_id, &grant.scopes)
+        .await?;
+    let id_token = if grant.scopes.iter().any(|scope| scope == OPENID_SCOPE) {
+        let id_token = app_state
+            .token_service
+            .issue_id_token(
+                &account,
+                &grant.client_id,
+                &grant.scopes,
+                grant.nonce.as_deref(),
+            )
+            .await?;
+        Some(id_token)
+    } else {
+        None
+    };
+    let token_response = TokenResponse {
+        access_token: access_token.token,
+        token_type: "Bearer".to_string(),
+        expires_in: app_state.token_service.access_token_ttl().num_seconds(),
+        refresh_token: Some(grant.refresh_token),
+        scope: grant.scopes.join(" "),
+        id_token,
+    };
+    // tokens must not be cached (RFC 6749 section 5.1)
+    Ok(([(CACHE_CONTROL, "no-store")], Json(token_response)).into_response())
+}
+
+/// Exchanges a service account's ID and secret for an access token. There's
+/// no refresh token, since the service can just authenticate again.
+async fn post_client_credentials_token<B: Backends, C: Clock<Utc>>(
+    app_state: &AppState<B, C>,
+    token_request: TokenRequest,
+) -> Result<Response, ApiError> {
+    let exchange: ClientCredentialsExchange = token_request.try_into()?;
+    let grant = app_state
+        .oauth_service
+        .exchange_client_credentials(&exchange)
+        .await?;
+    let access_token = app_state
+        .token_service
+        .issue_service_access_token(&grant.service_account_id, &grant.scopes)
+        .await?;
+    let token_response = TokenResponse {
+        access_token: access_token.token,
+        token_type: "Bearer".to_string(),
+        expires_in: app_state.token_service.access_token_ttl().num_seconds(),
+        refresh_token: None,
+        scope: grant.scopes.join(" "),
+        id_token: None,
+    };
+    Ok(([(CACHE_CONTROL, "no-store")], Json(token_response)).into_response())
+}
+
+/// Combines the client credentials sent using HTTP Basic authentication
+/// with those sent in the form, returning the client ID and secret.
+fn client_credentials(
+    basic: Option<(String, String)>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+) -> Result<(Option<String>, Option<String>), OAuthServiceError> {
+    let Some((basic_id, basic_secret)) = basic else {
+        return Ok((client_id, client_secret));
+    };
+    // clients must not use more than one authentication method (RFC 6749 section 2.3)
+    if client_secret.is_some() || client_id.is_some_and(|id| id != basic_id) {
+        return Err(OAuthServiceError::InvalidRequest(
+            "only one client authentication method can be used".to_string(),
+        ));
+    }
+    Ok((Some(basic_id), Some(basic_secret)))
+}
+
+/// Tells a resource server whether a token is active, and if so, what it was
+/// issued for (RFC 7662). Only service accounts can introspect tokens, since
+/// the response reveals who the token was issued for. Tokens are active until
+/// they expire or are revoked, and as long as the account (or service account)
+/// they were issued for is still active.
+async fn post_oauth_introspect<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    BasicCredentials(basic): BasicCredentials,
+    Form(request): Form<TokenHintRequest>,
+) -> Result<Response, ApiError> {
+    let (Some(client_id), Some(client_secret)) =
+        client_credentials(basic, request.client_id, request.client_secret)?
+    else {
+        return Err(OAuthServiceError::InvalidClient.into());
+    };
+    app_state
+        .oauth_service
+        .authenticate_service_account(&client_id, &Secret::new(Password::new(&client_secret)))
+        .await?;
+    let token = request.token.ok_or(OAuthServiceError::InvalidRequest(
+        "token is required".to_string(),
+    ))?;
+
+    let mut response = IntrospectionResponse::default();
+    match app_state.token_service.verify_access_token(&token).await {
+        Ok(claims) => {
+            if is_active_subject(&app_state, &claims.sub).await? {
+                response = IntrospectionResponse {
+                    iss: Some(app_state.token_service.issuer().to_string()),
+                    ..claims.into()
+                };
+            }
+        }
+        Err(TokenServiceError::InvalidAccessToken) => {
+            if let Some(refresh_token) = app_state
+                .oauth_service
+                .introspect_refresh_token(&token)
+                .await?
+            {
+                if is_active_subject(&app_state, &refresh_token.account_id).await? {
+                    response = IntrospectionResponse {
+                        iss: Some(app_state.token_service.issuer().to_string()),
+                        ..refresh_token.into()
+                    };
+                }
+            }
+        }
+        Err(err) => return Err(err.into()),
+    }
+    Ok(([(CACHE_CONTROL, "no-store")], Json(response)).into_response())
+}
+
+/// Returns true if the account or service account a token was issued for
+/// still exists and is active.
+async fn is_active_subject<B: Backends, C: Clock<Utc>>(
+    app_state: &AppState<B, C>,
+    sub: &str,
+) -> Result<bool, ApiError> {
+    match app_state.account_service.get_account(sub).await {
+        Ok(account) => return Ok(account.is_active()),
+        Err(AccountsServiceError::AccountNotFound(_)) => (),
+        Err(err) => return Err(err.into()),
+    }
+    match app_state.oauth_service.get_service_account(sub).await {
+        Ok(_) => Ok(true),
+        Err(OAuthServiceError::ClientNotFound(_)) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Revokes an access token or refresh token issued to the client (RFC 7009).
+/// Public OAuth clients authenticate with just their ID, and service accounts
+/// with a secret. Invalid tokens and tokens issued to other clients are
+/// ignored, so the response doesn't reveal anything about them.
+async fn post_oauth_revoke<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    BasicCredentials(basic): BasicCredentials,
+    Form(request): Form<TokenHintRequest>,
+) -> Result<StatusCode, ApiError> {
+    let (client_id, client_secret) =
+        client_credentials(basic, request.client_id, request.client_secret)?;
+    let authentication = ClientAuthentication {
+        client_id: client_id.ok_or(OAuthServiceError::InvalidClient)?,
+        client_secret: client_secret.map(|secret| Secret::new(Password::new(&secret))),
+    };
+    app_state
+        .oauth_service
+        .authenticate_client(&authentication)
+        .await?;
+    let token = request.token.ok_or(OAuthServiceError::InvalidRequest(
+        "token is required".to_string(),
+    ))?;
+
+    if !app_state
+        .token_service
+        .revoke_access_token(&token, &authentication.client_id)
+        .await?
+    {
+        app_state
+            .oauth_service
+            .revoke_refresh_token(&authentication.client_id, &token)
+            .await?;
+    }
+    Ok(StatusCode::OK)
+}
+
+/// Returns the OpenID Provider metadata, which points OpenID Connect
+/// client libraries at the other endpoints.
+async fn get_openid_configuration<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+) -> Json<OpenIdConfigurationResponse> {
+    let issuer = app_state.token_service.issuer();
+    let endpoint = |path: &str| format!("{}{}", issuer, path);
+    let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+    Json(OpenIdConfigurationResponse {
+        issuer: issuer.to_string(),
+        authorization_endpoint: endpoint(OAUTH_AUTHORIZE_RESOURCE),
+        token_endpoint: endpoint(OAUTH_TOKEN_RESOURCE),
+        userinfo_endpoint: endpoint(USERINFO_RESOURCE),
+        jwks_uri: endpoint(JWKS_RESOURCE),
+        scopes_supported: strings(&[OPENID_SCOPE, EMAIL_SCOPE, PROFILE_SCOPE]),
+        response_types_supported: strings(&["code"]),
+        grant_types_supported: strings(&[
+            "authorization_code",
+            "refresh_token",
+            "client_credentials",
+        ]),
+        subject_types_supported: strings(&["public"]),
+        id_token_signing_alg_values_supported: strings(&[SIGNING_ALGORITHM]),
+        // OAuth clients are public, so they don't authenticate to the
+        // token endpoint, but service accounts send their secret
+        token_endpoint_auth_methods_supported: strings(&[
+            "none",
+            "client_secret_basic",
+            "client_secret_post",
+        ]),
+        code_challenge_methods_supported: strings(&["S256"]),
+        claims_supported: strings(&[
+            "iss",
+            "aud",
+            "sub",
+            "iat",
+            "exp",
+            "nonce",
+            "email",
+            "email_verified",
+            "name",
+        ]),
+        introspection_endpoint: endpoint(OAUTH_INTROSPECT_RESOURCE),
+        introspection_endpoint_auth_methods_supported: strings(&[
+            "client_secret_basic",
+            "client_secret_post",
+        ]),
+        revocation_endpoint: endpoint(OAUTH_REVOKE_RESOURCE),
+        revocation_endpoint_auth_methods_supported: strings(&[
+            "none",
+            "client_secret_basic",
+            "client_secret_post",
+        ]),
+    })
+}
+
+/// Returns the claims about the account holder covered by the scopes
+/// granted to an OpenID Connect client's access token. The claims reflect
+/// the account as it is now, rather than when the token was issued.
+async fn get_userinfo<B: Backends, C: Clock<Utc>>(
+    State(app_state): State<Arc<AppState<B, C>>>,
+    BearerToken(token): BearerToken,
+) -> Result<Json<UserInfoResponse>, ApiError> {
+    let claims = match app_state.token_service.verify_access_token(&token).await {
+        Ok(claims) => claims,
+        Err(TokenServiceError::InvalidAccessToken) => {
+            return Err(OAuthServiceError::InvalidToken.into())
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let scopes: Vec<String> = claims
+        .scope
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(String::from)
+        .collect();
+    if !scopes.iter().any(|scope| scope == OPENID_SCOPE) {
+        return Err(OAuthServiceError::InsufficientScope(OPENID_SCOPE.to_string()).into());
+    }
+    let account = match app_state.account_service.get_account(&claims.sub).await {
+        Ok(account) if account.is_active() => account,
+        Ok(_) | Err(AccountsServiceError::AccountNotFound(_)) => {
+            return Err(OAuthServiceError::InvalidToken.into())
+        }
+        Err(err) => return Err(err.into()),
+    };
+    Ok(Json(IdentityClaims::for_scopes(&account, &scopes).into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    use axum::{
+        extract::ConnectInfo,
+        http::header::{RETRY_AFTER, WWW_AUTHENTICATE},
+    };
+    use axum_test::{TestResponse, TestServer};
+    use base64::{
+        engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+        Engine,
+    };
+    use ed25519_dalek::VerifyingKey;
+    use secrecy::Secret;
+    use url::Url;
+
+    use crate::{
+        apis::{
+            models::{
+                AccountStatusResponse, ApiErrorResponse, NewCredentialsRequest, OAuthErrorResponse,
+            },
+            rate_limit::RateLimits,
+        },
+        services::{
+            account::{
+                hashing::Argon2Hasher, models::Password, policy::PasswordPolicy,
+                stores::fake::FakeAccountStore, AccountService,
+            },
+            audit::stores::fake::FakeAuditStore,
+            breach::fake::FakeBreachedPasswordChecker,
+            credential::{
+                models::RelyingParty,
+                stores::fake::FakeCredentialStore,
+                webauthn::tests::{SoftwareAuthenticator, TEST_ORIGIN, TEST_RP_ID},
+            },
+            federation::{
+                models::{FederatedCallback, IdentityProvider},
+                oidc::tests::{
+                    MockIdentityProvider, TEST_REDIRECT_URL as TEST_FEDERATION_REDIRECT_URL,
+                },
+                stores::fake::FakeLinkedIdentityStore,
+            },
+            mfa::{cipher::SecretCipher, stores::fake::FakeMfaStore, totp},
+            notifier::fake::FakeNotifier,
+            oauth::stores::fake::{FakeAuthorizationCodeStore, FakeClientStore, FakeGrantStore},
+            session::{stores::fake::FakeSessionStore, SessionService},
+            token::{
+                models::{IdTokenClaims, PublicKey},
+                stores::fake::{FakeRevokedTokenStore, FakeSigningKeyStore},
+                tests::{verify_token, verify_token_claims},
+            },
+            SystemClock,
+        },
+    };
+
+    use super::*;
+
+    const TEST_ISSUER: &str = "https://id.example.com";
+    const TEST_LOGIN_URL: &str = "https://login.example.com/login";
+    const TEST_REDIRECT_URI: &str = "https://app.example.com/callback";
+    /// The PKCE code verifier and challenge from RFC 7636 appendix B.
+    const TEST_CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const TEST_CODE_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+    /// The [Backends] used in these tests.
+    struct FakeBackends;
+
+    impl Backends for FakeBackends {
+        type AccountStore = FakeAccountStore;
+        type AuditStore = FakeAuditStore;
+        type BreachedPasswordChecker = FakeBreachedPasswordChecker;
+        type CredentialStore = FakeCredentialStore;
+        type LinkedIdentityStore = FakeLinkedIdentityStore;
+        type MfaStore = FakeMfaStore;
+        type OAuthClientStore = FakeClientStore;
+        type AuthorizationCodeStore = FakeAuthorizationCodeStore;
+        type OAuthGrantStore = FakeGrantStore;
+        type SessionStore = FakeSessionStore;
+        type SigningKeyStore = FakeSigningKeyStore;
+        type RevokedTokenStore = FakeRevokedTokenStore;
+        type Notifier = FakeNotifier;
+    }
+
+    impl Default for NewAccountRequest {
+        fn default() -> Self {
+            NewAccountRequest {
+                email: "test@test.com".to_string(),
+                password: Secret::new(Password::new("tangerine_lantern_42")),
+                display_name: Some("Tester McTester".to_string()),
+            }
+        }
+    }
+
+    /// The address of the API gateway that test requests appear to come from.
+    const TEST_PEER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 1234);
+
+    /// The proxies trusted to set `X-Forwarded-For`, which include [TEST_PEER].
+    const TEST_TRUSTED_PROXIES: &str = "10.0.0.0/8";
+
+    /// Constructs a new [TestServer] using fresh services and fake stores.
+    fn test_server() -> TestServer {
+        test_server_with_notifier(FakeNotifier::new())
+    }
+
+    /// Like [test_server], but uses the provided [FakeNotifier] so that
+    /// the test can inspect the messages sent.
+    fn test_server_with_notifier(notifier: FakeNotifier) -> TestServer {
+        test_server_with(notifier, RateLimits::default())
+    }
+
+    /// Like [test_server_with_notifier], but also applies the [RateLimits].
+    fn test_server_with(notifier: FakeNotifier, rate_limits: RateLimits) -> TestServer {
+        test_server_with_providers(notifier, rate_limits, Vec::new())
+    }
+
+    /// Like [test_server_with], but account holders can also sign in with the
+    /// upstream identity providers.
+    fn test_server_with_providers(
+        notifier: FakeNotifier,
+        rate_limits: RateLimits,
+        providers: Vec<IdentityProvider>,
+    ) -> TestServer {
+        test_server_with_proxies(
+            notifier,
+            rate_limits,
+            providers,
+            TEST_TRUSTED_PROXIES.parse().unwrap(),
+        )
+    }
+
+    /// Like [test_server_with_providers], but only trusts the `X-Forwarded-For`
+    /// header from the given proxies. Requests appear to come from [TEST_PEER].
+    fn test_server_with_proxies(
+        notifier: FakeNotifier,
+        rate_limits: RateLimits,
+        providers: Vec<IdentityProvider>,
+        trusted_proxies: TrustedProxies,
+    ) -> TestServer {
+        let audit_store = FakeAuditStore::new();
+        TestServer::new(
+            router(AppState::<FakeBackends, _> {
+                account_service: AccountService::new_with_clock(
+                    FakeAccountStore::with_audit_store(audit_store.clone()),
+                    notifier,
+                    PasswordPolicy::default(),
+                    FakeBreachedPasswordChecker::new(),
+                    Argon2Hasher::default(),
+                    SystemClock::default(),
+                ),
+                audit_service: AuditService::new_with_clock(audit_store, SystemClock::default()),
+                mfa_service: MfaService::new_with_clock(
+                    FakeMfaStore::new(),
+                    SecretCipher::new(&[7u8; 32]).unwrap(),
+                    SystemClock::default(),
+                ),
+                session_service: SessionService::new_with_clock(
+                    FakeSessionStore::new(),
+                    SystemClock::default(),
+                ),
+                token_service: TokenService::new_with_clock(
+                    FakeSigningKeyStore::new(),
+                    FakeRevokedTokenStore::new(),
+                    TEST_ISSUER,
+                    SystemClock::default(),
+                ),
+                credential_service: CredentialService::new_with_clock(
+                    FakeCredentialStore::new(),
+                    RelyingParty {
+                        id: TEST_RP_ID.to_string(),
+                        name: "Test".to_string(),
+                        origin: TEST_ORIGIN.to_string(),
+                    },
+                    SystemClock::default(),
+                ),
+                federation_service: FederationService::new_with_clock(
+                    FakeLinkedIdentityStore::new(),
+                    providers,
+                    TEST_FEDERATION_REDIRECT_URL,
+                    SystemClock::default(),
+                )
+                .unwrap(),
+                oauth_service: OAuthService::new_with_clock(
+                    FakeClientStore::new(),
+                    FakeAuthorizationCodeStore::new(),
+                    FakeGrantStore::new(),
+                    Url::parse(TEST_LOGIN_URL).unwrap(),
+                    Argon2Hasher::default(),
+                    SystemClock::default(),
+                ),
+                rate_limiter: Arc::new(RateLimiter::new(rate_limits)),
+                trusted_proxies,
+            })
+            .layer(Extension(ConnectInfo(TEST_PEER))),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn root_returns_correct_response() {
+        let response = test_server().get("/").await;
+        response.assert_status_ok();
+        response.assert_text(ROOT_RESPONSE);
+    }
+
+    #[tokio::test]
+    async fn invalid_route_returns_not_found() {
+        let response = test_server().get("/invalid").await;
+        response.assert_status_not_found();
+    }
+
+    #[tokio::test]
+    async fn new_account_success() {
+        let new_account_request = NewAccountRequest::default();
+        let response = test_server()
+            .post(ACCOUNTS_RESOURCE)
+            .json(&new_account_request)
+            .await;
+
+        response.assert_status(StatusCode::CREATED);
+        let response_account: AccountResponse = response.json();
+        assert!(!response_account.id.is_empty());
+        assert!(response_account.id.starts_with("acct_"));
+        assert_eq!(new_account_request.email, response_account.email);
+        assert_eq!(
+            new_account_request.display_name,
+            response_account.display_name
+        );
+    }
+
+    #[tokio::test]
+    async fn new_account_no_display_name() {
+        let new_account_request = NewAccountRequest {
+            display_name: None,
+            ..NewAccountRequest::default()
+        };
+        let response = test_server()
+            .post(ACCOUNTS_RESOURCE)
+            .json(&new_account_request)
+            .await;
+
+        response.assert_status(StatusCode::CREATED);
+        let response_account: AccountResponse = response.json();
+        assert!(!response_account.id.is_empty());
+        assert_eq!(new_account_request.email, response_account.email);
+        assert_eq!(None, response_account.display_name);
+    }
+
+    #[tokio::test]
+    async fn new_account_empty_password() {
+        let new_account_request = NewAccountRequest {
+            password: Secret::new(Password::new("")),
+            ..NewAccountRequest::default()
+        };
+        let response = test_server()
+            .post(ACCOUNTS_RESOURCE)
+            .json(&new_account_request)
+            .await;
+
+        response.assert_status_bad_request();
+        let response_body: ApiErrorResponse = response.json();
+        assert!(response_body.message.starts_with("Validation error:"))
+    }
+
+    #[tokio::test]
+    async fn new_account_weak_password() {
+        let new_account_request = NewAccountRequest {
+            password: Secret::new(Password::new("password1234")),
+            ..NewAccountRequest::default()
+        };
+        let response = test_server()
+            .post(ACCOUNTS_RESOURCE)
+            .json(&new_account_request)
+            .await;
+
+        response.assert_status_bad_request();
+        let response_body: ApiErrorResponse = response.json();
+        assert!(response_body.message.contains("password_too_weak"))
+    }
+
+    #[tokio::test]
+    async fn new_account_invalid_email() {
+        let new_account_request = NewAccountRequest {
+            email: "invalid".to_string(),
+            ..NewAccountRequest::default()
+        };
+        let response = test_server()
+            .post(ACCOUNTS_RESOURCE)
+            .json(&new_account_request)
+            .await;
+
+        response.assert_status_bad_request();
+        let response_body: ApiErrorResponse = response.json();
+        assert!(response_body.message.starts_with("Validation error:"))
+    }
+
+    #[tokio::test]
+    async fn new_account_duplicate_email() {
+        let new_account_request = NewAccountRequest::default();
+        let server = test_server();
+
+        // insert the account
+        server
+            .post(ACCOUNTS_RESOURCE)
+            .json(&new_account_request)
+            .await
+            .assert_status(StatusCode::CREATED);
+        // try to insert it again -- should get a duplicate email bad request
+        let response = server.post("/accounts").json(&new_account_request).await;
+
+        response.assert_status_bad_request();
+        let response_body: ApiErrorResponse = response.json();
+        assert_eq!(
+            response_body.message,
+            "The email address 'test@test.com' is already registered".to_string()
+        )
+    }
+
+    #[tokio::test]
+    async fn authenticate_valid_credentials() {
+        let new_account_request = NewAccountRequest::default();
+        let server = test_server();
+        server
+            .post(ACCOUNTS_RESOURCE)
+            .json(&new_account_request)
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        let authenticate_request = AuthenticateRequest {
+            email: new_account_request.email.clone(),
+            password: new_account_request.password.clone(),
+            recovery_code: None,
+        };
+        let response = server
+            .post(SESSIONS_RESOURCE)
+            .json(&authenticate_request)
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let response_session: SessionResponse = response.json();
+        assert!(!response_session.id.is_empty());
+        assert!(response_session.expires_at > response_session.created_at);
+        assert_eq!(response_session.account.email, authenticate_request.email);
+        assert!(!response_session.account.id.is_empty());
+        let access_token = response_session.access_token.unwrap();
+        assert_eq!("Bearer", access_token.token_type);
+        assert!(!access_token.token.is_empty());
+        assert!(!response_session.refresh_token.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn authenticate_invalid_password() {
+        let new_account_request = NewAccountRequest::default();
+        let server = test_server();
+        server
+            .post(ACCOUNTS_RESOURCE)
+            .json(&new_account_request)
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        let authenticate_request = AuthenticateRequest {
+            email: new_account_request.email.clone(),
+            password: Secret::new(Password::new("invalid")),
+            recovery_code: None,
+        };
+        let response = server
+            .post(SESSIONS_RESOURCE)
+            .json(&authenticate_request)
+            .await;
+        response.assert_status_bad_request();
+        let error_response: ApiErrorResponse = response.json();
+        assert_eq!(
+            error_response.message,
+            "The email address or password was incorrect".to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn authenticate_invalid_email() {
+        let new_account_request = NewAccountRequest::default();
+        let server = test_server();
+        server
+            .post(ACCOUNTS_RESOURCE)
+            .json(&new_account_request)
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        let authenticate_request = AuthenticateRequest {
+            email: "invalid".to_string(),
+            password: Secret::new(Password::new("invalid")),
+            recovery_code: None,
+        };
+        let response = server
+            .post(SESSIONS_RESOURCE)
+            .json(&authenticate_request)
+            .await;
+        response.assert_status_bad_request();
+        let error_response: ApiErrorResponse = response.json();
+        assert_eq!(
+            error_response.message,
+            "The email address or password was incorrect".to_string()
+        );
+    }
+
+    /// Creates the default test account and signs it in, returning the new session.
+    async fn sign_in(server: &TestServer) -> SessionResponse {
+        server
+            .post(ACCOUNTS_RESOURCE)
+            .json(&NewAccountRequest::default())
+            .await
+            .assert_status(StatusCode::CREATED);
+        sign_in_again(server).await
+    }
+
+    /// Signs in the default test account, which must have already been created.
+    async fn sign_in_again(server: &TestServer) -> SessionResponse {
+        let new_account_request = NewAccountRequest::default();
+        let authenticate_request = AuthenticateRequest {
+            email: new_account_request.email,
+            password: new_account_request.password,
+            recovery_code: None,
+        };
+        let response = server
+            .post(SESSIONS_RESOURCE)
+            .json(&authenticate_request)
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        response.json()
+    }
+
+    #[tokio::test]
+    async fn validate_session() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+
+        let response = server
+            .get(&SESSION_RESOURCE.replace(":id", &session.id))
+            .await;
+        response.assert_status_ok();
+        let validated: SessionResponse = response.json();
+        assert_eq!(session.id, validated.id);
+        assert_eq!(session.account.id, validated.account.id);
+        assert!(validated.expires_at >= session.expires_at);
+    }
+
+    #[tokio::test]
+    async fn validate_invalid_session() {
+        let response = test_server()
+            .get(&SESSION_RESOURCE.replace(":id", "invalid"))
+            .await;
+        response.assert_status_not_found();
+        let error_response: ApiErrorResponse = response.json();
+        assert_eq!(
+            error_response.message,
+            "The session does not exist or has expired".to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_session() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let session_resource = SESSION_RESOURCE.replace(":id", &session.id);
+
+        server
+            .delete(&session_resource)
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        server
+            .get(&session_resource)
+            .await
+            .assert_status_not_found();
+    }
+
+    #[tokio::test]
+    async fn delete_account_sessions() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let another_session = sign_in_again(&server).await;
+
+        server
+            .delete(&ACCOUNT_SESSIONS_RESOURCE.replace(":id", &session.account.id))
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        for id in [&session.id, &another_session.id] {
+            server
+                .get(&SESSION_RESOURCE.replace(":id", id))
+                .await
+                .assert_status_not_found();
+        }
+    }
+
+    /// Converts a JWK from an API response back into a [PublicKey].
+    fn public_key(jwk: &JwkResponse) -> PublicKey {
+        PublicKey {
+            kid: jwk.kid.clone(),
+            key: VerifyingKey::try_from(URL_SAFE_NO_PAD.decode(&jwk.x).unwrap().as_slice())
+                .unwrap(),
+        }
+    }
+
+    /// Fetches the JWKS and converts it into a list of [PublicKey]s.
+    async fn public_keys(server: &TestServer) -> Vec<PublicKey> {
+        let response = server.get(JWKS_RESOURCE).await;
+        response.assert_status_ok();
+        let jwks: JwksResponse = response.json();
+        jwks.keys.iter().map(public_key).collect()
+    }
+
+    #[tokio::test]
+    async fn refresh_session() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+
+        let response = server
+            .post(SESSION_REFRESH_RESOURCE)
+            .json(&RefreshSessionRequest {
+                refresh_token: session.refresh_token.clone().unwrap(),
+            })
+            .await;
+        response.assert_status_ok();
+        let refreshed: SessionResponse = response.json();
+        assert_eq!(session.id, refreshed.id);
+        assert_eq!(session.account.id, refreshed.account.id);
+        assert!(refreshed.access_token.is_some());
+        assert_ne!(session.refresh_token, refreshed.refresh_token);
+    }
+
+    #[tokio::test]
+    async fn refresh_token_reuse_revokes_all_sessions() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let other_session = sign_in_again(&server).await;
+        let refresh_request = RefreshSessionRequest {
+            refresh_token: session.refresh_token.clone().unwrap(),
+        };
+        server
+            .post(SESSION_REFRESH_RESOURCE)
+            .json(&refresh_request)
+            .await
+            .assert_status_ok();
+
+        let response = server
+            .post(SESSION_REFRESH_RESOURCE)
+            .json(&refresh_request)
+            .await;
+        response.assert_status_bad_request();
+        let error_response: ApiErrorResponse = response.json();
+        assert_eq!(
+            error_response.message,
+            "The refresh token is invalid or has expired".to_string()
+        );
+        server
+            .get(&SESSION_RESOURCE.replace(":id", &session.id))
+            .await
+            .assert_status_not_found();
+        server
+            .get(&SESSION_RESOURCE.replace(":id", &other_session.id))
+            .await
+            .assert_status_not_found();
+    }
+
+    #[tokio::test]
+    async fn access_token_verifies_with_jwks() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let public_keys = public_keys(&server).await;
+
+        let claims = verify_token(&session.access_token.unwrap().token, &public_keys);
+        assert_eq!(session.account.id, claims.sub);
+        assert_eq!(Some(session.account.email), claims.email);
+        assert_eq!(session.account.display_name, claims.name);
+    }
+
+    #[tokio::test]
+    async fn rotate_signing_keys() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+
+        let response = server.post(SIGNING_KEYS_RESOURCE).await;
+        response.assert_status(StatusCode::CREATED);
+        let new_key: JwkResponse = response.json();
+
+        // both the old and new keys should be published
+        let public_keys = public_keys(&server).await;
+        assert_eq!(2, public_keys.len());
+        assert!(public_keys.iter().any(|k| k.kid == new_key.kid));
+        verify_token(&session.access_token.unwrap().token, &public_keys);
+
+        // new tokens are signed with the new key
+        let new_session = sign_in_again(&server).await;
+        verify_token(
+            &new_session.access_token.unwrap().token,
+            &[public_key(&new_key)],
+        );
+    }
+
+    #[tokio::test]
+    async fn password_reset() {
+        let notifier = FakeNotifier::new();
+        let server = test_server_with_notifier(notifier.clone());
+        let session = sign_in(&server).await;
+
+        server
+            .post(PASSWORD_RESETS_RESOURCE)
+            .json(&PasswordResetRequest {
+                email: session.account.email.clone(),
+            })
+            .await
+            .assert_status(StatusCode::ACCEPTED);
+        let message = notifier.sent_messages().pop().unwrap();
+        assert_eq!(session.account.email, message.to);
+        let token = message.body.lines().last().unwrap();
+
+        let new_password = Secret::new(Password::new("meadow-trumpet-58"));
+        let response = server
+            .put(&PASSWORD_RESET_RESOURCE.replace(":token", token))
+            .json(&NewPasswordRequest {
+                password: new_password.clone(),
+            })
+            .await;
+        response.assert_status_ok();
+        let account: AccountResponse = response.json();
+        assert_eq!(session.account.id, account.id);
+
+        // existing sessions are revoked, and the new password works
+        server
+            .get(&SESSION_RESOURCE.replace(":id", &session.id))
+            .await
+            .assert_status_not_found();
+        server
+            .post(SESSIONS_RESOURCE)
+            .json(&AuthenticateRequest {
+                email: session.account.email.clone(),
+                password: new_password.clone(),
+                recovery_code: None,
+            })
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        // the token can't be used again
+        let response = server
+            .put(&PASSWORD_RESET_RESOURCE.replace(":token", token))
+            .json(&NewPasswordRequest {
+                password: new_password,
+            })
+            .await;
+        response.assert_status_bad_request();
+        let error_response: ApiErrorResponse = response.json();
+        assert_eq!(
+            error_response.message,
+            "The password reset token is invalid or has expired".to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn password_reset_unknown_email() {
+        let notifier = FakeNotifier::new();
+        let server = test_server_with_notifier(notifier.clone());
+
+        server
+            .post(PASSWORD_RESETS_RESOURCE)
+            .json(&PasswordResetRequest {
+                email: "unknown@test.com".to_string(),
+            })
+            .await
+            .assert_status(StatusCode::ACCEPTED);
+        assert!(notifier.sent_messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn sign_in_code() {
+        let notifier = FakeNotifier::new();
+        let server = test_server_with_notifier(notifier.clone());
+        let sign_in_request = SignInCodeRequest {
+            email: "new@test.com".to_string(),
+        };
+
+        // an unregistered email address gets a code, which creates the account
+        server
+            .post(SIGN_IN_CODES_RESOURCE)
+            .json(&sign_in_request)
+            .await
+            .assert_status(StatusCode::ACCEPTED);
+        let message = notifier.sent_messages().pop().unwrap();
+        assert_eq!("new@test.com", message.to);
+        let code = message.body.lines().nth(4).unwrap();
+        let response = server
+            .post(SESSION_SIGN_IN_CODE_RESOURCE)
+            .json(&SignInCodeSessionRequest {
+                email: Some("new@test.com".to_string()),
+                code: Some(Secret::new(Password::new(code))),
+                token: None,
+            })
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let session: SessionResponse = response.json();
+        assert_eq!("new@test.com", session.account.email);
+        assert!(session.account.email_verified_at.is_some());
+        assert!(session.access_token.is_some());
+
+        // the emailed token works too, but only once
+        server
+            .post(SIGN_IN_CODES_RESOURCE)
+            .json(&sign_in_request)
+            .await
+            .assert_status(StatusCode::ACCEPTED);
+        let token_request = SignInCodeSessionRequest {
+            email: None,
+            code: None,
+            token: Some(sent_token(&notifier)),
+        };
+        let response = server
+            .post(SESSION_SIGN_IN_CODE_RESOURCE)
+            .json(&token_request)
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let token_session: SessionResponse = response.json();
+        assert_eq!(session.account.id, token_session.account.id);
+        let response = server
+            .post(SESSION_SIGN_IN_CODE_RESOURCE)
+            .json(&token_request)
+            .await;
+        response.assert_status_bad_request();
+        let error_response: ApiErrorResponse = response.json();
+        assert_eq!(
+            error_response.message,
+            "The sign-in code or token is incorrect or has expired".to_string()
+        );
+
+        // accounts with MFA enabled still have to complete a challenge, and
+        // since this account has no password, a recent session proves who it is
+        let totp_resource = ACCOUNT_TOTP_RESOURCE.replace(":id", &session.account.id);
+        let enrollment: TotpEnrollmentResponse = server
+            .post(&totp_resource)
+            .json(&ReauthenticationRequest {
+                session_id: Some(session.id.clone()),
+                ..Default::default()
+            })
+            .await
+            .json();
+        server
+            .put(&totp_resource)
+            .json(&TotpConfirmationRequest {
+                code: totp_code(&enrollment, 0),
+            })
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        server
+            .post(SIGN_IN_CODES_RESOURCE)
+            .json(&sign_in_request)
+            .await
+            .assert_status(StatusCode::ACCEPTED);
+        server
+            .post(SESSION_SIGN_IN_CODE_RESOURCE)
+            .json(&SignInCodeSessionRequest {
+                email: None,
+                code: None,
+                token: Some(sent_token(&notifier)),
+            })
+            .await
+            .assert_status(StatusCode::ACCEPTED);
+
+        // either a token, or an email address and code, are required
+        server
+            .post(SESSION_SIGN_IN_CODE_RESOURCE)
+            .json(&SignInCodeSessionRequest {
+                email: Some("new@test.com".to_string()),
+                code: None,
+                token: None,
+            })
+            .await
+            .assert_status_bad_request();
+    }
+
+    /// Extracts the token from the last message sent by the notifier.
+    fn sent_token(notifier: &FakeNotifier) -> String {
+        let message = notifier.sent_messages().pop().unwrap();
+        message.body.lines().last().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn verify_email() {
+        let notifier = FakeNotifier::new();
+        let server = test_server_with_notifier(notifier.clone());
+        let response = server
+            .post(ACCOUNTS_RESOURCE)
+            .json(&NewAccountRequest::default())
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let account: AccountResponse = response.json();
+        assert!(account.email_verified_at.is_none());
+
+        // a verification token is sent when the account is created
+        let token = sent_token(&notifier);
+        let response = server
+            .put(&EMAIL_VERIFICATION_RESOURCE.replace(":token", &token))
+            .await;
+        response.assert_status_ok();
+        let verified: AccountResponse = response.json();
+        assert!(verified.email_verified_at.is_some());
+
+        // the token can't be used again, and there's nothing left to verify
+        server
+            .put(&EMAIL_VERIFICATION_RESOURCE.replace(":token", &token))
+            .await
+            .assert_status_bad_request();
+        server
+            .post(&ACCOUNT_EMAIL_VERIFICATIONS_RESOURCE.replace(":id", &account.id))
+            .await
+            .assert_status_bad_request();
+
+        // the access token says the email is verified
+        let session = sign_in_again(&server).await;
+        let claims = verify_token(
+            &session.access_token.unwrap().token,
+            &public_keys(&server).await,
+        );
+        assert_eq!(Some(true), claims.email_verified);
+    }
+
+    #[tokio::test]
+    async fn resend_email_verification() {
+        let notifier = FakeNotifier::new();
+        let server = test_server_with_notifier(notifier.clone());
+        let session = sign_in(&server).await;
+
+        server
+            .post(&ACCOUNT_EMAIL_VERIFICATIONS_RESOURCE.replace(":id", &session.account.id))
+            .await
+            .assert_status(StatusCode::ACCEPTED);
+        assert_eq!(2, notifier.sent_messages().len());
+        server
+            .put(&EMAIL_VERIFICATION_RESOURCE.replace(":token", &sent_token(&notifier)))
+            .await
+            .assert_status_ok();
+
+        server
+            .post(&ACCOUNT_EMAIL_VERIFICATIONS_RESOURCE.replace(":id", "acct_unknown"))
+            .await
+            .assert_status_not_found();
+    }
+
+    #[tokio::test]
+    async fn update_email_requires_verification() {
+        let notifier = FakeNotifier::new();
+        let server = test_server_with_notifier(notifier.clone());
+        let session = sign_in(&server).await;
+        let new_account_request = NewAccountRequest::default();
+
+        let response = server
+            .put(&CREDENTIALS_RESOURCE.replace(":id", &session.account.id))
+            .json(&UpdateCredentialsRequest {
+                old: AuthenticateRequest {
+                    email: new_account_request.email.clone(),
+                    password: new_account_request.password.clone(),
+                    recovery_code: None,
+                },
+                new: NewCredentialsRequest {
+                    password: new_account_request.password.clone(),
+                    email: Some("new@test.com".to_string()),
+                },
+            })
+            .await;
+        response.assert_status_ok();
+        let account: AccountResponse = response.json();
+        assert_eq!(new_account_request.email, account.email);
+        assert_eq!(Some("new@test.com".to_string()), account.pending_email);
+
+        // the new email address can't be used to sign in until it's verified
+        server
+            .post(SESSIONS_RESOURCE)
+            .json(&AuthenticateRequest {
+                email: "new@test.com".to_string(),
+                password: new_account_request.password.clone(),
+                recovery_code: None,
+            })
+            .await
+            .assert_status_bad_request();
+        let message = notifier.sent_messages().pop().unwrap();
+        assert_eq!("new@test.com", message.to);
+        let response = server
+            .put(&EMAIL_VERIFICATION_RESOURCE.replace(":token", &sent_token(&notifier)))
+            .await;
+        response.assert_status_ok();
+        let account: AccountResponse = response.json();
+        assert_eq!("new@test.com", account.email);
+        assert!(account.pending_email.is_none());
+        assert!(account.email_verified_at.is_some());
+
+        server
+            .post(SESSIONS_RESOURCE)
+            .json(&AuthenticateRequest {
+                email: "new@test.com".to_string(),
+                password: new_account_request.password,
+                recovery_code: None,
+            })
+            .await
+            .assert_status(StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn unlock_account() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let new_account_request = NewAccountRequest::default();
+
+        // after a failed attempt, even the right password fails for a while,
+        // with the same response as an incorrect password
+        server
+            .post(SESSIONS_RESOURCE)
+            .json(&AuthenticateRequest {
+                email: new_account_request.email.clone(),
+                password: Secret::new(Password::new("wrong-password")),
+                recovery_code: None,
+            })
+            .await
+            .assert_status_bad_request();
+        let response = server
+            .post(SESSIONS_RESOURCE)
+            .json(&AuthenticateRequest {
+                email: new_account_request.email,
+                password: new_account_request.password,
+                recovery_code: None,
+            })
+            .await;
+        response.assert_status_bad_request();
+        let error_response: ApiErrorResponse = response.json();
+        assert_eq!(
+            error_response.message,
+            "The email address or password was incorrect".to_string()
+        );
+
+        server
+            .delete(&ACCOUNT_LOCKOUT_RESOURCE.replace(":id", &session.account.id))
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        sign_in_again(&server).await;
+        server
+            .delete(&ACCOUNT_LOCKOUT_RESOURCE.replace(":id", "acct_unknown"))
+            .await
+            .assert_status_not_found();
+    }
+
+    #[tokio::test]
+    async fn rate_limits() {
+        let server = test_server_with(
+            FakeNotifier::new(),
+            "/sessions ip=3/60 email=2/60".parse().unwrap(),
+        );
+        let authenticate = |email: &str, ip: &str| {
+            server
+                .post(SESSIONS_RESOURCE)
+                .add_header("x-forwarded-for", ip)
+                .json(&AuthenticateRequest {
+                    email: email.to_string(),
+                    password: Secret::new(Password::new("wrong-password")),
+                    recovery_code: None,
+                })
+        };
+
+        // the email limit applies across IP addresses, and is case-insensitive
+        authenticate("a@test.com", "192.0.2.1")
+            .await
+            .assert_status_bad_request();
+        authenticate("A@test.com", "192.0.2.2")
+            .await
+            .assert_status_bad_request();
+        let response = authenticate("a@test.com", "192.0.2.3").await;
+        response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!("30", response.header(RETRY_AFTER));
+        let error_response: ApiErrorResponse = response.json();
+        assert_eq!(429, error_response.status);
+
+        // the IP limit applies across email addresses
+        authenticate("b@test.com", "192.0.2.1")
+            .await
+            .assert_status_bad_request();
+        authenticate("c@test.com", "192.0.2.1")
+            .await
+            .assert_status_bad_request();
+        authenticate("d@test.com", "192.0.2.1")
+            .await
+            .assert_status(StatusCode::TOO_MANY_REQUESTS);
+
+        // routes without limits aren't affected
+        for _ in 0..5 {
+            server
+                .post(ACCOUNTS_RESOURCE)
+                .add_header("x-forwarded-for", "192.0.2.1")
+                .json(&NewAccountRequest::default())
+                .await;
+        }
+        server
+            .get("/")
+            .add_header("x-forwarded-for", "192.0.2.1")
+            .await
+            .assert_status_ok();
+    }
+
+    #[tokio::test]
+    async fn spoofed_forwarded_for_cannot_bypass_ip_limit() {
+        let authenticate = |server: &TestServer, forwarded_for: String| {
+            server
+                .post(SESSIONS_RESOURCE)
+                .add_header("x-forwarded-for", forwarded_for)
+                .json(&AuthenticateRequest {
+                    email: "a@test.com".to_string(),
+                    password: Secret::new(Password::new("wrong-password")),
+                    recovery_code: None,
+                })
+        };
+
+        // without trusted proxies, the header is ignored and the peer is limited
+        let server = test_server_with_proxies(
+            FakeNotifier::new(),
+            "/sessions ip=2/60".parse().unwrap(),
+            Vec::new(),
+            TrustedProxies::default(),
+        );
+        for i in 1..=2 {
+            authenticate(&server, format!("198.51.100.{i}"))
+                .await
+                .assert_status_bad_request();
+        }
+        authenticate(&server, "198.51.100.3".to_string())
+            .await
+            .assert_status(StatusCode::TOO_MANY_REQUESTS);
+
+        // behind a trusted proxy, addresses the client adds before its own are ignored
+        let server = test_server_with(FakeNotifier::new(), "/sessions ip=2/60".parse().unwrap());
+        for i in 1..=2 {
+            authenticate(&server, format!("198.51.100.{i}, 192.0.2.1"))
+                .await
+                .assert_status_bad_request();
+        }
+        authenticate(&server, "198.51.100.3, 192.0.2.1".to_string())
+            .await
+            .assert_status(StatusCode::TOO_MANY_REQUESTS);
+        authenticate(&server, "192.0.2.2".to_string())
+            .await
+            .assert_status_bad_request();
+    }
+
+    #[tokio::test]
+    async fn deactivate_and_reactivate_account() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let deactivation_resource =
+            ACCOUNT_DEACTIVATION_RESOURCE.replace(":id", &session.account.id);
+
+        let response = server.post(&deactivation_resource).await;
+        response.assert_status_ok();
+        let account: AccountResponse = response.json();
+        assert_eq!(AccountStatusResponse::Deactivated, account.status);
+
+        // existing sessions are revoked, and signing in fails just
+        // as it would with an incorrect password
+        server
+            .get(&SESSION_RESOURCE.replace(":id", &session.id))
+            .await
+            .assert_status_not_found();
+        let new_account_request = NewAccountRequest::default();
+        let response = server
+            .post(SESSIONS_RESOURCE)
+            .json(&AuthenticateRequest {
+                email: new_account_request.email,
+                password: new_account_request.password,
+                recovery_code: None,
+            })
+            .await;
+        response.assert_status_bad_request();
+        let error_response: ApiErrorResponse = response.json();
+        assert_eq!(
+            error_response.message,
+            "The email address or password was incorrect".to_string()
+        );
+
+        let response = server.delete(&deactivation_resource).await;
+        response.assert_status_ok();
+        let account: AccountResponse = response.json();
+        assert_eq!(AccountStatusResponse::Active, account.status);
+        sign_in_again(&server).await;
+    }
+
+    #[tokio::test]
+    async fn delete_account() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let account_resource = ACCOUNT_RESOURCE.replace(":id", &session.account.id);
+
+        server
+            .delete(&account_resource)
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        server
+            .get(&SESSION_RESOURCE.replace(":id", &session.id))
+            .await
+            .assert_status_not_found();
+
+        // deleted accounts can't be deleted again or reactivated
+        server
+            .delete(&account_resource)
+            .await
+            .assert_status_not_found();
+        server
+            .delete(&ACCOUNT_DEACTIVATION_RESOURCE.replace(":id", &session.account.id))
+            .await
+            .assert_status_not_found();
+
+        // the email address is free to be used by a new account
+        server
+            .post(ACCOUNTS_RESOURCE)
+            .json(&NewAccountRequest::default())
+            .await
+            .assert_status(StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn update_credentials() {
+        let new_account_request = NewAccountRequest::default();
+        let server = test_server();
+        let new_account_response = server
+            .post(ACCOUNTS_RESOURCE)
+            .json(&new_account_request)
+            .await;
+        new_account_response.assert_status(StatusCode::CREATED);
+        let account: AccountResponse = new_account_response.json();
+
+        let new_password = "updated-password".to_string();
+        let update_credentials_request = UpdateCredentialsRequest {
+            old: AuthenticateRequest {
+                email: new_account_request.email.clone(),
+                password: new_account_request.password.clone(),
+                recovery_code: None,
+            },
+            new: NewCredentialsRequest {
+                password: Secret::new(Password::new(&new_password)),
+                email: None,
+            },
+        };
+
+        let update_response = server
+            .put(&CREDENTIALS_RESOURCE.replace(":id", &account.id))
+            .json(&update_credentials_request)
+            .await;
+
+        update_response.assert_status_ok();
+        let update_response_account: AccountResponse = update_response.json();
+        assert_eq!(new_account_request.email, update_response_account.email);
+        assert_eq!(
+            new_account_request.display_name,
+            update_response_account.display_name
+        );
+
+        let authenticate_request = AuthenticateRequest {
+            email: new_account_request.email.clone(),
+            password: Secret::new(Password::new(&new_password)),
+            recovery_code: None,
+        };
+
+        let authenticate_response = server
+            .post(SESSIONS_RESOURCE)
+            .json(&authenticate_request)
+            .await;
+        authenticate_response.assert_status(StatusCode::CREATED);
+        let authenticate_response_session: SessionResponse = authenticate_response.json();
+        assert_eq!(
+            authenticate_response_session.account.email,
+            authenticate_request.email
+        );
+    }
+
+    #[tokio::test]
+    async fn account_events() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let new_account_request = NewAccountRequest::default();
+        server
+            .post(SESSIONS_RESOURCE)
+            .add_header("user-agent", "test-agent")
+            .add_header("x-forwarded-for", "192.0.2.1, 10.0.0.1")
+            .json(&AuthenticateRequest {
+                email: new_account_request.email,
+                password: Secret::new(Password::new("invalid")),
+                recovery_code: None,
+            })
+            .await
+            .assert_status_bad_request();
+        server
+            .delete(&ACCOUNT_SESSIONS_RESOURCE.replace(":id", &session.account.id))
+            .add_header("x-actor-id", "acct_support")
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+
+        let events_resource = ACCOUNT_EVENTS_RESOURCE.replace(":id", &session.account.id);
+        let response = server
+            .get(&events_resource)
+            .add_query_params(&AuditEventsQuery {
+                cursor: None,
+                limit: Some(2),
+            })
+            .await;
+        response.assert_status_ok();
+        let first_page: AuditEventsResponse = response.json();
+        let kinds: Vec<&str> = first_page
+            .events
+            .iter()
+            .map(|event| event.kind.as_str())
+            .collect();
+        assert_eq!(vec!["sessions_revoked", "authentication_failed"], kinds);
+        assert_eq!(Some("acct_support"), first_page.events[0].actor.as_deref());
+        let failed = &first_page.events[1];
+        assert!(failed.actor.is_none());
+        assert_eq!(Some("192.0.2.1"), failed.source_ip.as_deref());
+        assert_eq!(Some("test-agent"), failed.user_agent.as_deref());
+
+        let response = server
+            .get(&events_resource)
+            .add_query_params(&AuditEventsQuery {
+                cursor: first_page.next_cursor,
+                limit: Some(2),
+            })
+            .await;
+        response.assert_status_ok();
+        let second_page: AuditEventsResponse = response.json();
+        let kinds: Vec<&str> = second_page
+            .events
+            .iter()
+            .map(|event| event.kind.as_str())
+            .collect();
+        assert_eq!(vec!["authenticated", "account_created"], kinds);
+        assert!(second_page.next_cursor.is_none());
+
+        server
+            .get(&events_resource)
+            .add_query_params(&AuditEventsQuery {
+                cursor: Some("invalid".to_string()),
+                limit: None,
+            })
+            .await
+            .assert_status_bad_request();
+    }
+
+    /// Returns the TOTP code for the time step `offset` steps from now.
+    /// Proves who the account holder is using the default test account's password.
+    fn password_proof() -> ReauthenticationRequest {
+        ReauthenticationRequest {
+            password: Some(NewAccountRequest::default().password),
+            ..Default::default()
+        }
+    }
+
+    fn totp_code(enrollment: &TotpEnrollmentResponse, offset: i64) -> String {
+        let secret = totp::tests::decode_secret(&enrollment.secret);
+        totp::generate(&secret, totp::time_step(Utc::now()) + offset)
+    }
+
+    #[tokio::test]
+    async fn mfa_failures_count_towards_lockout() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let totp_resource = ACCOUNT_TOTP_RESOURCE.replace(":id", &session.account.id);
+        let enrollment: TotpEnrollmentResponse = server
+            .post(&totp_resource)
+            .json(&password_proof())
+            .await
+            .json();
+        server
+            .put(&totp_resource)
+            .json(&TotpConfirmationRequest {
+                code: totp_code(&enrollment, 0),
+            })
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        let new_account_request = NewAccountRequest::default();
+        let start_challenge = || async {
+            let response = server
+                .post(SESSIONS_RESOURCE)
+                .json(&AuthenticateRequest {
+                    email: new_account_request.email.clone(),
+                    password: new_account_request.password.clone(),
+                    recovery_code: None,
+                })
+                .await;
+            response.assert_status(StatusCode::ACCEPTED);
+            response.json::<MfaChallengeResponse>().mfa_challenge
+        };
+        let first_challenge = start_challenge().await;
+        let second_challenge = start_challenge().await;
+
+        // a wrong code for one challenge delays attempts at every other one,
+        // so starting new challenges doesn't give more guesses
+        server
+            .post(SESSION_MFA_RESOURCE)
+            .json(&MfaSessionRequest {
+                mfa_challenge: first_challenge,
+                code: Some("000000".to_string()),
+                recovery_code: None,
+            })
+            .await
+            .assert_status_bad_request();
+        let mfa_request = MfaSessionRequest {
+            mfa_challenge: second_challenge,
+            code: Some(totp_code(&enrollment, 1)),
+            recovery_code: None,
+        };
+        server
+            .post(SESSION_MFA_RESOURCE)
+            .json(&mfa_request)
+            .await
+            .assert_status_bad_request();
+        let events: AuditEventsResponse = server
+            .get(&ACCOUNT_EVENTS_RESOURCE.replace(":id", &session.account.id))
+            .await
+            .json();
+        assert_eq!("authentication_failed", events.events[0].kind);
+
+        // the challenge can be completed once the account is unlocked
+        server
+            .delete(&ACCOUNT_LOCKOUT_RESOURCE.replace(":id", &session.account.id))
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        server
+            .post(SESSION_MFA_RESOURCE)
+            .json(&mfa_request)
+            .await
+            .assert_status(StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn mfa_sign_in() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let totp_resource = ACCOUNT_TOTP_RESOURCE.replace(":id", &session.account.id);
+
+        let response = server.post(&totp_resource).json(&password_proof()).await;
+        response.assert_status(StatusCode::CREATED);
+        let enrollment: TotpEnrollmentResponse = response.json();
+        assert!(enrollment.provisioning_uri.starts_with("otpauth://totp/"));
+        // signing in doesn't require a code until the enrollment is confirmed
+        sign_in_again(&server).await;
+        server
+            .put(&totp_resource)
+            .json(&TotpConfirmationRequest {
+                code: totp_code(&enrollment, 0),
+            })
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+
+        let new_account_request = NewAccountRequest::default();
+        let authenticate_request = AuthenticateRequest {
+            email: new_account_request.email,
+            password: new_account_request.password,
+            recovery_code: None,
         };
         let response = server
             .post(SESSIONS_RESOURCE)
             .json(&authenticate_request)
             .await;
-        response.assert_status_bad_request();
-        let error_response: ApiErrorResponse = response.json();
+        response.assert_status(StatusCode::ACCEPTED);
+        let challenge: MfaChallengeResponse = response.json();
+
+        // the code used to confirm the enrollment can't be used again,
+        // but the authenticator's next code is accepted
+        let response = server
+            .post(SESSION_MFA_RESOURCE)
+            .json(&MfaSessionRequest {
+                mfa_challenge: challenge.mfa_challenge.clone(),
+                code: Some(totp_code(&enrollment, -1)),
+                recovery_code: None,
+            })
+            .await;
+        response.assert_status_bad_request();
+        // which counts towards locking the account, delaying the next attempt
+        let response = server
+            .post(SESSION_MFA_RESOURCE)
+            .json(&MfaSessionRequest {
+                mfa_challenge: challenge.mfa_challenge.clone(),
+                code: Some(totp_code(&enrollment, 1)),
+                recovery_code: None,
+            })
+            .await;
+        response.assert_status_bad_request();
+        server
+            .delete(&ACCOUNT_LOCKOUT_RESOURCE.replace(":id", &session.account.id))
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        let response = server
+            .post(SESSION_MFA_RESOURCE)
+            .json(&MfaSessionRequest {
+                mfa_challenge: challenge.mfa_challenge.clone(),
+                code: Some(totp_code(&enrollment, 1)),
+                recovery_code: None,
+            })
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let mfa_session: SessionResponse = response.json();
+        assert_eq!(session.account.id, mfa_session.account.id);
+        assert!(mfa_session.access_token.is_some());
+        assert!(mfa_session.refresh_token.is_some());
+        // the challenge can only be completed once
+        server
+            .post(SESSION_MFA_RESOURCE)
+            .json(&MfaSessionRequest {
+                mfa_challenge: challenge.mfa_challenge,
+                code: Some(totp_code(&enrollment, 1)),
+                recovery_code: None,
+            })
+            .await
+            .assert_status_bad_request();
+
+        server
+            .delete(&totp_resource)
+            .json(&password_proof())
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        sign_in_again(&server).await;
+    }
+
+    #[tokio::test]
+    async fn changing_mfa_requires_reauthentication() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let totp_resource = ACCOUNT_TOTP_RESOURCE.replace(":id", &session.account.id);
+        let security_resource = ACCOUNT_SECURITY_RESOURCE.replace(":id", &session.account.id);
+
+        let response = server
+            .post(&totp_resource)
+            .json(&ReauthenticationRequest::default())
+            .await;
+        response.assert_status_bad_request();
+        let error_response: ApiErrorResponse = response.json();
+        assert_eq!(
+            error_response.message,
+            ApiError::ReauthenticationRequired.to_string()
+        );
+        let enrollment: TotpEnrollmentResponse = server
+            .post(&totp_resource)
+            .json(&password_proof())
+            .await
+            .json();
+        server
+            .put(&totp_resource)
+            .json(&TotpConfirmationRequest {
+                code: totp_code(&enrollment, 0),
+            })
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+
+        // knowing the account ID isn't enough to remove the second factor
+        for proof in [
+            ReauthenticationRequest::default(),
+            ReauthenticationRequest {
+                password: Some(Secret::new(Password::new("wrong_password_42"))),
+                ..Default::default()
+            },
+            ReauthenticationRequest {
+                code: Some("000000".to_string()),
+                ..Default::default()
+            },
+            ReauthenticationRequest {
+                session_id: Some("invalid".to_string()),
+                ..Default::default()
+            },
+        ] {
+            server
+                .delete(&totp_resource)
+                .json(&proof)
+                .await
+                .assert_status_bad_request();
+        }
+        let summary: SecuritySummaryResponse = server.get(&security_resource).await.json();
+        assert!(summary.mfa_enabled);
+
+        // the failures above count towards locking the account, so clear them first
+        server
+            .delete(&ACCOUNT_LOCKOUT_RESOURCE.replace(":id", &session.account.id))
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        server
+            .delete(&totp_resource)
+            .json(&ReauthenticationRequest {
+                code: Some(totp_code(&enrollment, 1)),
+                ..Default::default()
+            })
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        let summary: SecuritySummaryResponse = server.get(&security_resource).await.json();
+        assert!(!summary.mfa_enabled);
+    }
+
+    #[tokio::test]
+    async fn recovery_codes() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let security_resource = ACCOUNT_SECURITY_RESOURCE.replace(":id", &session.account.id);
+        let totp_resource = ACCOUNT_TOTP_RESOURCE.replace(":id", &session.account.id);
+
+        let response = server.get(&security_resource).await;
+        response.assert_status_ok();
+        let summary: SecuritySummaryResponse = response.json();
+        assert!(!summary.mfa_enabled);
+        assert_eq!(0, summary.recovery_codes_remaining);
+        assert!(summary.recovery_codes_generated_at.is_none());
+
+        let enrollment: TotpEnrollmentResponse = server
+            .post(&totp_resource)
+            .json(&password_proof())
+            .await
+            .json();
+        server
+            .put(&totp_resource)
+            .json(&TotpConfirmationRequest {
+                code: totp_code(&enrollment, 0),
+            })
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        let recovery_codes_resource =
+            ACCOUNT_RECOVERY_CODES_RESOURCE.replace(":id", &session.account.id);
+        let response = server
+            .post(&recovery_codes_resource)
+            .json(&password_proof())
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let recovery_codes: RecoveryCodesResponse = response.json();
+        assert_eq!(10, recovery_codes.codes.len());
+
+        // a recovery code can be used as the second factor
+        let new_account_request = NewAccountRequest::default();
+        let challenge: MfaChallengeResponse = server
+            .post(SESSIONS_RESOURCE)
+            .json(&AuthenticateRequest {
+                email: new_account_request.email.clone(),
+                password: new_account_request.password.clone(),
+                recovery_code: None,
+            })
+            .await
+            .json();
+        let mfa_request = MfaSessionRequest {
+            mfa_challenge: challenge.mfa_challenge,
+            code: None,
+            recovery_code: Some(Secret::new(Password::new(&recovery_codes.codes[0]))),
+        };
+        server
+            .post(SESSION_MFA_RESOURCE)
+            .json(&mfa_request)
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        // or alongside the password, skipping the challenge, but only once
+        let authenticate_request = AuthenticateRequest {
+            email: new_account_request.email,
+            password: new_account_request.password,
+            recovery_code: Some(Secret::new(Password::new(&recovery_codes.codes[1]))),
+        };
+        server
+            .post(SESSIONS_RESOURCE)
+            .json(&authenticate_request)
+            .await
+            .assert_status(StatusCode::CREATED);
+        server
+            .post(SESSIONS_RESOURCE)
+            .json(&authenticate_request)
+            .await
+            .assert_status_bad_request();
+        // the reused code counts towards locking the account, so clear it
+        server
+            .delete(&ACCOUNT_LOCKOUT_RESOURCE.replace(":id", &session.account.id))
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+
+        let summary: SecuritySummaryResponse = server.get(&security_resource).await.json();
+        assert!(!summary.email_verified);
+        assert!(summary.mfa_enabled);
+        assert_eq!(8, summary.recovery_codes_remaining);
+        assert!(summary.recovery_codes_generated_at.is_some());
+
+        // generating a new set, which cancels the old one, requires re-authenticating
+        server
+            .post(&recovery_codes_resource)
+            .json(&ReauthenticationRequest::default())
+            .await
+            .assert_status_bad_request();
+        let summary: SecuritySummaryResponse = server.get(&security_resource).await.json();
+        assert_eq!(8, summary.recovery_codes_remaining);
+        server
+            .post(&recovery_codes_resource)
+            .json(&ReauthenticationRequest {
+                recovery_code: Some(Secret::new(Password::new(&recovery_codes.codes[2]))),
+                ..Default::default()
+            })
+            .await
+            .assert_status(StatusCode::CREATED);
+        let summary: SecuritySummaryResponse = server.get(&security_resource).await.json();
+        assert_eq!(10, summary.recovery_codes_remaining);
+    }
+
+    #[tokio::test]
+    async fn passkey_sign_in() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let passkeys_resource = ACCOUNT_PASSKEYS_RESOURCE.replace(":id", &session.account.id);
+
+        let registrations_resource =
+            ACCOUNT_PASSKEY_REGISTRATIONS_RESOURCE.replace(":id", &session.account.id);
+
+        // knowing the account ID isn't enough to register a passkey for it
+        server
+            .post(&registrations_resource)
+            .json(&ReauthenticationRequest::default())
+            .await
+            .assert_status_bad_request();
+        server
+            .post(&registrations_resource)
+            .json(&ReauthenticationRequest {
+                password: Some(Secret::new(Password::new("wrong_password_42"))),
+                ..Default::default()
+            })
+            .await
+            .assert_status_bad_request();
+
+        let response = server
+            .post(&registrations_resource)
+            .json(&ReauthenticationRequest {
+                session_id: Some(session.id.clone()),
+                ..Default::default()
+            })
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let options: PasskeyRegistrationOptionsResponse = response.json();
+        assert_eq!(TEST_RP_ID, options.rp_id);
+        assert_eq!("test@test.com", options.user_name);
+
+        let mut authenticator = SoftwareAuthenticator::new();
+        let credential = authenticator.register(&options.challenge, "packed");
+        let response = server
+            .post(&passkeys_resource)
+            .json(&NewPasskeyRequest {
+                name: "Laptop".to_string(),
+                client_data_json: credential.client_data_json,
+                attestation_object: credential.attestation_object,
+            })
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let passkey: PasskeyResponse = response.json();
+        assert_eq!("Laptop", passkey.name);
+
+        let response = server.post(SESSION_PASSKEY_CHALLENGES_RESOURCE).await;
+        response.assert_status(StatusCode::CREATED);
+        let options: PasskeyAuthenticationOptionsResponse = response.json();
+        let assertion = authenticator.assert(&options.challenge, Some(&session.account.id));
+        let passkey_request = PasskeySessionRequest {
+            credential_id: assertion.credential_id,
+            client_data_json: assertion.client_data_json,
+            authenticator_data: assertion.authenticator_data,
+            signature: assertion.signature,
+            user_handle: assertion.user_handle,
+        };
+        let response = server
+            .post(SESSION_PASSKEY_RESOURCE)
+            .json(&passkey_request)
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let passkey_session: SessionResponse = response.json();
+        assert_eq!(session.account.id, passkey_session.account.id);
+        assert!(passkey_session.access_token.is_some());
+        // the challenge can only be used once
+        server
+            .post(SESSION_PASSKEY_RESOURCE)
+            .json(&passkey_request)
+            .await
+            .assert_status_bad_request();
+
+        let passkey_resource = ACCOUNT_PASSKEY_RESOURCE
+            .replace(":id", &session.account.id)
+            .replace(":passkey_id", &passkey.id);
+        let response = server
+            .put(&passkey_resource)
+            .json(&PasskeyNameRequest {
+                name: "Work laptop".to_string(),
+            })
+            .await;
+        response.assert_status_ok();
+        let passkeys: PasskeysResponse = server.get(&passkeys_resource).await.json();
+        assert_eq!(1, passkeys.passkeys.len());
+        assert_eq!("Work laptop", passkeys.passkeys[0].name);
+        assert!(passkeys.passkeys[0].last_used_at.is_some());
+
+        server
+            .delete(&passkey_resource)
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        server
+            .delete(&passkey_resource)
+            .await
+            .assert_status_not_found();
+    }
+
+    /// Starts a federated authorization request at `resource`, and signs in
+    /// to the mock provider as `subject`, returning what it redirects back with.
+    async fn federated_callback(
+        server: &TestServer,
+        provider: &MockIdentityProvider,
+        resource: &str,
+        subject: &str,
+    ) -> FederatedCallbackRequest {
+        let response = server
+            .post(resource)
+            .json(&FederatedAuthorizationRequest {
+                provider: "mock".to_string(),
+            })
+            .await;
+        let callback = authorize_upstream(response, provider, subject);
+        FederatedCallbackRequest {
+            state: callback.state,
+            code: callback.code,
+        }
+    }
+
+    /// Like [federated_callback], but starts linking an identity to the
+    /// account, re-authenticating with its password.
+    async fn linking_callback(
+        server: &TestServer,
+        provider: &MockIdentityProvider,
+        account_id: &str,
+        subject: &str,
+    ) -> NewLinkedIdentityRequest {
+        let response = server
+            .post(&ACCOUNT_LINKED_IDENTITY_AUTHORIZATIONS_RESOURCE.replace(":id", account_id))
+            .json(&LinkedIdentityAuthorizationRequest {
+                provider: "mock".to_string(),
+                reauthentication: password_proof(),
+            })
+            .await;
+        let callback = authorize_upstream(response, provider, subject);
+        NewLinkedIdentityRequest {
+            state: callback.state,
+            code: callback.code,
+            reauthentication: password_proof(),
+        }
+    }
+
+    /// Signs in to the mock provider as `subject` using the authorization
+    /// URL in the response.
+    fn authorize_upstream(
+        response: TestResponse,
+        provider: &MockIdentityProvider,
+        subject: &str,
+    ) -> FederatedCallback {
+        response.assert_status(StatusCode::CREATED);
+        let authorization: FederatedAuthorizationResponse = response.json();
+        provider.authorize(
+            &authorization.authorization_url,
+            subject,
+            "upstream@test.com",
+        )
+    }
+
+    #[tokio::test]
+    async fn federated_sign_in() {
+        let provider = MockIdentityProvider::start().await;
+        let server = test_server_with_providers(
+            FakeNotifier::new(),
+            RateLimits::default(),
+            vec![provider.provider("mock")],
+        );
+        let providers: IdentityProvidersResponse =
+            server.get(IDENTITY_PROVIDERS_RESOURCE).await.json();
+        assert_eq!(1, providers.providers.len());
+        assert_eq!("mock", providers.providers[0].name);
+        assert_eq!(provider.issuer(), providers.providers[0].issuer);
+        server
+            .post(SESSION_FEDERATED_AUTHORIZATIONS_RESOURCE)
+            .json(&FederatedAuthorizationRequest {
+                provider: "unknown".to_string(),
+            })
+            .await
+            .assert_status_not_found();
+
+        // an identity that isn't linked to an account can't be used to sign in
+        let callback = federated_callback(
+            &server,
+            &provider,
+            SESSION_FEDERATED_AUTHORIZATIONS_RESOURCE,
+            "upstream-subject",
+        )
+        .await;
+        server
+            .post(SESSION_FEDERATED_RESOURCE)
+            .json(&callback)
+            .await
+            .assert_status_bad_request();
+
+        let session = sign_in(&server).await;
+        let identities_resource =
+            ACCOUNT_LINKED_IDENTITIES_RESOURCE.replace(":id", &session.account.id);
+        let callback =
+            linking_callback(&server, &provider, &session.account.id, "upstream-subject").await;
+        let response = server.post(&identities_resource).json(&callback).await;
+        response.assert_status(StatusCode::CREATED);
+        let identity: LinkedIdentityResponse = response.json();
+        assert_eq!("mock", identity.provider);
+        assert_eq!("upstream-subject", identity.subject);
+        assert_eq!(Some("upstream@test.com".to_string()), identity.email);
+        // the state can only be used once
+        server
+            .post(&identities_resource)
+            .json(&callback)
+            .await
+            .assert_status_bad_request();
+
+        let callback = federated_callback(
+            &server,
+            &provider,
+            SESSION_FEDERATED_AUTHORIZATIONS_RESOURCE,
+            "upstream-subject",
+        )
+        .await;
+        let response = server
+            .post(SESSION_FEDERATED_RESOURCE)
+            .json(&callback)
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let federated_session: SessionResponse = response.json();
+        assert_eq!(session.account.id, federated_session.account.id);
+        assert!(federated_session.access_token.is_some());
+
+        let identities: LinkedIdentitiesResponse = server.get(&identities_resource).await.json();
+        assert_eq!(1, identities.identities.len());
+        assert!(identities.identities[0].last_used_at.is_some());
+        let events: AuditEventsResponse = server
+            .get(&ACCOUNT_EVENTS_RESOURCE.replace(":id", &session.account.id))
+            .await
+            .json();
+        assert_eq!("authenticated", events.events[0].kind);
+        assert_eq!("identity_linked", events.events[1].kind);
+
+        let identity_resource = ACCOUNT_LINKED_IDENTITY_RESOURCE
+            .replace(":identity_id", &identity.id)
+            .replace(":id", &session.account.id);
+        server
+            .delete(&identity_resource)
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        server
+            .delete(&identity_resource)
+            .await
+            .assert_status_not_found();
+        let callback = federated_callback(
+            &server,
+            &provider,
+            SESSION_FEDERATED_AUTHORIZATIONS_RESOURCE,
+            "upstream-subject",
+        )
+        .await;
+        server
+            .post(SESSION_FEDERATED_RESOURCE)
+            .json(&callback)
+            .await
+            .assert_status_bad_request();
+    }
+
+    #[tokio::test]
+    async fn linking_identity_requires_reauthentication() {
+        let provider = MockIdentityProvider::start().await;
+        let server = test_server_with_providers(
+            FakeNotifier::new(),
+            RateLimits::default(),
+            vec![provider.provider("mock")],
+        );
+        let session = sign_in(&server).await;
+        let authorizations_resource =
+            ACCOUNT_LINKED_IDENTITY_AUTHORIZATIONS_RESOURCE.replace(":id", &session.account.id);
+        let identities_resource =
+            ACCOUNT_LINKED_IDENTITIES_RESOURCE.replace(":id", &session.account.id);
+
+        // starting a link needs proof that the caller is the account holder
+        for proof in [
+            ReauthenticationRequest::default(),
+            ReauthenticationRequest {
+                password: Some(Secret::new(Password::new("wrong-password"))),
+                ..Default::default()
+            },
+            ReauthenticationRequest {
+                session_id: Some("sess_unknown".to_string()),
+                ..Default::default()
+            },
+        ] {
+            server
+                .post(&authorizations_resource)
+                .json(&LinkedIdentityAuthorizationRequest {
+                    provider: "mock".to_string(),
+                    reauthentication: proof,
+                })
+                .await
+                .assert_status_bad_request();
+        }
+        // the wrong password counts towards locking the account, so clear it
+        server
+            .delete(&ACCOUNT_LOCKOUT_RESOURCE.replace(":id", &session.account.id))
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+
+        // and so does finishing it
+        let callback =
+            linking_callback(&server, &provider, &session.account.id, "upstream-subject").await;
+        let response = server
+            .post(&identities_resource)
+            .json(&NewLinkedIdentityRequest {
+                state: callback.state.clone(),
+                code: callback.code.clone(),
+                reauthentication: ReauthenticationRequest::default(),
+            })
+            .await;
+        response.assert_status_bad_request();
+        let error_response: ApiErrorResponse = response.json();
+        assert_eq!(
+            ApiError::ReauthenticationRequired.to_string(),
+            error_response.message
+        );
+        let identities: LinkedIdentitiesResponse = server.get(&identities_resource).await.json();
+        assert!(identities.identities.is_empty());
+
+        // the callback can still be used once the caller re-authenticates
+        server
+            .post(&identities_resource)
+            .json(&NewLinkedIdentityRequest {
+                reauthentication: ReauthenticationRequest {
+                    session_id: Some(session.id.clone()),
+                    ..Default::default()
+                },
+                ..callback
+            })
+            .await
+            .assert_status(StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn federated_sign_in_requires_active_account() {
+        let provider = MockIdentityProvider::start().await;
+        let server = test_server_with_providers(
+            FakeNotifier::new(),
+            RateLimits::default(),
+            vec![provider.provider("mock")],
+        );
+        let session = sign_in(&server).await;
+        let callback =
+            linking_callback(&server, &provider, &session.account.id, "upstream-subject").await;
+        server
+            .post(&ACCOUNT_LINKED_IDENTITIES_RESOURCE.replace(":id", &session.account.id))
+            .json(&callback)
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        server
+            .post(&ACCOUNT_DEACTIVATION_RESOURCE.replace(":id", &session.account.id))
+            .await
+            .assert_status_ok();
+        let callback = federated_callback(
+            &server,
+            &provider,
+            SESSION_FEDERATED_AUTHORIZATIONS_RESOURCE,
+            "upstream-subject",
+        )
+        .await;
+        server
+            .post(SESSION_FEDERATED_RESOURCE)
+            .json(&callback)
+            .await
+            .assert_status_bad_request();
+    }
+
+    /// Registers an OAuth client that can request the `openid`, `profile` and `email` scopes.
+    async fn create_oauth_client(server: &TestServer) -> OAuthClientResponse {
+        let response = server
+            .post(OAUTH_CLIENTS_RESOURCE)
+            .json(&NewOAuthClientRequest {
+                name: "Example App".to_string(),
+                redirect_uris: vec![TEST_REDIRECT_URI.to_string()],
+                scopes: vec![
+                    "openid".to_string(),
+                    "profile".to_string(),
+                    "email".to_string(),
+                ],
+            })
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        response.json()
+    }
+
+    fn authorization_params(client: &OAuthClientResponse) -> AuthorizationParams {
+        AuthorizationParams {
+            response_type: Some("code".to_string()),
+            client_id: Some(client.id.clone()),
+            redirect_uri: Some(TEST_REDIRECT_URI.to_string()),
+            scope: Some("profile".to_string()),
+            state: Some("xyz".to_string()),
+            code_challenge: Some(TEST_CODE_CHALLENGE.to_string()),
+            code_challenge_method: Some("S256".to_string()),
+            nonce: None,
+        }
+    }
+
+    /// Returns a request that signs the default test account in
+    /// to authorize the client, with the given consent.
+    fn authorize_request(client: &OAuthClientResponse, consent: Option<bool>) -> AuthorizeRequest {
+        let new_account_request = NewAccountRequest::default();
+        AuthorizeRequest {
+            params: authorization_params(client),
+            email: Some(new_account_request.email),
+            password: Some(new_account_request.password),
+            mfa_challenge: None,
+            code: None,
+            recovery_code: None,
+            consent,
+        }
+    }
+
+    /// Returns the value of a query parameter in a URL.
+    fn query_param(url: &str, name: &str) -> Option<String> {
+        Url::parse(url)
+            .unwrap()
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+
+    fn code_token_request(client: &OAuthClientResponse, code: &str) -> TokenRequest {
+        TokenRequest {
+            grant_type: Some("authorization_code".to_string()),
+            client_id: Some(client.id.clone()),
+            client_secret: None,
+            code: Some(code.to_string()),
+            redirect_uri: Some(TEST_REDIRECT_URI.to_string()),
+            code_verifier: Some(TEST_CODE_VERIFIER.to_string()),
+            refresh_token: None,
+            scope: None,
+        }
+    }
+
+    fn refresh_token_request(client: &OAuthClientResponse, refresh_token: &str) -> TokenRequest {
+        TokenRequest {
+            grant_type: Some("refresh_token".to_string()),
+            client_id: Some(client.id.clone()),
+            client_secret: None,
+            code: None,
+            redirect_uri: None,
+            code_verifier: None,
+            refresh_token: Some(refresh_token.to_string()),
+            scope: None,
+        }
+    }
+
+    /// Runs the default test account through the authorization
+    /// request for the client, returning the authorization code.
+    async fn authorize(server: &TestServer, client: &OAuthClientResponse) -> String {
+        let response = server
+            .post(OAUTH_AUTHORIZE_RESOURCE)
+            .json(&authorize_request(client, Some(true)))
+            .await;
+        response.assert_status_ok();
+        let authorized: AuthorizeResponse = response.json();
+        query_param(&authorized.redirect_to, "code").unwrap()
+    }
+
+    #[tokio::test]
+    async fn oauth_clients() {
+        let server = test_server();
+        let client = create_oauth_client(&server).await;
+        assert!(client.id.starts_with("client_"));
+        let client_resource = OAUTH_CLIENT_RESOURCE.replace(":id", &client.id);
+
+        let response = server.get(&client_resource).await;
+        response.assert_status_ok();
+        let loaded: OAuthClientResponse = response.json();
+        assert_eq!(client.redirect_uris, loaded.redirect_uris);
+
+        let response = server
+            .post(OAUTH_CLIENTS_RESOURCE)
+            .json(&NewOAuthClientRequest {
+                name: "Insecure App".to_string(),
+                redirect_uris: vec!["http://app.example.com/callback".to_string()],
+                scopes: vec![],
+            })
+            .await;
+        response.assert_status_bad_request();
+        let error: OAuthErrorResponse = response.json();
+        assert_eq!("invalid_client_metadata", error.error);
+
+        server
+            .delete(&client_resource)
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        server.get(&client_resource).await.assert_status_not_found();
+    }
+
+    #[tokio::test]
+    async fn oauth_authorization_code_flow() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let client = create_oauth_client(&server).await;
+
+        // the browser is sent to the login page, with the request's parameters
+        let response = server
+            .get(OAUTH_AUTHORIZE_RESOURCE)
+            .add_query_params(authorization_params(&client))
+            .await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let location = response.header("location").to_str().unwrap().to_string();
+        assert!(location.starts_with(TEST_LOGIN_URL));
+        assert_eq!(Some(client.id.clone()), query_param(&location, "client_id"));
+        assert_eq!(
+            Some(TEST_CODE_CHALLENGE.to_string()),
+            query_param(&location, "code_challenge")
+        );
+
+        // consent is required the first time
+        let response = server
+            .post(OAUTH_AUTHORIZE_RESOURCE)
+            .json(&authorize_request(&client, None))
+            .await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let error: OAuthErrorResponse = response.json();
+        assert_eq!("consent_required", error.error);
+
+        let response = server
+            .post(OAUTH_AUTHORIZE_RESOURCE)
+            .json(&authorize_request(&client, Some(true)))
+            .await;
+        response.assert_status_ok();
+        let authorized: AuthorizeResponse = response.json();
+        assert!(authorized.redirect_to.starts_with(TEST_REDIRECT_URI));
+        assert_eq!(
+            Some("xyz".to_string()),
+            query_param(&authorized.redirect_to, "state")
+        );
+        let code = query_param(&authorized.redirect_to, "code").unwrap();
+
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&code_token_request(&client, &code))
+            .await;
+        response.assert_status_ok();
+        assert_eq!("no-store", response.header(CACHE_CONTROL));
+        let tokens: TokenResponse = response.json();
+        assert_eq!("Bearer", tokens.token_type);
+        assert_eq!("profile", tokens.scope);
+        assert_eq!(15 * 60, tokens.expires_in);
+        let claims = verify_token(&tokens.access_token, &public_keys(&server).await);
+        assert_eq!(session.account.id, claims.sub);
+        assert_eq!(Some(client.id.clone()), claims.client_id);
+        assert_eq!(Some("profile".to_string()), claims.scope);
+
+        // the code can only be exchanged once
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&code_token_request(&client, &code))
+            .await;
+        response.assert_status_bad_request();
+        let error: OAuthErrorResponse = response.json();
+        assert_eq!("invalid_grant", error.error);
+
+        // consent is remembered, and the refresh token was revoked
+        // when the code was presented again
+        let code = authorize_with_remembered_consent(&server, &client).await;
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&refresh_token_request(
+                &client,
+                tokens.refresh_token.as_deref().unwrap(),
+            ))
+            .await;
+        response.assert_status_bad_request();
+
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&code_token_request(&client, &code))
+            .await;
+        response.assert_status_ok();
+        let tokens: TokenResponse = response.json();
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&refresh_token_request(
+                &client,
+                tokens.refresh_token.as_deref().unwrap(),
+            ))
+            .await;
+        response.assert_status_ok();
+        let refreshed: TokenResponse = response.json();
+        assert_ne!(tokens.refresh_token, refreshed.refresh_token);
+    }
+
+    /// Like [authorize], but without giving consent, which must have been given before.
+    async fn authorize_with_remembered_consent(
+        server: &TestServer,
+        client: &OAuthClientResponse,
+    ) -> String {
+        let response = server
+            .post(OAUTH_AUTHORIZE_RESOURCE)
+            .json(&authorize_request(client, None))
+            .await;
+        response.assert_status_ok();
+        let authorized: AuthorizeResponse = response.json();
+        query_param(&authorized.redirect_to, "code").unwrap()
+    }
+
+    #[tokio::test]
+    async fn oauth_authorize_errors() {
+        let server = test_server();
+        sign_in(&server).await;
+        let client = create_oauth_client(&server).await;
+
+        // errors with the client or redirect URI aren't redirected
+        let response = server
+            .get(OAUTH_AUTHORIZE_RESOURCE)
+            .add_query_params(AuthorizationParams {
+                redirect_uri: Some("https://evil.example.com/callback".to_string()),
+                ..authorization_params(&client)
+            })
+            .await;
+        response.assert_status_bad_request();
+        let error: OAuthErrorResponse = response.json();
+        assert_eq!("invalid_redirect_uri", error.error);
+
+        // other errors are sent to the client
+        let response = server
+            .get(OAUTH_AUTHORIZE_RESOURCE)
+            .add_query_params(AuthorizationParams {
+                code_challenge: None,
+                ..authorization_params(&client)
+            })
+            .await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let location = response.header("location").to_str().unwrap().to_string();
+        assert!(location.starts_with(TEST_REDIRECT_URI));
+        assert_eq!(
+            Some("invalid_request".to_string()),
+            query_param(&location, "error")
+        );
+        assert_eq!(Some("xyz".to_string()), query_param(&location, "state"));
+
+        // denying consent is sent to the client too
+        let response = server
+            .post(OAUTH_AUTHORIZE_RESOURCE)
+            .json(&authorize_request(&client, Some(false)))
+            .await;
+        response.assert_status_ok();
+        let denied: AuthorizeResponse = response.json();
         assert_eq!(
-            error_response.message,
-            "The email address or password was incorrect".to_string()
+            Some("access_denied".to_string()),
+            query_param(&denied.redirect_to, "error")
         );
+
+        // the code verifier must match the challenge
+        let code = authorize(&server, &client).await;
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&TokenRequest {
+                code_verifier: Some("x".repeat(43)),
+                ..code_token_request(&client, &code)
+            })
+            .await;
+        response.assert_status_bad_request();
+        let error: OAuthErrorResponse = response.json();
+        assert_eq!("invalid_grant", error.error);
+
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&TokenRequest {
+                grant_type: Some("password".to_string()),
+                ..code_token_request(&client, &code)
+            })
+            .await;
+        response.assert_status_bad_request();
+        let error: OAuthErrorResponse = response.json();
+        assert_eq!("unsupported_grant_type", error.error);
+
+        // signing in is required
+        let response = server
+            .post(OAUTH_AUTHORIZE_RESOURCE)
+            .json(&AuthorizeRequest {
+                password: Some(Secret::new(Password::new("wrong-password"))),
+                ..authorize_request(&client, Some(true))
+            })
+            .await;
+        response.assert_status_bad_request();
     }
 
     #[tokio::test]
-    async fn authenticate_invalid_email() {
-        let new_account_request = NewAccountRequest::default();
+    async fn oauth_authorize_with_mfa() {
         let server = test_server();
+        let session = sign_in(&server).await;
+        let client = create_oauth_client(&server).await;
+        let totp_resource = ACCOUNT_TOTP_RESOURCE.replace(":id", &session.account.id);
+        let response = server.post(&totp_resource).json(&password_proof()).await;
+        let enrollment: TotpEnrollmentResponse = response.json();
         server
-            .post(ACCOUNTS_RESOURCE)
-            .json(&new_account_request)
+            .put(&totp_resource)
+            .json(&TotpConfirmationRequest {
+                code: totp_code(&enrollment, 0),
+            })
             .await
-            .assert_status(StatusCode::CREATED);
+            .assert_status(StatusCode::NO_CONTENT);
 
-        let authenticate_request = AuthenticateRequest {
-            email: "invalid".to_string(),
-            password: Secret::new(Password::new("invalid")),
+        // consent is asked for before the second factor
+        let response = server
+            .post(OAUTH_AUTHORIZE_RESOURCE)
+            .json(&authorize_request(&client, None))
+            .await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        let response = server
+            .post(OAUTH_AUTHORIZE_RESOURCE)
+            .json(&authorize_request(&client, Some(true)))
+            .await;
+        response.assert_status(StatusCode::ACCEPTED);
+        let challenge: MfaChallengeResponse = response.json();
+
+        let response = server
+            .post(OAUTH_AUTHORIZE_RESOURCE)
+            .json(&AuthorizeRequest {
+                params: authorization_params(&client),
+                email: None,
+                password: None,
+                mfa_challenge: Some(challenge.mfa_challenge),
+                code: Some(totp_code(&enrollment, 1)),
+                recovery_code: None,
+                consent: Some(true),
+            })
+            .await;
+        response.assert_status_ok();
+        let authorized: AuthorizeResponse = response.json();
+        assert!(query_param(&authorized.redirect_to, "code").is_some());
+    }
+
+    #[tokio::test]
+    async fn oauth_grants() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let client = create_oauth_client(&server).await;
+        let code = authorize(&server, &client).await;
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&code_token_request(&client, &code))
+            .await;
+        let tokens: TokenResponse = response.json();
+
+        let grants_resource = ACCOUNT_OAUTH_GRANTS_RESOURCE.replace(":id", &session.account.id);
+        let response = server.get(&grants_resource).await;
+        response.assert_status_ok();
+        let grants: OAuthGrantsResponse = response.json();
+        assert_eq!(1, grants.grants.len());
+        assert_eq!(client.id, grants.grants[0].client_id);
+        assert_eq!(vec!["profile"], grants.grants[0].scopes);
+
+        let grant_resource = ACCOUNT_OAUTH_GRANT_RESOURCE
+            .replace(":id", &session.account.id)
+            .replace(":client_id", &client.id);
+        server
+            .delete(&grant_resource)
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        server
+            .delete(&grant_resource)
+            .await
+            .assert_status_not_found();
+
+        // revoking consent revokes the client's refresh tokens
+        server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&refresh_token_request(
+                &client,
+                tokens.refresh_token.as_deref().unwrap(),
+            ))
+            .await
+            .assert_status_bad_request();
+        let response = server
+            .post(OAUTH_AUTHORIZE_RESOURCE)
+            .json(&authorize_request(&client, None))
+            .await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        let events_resource = ACCOUNT_EVENTS_RESOURCE.replace(":id", &session.account.id);
+        let events: AuditEventsResponse = server.get(&events_resource).await.json();
+        let kinds: Vec<&str> = events
+            .events
+            .iter()
+            .map(|event| event.kind.as_str())
+            .collect();
+        assert!(kinds.contains(&"oauth_consent_granted"));
+        assert!(kinds.contains(&"oauth_consent_revoked"));
+    }
+
+    #[tokio::test]
+    async fn openid_configuration() {
+        let server = test_server();
+        let response = server.get(OPENID_CONFIGURATION_RESOURCE).await;
+        response.assert_status_ok();
+        let configuration: OpenIdConfigurationResponse = response.json();
+        assert_eq!(TEST_ISSUER, configuration.issuer);
+        assert_eq!(
+            "https://id.example.com/oauth/authorize",
+            configuration.authorization_endpoint
+        );
+        assert_eq!(
+            "https://id.example.com/oauth/token",
+            configuration.token_endpoint
+        );
+        assert_eq!(
+            "https://id.example.com/userinfo",
+            configuration.userinfo_endpoint
+        );
+        assert_eq!(
+            "https://id.example.com/.well-known/jwks.json",
+            configuration.jwks_uri
+        );
+        assert_eq!(
+            "https://id.example.com/oauth/introspect",
+            configuration.introspection_endpoint
+        );
+        assert_eq!(
+            "https://id.example.com/oauth/revoke",
+            configuration.revocation_endpoint
+        );
+        assert_eq!(
+            vec!["EdDSA"],
+            configuration.id_token_signing_alg_values_supported
+        );
+        assert!(configuration
+            .scopes_supported
+            .contains(&"openid".to_string()));
+    }
+
+    #[tokio::test]
+    async fn openid_connect_flow() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let client = create_oauth_client(&server).await;
+
+        // the nonce is passed on to the login page
+        let params = AuthorizationParams {
+            scope: Some("openid email".to_string()),
+            nonce: Some("n-0S6_WzA2Mj".to_string()),
+            ..authorization_params(&client)
         };
         let response = server
-            .post(SESSIONS_RESOURCE)
-            .json(&authenticate_request)
+            .get(OAUTH_AUTHORIZE_RESOURCE)
+            .add_query_params(&params)
             .await;
-        response.assert_status_bad_request();
-        let error_response: ApiErrorResponse = response.json();
+        response.assert_status(StatusCode::SEE_OTHER);
+        let location = response.header("location").to_str().unwrap().to_string();
         assert_eq!(
-            error_response.message,
-            "The email address or password was incorrect".to_string()
+            Some("n-0S6_WzA2Mj".to_string()),
+            query_param(&location, "nonce")
         );
+
+        let response = server
+            .post(OAUTH_AUTHORIZE_RESOURCE)
+            .json(&AuthorizeRequest {
+                params,
+                ..authorize_request(&client, Some(true))
+            })
+            .await;
+        response.assert_status_ok();
+        let authorized: AuthorizeResponse = response.json();
+        let code = query_param(&authorized.redirect_to, "code").unwrap();
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&code_token_request(&client, &code))
+            .await;
+        response.assert_status_ok();
+        let tokens: TokenResponse = response.json();
+
+        // the ID token only has the claims covered by the scopes
+        let claims: IdTokenClaims =
+            verify_token_claims(&tokens.id_token.unwrap(), &public_keys(&server).await);
+        assert_eq!(TEST_ISSUER, claims.iss);
+        assert_eq!(client.id, claims.aud);
+        assert_eq!(session.account.id, claims.identity.sub);
+        assert_eq!(Some("n-0S6_WzA2Mj".to_string()), claims.nonce);
+        assert_eq!(Some("test@test.com".to_string()), claims.identity.email);
+        assert_eq!(Some(false), claims.identity.email_verified);
+        assert_eq!(None, claims.identity.name);
+
+        // the userinfo endpoint accepts GET and POST
+        for response in [
+            server
+                .get(USERINFO_RESOURCE)
+                .authorization_bearer(&tokens.access_token)
+                .await,
+            server
+                .post(USERINFO_RESOURCE)
+                .authorization_bearer(&tokens.access_token)
+                .await,
+        ] {
+            response.assert_status_ok();
+            let user_info: UserInfoResponse = response.json();
+            assert_eq!(session.account.id, user_info.sub);
+            assert_eq!(Some("test@test.com".to_string()), user_info.email);
+            assert_eq!(Some(false), user_info.email_verified);
+            assert_eq!(None, user_info.name);
+        }
+
+        // refreshing issues a new ID token, without the nonce
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&refresh_token_request(
+                &client,
+                tokens.refresh_token.as_deref().unwrap(),
+            ))
+            .await;
+        response.assert_status_ok();
+        let tokens: TokenResponse = response.json();
+        let claims: IdTokenClaims =
+            verify_token_claims(&tokens.id_token.unwrap(), &public_keys(&server).await);
+        assert_eq!(session.account.id, claims.identity.sub);
+        assert_eq!(None, claims.nonce);
+
+        // clients that don't request the openid scope don't get an ID token
+        let code = authorize(&server, &client).await;
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&code_token_request(&client, &code))
+            .await;
+        response.assert_status_ok();
+        let tokens: TokenResponse = response.json();
+        assert!(tokens.id_token.is_none());
     }
 
     #[tokio::test]
-    async fn update_credentials() {
-        let new_account_request = NewAccountRequest::default();
+    async fn userinfo_errors() {
         let server = test_server();
-        let new_account_response = server
-            .post(ACCOUNTS_RESOURCE)
-            .json(&new_account_request)
+        let session = sign_in(&server).await;
+        let client = create_oauth_client(&server).await;
+
+        for response in [
+            server.get(USERINFO_RESOURCE).await,
+            server
+                .get(USERINFO_RESOURCE)
+                .authorization_bearer("not-a-token")
+                .await,
+        ] {
+            response.assert_status(StatusCode::UNAUTHORIZED);
+            assert_eq!(
+                r#"Bearer error="invalid_token""#,
+                response.header(WWW_AUTHENTICATE)
+            );
+            let error: OAuthErrorResponse = response.json();
+            assert_eq!("invalid_token", error.error);
+        }
+
+        // session access tokens and client access tokens without
+        // the openid scope can't be used
+        let code = authorize(&server, &client).await;
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&code_token_request(&client, &code))
             .await;
-        new_account_response.assert_status(StatusCode::CREATED);
-        let account: AccountResponse = new_account_response.json();
+        let tokens: TokenResponse = response.json();
+        for access_token in [session.access_token.unwrap().token, tokens.access_token] {
+            let response = server
+                .get(USERINFO_RESOURCE)
+                .authorization_bearer(&access_token)
+                .await;
+            response.assert_status(StatusCode::FORBIDDEN);
+            let error: OAuthErrorResponse = response.json();
+            assert_eq!("insufficient_scope", error.error);
+        }
+    }
 
-        let new_password = "updated-password".to_string();
-        let update_credentials_request = UpdateCredentialsRequest {
-            old: AuthenticateRequest {
-                email: new_account_request.email.clone(),
-                password: new_account_request.password.clone(),
-            },
-            new: NewCredentialsRequest {
-                password: Secret::new(Password::new(&new_password)),
-                email: None,
-            },
+    /// Creates a service account that can request the `orders.read` and `orders.write` scopes.
+    async fn create_service_account(server: &TestServer) -> NewServiceAccountResponse {
+        let response = server
+            .post(SERVICE_ACCOUNTS_RESOURCE)
+            .json(&NewServiceAccountRequest {
+                name: "Order Processor".to_string(),
+                scopes: vec!["orders.read".to_string(), "orders.write".to_string()],
+            })
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        response.json()
+    }
+
+    fn client_credentials_request(
+        client_id: Option<&str>,
+        client_secret: Option<&str>,
+        scope: Option<&str>,
+    ) -> TokenRequest {
+        TokenRequest {
+            grant_type: Some("client_credentials".to_string()),
+            client_id: client_id.map(String::from),
+            client_secret: client_secret.map(String::from),
+            code: None,
+            redirect_uri: None,
+            code_verifier: None,
+            refresh_token: None,
+            scope: scope.map(String::from),
+        }
+    }
+
+    /// Returns an `Authorization` header value using the `Basic` scheme.
+    fn basic_authorization(client_id: &str, client_secret: &str) -> String {
+        format!(
+            "Basic {}",
+            STANDARD.encode(format!("{}:{}", client_id, client_secret))
+        )
+    }
+
+    #[tokio::test]
+    async fn client_credentials_grant() {
+        let server = test_server();
+        let created = create_service_account(&server).await;
+        let service_account = created.service_account;
+        assert!(service_account.id.starts_with("svc_"));
+        assert_eq!("Order Processor", service_account.name);
+
+        // the secret can be sent using HTTP Basic authentication
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .authorization(basic_authorization(
+                &service_account.id,
+                &created.secret.client_secret,
+            ))
+            .form(&client_credentials_request(None, None, None))
+            .await;
+        response.assert_status_ok();
+        assert_eq!("no-store", response.header(CACHE_CONTROL));
+        let tokens: TokenResponse = response.json();
+        assert_eq!("Bearer", tokens.token_type);
+        assert_eq!("orders.read orders.write", tokens.scope);
+        assert!(tokens.refresh_token.is_none());
+        assert!(tokens.id_token.is_none());
+        let claims = verify_token(&tokens.access_token, &public_keys(&server).await);
+        assert_eq!(service_account.id, claims.sub);
+        assert_eq!(Some(service_account.id.clone()), claims.client_id);
+        assert_eq!(None, claims.email);
+
+        // or in the form, and the scope can be narrowed
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&client_credentials_request(
+                Some(&service_account.id),
+                Some(&created.secret.client_secret),
+                Some("orders.read"),
+            ))
+            .await;
+        response.assert_status_ok();
+        let tokens: TokenResponse = response.json();
+        assert_eq!("orders.read", tokens.scope);
+
+        // but not both
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .authorization(basic_authorization(
+                &service_account.id,
+                &created.secret.client_secret,
+            ))
+            .form(&client_credentials_request(
+                None,
+                Some(&created.secret.client_secret),
+                None,
+            ))
+            .await;
+        response.assert_status_bad_request();
+        let error: OAuthErrorResponse = response.json();
+        assert_eq!("invalid_request", error.error);
+
+        // service accounts can't be granted scopes they weren't created with
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&client_credentials_request(
+                Some(&service_account.id),
+                Some(&created.secret.client_secret),
+                Some("orders.delete"),
+            ))
+            .await;
+        response.assert_status_bad_request();
+        let error: OAuthErrorResponse = response.json();
+        assert_eq!("invalid_scope", error.error);
+
+        // wrong secrets and unknown service accounts are rejected
+        for (client_id, client_secret) in [
+            (service_account.id.as_str(), "wrong-secret"),
+            ("svc_unknown", created.secret.client_secret.as_str()),
+        ] {
+            let response = server
+                .post(OAUTH_TOKEN_RESOURCE)
+                .authorization(basic_authorization(client_id, client_secret))
+                .form(&client_credentials_request(None, None, None))
+                .await;
+            response.assert_status_unauthorized();
+            assert_eq!("Basic", response.header(WWW_AUTHENTICATE));
+            let error: OAuthErrorResponse = response.json();
+            assert_eq!("invalid_client", error.error);
+        }
+    }
+
+    #[tokio::test]
+    async fn rotate_service_account_secrets() {
+        let server = test_server();
+        let created = create_service_account(&server).await;
+        let id = created.service_account.id;
+        let secrets_resource = SERVICE_ACCOUNT_SECRETS_RESOURCE.replace(":id", &id);
+        let exchange = |secret: &str| {
+            server
+                .post(OAUTH_TOKEN_RESOURCE)
+                .authorization(basic_authorization(&id, secret))
+                .form(&client_credentials_request(None, None, None))
         };
 
-        let update_response = server
-            .put(&CREDENTIALS_RESOURCE.replace(":id", &account.id))
-            .json(&update_credentials_request)
+        // both secrets work after rotating
+        let response = server.post(&secrets_resource).await;
+        response.assert_status(StatusCode::CREATED);
+        let second: IssuedSecretResponse = response.json();
+        exchange(&created.secret.client_secret)
+            .await
+            .assert_status_ok();
+        exchange(&second.client_secret).await.assert_status_ok();
+
+        // rotating again retires the oldest secret
+        let response = server.post(&secrets_resource).await;
+        response.assert_status(StatusCode::CREATED);
+        let third: IssuedSecretResponse = response.json();
+        exchange(&created.secret.client_secret)
+            .await
+            .assert_status_unauthorized();
+        exchange(&third.client_secret).await.assert_status_ok();
+
+        // secrets are listed without the secret itself
+        let response = server.get(&secrets_resource).await;
+        response.assert_status_ok();
+        let secrets: ServiceAccountSecretsResponse = response.json();
+        let ids: Vec<String> = secrets.secrets.into_iter().map(|s| s.id).collect();
+        assert_eq!(vec![second.id.clone(), third.id.clone()], ids);
+
+        // revoked secrets stop working straight away
+        let secret_resource = SERVICE_ACCOUNT_SECRET_RESOURCE
+            .replace(":id", &id)
+            .replace(":secret_id", &second.id);
+        server
+            .delete(&secret_resource)
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        exchange(&second.client_secret)
+            .await
+            .assert_status_unauthorized();
+        server
+            .delete(&secret_resource)
+            .await
+            .assert_status_not_found();
+
+        // deleting the service account revokes all of its secrets
+        let service_account_resource = SERVICE_ACCOUNT_RESOURCE.replace(":id", &id);
+        server
+            .get(&service_account_resource)
+            .await
+            .assert_status_ok();
+        server
+            .delete(&service_account_resource)
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        server
+            .get(&service_account_resource)
+            .await
+            .assert_status_not_found();
+        exchange(&third.client_secret)
+            .await
+            .assert_status_unauthorized();
+    }
+
+    fn token_hint_request(token: &str, client_id: Option<&str>) -> TokenHintRequest {
+        TokenHintRequest {
+            token: Some(token.to_string()),
+            token_type_hint: None,
+            client_id: client_id.map(String::from),
+            client_secret: None,
+        }
+    }
+
+    /// Introspects the token, authenticating as the service account.
+    async fn introspect(
+        server: &TestServer,
+        service_account: &NewServiceAccountResponse,
+        token: &str,
+    ) -> IntrospectionResponse {
+        let response = server
+            .post(OAUTH_INTROSPECT_RESOURCE)
+            .authorization(basic_authorization(
+                &service_account.service_account.id,
+                &service_account.secret.client_secret,
+            ))
+            .form(&token_hint_request(token, None))
+            .await;
+        response.assert_status_ok();
+        assert_eq!("no-store", response.header(CACHE_CONTROL));
+        response.json()
+    }
+
+    #[tokio::test]
+    async fn introspect_tokens() {
+        let server = test_server();
+        let session = sign_in(&server).await;
+        let client = create_oauth_client(&server).await;
+        let resource_server = create_service_account(&server).await;
+        let code = authorize(&server, &client).await;
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&code_token_request(&client, &code))
             .await;
+        let tokens: TokenResponse = response.json();
 
-        update_response.assert_status_ok();
-        let update_response_account: AccountResponse = update_response.json();
-        assert_eq!(new_account_request.email, update_response_account.email);
+        let introspection = introspect(&server, &resource_server, &tokens.access_token).await;
+        assert!(introspection.active);
+        assert_eq!(Some(session.account.id.clone()), introspection.sub);
+        assert_eq!(Some(client.id.clone()), introspection.client_id);
+        assert_eq!(Some("profile".to_string()), introspection.scope);
+        assert_eq!(Some("Bearer".to_string()), introspection.token_type);
+        assert_eq!(Some(TEST_ISSUER.to_string()), introspection.iss);
+
+        let refresh_token = tokens.refresh_token.unwrap();
+        let introspection = introspect(&server, &resource_server, &refresh_token).await;
+        assert!(introspection.active);
+        assert_eq!(Some(session.account.id.clone()), introspection.sub);
+        assert_eq!(Some("refresh_token".to_string()), introspection.token_type);
+
+        // session and service account access tokens can be introspected too
+        let introspection = introspect(
+            &server,
+            &resource_server,
+            &session.access_token.unwrap().token,
+        )
+        .await;
+        assert!(introspection.active);
+        assert_eq!(None, introspection.client_id);
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&client_credentials_request(
+                Some(&resource_server.service_account.id),
+                Some(&resource_server.secret.client_secret),
+                None,
+            ))
+            .await;
+        let service_tokens: TokenResponse = response.json();
+        let introspection =
+            introspect(&server, &resource_server, &service_tokens.access_token).await;
+        assert!(introspection.active);
         assert_eq!(
-            new_account_request.display_name,
-            update_response_account.display_name
+            Some(resource_server.service_account.id.clone()),
+            introspection.sub
         );
 
-        let authenticate_request = AuthenticateRequest {
-            email: new_account_request.email.clone(),
-            password: Secret::new(Password::new(&new_password)),
+        // inactive tokens only say so
+        let response = server
+            .post(OAUTH_INTROSPECT_RESOURCE)
+            .authorization(basic_authorization(
+                &resource_server.service_account.id,
+                &resource_server.secret.client_secret,
+            ))
+            .form(&token_hint_request("not-a-token", None))
+            .await;
+        response.assert_status_ok();
+        assert_eq!(
+            serde_json::json!({ "active": false }),
+            response.json::<serde_json::Value>()
+        );
+
+        // tokens stop being active when the account is deactivated
+        server
+            .post(&ACCOUNT_DEACTIVATION_RESOURCE.replace(":id", &session.account.id))
+            .await
+            .assert_status_ok();
+        for token in [&tokens.access_token, &refresh_token] {
+            assert!(!introspect(&server, &resource_server, token).await.active);
+        }
+
+        // only service accounts can introspect tokens
+        for request in [
+            server
+                .post(OAUTH_INTROSPECT_RESOURCE)
+                .form(&token_hint_request(&tokens.access_token, Some(&client.id))),
+            server
+                .post(OAUTH_INTROSPECT_RESOURCE)
+                .authorization(basic_authorization(
+                    &resource_server.service_account.id,
+                    "wrong-secret",
+                ))
+                .form(&token_hint_request(&tokens.access_token, None)),
+        ] {
+            let response = request.await;
+            response.assert_status_unauthorized();
+            let error: OAuthErrorResponse = response.json();
+            assert_eq!("invalid_client", error.error);
+        }
+    }
+
+    #[tokio::test]
+    async fn revoke_tokens() {
+        let server = test_server();
+        sign_in(&server).await;
+        let client = create_oauth_client(&server).await;
+        let resource_server = create_service_account(&server).await;
+        let params = AuthorizationParams {
+            scope: Some("openid".to_string()),
+            ..authorization_params(&client)
         };
+        let response = server
+            .post(OAUTH_AUTHORIZE_RESOURCE)
+            .json(&AuthorizeRequest {
+                params,
+                ..authorize_request(&client, Some(true))
+            })
+            .await;
+        let authorized: AuthorizeResponse = response.json();
+        let code = query_param(&authorized.redirect_to, "code").unwrap();
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&code_token_request(&client, &code))
+            .await;
+        let tokens: TokenResponse = response.json();
+        let refresh_token = tokens.refresh_token.unwrap();
 
-        let authenticate_response = server
-            .post(SESSIONS_RESOURCE)
-            .json(&authenticate_request)
+        // other clients can't revoke the tokens, and invalid tokens are ignored
+        for token in [tokens.access_token.as_str(), &refresh_token] {
+            server
+                .post(OAUTH_REVOKE_RESOURCE)
+                .authorization(basic_authorization(
+                    &resource_server.service_account.id,
+                    &resource_server.secret.client_secret,
+                ))
+                .form(&token_hint_request(token, None))
+                .await
+                .assert_status_ok();
+            assert!(introspect(&server, &resource_server, token).await.active);
+        }
+        server
+            .post(OAUTH_REVOKE_RESOURCE)
+            .form(&token_hint_request("not-a-token", Some(&client.id)))
+            .await
+            .assert_status_ok();
+
+        // revoked access tokens are rejected everywhere straight away
+        server
+            .post(OAUTH_REVOKE_RESOURCE)
+            .form(&token_hint_request(&tokens.access_token, Some(&client.id)))
+            .await
+            .assert_status_ok();
+        assert!(
+            !introspect(&server, &resource_server, &tokens.access_token)
+                .await
+                .active
+        );
+        server
+            .get(USERINFO_RESOURCE)
+            .authorization_bearer(&tokens.access_token)
+            .await
+            .assert_status_unauthorized();
+
+        server
+            .post(OAUTH_REVOKE_RESOURCE)
+            .form(&TokenHintRequest {
+                token_type_hint: Some("refresh_token".to_string()),
+                ..token_hint_request(&refresh_token, Some(&client.id))
+            })
+            .await
+            .assert_status_ok();
+        assert!(
+            !introspect(&server, &resource_server, &refresh_token)
+                .await
+                .active
+        );
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&refresh_token_request(&client, &refresh_token))
             .await;
-        authenticate_response.assert_status_ok();
-        let authenticate_response_account: AccountResponse = authenticate_response.json();
-        assert_eq!(
-            authenticate_response_account.email,
-            authenticate_request.email
+        response.assert_status_bad_request();
+        let error: OAuthErrorResponse = response.json();
+        assert_eq!("invalid_grant", error.error);
+
+        // service accounts can revoke their own access tokens
+        let response = server
+            .post(OAUTH_TOKEN_RESOURCE)
+            .form(&client_credentials_request(
+                Some(&resource_server.service_account.id),
+                Some(&resource_server.secret.client_secret),
+                None,
+            ))
+            .await;
+        let service_tokens: TokenResponse = response.json();
+        server
+            .post(OAUTH_REVOKE_RESOURCE)
+            .authorization(basic_authorization(
+                &resource_server.service_account.id,
+                &resource_server.secret.client_secret,
+            ))
+            .form(&token_hint_request(&service_tokens.access_token, None))
+            .await
+            .assert_status_ok();
+        assert!(
+            !introspect(&server, &resource_server, &service_tokens.access_token)
+                .await
+                .active
         );
+
+        // clients must authenticate
+        for request in [
+            server
+                .post(OAUTH_REVOKE_RESOURCE)
+                .form(&token_hint_request(&refresh_token, None)),
+            server
+                .post(OAUTH_REVOKE_RESOURCE)
+                .form(&token_hint_request(&refresh_token, Some("client_unknown"))),
+        ] {
+            let response = request.await;
+            response.assert_status_unauthorized();
+            let error: OAuthErrorResponse = response.json();
+            assert_eq!("invalid_client", error.error);
+        }
     }
 }
@@ -2,13 +2,95 @@ mod apis;
 mod error;
 mod services;
 
+use apis::{
+    extractors::TrustedProxies,
+    rate_limit::{RateLimiter, RateLimits},
+    rest::AppState,
+};
 use axum_prometheus::metrics_exporter_prometheus::PrometheusBuilder;
+use base64::{engine::general_purpose::STANDARD, Engine};
 use dotenvy::dotenv;
 use error::StartupError;
-use services::account::{stores::postgres::PostgresAccountStore, AccountService};
-use std::{env, error::Error, str::FromStr};
+use secrecy::Secret;
+use services::{
+    account::{
+        hashing::{Argon2Hasher, HashingParams},
+        policy::{CharacterClass, PasswordPolicy},
+        stores::postgres::PostgresAccountStore,
+        AccountService,
+    },
+    audit::{stores::postgres::PostgresAuditStore, AuditService},
+    breach::{
+        bloom::BloomFilterChecker, error::BreachCheckError, range_api::RangeApiChecker,
+        BreachedPasswordChecker, DisabledChecker,
+    },
+    credential::{
+        models::RelyingParty, stores::postgres::PostgresCredentialStore, CredentialService,
+    },
+    federation::{
+        models::IdentityProvider, stores::postgres::PostgresLinkedIdentityStore, FederationService,
+    },
+    mfa::{cipher::SecretCipher, stores::postgres::PostgresMfaStore, MfaService},
+    notifier::{directory::DirectoryNotifier, error::NotifierError, smtp::SmtpNotifier, Notifier},
+    oauth::{
+        stores::postgres::{
+            PostgresAuthorizationCodeStore, PostgresClientStore, PostgresGrantStore,
+        },
+        OAuthService,
+    },
+    session::{stores::postgres::PostgresSessionStore, SessionService},
+    token::{
+        models::OPENID_SCOPE,
+        read_signing_key,
+        stores::postgres::{PostgresRevokedTokenStore, PostgresSigningKeyStore},
+        TokenService,
+    },
+    Backends,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::{
+    env, error::Error, net::SocketAddr, path::Path, str::FromStr, sync::Arc, time::Duration,
+};
+use url::Url;
 
 const DEFAULT_POSTGRES_MAX_CONNS: u32 = 5;
+const DEFAULT_MAIL_FROM: &str = "identity-service@localhost";
+const DEFAULT_MAIL_DIRECTORY: &str = "mail";
+const DEFAULT_WEBAUTHN_RP_ID: &str = "localhost";
+const DEFAULT_WEBAUTHN_RP_NAME: &str = "Identity Service";
+const DEFAULT_WEBAUTHN_ORIGIN: &str = "http://localhost:3000";
+const DEFAULT_OAUTH_LOGIN_URL: &str = "http://localhost:3000/login";
+const DEFAULT_OIDC_ISSUER: &str = "http://localhost:3000";
+const DEFAULT_FEDERATION_REDIRECT_URL: &str = "http://localhost:3000/federated-callback";
+const DEFAULT_FEDERATION_SCOPES: &str = "openid email profile";
+/// The rate limits used if RATE_LIMITS isn't set (see [RateLimits] for the format).
+const DEFAULT_RATE_LIMITS: &str = "* ip=300/60; \
+    /sessions ip=20/60 email=5/60; \
+    /accounts ip=10/60 email=5/3600; \
+    /sign-in-codes ip=10/60 email=5/3600; \
+    /password-resets ip=10/60 email=5/3600; \
+    /oauth/authorize ip=20/60 email=5/60";
+/// How often to check whether the token signing key is due for rotation.
+const KEY_ROTATION_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The [Backends] used at runtime.
+struct PostgresBackends;
+
+impl Backends for PostgresBackends {
+    type AccountStore = PostgresAccountStore;
+    type AuditStore = PostgresAuditStore;
+    type BreachedPasswordChecker = Box<dyn BreachedPasswordChecker>;
+    type CredentialStore = PostgresCredentialStore;
+    type LinkedIdentityStore = PostgresLinkedIdentityStore;
+    type MfaStore = PostgresMfaStore;
+    type OAuthClientStore = PostgresClientStore;
+    type AuthorizationCodeStore = PostgresAuthorizationCodeStore;
+    type OAuthGrantStore = PostgresGrantStore;
+    type SessionStore = PostgresSessionStore;
+    type SigningKeyStore = PostgresSigningKeyStore;
+    type RevokedTokenStore = PostgresRevokedTokenStore;
+    type Notifier = Box<dyn Notifier>;
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -23,7 +105,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // http scrape endpoint (defaults to running on port 9000)
     PrometheusBuilder::new().install()?;
 
-    // Connect to database and construct the account service with the appropriate account store
+    // Connect to database and construct the services with the appropriate stores
     let postgres_url = env::var("POSTGRES_URL").map_err(|_| StartupError::PostgresUrlNotSet)?;
     let max_db_conns: u32 = max_db_conns()?;
     tracing::info!(
@@ -31,9 +113,88 @@ async fn main() -> Result<(), Box<dyn Error>> {
         max_db_conns
     );
     tracing::info!("Connecting to the database...");
-    let account_store = PostgresAccountStore::new(&postgres_url, max_db_conns).await?;
-    let account_service = AccountService::new(account_store);
-    let rest_router = apis::rest::router(account_service);
+    let pool = PgPoolOptions::new()
+        .max_connections(max_db_conns)
+        .connect(&postgres_url)
+        .await?;
+    let account_store = PostgresAccountStore::new(pool.clone());
+    let account_service = AccountService::new(
+        account_store,
+        notifier()?,
+        password_policy()?,
+        breached_password_checker()?,
+        password_hasher()?,
+    );
+    let session_store = PostgresSessionStore::new(pool.clone());
+    let session_service = SessionService::new(session_store);
+    let signing_key_store = PostgresSigningKeyStore::new(pool.clone());
+    let issuer = oidc_issuer()?;
+    let revoked_token_store = PostgresRevokedTokenStore::new(pool.clone());
+    let token_service = TokenService::new(signing_key_store, revoked_token_store, &issuer);
+    let audit_store = PostgresAuditStore::new(pool.clone());
+    let audit_service = AuditService::new(audit_store);
+    let mfa_encryption_key =
+        env::var("MFA_ENCRYPTION_KEY").map_err(|_| StartupError::MfaEncryptionKeyNotSet)?;
+    let mfa_store = PostgresMfaStore::new(pool.clone());
+    let mfa_service = MfaService::new(mfa_store, SecretCipher::from_base64(&mfa_encryption_key)?);
+    let credential_store = PostgresCredentialStore::new(pool.clone());
+    let credential_service = CredentialService::new(credential_store, relying_party());
+    let oauth_service = OAuthService::new(
+        PostgresClientStore::new(pool.clone()),
+        PostgresAuthorizationCodeStore::new(pool.clone()),
+        PostgresGrantStore::new(pool.clone()),
+        oauth_login_url()?,
+        password_hasher()?,
+    );
+    let federation_service = FederationService::new(
+        PostgresLinkedIdentityStore::new(pool.clone()),
+        identity_providers()?,
+        &federation_redirect_url()?,
+    )?;
+
+    // Import a token signing key from a file if one was provided.
+    // Otherwise a key will be generated when one is first needed.
+    if let Ok(signing_key_file) = env::var("SIGNING_KEY_FILE") {
+        tracing::info!("Importing token signing key from {}", &signing_key_file);
+        let signing_key = read_signing_key(Path::new(&signing_key_file))?;
+        token_service.import_signing_key(signing_key).await?;
+    }
+
+    // Periodically rotate the token signing keys and forget revoked tokens that
+    // have expired. The key ring and revoked tokens live in the database, so this
+    // can use its own instance of the TokenService.
+    let rotation_service = TokenService::new(
+        PostgresSigningKeyStore::new(pool.clone()),
+        PostgresRevokedTokenStore::new(pool.clone()),
+        &issuer,
+    );
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(KEY_ROTATION_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            match rotation_service.rotate_keys_if_due().await {
+                Ok(true) => tracing::info!("Rotated token signing keys"),
+                Ok(false) => (),
+                Err(e) => tracing::error!("Failed to rotate token signing keys: {}", e),
+            }
+            if let Err(e) = rotation_service.delete_expired_revocations().await {
+                tracing::error!("Failed to delete expired token revocations: {}", e);
+            }
+        }
+    });
+
+    let rest_router = apis::rest::router(AppState::<PostgresBackends, _> {
+        account_service,
+        session_service,
+        token_service,
+        audit_service,
+        mfa_service,
+        credential_service,
+        federation_service,
+        oauth_service,
+        rate_limiter: Arc::new(RateLimiter::new(rate_limits()?)),
+        trusted_proxies: trusted_proxies()?,
+    });
 
     // Listen on requested address
     let addr = env::var("REST_ADDR").map_err(|_| StartupError::RestAddrNotSet)?;
@@ -42,7 +203,12 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .expect("Failed to listen on port");
 
     tracing::info!("Service is listening on {}", &addr);
-    axum::serve(listener, rest_router).await?;
+    // the peer address identifies the client unless it's one of the TRUSTED_PROXIES
+    axum::serve(
+        listener,
+        rest_router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
@@ -62,3 +228,230 @@ fn max_db_conns() -> Result<u32, StartupError> {
             .map_err(|e| StartupError::InvalidPostgresMaxConns(s, e)),
     }
 }
+
+/// Returns the [BreachedPasswordChecker] to use: a [BloomFilterChecker] built from the
+/// Pwned Passwords dataset file in BREACHED_PASSWORDS_FILE if that's set, or else a
+/// [RangeApiChecker] for the API at BREACHED_PASSWORDS_API_URL if that's set.
+/// Otherwise, passwords aren't checked.
+fn breached_password_checker() -> Result<Box<dyn BreachedPasswordChecker>, BreachCheckError> {
+    if let Ok(path) = env::var("BREACHED_PASSWORDS_FILE") {
+        tracing::info!("Loading breached passwords from {}...", &path);
+        return Ok(Box::new(BloomFilterChecker::load(Path::new(&path))?));
+    }
+    match env::var("BREACHED_PASSWORDS_API_URL") {
+        Ok(url) => {
+            tracing::info!("Checking for breached passwords using the API at {}", &url);
+            Ok(Box::new(RangeApiChecker::new(&url)?))
+        }
+        Err(_) => {
+            tracing::warn!(
+                "Neither BREACHED_PASSWORDS_FILE nor BREACHED_PASSWORDS_API_URL is set, \
+                so passwords won't be checked against known breaches"
+            );
+            Ok(Box::new(DisabledChecker))
+        }
+    }
+}
+
+/// Returns the [PasswordPolicy] for new passwords. The defaults can be changed using the
+/// PASSWORD_MIN_LENGTH, PASSWORD_MAX_LENGTH, PASSWORD_REQUIRED_CLASSES (a comma-separated
+/// list of `lowercase`, `uppercase`, `digit` and `symbol`), PASSWORD_REJECT_PERSONAL_INFO,
+/// PASSWORD_MIN_STRENGTH and PASSWORD_HISTORY_SIZE environment variables.
+fn password_policy() -> Result<PasswordPolicy, StartupError> {
+    let mut policy = PasswordPolicy::default();
+    policy.min_length = password_setting("PASSWORD_MIN_LENGTH", policy.min_length)?;
+    policy.max_length = password_setting("PASSWORD_MAX_LENGTH", policy.max_length)?;
+    policy.reject_personal_info =
+        password_setting("PASSWORD_REJECT_PERSONAL_INFO", policy.reject_personal_info)?;
+    policy.min_strength = password_setting("PASSWORD_MIN_STRENGTH", policy.min_strength)?;
+    policy.history_size = password_setting("PASSWORD_HISTORY_SIZE", policy.history_size)?;
+    if let Ok(s) = env::var("PASSWORD_REQUIRED_CLASSES") {
+        policy.required_classes = s
+            .split(',')
+            .map(str::trim)
+            .filter(|class| !class.is_empty())
+            .map(CharacterClass::from_str)
+            .collect::<Result<_, _>>()
+            .map_err(|_| StartupError::InvalidPasswordSetting("PASSWORD_REQUIRED_CLASSES", s))?;
+    }
+    policy.check()?;
+    Ok(policy)
+}
+
+/// Returns an [Argon2Hasher] for passwords, codes and service account secrets. The
+/// default Argon2id parameters can be changed using the PASSWORD_HASH_MEMORY_KIB,
+/// PASSWORD_HASH_ITERATIONS and PASSWORD_HASH_PARALLELISM environment variables.
+/// If PASSWORD_PEPPER is set to a base64-encoded secret, it's mixed into every new
+/// hash. Existing hashes are upgraded to the current parameters and pepper the next
+/// time their account signs in.
+fn password_hasher() -> Result<Argon2Hasher, StartupError> {
+    let mut params = HashingParams::default();
+    params.memory_kib = password_setting("PASSWORD_HASH_MEMORY_KIB", params.memory_kib)?;
+    params.iterations = password_setting("PASSWORD_HASH_ITERATIONS", params.iterations)?;
+    params.parallelism = password_setting("PASSWORD_HASH_PARALLELISM", params.parallelism)?;
+    let pepper = match env::var("PASSWORD_PEPPER") {
+        Err(_) => None,
+        Ok(s) => match STANDARD.decode(s.trim()) {
+            Ok(pepper) if !pepper.is_empty() => Some(Secret::new(pepper)),
+            _ => return Err(StartupError::InvalidPasswordPepper),
+        },
+    };
+    Ok(Argon2Hasher::new(&params, pepper)?)
+}
+
+/// Returns the value of a password policy or hashing environment variable,
+/// or the default if it isn't set.
+fn password_setting<T: FromStr>(name: &'static str, default: T) -> Result<T, StartupError> {
+    match env::var(name) {
+        Err(_) => Ok(default),
+        Ok(s) => s
+            .parse()
+            .map_err(|_| StartupError::InvalidPasswordSetting(name, s)),
+    }
+}
+
+/// Returns the [RateLimits] for the REST API, configured by the RATE_LIMITS environment variable.
+fn rate_limits() -> Result<RateLimits, StartupError> {
+    let rate_limits = env::var("RATE_LIMITS").unwrap_or(DEFAULT_RATE_LIMITS.to_string());
+    rate_limits
+        .parse()
+        .map_err(|e| StartupError::InvalidRateLimits(rate_limits, e))
+}
+
+/// Returns the [TrustedProxies] whose X-Forwarded-For headers are used to find the
+/// client IP, configured by the TRUSTED_PROXIES environment variable.
+fn trusted_proxies() -> Result<TrustedProxies, StartupError> {
+    let trusted_proxies = env::var("TRUSTED_PROXIES").unwrap_or_default();
+    trusted_proxies
+        .parse()
+        .map_err(|e| StartupError::InvalidTrustedProxies(trusted_proxies, e))
+}
+
+/// Returns the [RelyingParty] that passkeys are registered with, configured by the
+/// WEBAUTHN_RP_ID, WEBAUTHN_RP_NAME and WEBAUTHN_ORIGIN environment variables.
+fn relying_party() -> RelyingParty {
+    let relying_party = RelyingParty {
+        id: env::var("WEBAUTHN_RP_ID").unwrap_or(DEFAULT_WEBAUTHN_RP_ID.to_string()),
+        name: env::var("WEBAUTHN_RP_NAME").unwrap_or(DEFAULT_WEBAUTHN_RP_NAME.to_string()),
+        origin: env::var("WEBAUTHN_ORIGIN").unwrap_or(DEFAULT_WEBAUTHN_ORIGIN.to_string()),
+    };
+    tracing::info!(
+        "Passkeys are scoped to the relying party ID '{}' and origin '{}'",
+        relying_party.id,
+        relying_party.origin
+    );
+    relying_party
+}
+
+/// Returns the URL of the login page that OAuth authorization requests are sent
+/// to, configured by the OAUTH_LOGIN_URL environment variable.
+fn oauth_login_url() -> Result<Url, StartupError> {
+    let login_url = env::var("OAUTH_LOGIN_URL").unwrap_or(DEFAULT_OAUTH_LOGIN_URL.to_string());
+    Url::parse(&login_url).map_err(|e| StartupError::InvalidOAuthLoginUrl(login_url, e))
+}
+
+/// Returns the OpenID Connect issuer identifier: the URL this service is reached at,
+/// which appears in ID tokens and prefixes the other endpoints in the discovery
+/// document. This is configured by the OIDC_ISSUER environment variable.
+fn oidc_issuer() -> Result<String, StartupError> {
+    let issuer = env::var("OIDC_ISSUER").unwrap_or(DEFAULT_OIDC_ISSUER.to_string());
+    match Url::parse(&issuer) {
+        // the issuer can't have a query or fragment (OpenID Connect Discovery section 3)
+        Ok(url)
+            if matches!(url.scheme(), "https" | "http")
+                && url.query().is_none()
+                && url.fragment().is_none() =>
+        {
+            Ok(issuer)
+        }
+        _ => Err(StartupError::InvalidOidcIssuer(issuer)),
+    }
+}
+
+/// Returns the upstream OpenID Connect providers account holders can sign in with.
+/// FEDERATION_PROVIDERS is a comma-separated list of provider names, and each provider
+/// is configured by the FEDERATION_<NAME>_ISSUER, FEDERATION_<NAME>_CLIENT_ID,
+/// FEDERATION_<NAME>_CLIENT_SECRET and optional FEDERATION_<NAME>_SCOPES environment
+/// variables (e.g., FEDERATION_GOOGLE_ISSUER for a provider named `google`).
+fn identity_providers() -> Result<Vec<IdentityProvider>, StartupError> {
+    let names = env::var("FEDERATION_PROVIDERS").unwrap_or_default();
+    let mut providers: Vec<IdentityProvider> = Vec::new();
+    for name in names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let name = name.to_ascii_lowercase();
+        if providers.iter().any(|provider| provider.name == name) {
+            return Err(StartupError::InvalidFederationSetting(
+                "FEDERATION_PROVIDERS".to_string(),
+                names,
+            ));
+        }
+        let setting = |suffix: &str| {
+            let variable = format!("FEDERATION_{}_{}", name.to_ascii_uppercase(), suffix);
+            env::var(&variable).map_err(|_| StartupError::FederationSettingNotSet(variable))
+        };
+        let issuer = setting("ISSUER")?;
+        if !Url::parse(&issuer).is_ok_and(|url| matches!(url.scheme(), "https" | "http")) {
+            return Err(StartupError::InvalidFederationSetting(
+                format!("FEDERATION_{}_ISSUER", name.to_ascii_uppercase()),
+                issuer,
+            ));
+        }
+        let scopes = setting("SCOPES").unwrap_or(DEFAULT_FEDERATION_SCOPES.to_string());
+        let scopes: Vec<String> = scopes.split_whitespace().map(String::from).collect();
+        // the ID token, which identifies the account holder, is only issued for `openid`
+        if !scopes.iter().any(|scope| scope == OPENID_SCOPE) {
+            return Err(StartupError::InvalidFederationSetting(
+                format!("FEDERATION_{}_SCOPES", name.to_ascii_uppercase()),
+                scopes.join(" "),
+            ));
+        }
+        tracing::info!("Account holders can sign in with {} ({})", name, issuer);
+        providers.push(IdentityProvider {
+            client_id: setting("CLIENT_ID")?,
+            client_secret: Secret::new(setting("CLIENT_SECRET")?),
+            name,
+            issuer,
+            scopes,
+        });
+    }
+    Ok(providers)
+}
+
+/// Returns the URL that upstream identity providers redirect the browser back to,
+/// configured by the FEDERATION_REDIRECT_URL environment variable. This must be
+/// registered with each provider.
+fn federation_redirect_url() -> Result<String, StartupError> {
+    let redirect_url =
+        env::var("FEDERATION_REDIRECT_URL").unwrap_or(DEFAULT_FEDERATION_REDIRECT_URL.to_string());
+    match Url::parse(&redirect_url) {
+        Ok(_) => Ok(redirect_url),
+        Err(e) => Err(StartupError::InvalidFederationRedirectUrl(redirect_url, e)),
+    }
+}
+
+/// Returns the [Notifier] to use: an [SmtpNotifier] if the SMTP_URL environment
+/// variable is set, or else a [DirectoryNotifier] that writes email to MAIL_DIRECTORY.
+fn notifier() -> Result<Box<dyn Notifier>, NotifierError> {
+    let from = env::var("MAIL_FROM").unwrap_or(DEFAULT_MAIL_FROM.to_string());
+    match env::var("SMTP_URL") {
+        Ok(url) => {
+            tracing::info!("Sending email via SMTP");
+            Ok(Box::new(SmtpNotifier::new(&url, &from)?))
+        }
+        Err(_) => {
+            let directory =
+                env::var("MAIL_DIRECTORY").unwrap_or(DEFAULT_MAIL_DIRECTORY.to_string());
+            tracing::warn!(
+                "SMTP_URL is not set, so email will be written to the '{}' directory instead",
+                directory
+            );
+            Ok(Box::new(DirectoryNotifier::new(
+                Path::new(&directory),
+                &from,
+            )?))
+        }
+    }
+}
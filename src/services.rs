@@ -1,8 +1,71 @@
 #[cfg(test)]
+use std::sync::{Arc, Mutex};
+
+use account::stores::AccountStore;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use audit::stores::AuditStore;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use breach::BreachedPasswordChecker;
+#[cfg(test)]
 use chrono::TimeDelta;
 use chrono::{DateTime, TimeZone, Utc};
+use credential::stores::CredentialStore;
+use federation::stores::LinkedIdentityStore;
+use mfa::stores::MfaStore;
+use notifier::Notifier;
+use oauth::stores::{AuthorizationCodeStore, ClientStore, GrantStore};
+use session::stores::SessionStore;
+use sha2::{Digest, Sha256};
+use token::stores::{RevokedTokenStore, SigningKeyStore};
 
 pub mod account;
+pub mod audit;
+pub mod breach;
+pub mod credential;
+pub mod federation;
+pub mod mfa;
+pub mod notifier;
+pub mod oauth;
+pub mod session;
+pub mod token;
+
+/// Number of random bytes in a token generated by [random_token] (before encoding).
+const RANDOM_TOKEN_BYTES: usize = 32;
+
+/// Bundles the concrete types of the pluggable backends (e.g., stores) used by
+/// the services. Code that holds several services, such as the API layers, can
+/// then be generic over this one type rather than a type parameter per backend.
+pub trait Backends: Send + Sync + 'static {
+    type AccountStore: AccountStore;
+    type AuditStore: AuditStore;
+    type BreachedPasswordChecker: BreachedPasswordChecker;
+    type CredentialStore: CredentialStore;
+    type LinkedIdentityStore: LinkedIdentityStore;
+    type MfaStore: MfaStore;
+    type OAuthClientStore: ClientStore;
+    type AuthorizationCodeStore: AuthorizationCodeStore;
+    type OAuthGrantStore: GrantStore;
+    type SessionStore: SessionStore;
+    type SigningKeyStore: SigningKeyStore;
+    type RevokedTokenStore: RevokedTokenStore;
+    type Notifier: Notifier;
+}
+
+/// Generates an opaque token (e.g., a session ID or password reset token) from
+/// the operating system's cryptographically-secure random number generator.
+/// Unlike the IDs created by [account::id::ID], these must be unguessable,
+/// so they contain no prefix or other structure.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; RANDOM_TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Hashes a token generated by [random_token] for storage. The tokens are
+/// long random values, so a fast hash is sufficient (unlike passwords).
+pub fn hash_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
 
 /// A clock that can return the current time in UTC.
 pub trait Clock<TZ: TimeZone + Send + Sync + 'static>: Send + Sync + 'static {
@@ -10,6 +73,7 @@ pub trait Clock<TZ: TimeZone + Send + Sync + 'static>: Send + Sync + 'static {
 }
 
 /// An implementation of [Clock] that uses the system clock.
+#[derive(Clone)]
 pub struct SystemClock<TZ: TimeZone> {
     timezone: TZ,
 }
@@ -37,32 +101,40 @@ impl<TZ: TimeZone + Send + Sync + 'static> Clock<TZ> for SystemClock<TZ> {
 /// returns the same time value it is tracking internally,
 /// which is initialized when calling [TestClock::new],
 /// and adjusted using either [TestClock::advance] or
-/// [TestClock::rewind].
+/// [TestClock::rewind]. Clones share the same time value,
+/// so a test can keep a clone of the clock it passed to a
+/// service and advance it to simulate the passage of time.
 #[cfg(test)]
+#[derive(Clone)]
 pub struct TestClock<TZ: TimeZone + Send + Sync + 'static> {
-    now: DateTime<TZ>,
+    now: Arc<Mutex<DateTime<TZ>>>,
 }
 
 #[cfg(test)]
 #[allow(dead_code)]
 impl<TZ: TimeZone + Send + Sync + 'static> TestClock<TZ> {
     pub fn new(now: DateTime<TZ>) -> Self {
-        Self { now }
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
     }
 
-    pub fn advance(&mut self, delta: TimeDelta) {
-        self.now += delta;
+    pub fn advance(&self, delta: TimeDelta) {
+        *self.now.lock().unwrap() += delta;
     }
 
-    pub fn rewind(&mut self, delta: TimeDelta) {
-        self.now -= delta;
+    pub fn rewind(&self, delta: TimeDelta) {
+        *self.now.lock().unwrap() -= delta;
     }
 }
 
 #[cfg(test)]
-impl<TZ: TimeZone + Send + Sync + 'static> Clock<TZ> for TestClock<TZ> where <TZ as TimeZone>::Offset: Sync + Send {
+impl<TZ: TimeZone + Send + Sync + 'static> Clock<TZ> for TestClock<TZ>
+where
+    <TZ as TimeZone>::Offset: Sync + Send,
+{
     fn now(&self) -> DateTime<TZ> {
-        self.now.clone()
+        self.now.lock().unwrap().clone()
     }
 }
 
@@ -75,12 +147,22 @@ mod tests {
     #[test]
     fn test_clock() {
         let start = Utc::now();
-        let mut clock = TestClock::new(start);
-        
+        let clock = TestClock::new(start);
+
         assert_eq!(start, clock.now());
         clock.advance(TimeDelta::days(1));
         assert_eq!(1, (clock.now() - start).num_days());
         clock.rewind(TimeDelta::days(1));
         assert_eq!(start, clock.now());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_clock_clones_share_time() {
+        let start = Utc::now();
+        let clock = TestClock::new(start);
+        let clone = clock.clone();
+
+        clock.advance(TimeDelta::hours(1));
+        assert_eq!(start + TimeDelta::hours(1), clone.now());
+    }
+}
@@ -1,36 +1,92 @@
-use argon2::{
-    password_hash::{rand_core::OsRng, SaltString},
-    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
-};
-use chrono::Utc;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{TimeDelta, Utc};
 use error::AccountsServiceError;
+use hashing::Argon2Hasher;
 use id::ID;
-use models::{Account, AccountCredentials, NewAccount, NewAccountCredentials, Password};
-
+use models::{
+    Account, AccountCredentials, AccountStatus, EmailVerificationToken, NewAccount,
+    NewAccountCredentials, Password, PasswordHistoryEntry, PasswordReset, PasswordResetToken,
+    RecoveryCode, RecoveryCodeSummary, SignInRequest, SignInToken,
+};
+use policy::PasswordPolicy;
 use secrecy::{ExposeSecret, Secret};
 use stores::AccountStore;
-use validify::Validate;
+use validify::{Validate, ValidationError, ValidationErrors};
 
-use super::{Clock, SystemClock};
+use super::{
+    audit::models::{AuditContext, AuditEvent, AuditEventKind},
+    breach::BreachedPasswordChecker,
+    hash_token,
+    notifier::Notifier,
+    random_token, Clock, SystemClock,
+};
 
 pub mod error;
+pub mod hashing;
 pub mod id;
 pub mod models;
+pub mod policy;
 pub mod stores;
+pub mod templates;
 
-const BOGUS_ARGON2_HASH: &str =
-    "$argon2id$v=19$m=16,t=2,p=1$ZlpXbUc0MUw5eVBBbmcxcQ$r79YwaBmNT2s6MplBZYgUw";
+/// Failed authentications older than this are forgotten.
+const AUTHENTICATION_FAILURE_WINDOW: TimeDelta = TimeDelta::minutes(15);
+/// Number of failed authentications within the window that locks the account.
+const MAX_AUTHENTICATION_FAILURES: i32 = 5;
+/// How long the first failed authentication delays the next attempt.
+/// Each further failure doubles the delay.
+const AUTHENTICATION_FAILURE_DELAY: TimeDelta = TimeDelta::seconds(1);
+/// How long an account stays locked after too many failed authentications.
+const LOCKOUT_DURATION: TimeDelta = TimeDelta::minutes(15);
+/// How long a password reset token remains valid.
+const PASSWORD_RESET_TOKEN_TTL: TimeDelta = TimeDelta::hours(1);
+/// How long an email verification token remains valid.
+const EMAIL_VERIFICATION_TOKEN_TTL: TimeDelta = TimeDelta::hours(24);
+/// How long a sign-in code and token remain valid.
+const SIGN_IN_TOKEN_TTL: TimeDelta = TimeDelta::minutes(15);
+/// Number of digits in each sign-in code.
+const SIGN_IN_CODE_LENGTH: u32 = 6;
+/// Number of incorrect sign-in codes that can be presented before
+/// the code (and its token) is invalidated.
+const MAX_SIGN_IN_CODE_ATTEMPTS: i32 = 5;
+/// Number of recovery codes in each set.
+const RECOVERY_CODE_COUNT: usize = 10;
+/// Number of characters in each recovery code.
+const RECOVERY_CODE_LENGTH: usize = 10;
+/// The characters used in recovery codes (Crockford's base32), which
+/// excludes letters that are easily confused with digits.
+const RECOVERY_CODE_ALPHABET: &[u8; 32] = b"0123456789abcdefghjkmnpqrstvwxyz";
 
-pub struct AccountService<S: AccountStore, C: Clock<Utc>> {
+pub struct AccountService<S: AccountStore, N: Notifier, P: BreachedPasswordChecker, C: Clock<Utc>> {
     store: S,
+    notifier: N,
+    password_policy: PasswordPolicy,
+    breached_passwords: P,
+    hasher: Argon2Hasher,
     clock: C,
 }
 
-impl<S: AccountStore, C: Clock<Utc>> AccountService<S, C> {
-    /// Constructs a new [AccountService] given the [AccountStore] to use.
-    pub fn new_with_clock(account_store: S, clock: C) -> Self {
+impl<S: AccountStore, N: Notifier, P: BreachedPasswordChecker, C: Clock<Utc>>
+    AccountService<S, N, P, C>
+{
+    /// Constructs a new [AccountService] given the [AccountStore], [Notifier],
+    /// [PasswordPolicy] new passwords must follow, [BreachedPasswordChecker]
+    /// to reject compromised passwords with, [Argon2Hasher] to hash passwords
+    /// and codes with, and [Clock] to use.
+    pub fn new_with_clock(
+        account_store: S,
+        notifier: N,
+        password_policy: PasswordPolicy,
+        breached_passwords: P,
+        hasher: Argon2Hasher,
+        clock: C,
+    ) -> Self {
         Self {
             store: account_store,
+            notifier,
+            password_policy,
+            breached_passwords,
+            hasher,
             clock,
         }
     }
@@ -39,118 +95,1074 @@ impl<S: AccountStore, C: Clock<Utc>> AccountService<S, C> {
     pub async fn create_account(
         &self,
         new_account: &NewAccount,
+        context: &AuditContext,
     ) -> Result<Account, AccountsServiceError> {
         new_account.validate()?;
-        let salt = SaltString::generate(&mut OsRng);
-        let password_hash = Argon2::default()
-            .hash_password(new_account.password.expose_secret().raw().as_bytes(), &salt)?;
+        let personal_info = [
+            Some(new_account.email.as_str()),
+            new_account.display_name.as_deref(),
+        ];
+        self.check_new_password(&new_account.password, &personal_info)
+            .await?;
+        let password_hash = self.hasher.hash(&new_account.password)?;
         let id = ID::Acct.create();
         let account = Account {
             id,
             email: new_account.email.trim().to_string(),
-            password_hash: password_hash.to_string(),
+            password_hash: Some(password_hash),
             display_name: new_account
                 .display_name
                 .clone()
                 .map(|v| v.trim().to_string()),
             created_at: self.clock.now(),
+            email_verified_at: None,
+            pending_email: None,
+            status: AccountStatus::Active,
         };
-        self.store.insert(&account).await?;
+        let event = self.event(
+            &account.id,
+            AuditEventKind::AccountCreated,
+            &context.acting_as(&account.id),
+        );
+        self.store.insert(&account, &event).await?;
+        self.send_email_verification(&account, &account.email)
+            .await?;
         Ok(account)
     }
 
+    /// Returns the [Account] with the given ID. Deleted accounts are not returned.
+    pub async fn get_account(&self, id: &str) -> Result<Account, AccountsServiceError> {
+        self.store
+            .load_by_id(id)
+            .await?
+            .filter(|account| account.status != AccountStatus::Deleted)
+            .ok_or_else(|| AccountsServiceError::AccountNotFound(id.to_string()))
+    }
+
+    /// Deactivates an account, so that it can't be used to sign in until it is
+    /// reactivated. Any outstanding password reset, email verification, and
+    /// sign-in tokens are invalidated. Deactivating an already deactivated account is not an error.
+    pub async fn deactivate(
+        &self,
+        id: &str,
+        context: &AuditContext,
+    ) -> Result<Account, AccountsServiceError> {
+        let account = self.get_account(id).await?;
+        let event = self.event(id, AuditEventKind::Deactivated, context);
+        self.store
+            .update_status(id, AccountStatus::Deactivated, &event)
+            .await?;
+        self.delete_tokens(id, &account.email).await?;
+        Ok(Account {
+            status: AccountStatus::Deactivated,
+            ..account
+        })
+    }
+
+    /// Reactivates a deactivated account. Reactivating an already
+    /// active account is not an error, but deleted accounts can't be reactivated.
+    pub async fn reactivate(
+        &self,
+        id: &str,
+        context: &AuditContext,
+    ) -> Result<Account, AccountsServiceError> {
+        let account = self.get_account(id).await?;
+        let event = self.event(id, AuditEventKind::Reactivated, context);
+        self.store
+            .update_status(id, AccountStatus::Active, &event)
+            .await?;
+        Ok(Account {
+            status: AccountStatus::Active,
+            ..account
+        })
+    }
+
+    /// Unlocks an account that was locked after too many failed authentications,
+    /// and forgets those failures. Unlocking an account that isn't locked is not
+    /// an error.
+    pub async fn unlock(
+        &self,
+        id: &str,
+        context: &AuditContext,
+    ) -> Result<(), AccountsServiceError> {
+        self.get_account(id).await?;
+        self.store.delete_authentication_failures(id).await?;
+        let event = self.event(id, AuditEventKind::Unlocked, context);
+        self.store.insert_event(&event).await?;
+        Ok(())
+    }
+
+    /// Deletes an account. The account record is kept with a status of
+    /// [AccountStatus::Deleted] so that its ID is never reused, but all
+    /// personal data (email addresses, display name, and password hash)
+    /// is erased, along with any outstanding tokens and the IP addresses and
+    /// user agents in its audit events.
+    pub async fn delete(
+        &self,
+        id: &str,
+        context: &AuditContext,
+    ) -> Result<(), AccountsServiceError> {
+        let account = self.get_account(id).await?;
+        let scrubbed_account = Account {
+            // emails must be unique, so use one that can never be delivered
+            email: format!("{}@deleted.invalid", account.id),
+            password_hash: None,
+            display_name: None,
+            email_verified_at: None,
+            pending_email: None,
+            status: AccountStatus::Deleted,
+            ..account
+        };
+        let event = self.event(id, AuditEventKind::Deleted, context);
+        self.store.update(&scrubbed_account, &event).await?;
+        self.store.erase_event_details(id).await?;
+        self.store.delete_recovery_codes(id).await?;
+        self.store.delete_authentication_failures(id).await?;
+        self.store.delete_password_history(id).await?;
+        self.delete_tokens(id, &account.email).await
+    }
+
     /// Authenticates a set of credentials against a stored account,
-    /// and returns the [Account] if authentication is successful.
+    /// and returns the [Account] if authentication is successful. If the
+    /// credentials include a recovery code, it must also match one of the
+    /// account's unused recovery codes, which is then used up.
     pub async fn authenticate(
         &self,
         credentials: &AccountCredentials,
+        context: &AuditContext,
+    ) -> Result<Account, AccountsServiceError> {
+        let account = self.check_credentials(credentials, context).await?;
+        if let Some(recovery_code) = &credentials.recovery_code {
+            if !self
+                .use_recovery_code(&account, recovery_code, context)
+                .await?
+            {
+                let event = self.event(&account.id, AuditEventKind::AuthenticationFailed, context);
+                self.store.insert_event(&event).await?;
+                self.record_authentication_failure(&account.id, context)
+                    .await?;
+                return Err(AccountsServiceError::InvalidCredentials);
+            }
+        }
+        let account = self
+            .rehash_outdated_password(account, &credentials.password, context)
+            .await?;
+        let event = self.event(
+            &account.id,
+            AuditEventKind::Authenticated,
+            &context.acting_as(&account.id),
+        );
+        self.store.insert_event(&event).await?;
+        Ok(account)
+    }
+
+    /// Checks the account's current password before a change that would let
+    /// someone else take over the account (e.g., disabling MFA). As with
+    /// signing in, failures count towards locking the account.
+    pub async fn verify_password(
+        &self,
+        id: &str,
+        password: &Secret<Password>,
+        context: &AuditContext,
+    ) -> Result<Account, AccountsServiceError> {
+        let account = self.get_account(id).await?;
+        let credentials = AccountCredentials {
+            email: account.email,
+            password: password.clone(),
+            recovery_code: None,
+        };
+        self.check_credentials(&credentials, context).await
+    }
+
+    /// Checks that the account is active and not locked after failed
+    /// authentications, before a second factor is checked (see
+    /// [AccountService::record_failed_authentication]).
+    pub async fn check_can_authenticate(&self, id: &str) -> Result<(), AccountsServiceError> {
+        let account = self.get_account(id).await?;
+        if !account.is_active() {
+            return Err(AccountsServiceError::AccountNotActive);
+        }
+        let failures = self.store.load_authentication_failures(id).await?;
+        if failures.is_some_and(|failures| self.clock.now() < failures.locked_until) {
+            return Err(AccountsServiceError::AccountLocked);
+        }
+        Ok(())
+    }
+
+    /// Records a second factor that didn't match (e.g., a code from the
+    /// authenticator app presented during sign-in, or before disabling MFA),
+    /// which counts towards locking the account like a wrong password does.
+    pub async fn record_failed_authentication(
+        &self,
+        id: &str,
+        context: &AuditContext,
+    ) -> Result<(), AccountsServiceError> {
+        let event = self.event(id, AuditEventKind::AuthenticationFailed, context);
+        self.store.insert_event(&event).await?;
+        self.record_authentication_failure(id, context).await
+    }
+
+    /// Forgets the account's failed authentications once it has completely
+    /// signed in. This isn't done when only the password is checked, since
+    /// that would let someone who knows it keep guessing the second factor.
+    pub async fn clear_authentication_failures(
+        &self,
+        id: &str,
+    ) -> Result<(), AccountsServiceError> {
+        self.store.delete_authentication_failures(id).await?;
+        Ok(())
+    }
+
+    /// Replaces an account's password hash if it was made with outdated
+    /// parameters (or without the current pepper), now that the password
+    /// is known to be correct.
+    async fn rehash_outdated_password(
+        &self,
+        account: Account,
+        password: &Secret<Password>,
+        context: &AuditContext,
+    ) -> Result<Account, AccountsServiceError> {
+        match &account.password_hash {
+            Some(password_hash) if self.hasher.needs_rehash(password_hash) => {
+                let rehashed_account = Account {
+                    password_hash: Some(self.hasher.hash(password)?),
+                    ..account
+                };
+                let event = self.event(
+                    &rehashed_account.id,
+                    AuditEventKind::PasswordRehashed,
+                    &context.acting_as(&rehashed_account.id),
+                );
+                self.store.update(&rehashed_account, &event).await?;
+                Ok(rehashed_account)
+            }
+            _ => Ok(account),
+        }
+    }
+
+    /// Checks a set of credentials against a stored account, recording an
+    /// [AuditEventKind::AuthenticationFailed] event if they don't match.
+    /// While the account is locked after earlier failures, the password
+    /// isn't checked at all.
+    async fn check_credentials(
+        &self,
+        credentials: &AccountCredentials,
+        context: &AuditContext,
     ) -> Result<Account, AccountsServiceError> {
         match self.store.load_by_email(&credentials.email).await? {
             None => {
-                // To mitigate a timing attack, verify a bogus password but
-                // ignore the results so that the API takes about the same duration
-                // as it would if the email address was found.
-                let _ = Self::validate_password(
-                    &Secret::new(Password::new("bogus")),
-                    BOGUS_ARGON2_HASH,
-                );
+                self.hasher.verify_bogus();
                 Err(AccountsServiceError::InvalidCredentials)
             }
             Some(account) => {
-                match Self::validate_password(&credentials.password, &account.password_hash) {
-                    Err(_) => Err(AccountsServiceError::InvalidCredentials),
-                    Ok(_) => Ok(account),
+                let failures = self.store.load_authentication_failures(&account.id).await?;
+                if failures
+                    .as_ref()
+                    .is_some_and(|failures| self.clock.now() < failures.locked_until)
+                {
+                    self.hasher.verify_bogus();
+                    let event =
+                        self.event(&account.id, AuditEventKind::AuthenticationFailed, context);
+                    self.store.insert_event(&event).await?;
+                    return Err(AccountsServiceError::AccountLocked);
+                }
+
+                let verified = match &account.password_hash {
+                    Some(password_hash) => self
+                        .hasher
+                        .verify(&credentials.password, password_hash)
+                        .is_ok(),
+                    // accounts created by a passwordless sign-in may not have a password
+                    None => {
+                        self.hasher.verify_bogus();
+                        false
+                    }
+                };
+                let result = match verified {
+                    false => Err(AccountsServiceError::InvalidCredentials),
+                    true if !account.is_active() => Err(AccountsServiceError::AccountNotActive),
+                    // failures are only forgotten once the second factor (if
+                    // any) is checked too (see [Self::clear_authentication_failures])
+                    true => return Ok(account),
+                };
+                let event = self.event(&account.id, AuditEventKind::AuthenticationFailed, context);
+                self.store.insert_event(&event).await?;
+                if !verified {
+                    self.record_authentication_failure(&account.id, context)
+                        .await?;
                 }
+                result
             }
         }
     }
 
+    /// Counts a failed authentication against the account. Each failure delays
+    /// the next attempt for twice as long as the previous one, until
+    /// [MAX_AUTHENTICATION_FAILURES] within [AUTHENTICATION_FAILURE_WINDOW]
+    /// lock the account for [LOCKOUT_DURATION].
+    async fn record_authentication_failure(
+        &self,
+        account_id: &str,
+        context: &AuditContext,
+    ) -> Result<(), AccountsServiceError> {
+        let now = self.clock.now();
+        // failures from before the window, or before a lockout, are forgotten
+        let failures = self
+            .store
+            .increment_authentication_failures(
+                account_id,
+                now,
+                now - AUTHENTICATION_FAILURE_WINDOW,
+                MAX_AUTHENTICATION_FAILURES,
+            )
+            .await?;
+        let locked = failures.failed_attempts >= MAX_AUTHENTICATION_FAILURES;
+        let delay = if locked {
+            LOCKOUT_DURATION
+        } else {
+            AUTHENTICATION_FAILURE_DELAY * 2i32.pow(failures.failed_attempts as u32 - 1)
+        };
+        self.store.extend_lockout(account_id, now + delay).await?;
+        if locked {
+            let event = self.event(account_id, AuditEventKind::Locked, context);
+            self.store.insert_event(&event).await?;
+        }
+        Ok(())
+    }
+
+    /// Updates the account's password, and optionally its email address.
+    /// A new email address is held as the account's `pending_email` and a
+    /// verification token is sent to it: it replaces the current email
+    /// address only once verified (see [AccountService::verify_email]).
     pub async fn update_credentials(
         &self,
         id: &str,
         current_credentials: &AccountCredentials,
         new_credentials: &NewAccountCredentials,
+        context: &AuditContext,
     ) -> Result<Account, AccountsServiceError> {
-        let account = self.authenticate(current_credentials).await?;
+        new_credentials.validate()?;
+        let account = self.check_credentials(current_credentials, context).await?;
         if id != account.id {
             return Err(AccountsServiceError::InvalidCredentials);
         }
-        let salt = SaltString::generate(&mut OsRng);
-        let new_password_hash = Argon2::default().hash_password(
-            new_credentials.password.expose_secret().raw().as_bytes(),
-            &salt,
-        )?;
+        let personal_info = [
+            Some(account.email.as_str()),
+            new_credentials.email.as_deref(),
+            account.display_name.as_deref(),
+        ];
+        self.check_new_password(&new_credentials.password, &personal_info)
+            .await?;
+        let rotated = self
+            .check_password_rotation(&account, &new_credentials.password)
+            .await?;
 
+        let new_email = new_credentials.email.as_deref().map(str::trim);
+        let pending_email = match new_email {
+            None => account.pending_email.clone(),
+            Some(email) if email == account.email => None,
+            Some(email) => {
+                if self.store.load_by_email(email).await?.is_some() {
+                    return Err(AccountsServiceError::EmailAlreadyExists(email.to_string()));
+                }
+                Some(email.to_string())
+            }
+        };
+        let previous_hash = account.password_hash.clone().filter(|_| rotated);
         let updated_account = Account {
-            password_hash: new_password_hash.to_string(),
-            email: new_credentials
-                .email
-                .clone()
-                .unwrap_or(account.email.clone()),
+            password_hash: Some(self.hasher.hash(&new_credentials.password)?),
+            pending_email,
+            ..account
+        };
+
+        let event = self.event(
+            id,
+            AuditEventKind::CredentialsUpdated,
+            &context.acting_as(id),
+        );
+        self.store.update(&updated_account, &event).await?;
+        self.record_password_history(id, previous_hash).await?;
+        // any outstanding password reset tokens were issued for the old credentials
+        self.store
+            .delete_password_reset_tokens(&updated_account.id)
+            .await?;
+        if let Some(email) = new_email.filter(|email| *email != updated_account.email) {
+            self.send_email_verification(&updated_account, email)
+                .await?;
+        }
+        Ok(updated_account)
+    }
+
+    /// Sends a new email verification token for the account with the given ID.
+    /// If the account holder asked to change their email address, the token
+    /// is sent to the new address, otherwise it is sent to the current one.
+    pub async fn request_email_verification(&self, id: &str) -> Result<(), AccountsServiceError> {
+        let account = self.get_account(id).await?;
+        if !account.is_active() {
+            return Err(AccountsServiceError::AccountNotActive);
+        }
+        let email = match &account.pending_email {
+            Some(pending_email) => pending_email,
+            None if account.email_verified() => {
+                return Err(AccountsServiceError::EmailAlreadyVerified(account.email));
+            }
+            None => &account.email,
+        };
+        self.send_email_verification(&account, email).await
+    }
+
+    /// Verifies an email address using an email verification token, and returns
+    /// the updated [Account]. If the token was sent to the account's pending email
+    /// address, that address replaces the current one. The account's outstanding
+    /// email verification tokens are then invalidated.
+    pub async fn verify_email(
+        &self,
+        token: &str,
+        context: &AuditContext,
+    ) -> Result<Account, AccountsServiceError> {
+        let token = self
+            .store
+            .load_email_verification_token(&hash_token(token))
+            .await?
+            .ok_or(AccountsServiceError::InvalidEmailVerificationToken)?;
+        let now = self.clock.now();
+        if now >= token.expires_at {
+            return Err(AccountsServiceError::InvalidEmailVerificationToken);
+        }
+
+        let account = self
+            .store
+            .load_by_id(&token.account_id)
+            .await?
+            .filter(Account::is_active)
+            .ok_or(AccountsServiceError::InvalidEmailVerificationToken)?;
+        let (updated_account, kind) = if account.pending_email.as_ref() == Some(&token.email) {
+            let updated_account = Account {
+                email: token.email,
+                email_verified_at: Some(now),
+                pending_email: None,
+                ..account
+            };
+            (updated_account, AuditEventKind::EmailChanged)
+        } else if account.email == token.email {
+            let updated_account = Account {
+                email_verified_at: account.email_verified_at.or(Some(now)),
+                ..account
+            };
+            (updated_account, AuditEventKind::EmailVerified)
+        } else {
+            // the token was sent to an address the account no longer uses
+            return Err(AccountsServiceError::InvalidEmailVerificationToken);
+        };
+
+        let event = self.event(
+            &updated_account.id,
+            kind,
+            &context.acting_as(&updated_account.id),
+        );
+        self.store.update(&updated_account, &event).await?;
+        self.store
+            .delete_email_verification_tokens(&updated_account.id)
+            .await?;
+        Ok(updated_account)
+    }
+
+    /// Starts the password reset flow for the account with the given email
+    /// address, if there is one, by sending it a single-use reset token. To
+    /// avoid revealing which email addresses are registered, this returns
+    /// the same result whether or not the account exists.
+    pub async fn request_password_reset(&self, email: &str) -> Result<(), AccountsServiceError> {
+        let Some(account) = self
+            .store
+            .load_by_email(email.trim())
+            .await?
+            .filter(Account::is_active)
+        else {
+            return Ok(());
+        };
+
+        let token = random_token();
+        let now = self.clock.now();
+        self.store
+            .insert_password_reset_token(&PasswordResetToken {
+                token_hash: hash_token(&token),
+                account_id: account.id.clone(),
+                created_at: now,
+                expires_at: now + PASSWORD_RESET_TOKEN_TTL,
+            })
+            .await?;
+
+        let message = templates::password_reset(&account, &token, PASSWORD_RESET_TOKEN_TTL);
+        // A delivery failure is logged rather than returned,
+        // as returning it would reveal that the account exists.
+        if let Err(err) = self.notifier.send(&message).await {
+            tracing::error!("Failed to send password reset message: {}", err);
+        }
+        Ok(())
+    }
+
+    /// Sets a new password using a password reset token, and returns the updated
+    /// [Account]. The token is then invalidated, along with any others issued
+    /// for the same account, and the account is unlocked if it was locked.
+    pub async fn reset_password(
+        &self,
+        password_reset: &PasswordReset,
+        context: &AuditContext,
+    ) -> Result<Account, AccountsServiceError> {
+        password_reset.validate()?;
+        let token = self
+            .store
+            .load_password_reset_token(&hash_token(&password_reset.token))
+            .await?
+            .ok_or(AccountsServiceError::InvalidPasswordResetToken)?;
+        if self.clock.now() >= token.expires_at {
+            return Err(AccountsServiceError::InvalidPasswordResetToken);
+        }
+
+        let account = self
+            .store
+            .load_by_id(&token.account_id)
+            .await?
+            .filter(Account::is_active)
+            .ok_or(AccountsServiceError::InvalidPasswordResetToken)?;
+        let personal_info = [
+            Some(account.email.as_str()),
+            account.display_name.as_deref(),
+        ];
+        self.check_new_password(&password_reset.password, &personal_info)
+            .await?;
+        let rotated = self
+            .check_password_rotation(&account, &password_reset.password)
+            .await?;
+        let previous_hash = account.password_hash.clone().filter(|_| rotated);
+        let updated_account = Account {
+            password_hash: Some(self.hasher.hash(&password_reset.password)?),
             ..account
         };
 
-        self.store.update(&updated_account).await?;
+        let event = self.event(
+            &updated_account.id,
+            AuditEventKind::PasswordReset,
+            &context.acting_as(&updated_account.id),
+        );
+        self.store.update(&updated_account, &event).await?;
+        self.record_password_history(&updated_account.id, previous_hash)
+            .await?;
+        self.store
+            .delete_password_reset_tokens(&updated_account.id)
+            .await?;
+        self.store
+            .delete_authentication_failures(&updated_account.id)
+            .await?;
         Ok(updated_account)
     }
 
-    fn validate_password(
+    /// Starts a passwordless sign-in by sending a short sign-in code and a
+    /// single-use token to the email address. Either can then be exchanged for
+    /// the account (see [AccountService::sign_in_with_code] and
+    /// [AccountService::sign_in_with_token]), and if the address isn't registered
+    /// yet, an account without a password is created for it at that point. So
+    /// unless the account is deactivated (in which case nothing is sent), this
+    /// does the same work whether or not the account exists.
+    pub async fn request_sign_in(
+        &self,
+        sign_in_request: &SignInRequest,
+    ) -> Result<(), AccountsServiceError> {
+        sign_in_request.validate()?;
+        let email = sign_in_request.email.trim();
+        let account = self.store.load_by_email(email).await?;
+        if account.as_ref().is_some_and(|account| !account.is_active()) {
+            return Ok(());
+        }
+
+        let code = random_sign_in_code();
+        let token = random_token();
+        let now = self.clock.now();
+        self.store
+            .replace_sign_in_token(&SignInToken {
+                token_hash: hash_token(&token),
+                email: email.to_string(),
+                code_hash: self.hasher.hash(&Secret::new(Password::new(&code)))?,
+                created_at: now,
+                expires_at: now + SIGN_IN_TOKEN_TTL,
+                failed_attempts: 0,
+            })
+            .await?;
+
+        let message = templates::sign_in(account.as_ref(), email, &code, &token, SIGN_IN_TOKEN_TTL);
+        if let Err(err) = self.notifier.send(&message).await {
+            tracing::error!("Failed to send sign-in message: {}", err);
+        }
+        Ok(())
+    }
+
+    /// Exchanges a sign-in token sent by [AccountService::request_sign_in] for
+    /// the account it was sent to, in the same way [AccountService::authenticate]
+    /// exchanges a password.
+    pub async fn sign_in_with_token(
+        &self,
+        token: &str,
+        context: &AuditContext,
+    ) -> Result<Account, AccountsServiceError> {
+        let token = self
+            .store
+            .load_sign_in_token(&hash_token(token))
+            .await?
+            .filter(|token| self.clock.now() < token.expires_at)
+            .ok_or(AccountsServiceError::InvalidSignInToken)?;
+        self.complete_sign_in(&token, context).await
+    }
+
+    /// Exchanges a sign-in code sent by [AccountService::request_sign_in] for
+    /// the account it was sent to. Codes are short enough to guess, so after
+    /// [MAX_SIGN_IN_CODE_ATTEMPTS] incorrect ones, the code and its token are
+    /// invalidated.
+    pub async fn sign_in_with_code(
+        &self,
+        email: &str,
+        code: &Secret<Password>,
+        context: &AuditContext,
+    ) -> Result<Account, AccountsServiceError> {
+        let email = email.trim();
+        let Some(token) = self.store.load_sign_in_token_by_email(email).await? else {
+            self.hasher.verify_bogus();
+            return Err(AccountsServiceError::InvalidSignInToken);
+        };
+        let account = self.store.load_by_email(email).await?;
+        let locked = match &account {
+            Some(account) => self
+                .store
+                .load_authentication_failures(&account.id)
+                .await?
+                .is_some_and(|failures| self.clock.now() < failures.locked_until),
+            None => false,
+        };
+        // like passwords, codes aren't checked at all while the account is
+        // waiting out a failed authentication, or once they've expired
+        if locked || self.clock.now() >= token.expires_at {
+            self.hasher.verify_bogus();
+            if let Some(account) = &account {
+                let event = self.event(&account.id, AuditEventKind::AuthenticationFailed, context);
+                self.store.insert_event(&event).await?;
+            }
+            return Err(AccountsServiceError::InvalidSignInToken);
+        }
+        if self.hasher.verify(code, &token.code_hash).is_err() {
+            let failed_attempts = self
+                .store
+                .increment_sign_in_token_attempts(&token.token_hash)
+                .await?;
+            if failed_attempts.is_none_or(|attempts| attempts >= MAX_SIGN_IN_CODE_ATTEMPTS) {
+                self.store.delete_sign_in_tokens(email).await?;
+            }
+            if let Some(account) = &account {
+                self.record_failed_authentication(&account.id, context)
+                    .await?;
+            }
+            return Err(AccountsServiceError::InvalidSignInToken);
+        }
+        self.complete_sign_in(&token, context).await
+    }
+
+    /// Finishes a passwordless sign-in with a valid sign-in token: the token is
+    /// invalidated, and the account it was sent to is returned. If there is no
+    /// such account, one without a password is created. Either way, the account's
+    /// email address is verified, as the token was delivered to it.
+    async fn complete_sign_in(
+        &self,
+        token: &SignInToken,
+        context: &AuditContext,
+    ) -> Result<Account, AccountsServiceError> {
+        self.store.delete_sign_in_tokens(&token.email).await?;
+        let now = self.clock.now();
+        let account = match self.store.load_by_email(&token.email).await? {
+            Some(account) if !account.is_active() => {
+                let event = self.event(&account.id, AuditEventKind::AuthenticationFailed, context);
+                self.store.insert_event(&event).await?;
+                return Err(AccountsServiceError::AccountNotActive);
+            }
+            Some(account) if !account.email_verified() => {
+                let verified_account = Account {
+                    email_verified_at: Some(now),
+                    ..account
+                };
+                let event = self.event(
+                    &verified_account.id,
+                    AuditEventKind::EmailVerified,
+                    &context.acting_as(&verified_account.id),
+                );
+                self.store.update(&verified_account, &event).await?;
+                verified_account
+            }
+            Some(account) => account,
+            None => {
+                let account = Account {
+                    id: ID::Acct.create(),
+                    email: token.email.clone(),
+                    password_hash: None,
+                    display_name: None,
+                    created_at: now,
+                    email_verified_at: Some(now),
+                    pending_email: None,
+                    status: AccountStatus::Active,
+                };
+                let event = self.event(
+                    &account.id,
+                    AuditEventKind::AccountCreated,
+                    &context.acting_as(&account.id),
+                );
+                self.store.insert(&account, &event).await?;
+                account
+            }
+        };
+
+        let event = self.event(
+            &account.id,
+            AuditEventKind::Authenticated,
+            &context.acting_as(&account.id),
+        );
+        self.store.insert_event(&event).await?;
+        Ok(account)
+    }
+
+    /// Generates a new set of recovery codes for the account, replacing any
+    /// existing ones. Only hashes of the codes are stored, so this is the only
+    /// time the codes are available to show to the account holder.
+    pub async fn generate_recovery_codes(
+        &self,
+        id: &str,
+        context: &AuditContext,
+    ) -> Result<Vec<String>, AccountsServiceError> {
+        let account = self.get_account(id).await?;
+        let now = self.clock.now();
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+            .map(|_| random_recovery_code())
+            .collect();
+        let recovery_codes = codes
+            .iter()
+            .map(|code| {
+                Ok(RecoveryCode {
+                    id: ID::Rcode.create(),
+                    account_id: account.id.clone(),
+                    code_hash: self
+                        .hasher
+                        .hash(&Secret::new(Password::new(&normalize_recovery_code(code))))?,
+                    created_at: now,
+                    used_at: None,
+                })
+            })
+            .collect::<Result<Vec<RecoveryCode>, AccountsServiceError>>()?;
+
+        let event = self.event(id, AuditEventKind::RecoveryCodesGenerated, context);
+        self.store
+            .replace_recovery_codes(id, &recovery_codes, &event)
+            .await?;
+        Ok(codes)
+    }
+
+    /// Returns a summary of the account's current set of recovery codes.
+    pub async fn recovery_code_summary(
+        &self,
+        id: &str,
+    ) -> Result<RecoveryCodeSummary, AccountsServiceError> {
+        self.get_account(id).await?;
+        let codes = self.store.load_recovery_codes(id).await?;
+        Ok(RecoveryCodeSummary {
+            remaining: codes.iter().filter(|code| code.used_at.is_none()).count(),
+            generated_at: codes.iter().map(|code| code.created_at).max(),
+        })
+    }
+
+    /// Uses up one of the account's recovery codes as a second factor
+    /// (i.e., when the account's password has already been checked). Wrong
+    /// codes count towards locking the account, like wrong passwords do.
+    pub async fn redeem_recovery_code(
+        &self,
+        id: &str,
+        recovery_code: &Secret<Password>,
+        context: &AuditContext,
+    ) -> Result<(), AccountsServiceError> {
+        self.check_can_authenticate(id).await?;
+        let account = self.get_account(id).await?;
+        if !self
+            .use_recovery_code(&account, recovery_code, context)
+            .await?
+        {
+            self.record_failed_authentication(id, context).await?;
+            return Err(AccountsServiceError::InvalidRecoveryCode);
+        }
+        Ok(())
+    }
+
+    /// Marks the account's unused recovery code matching the given one as
+    /// used, recording a [AuditEventKind::RecoveryCodeUsed] event. Returns
+    /// false if none match.
+    async fn use_recovery_code(
+        &self,
+        account: &Account,
+        recovery_code: &Secret<Password>,
+        context: &AuditContext,
+    ) -> Result<bool, AccountsServiceError> {
+        let recovery_code = Secret::new(Password::new(&normalize_recovery_code(
+            recovery_code.expose_secret().raw(),
+        )));
+        let unused_codes = self
+            .store
+            .load_recovery_codes(&account.id)
+            .await?
+            .into_iter()
+            .filter(|code| code.used_at.is_none());
+        for code in unused_codes {
+            if self.hasher.verify(&recovery_code, &code.code_hash).is_ok() {
+                let event = self.event(
+                    &account.id,
+                    AuditEventKind::RecoveryCodeUsed,
+                    &context.acting_as(&account.id),
+                );
+                return Ok(self
+                    .store
+                    .mark_recovery_code_used(&code.id, self.clock.now(), &event)
+                    .await?);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Stores a new email verification token for the given email address
+    /// of the account, and sends it to that address. As with password resets,
+    /// delivery failures are logged rather than returned.
+    async fn send_email_verification(
+        &self,
+        account: &Account,
+        email: &str,
+    ) -> Result<(), AccountsServiceError> {
+        let token = random_token();
+        let now = self.clock.now();
+        self.store
+            .insert_email_verification_token(&EmailVerificationToken {
+                token_hash: hash_token(&token),
+                account_id: account.id.clone(),
+                email: email.to_string(),
+                created_at: now,
+                expires_at: now + EMAIL_VERIFICATION_TOKEN_TTL,
+            })
+            .await?;
+
+        let message =
+            templates::email_verification(account, email, &token, EMAIL_VERIFICATION_TOKEN_TTL);
+        if let Err(err) = self.notifier.send(&message).await {
+            tracing::error!("Failed to send email verification message: {}", err);
+        }
+        Ok(())
+    }
+
+    /// Constructs an [AuditEvent] about the account that is happening now.
+    fn event(&self, account_id: &str, kind: AuditEventKind, context: &AuditContext) -> AuditEvent {
+        AuditEvent::new(account_id, kind, context, self.clock.now())
+    }
+
+    /// Deletes all of the account's outstanding password reset, email
+    /// verification, and sign-in tokens.
+    async fn delete_tokens(&self, id: &str, email: &str) -> Result<(), AccountsServiceError> {
+        self.store.delete_password_reset_tokens(id).await?;
+        self.store.delete_email_verification_tokens(id).await?;
+        self.store.delete_sign_in_tokens(email).await?;
+        Ok(())
+    }
+
+    /// Checks a new password against the [PasswordPolicy], given the personal
+    /// information of the account it is for (any of which may be missing), and
+    /// then checks that it hasn't appeared in a data breach. If the breach
+    /// check fails (e.g., the API is unavailable), the password is accepted.
+    async fn check_new_password(
+        &self,
         password: &Secret<Password>,
-        password_hash: &str,
-    ) -> Result<(), argon2::password_hash::Error> {
-        let parsed_hash = PasswordHash::new(password_hash)?;
-        Argon2::default().verify_password(password.expose_secret().raw().as_bytes(), &parsed_hash)
+        personal_info: &[Option<&str>],
+    ) -> Result<(), AccountsServiceError> {
+        let personal_info: Vec<&str> = personal_info.iter().flatten().copied().collect();
+        self.password_policy.validate(password, &personal_info)?;
+        match self.breached_passwords.is_breached(password).await {
+            Ok(false) => Ok(()),
+            Ok(true) => Err(invalid_password(
+                "password_breached",
+                "The password has appeared in a data breach, so it isn't safe to use",
+            )),
+            Err(err) => {
+                tracing::warn!("Failed to check for a breached password: {}", err);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns true if a new password differs from the account's current one,
+    /// in which case it must also differ from each of the previous passwords
+    /// the [PasswordPolicy] remembers. Keeping the current password (e.g.,
+    /// when only changing the email address) doesn't count as reuse.
+    async fn check_password_rotation(
+        &self,
+        account: &Account,
+        password: &Secret<Password>,
+    ) -> Result<bool, AccountsServiceError> {
+        if let Some(current_hash) = &account.password_hash {
+            if self.hasher.verify(password, current_hash).is_ok() {
+                return Ok(false);
+            }
+        }
+        let history = self.store.load_password_history(&account.id).await?;
+        let reused = history
+            .iter()
+            .take(self.password_policy.history_size)
+            .any(|entry| self.hasher.verify(password, &entry.password_hash).is_ok());
+        if reused {
+            return Err(invalid_password(
+                "password_reused",
+                "The password has been used recently, so please choose a different one",
+            ));
+        }
+        Ok(true)
     }
+
+    /// Adds the password an account just replaced (if any)
+    /// to its history, keeping as many as the [PasswordPolicy] requires.
+    async fn record_password_history(
+        &self,
+        account_id: &str,
+        previous_hash: Option<String>,
+    ) -> Result<(), AccountsServiceError> {
+        let Some(password_hash) = previous_hash else {
+            return Ok(());
+        };
+        let entry = PasswordHistoryEntry {
+            id: ID::Pwhist.create(),
+            account_id: account_id.to_string(),
+            password_hash,
+            replaced_at: self.clock.now(),
+        };
+        self.store
+            .add_password_history(&entry, self.password_policy.history_size)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Returns a validation error on the `password` field, for new passwords
+/// that are rejected for reasons outside the [PasswordPolicy] itself.
+fn invalid_password(code: &'static str, message: &str) -> AccountsServiceError {
+    let mut errors = ValidationErrors::new();
+    errors
+        .add(ValidationError::new_field_named("password", code).with_message(message.to_string()));
+    errors.into()
+}
+
+/// Generates a random recovery code, formatted in two groups for readability
+/// (e.g., `4f8kq-x2m9d`).
+fn random_recovery_code() -> String {
+    let mut bytes = [0u8; RECOVERY_CODE_LENGTH];
+    OsRng.fill_bytes(&mut bytes);
+    let chars: String = bytes
+        .iter()
+        .map(|b| RECOVERY_CODE_ALPHABET[(b & 0x1f) as usize] as char)
+        .collect();
+    let (first, second) = chars.split_at(RECOVERY_CODE_LENGTH / 2);
+    format!("{}-{}", first, second)
+}
+
+/// Generates a random numeric sign-in code (e.g., `042917`).
+fn random_sign_in_code() -> String {
+    let modulus = 10u32.pow(SIGN_IN_CODE_LENGTH);
+    // reject values that would make some codes more likely than others
+    let limit = u32::MAX - u32::MAX % modulus;
+    let value = loop {
+        let value = OsRng.next_u32();
+        if value < limit {
+            break value % modulus;
+        }
+    };
+    format!("{:0width$}", value, width = SIGN_IN_CODE_LENGTH as usize)
 }
 
-impl<S: AccountStore> AccountService<S, SystemClock<Utc>> {
-    pub fn new(account_store: S) -> Self {
-        Self::new_with_clock(account_store, SystemClock::default())
+/// Normalizes a recovery code entered by an account holder, ignoring case
+/// and separators, and treating letters that look like digits as those digits.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| match c.to_ascii_lowercase() {
+            'o' => '0',
+            'i' | 'l' => '1',
+            c => c,
+        })
+        .collect()
+}
+
+impl<S: AccountStore, N: Notifier, P: BreachedPasswordChecker>
+    AccountService<S, N, P, SystemClock<Utc>>
+{
+    pub fn new(
+        account_store: S,
+        notifier: N,
+        password_policy: PasswordPolicy,
+        breached_passwords: P,
+        hasher: Argon2Hasher,
+    ) -> Self {
+        Self::new_with_clock(
+            account_store,
+            notifier,
+            password_policy,
+            breached_passwords,
+            hasher,
+            SystemClock::default(),
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
     use chrono::Utc;
+    use hashing::HashingParams;
     use models::Password;
     use stores::fake::FakeAccountStore;
 
-    use crate::services::TestClock;
+    use crate::services::{
+        audit::{
+            models::{AuditContext, AuditEventKind},
+            stores::{fake::FakeAuditStore, AuditStore},
+        },
+        breach::{fake::FakeBreachedPasswordChecker, tests::BREACHED_PASSWORD},
+        notifier::fake::FakeNotifier,
+        TestClock,
+    };
 
     use super::*;
 
+    /// Constructs an [AccountService] using fake stores and the given [TestClock].
+    fn test_service(
+        clock: &TestClock<Utc>,
+    ) -> AccountService<FakeAccountStore, FakeNotifier, FakeBreachedPasswordChecker, TestClock<Utc>>
+    {
+        AccountService::new_with_clock(
+            FakeAccountStore::new(),
+            FakeNotifier::new(),
+            PasswordPolicy::default(),
+            FakeBreachedPasswordChecker::new(),
+            Argon2Hasher::default(),
+            clock.clone(),
+        )
+    }
+
     #[tokio::test]
     async fn create_account() {
-        let store = FakeAccountStore::new();
         let now = Utc::now();
         let test_clock = TestClock::new(now);
-        let service = AccountService::new_with_clock(store, test_clock);
+        let service = test_service(&test_clock);
         let new_account = NewAccount {
             email: "test@test.com".to_string(),
-            password: Secret::new(Password::new("test-password")),
+            password: Secret::new(Password::new("tangerine-lantern-42")),
             display_name: Some("Tester McTester".to_string()),
         };
-        let account = service.create_account(&new_account).await.unwrap();
+        let account = service
+            .create_account(&new_account, &AuditContext::default())
+            .await
+            .unwrap();
 
         assert_eq!(new_account.email, account.email);
         assert_eq!(new_account.display_name, account.display_name);
@@ -158,7 +1170,1232 @@ mod tests {
         // ensure password was hashed and not stored as plain text!
         assert_ne!(
             new_account.password.expose_secret().raw(),
-            account.password_hash.as_str()
+            account.password_hash.as_deref().unwrap()
+        );
+    }
+    #[tokio::test]
+    async fn password_policy() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let codes = |result: Result<Account, AccountsServiceError>| match result {
+            Err(AccountsServiceError::ValidationErrors(errors)) => errors
+                .errors()
+                .iter()
+                .map(|error| error.code())
+                .collect::<Vec<_>>(),
+            other => panic!("expected validation errors, got {:?}", other),
+        };
+
+        // new accounts
+        let new_account = |password: &str| NewAccount {
+            email: "tester@test.com".to_string(),
+            password: Secret::new(Password::new(password)),
+            display_name: Some("Marigold Stone".to_string()),
+        };
+        assert_eq!(
+            vec!["password_too_short"],
+            codes(
+                service
+                    .create_account(&new_account("xkqv-mtrz"), &AuditContext::default())
+                    .await
+            )
+        );
+        assert_eq!(
+            vec!["password_contains_personal_info", "password_too_weak"],
+            codes(
+                service
+                    .create_account(&new_account("marigold1234"), &AuditContext::default())
+                    .await
+            )
+        );
+        let account = service
+            .create_account(
+                &new_account("tangerine-lantern-42"),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        // changed credentials, including the new email address
+        let credentials = AccountCredentials {
+            email: account.email.clone(),
+            password: Secret::new(Password::new("tangerine-lantern-42")),
+            recovery_code: None,
+        };
+        let update = |password: &str, email: Option<&str>| NewAccountCredentials {
+            password: Secret::new(Password::new(password)),
+            email: email.map(str::to_string),
+        };
+        assert_eq!(
+            vec!["password_too_weak"],
+            codes(
+                service
+                    .update_credentials(
+                        &account.id,
+                        &credentials,
+                        &update("password1234", None),
+                        &AuditContext::default(),
+                    )
+                    .await
+            )
+        );
+        assert_eq!(
+            vec!["password_contains_personal_info"],
+            codes(
+                service
+                    .update_credentials(
+                        &account.id,
+                        &credentials,
+                        &update("quillfeather-velvet-97", Some("quillfeather@test.com")),
+                        &AuditContext::default(),
+                    )
+                    .await
+            )
+        );
+
+        // password resets
+        service
+            .request_password_reset(&account.email)
+            .await
+            .unwrap();
+        let reset = |password: &str| PasswordReset {
+            token: sent_token(&service.notifier),
+            password: Secret::new(Password::new(password)),
+        };
+        assert_eq!(
+            vec!["password_too_long"],
+            codes(
+                service
+                    .reset_password(&reset(&"x7-Q".repeat(50)), &AuditContext::default())
+                    .await
+            )
+        );
+        // the token wasn't used up by the rejected password
+        service
+            .reset_password(&reset("velvet-compass-97"), &AuditContext::default())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn breached_password() {
+        let service = AccountService {
+            breached_passwords: FakeBreachedPasswordChecker::with_breached(&[BREACHED_PASSWORD]),
+            ..test_service(&TestClock::new(Utc::now()))
+        };
+        let is_breached_error = |result: Result<Account, AccountsServiceError>| match result {
+            Err(AccountsServiceError::ValidationErrors(errors)) => {
+                errors.errors().iter().map(|e| e.code()).collect::<Vec<_>>()
+                    == vec!["password_breached"]
+            }
+            _ => false,
+        };
+
+        let new_account = NewAccount {
+            email: "test@test.com".to_string(),
+            password: Secret::new(Password::new(BREACHED_PASSWORD)),
+            display_name: None,
+        };
+        assert!(is_breached_error(
+            service
+                .create_account(&new_account, &AuditContext::default())
+                .await
+        ));
+
+        let account = create_test_account(&service).await;
+        assert!(is_breached_error(
+            service
+                .update_credentials(
+                    &account.id,
+                    &AccountCredentials {
+                        email: account.email.clone(),
+                        password: Secret::new(Password::new("tangerine-lantern-42")),
+                        recovery_code: None,
+                    },
+                    &NewAccountCredentials {
+                        password: Secret::new(Password::new(BREACHED_PASSWORD)),
+                        email: None,
+                    },
+                    &AuditContext::default(),
+                )
+                .await
+        ));
+    }
+
+    #[tokio::test]
+    async fn password_history() {
+        let service = AccountService {
+            password_policy: PasswordPolicy {
+                history_size: 2,
+                ..Default::default()
+            },
+            ..test_service(&TestClock::new(Utc::now()))
+        };
+        let account = create_test_account(&service).await;
+        let change_password = |current: &'static str, new: &'static str| {
+            let service = &service;
+            let account = &account;
+            async move {
+                service
+                    .update_credentials(
+                        &account.id,
+                        &AccountCredentials {
+                            email: account.email.clone(),
+                            password: Secret::new(Password::new(current)),
+                            recovery_code: None,
+                        },
+                        &NewAccountCredentials {
+                            password: Secret::new(Password::new(new)),
+                            email: None,
+                        },
+                        &AuditContext::default(),
+                    )
+                    .await
+            }
+        };
+        let is_reused = |result: Result<Account, AccountsServiceError>| match result {
+            Err(AccountsServiceError::ValidationErrors(errors)) => {
+                errors.errors()[0].code() == "password_reused"
+            }
+            _ => false,
+        };
+
+        // keeping the current password isn't a rotation, so it isn't recorded
+        change_password("tangerine-lantern-42", "tangerine-lantern-42")
+            .await
+            .unwrap();
+        assert!(service
+            .store
+            .load_password_history(&account.id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        change_password("tangerine-lantern-42", "velvet-compass-97")
+            .await
+            .unwrap();
+        assert!(is_reused(
+            change_password("velvet-compass-97", "tangerine-lantern-42").await
+        ));
+        change_password("velvet-compass-97", "orchard-glacier-31")
+            .await
+            .unwrap();
+        change_password("orchard-glacier-31", "meadow-trumpet-58")
+            .await
+            .unwrap();
+        // only the last two previous passwords are remembered
+        let history = service
+            .store
+            .load_password_history(&account.id)
+            .await
+            .unwrap();
+        assert_eq!(2, history.len());
+        assert!(is_reused(
+            change_password("meadow-trumpet-58", "velvet-compass-97").await
+        ));
+        change_password("meadow-trumpet-58", "tangerine-lantern-42")
+            .await
+            .unwrap();
+
+        // the history is erased along with the account
+        service
+            .delete(&account.id, &AuditContext::default())
+            .await
+            .unwrap();
+        assert!(service
+            .store
+            .load_password_history(&account.id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn rehash_outdated_password() {
+        let audit_store = FakeAuditStore::new();
+        let clock = TestClock::new(Utc::now());
+        let service = AccountService {
+            store: FakeAccountStore::with_audit_store(audit_store.clone()),
+            ..test_service(&clock)
+        };
+        let account = create_test_account(&service).await;
+        clock.advance(TimeDelta::seconds(1));
+        // replace the hash with one made with older parameters
+        let outdated_hasher = Argon2Hasher::new(
+            &HashingParams {
+                iterations: 3,
+                ..HashingParams::default()
+            },
+            None,
+        )
+        .unwrap();
+        let password = Secret::new(Password::new("tangerine-lantern-42"));
+        let outdated_hash = outdated_hasher.hash(&password).unwrap();
+        let outdated_account = Account {
+            password_hash: Some(outdated_hash.clone()),
+            ..account.clone()
+        };
+        let event = service.event(
+            &account.id,
+            AuditEventKind::CredentialsUpdated,
+            &AuditContext::default(),
+        );
+        service
+            .store
+            .update(&outdated_account, &event)
+            .await
+            .unwrap();
+        assert!(service.hasher.needs_rehash(&outdated_hash));
+        clock.advance(TimeDelta::seconds(1));
+
+        let credentials = AccountCredentials {
+            email: account.email.clone(),
+            password: password.clone(),
+            recovery_code: None,
+        };
+        let authenticated = service
+            .authenticate(&credentials, &AuditContext::default())
+            .await
+            .unwrap();
+        let stored = service.get_account(&account.id).await.unwrap();
+        assert_eq!(authenticated.password_hash, stored.password_hash);
+        let rehashed = stored.password_hash.unwrap();
+        assert_ne!(outdated_hash, rehashed);
+        assert!(!service.hasher.needs_rehash(&rehashed));
+        assert!(service.hasher.verify(&password, &rehashed).is_ok());
+
+        // up-to-date hashes are left alone
+        clock.advance(TimeDelta::seconds(1));
+        service
+            .authenticate(&credentials, &AuditContext::default())
+            .await
+            .unwrap();
+        let stored = service.get_account(&account.id).await.unwrap();
+        assert_eq!(Some(rehashed), stored.password_hash);
+
+        // the rehash is recorded once, as the account holder
+        let events = audit_store
+            .load_for_account(&account.id, None, 10)
+            .await
+            .unwrap();
+        let rehashed_events: Vec<&AuditEvent> = events
+            .iter()
+            .filter(|event| event.kind == AuditEventKind::PasswordRehashed)
+            .collect();
+        assert_eq!(1, rehashed_events.len());
+        assert_eq!(Some(account.id.clone()), rehashed_events[0].actor);
+    }
+
+    /// Creates the default test account using the given service.
+    async fn create_test_account<C: Clock<Utc>>(
+        service: &AccountService<FakeAccountStore, FakeNotifier, FakeBreachedPasswordChecker, C>,
+    ) -> Account {
+        service
+            .create_account(
+                &NewAccount {
+                    email: "test@test.com".to_string(),
+                    password: Secret::new(Password::new("tangerine-lantern-42")),
+                    display_name: None,
+                },
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap()
+    }
+
+    /// Extracts the token from the last message sent by the notifier.
+    fn sent_token(notifier: &FakeNotifier) -> String {
+        let message = notifier.sent_messages().pop().unwrap();
+        message.body.lines().last().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn reset_password() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let account = create_test_account(&service).await;
+
+        service
+            .request_password_reset(&account.email)
+            .await
+            .unwrap();
+        assert_eq!(account.email, service.notifier.sent_messages()[0].to);
+
+        let password_reset = PasswordReset {
+            token: sent_token(&service.notifier),
+            password: Secret::new(Password::new("velvet-compass-97")),
+        };
+        service
+            .reset_password(&password_reset, &AuditContext::default())
+            .await
+            .unwrap();
+        service
+            .authenticate(
+                &AccountCredentials {
+                    email: account.email.clone(),
+                    password: Secret::new(Password::new("velvet-compass-97")),
+                    recovery_code: None,
+                },
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        // the token can only be used once
+        assert!(matches!(
+            service
+                .reset_password(&password_reset, &AuditContext::default())
+                .await,
+            Err(AccountsServiceError::InvalidPasswordResetToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn reset_password_unknown_email() {
+        let service = test_service(&TestClock::new(Utc::now()));
+
+        service
+            .request_password_reset("unknown@test.com")
+            .await
+            .unwrap();
+        assert!(service.notifier.sent_messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reset_password_token_expires() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let account = create_test_account(&service).await;
+        service
+            .request_password_reset(&account.email)
+            .await
+            .unwrap();
+
+        clock.advance(PASSWORD_RESET_TOKEN_TTL);
+        let result = service
+            .reset_password(
+                &PasswordReset {
+                    token: sent_token(&service.notifier),
+                    password: Secret::new(Password::new("velvet-compass-97")),
+                },
+                &AuditContext::default(),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(AccountsServiceError::InvalidPasswordResetToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn credential_change_invalidates_reset_token() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let account = create_test_account(&service).await;
+        service
+            .request_password_reset(&account.email)
+            .await
+            .unwrap();
+
+        service
+            .update_credentials(
+                &account.id,
+                &AccountCredentials {
+                    email: account.email.clone(),
+                    password: Secret::new(Password::new("tangerine-lantern-42")),
+                    recovery_code: None,
+                },
+                &NewAccountCredentials {
+                    password: Secret::new(Password::new("orchard-glacier-31")),
+                    email: None,
+                },
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        let result = service
+            .reset_password(
+                &PasswordReset {
+                    token: sent_token(&service.notifier),
+                    password: Secret::new(Password::new("velvet-compass-97")),
+                },
+                &AuditContext::default(),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(AccountsServiceError::InvalidPasswordResetToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn verify_email_token_expires() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let account = create_test_account(&service).await;
+
+        clock.advance(EMAIL_VERIFICATION_TOKEN_TTL);
+        assert!(matches!(
+            service
+                .verify_email(&sent_token(&service.notifier), &AuditContext::default())
+                .await,
+            Err(AccountsServiceError::InvalidEmailVerificationToken)
+        ));
+        service
+            .request_email_verification(&account.id)
+            .await
+            .unwrap();
+        let verified = service
+            .verify_email(&sent_token(&service.notifier), &AuditContext::default())
+            .await
+            .unwrap();
+        assert_eq!(Some(clock.now()), verified.email_verified_at);
+    }
+
+    #[tokio::test]
+    async fn change_email_pending_until_verified() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let account = create_test_account(&service).await;
+        let change_email = |email: &str| NewAccountCredentials {
+            password: Secret::new(Password::new("tangerine-lantern-42")),
+            email: Some(email.to_string()),
+        };
+        let credentials = AccountCredentials {
+            email: account.email.clone(),
+            password: Secret::new(Password::new("tangerine-lantern-42")),
+            recovery_code: None,
+        };
+
+        let updated = service
+            .update_credentials(
+                &account.id,
+                &credentials,
+                &change_email("first@test.com"),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(account.email, updated.email);
+        assert_eq!(Some("first@test.com".to_string()), updated.pending_email);
+        let first_token = sent_token(&service.notifier);
+
+        // changing the email again makes the first token useless
+        service
+            .update_credentials(
+                &account.id,
+                &credentials,
+                &change_email("second@test.com"),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            service
+                .verify_email(&first_token, &AuditContext::default())
+                .await,
+            Err(AccountsServiceError::InvalidEmailVerificationToken)
+        ));
+
+        let verified = service
+            .verify_email(&sent_token(&service.notifier), &AuditContext::default())
+            .await
+            .unwrap();
+        assert_eq!("second@test.com", verified.email);
+        assert!(verified.pending_email.is_none());
+        assert!(verified.email_verified());
+
+        // an address that belongs to another account is rejected
+        service
+            .create_account(
+                &NewAccount {
+                    email: "other@test.com".to_string(),
+                    password: Secret::new(Password::new("tangerine-lantern-42")),
+                    display_name: None,
+                },
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+        let result = service
+            .update_credentials(
+                &account.id,
+                &AccountCredentials {
+                    email: "second@test.com".to_string(),
+                    password: Secret::new(Password::new("tangerine-lantern-42")),
+                    recovery_code: None,
+                },
+                &change_email("other@test.com"),
+                &AuditContext::default(),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(AccountsServiceError::EmailAlreadyExists(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn deactivated_account_cannot_authenticate_or_reset() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let account = create_test_account(&service).await;
+        let credentials = AccountCredentials {
+            email: account.email.clone(),
+            password: Secret::new(Password::new("tangerine-lantern-42")),
+            recovery_code: None,
+        };
+        service
+            .request_password_reset(&account.email)
+            .await
+            .unwrap();
+        let reset_token = sent_token(&service.notifier);
+
+        let deactivated = service
+            .deactivate(&account.id, &AuditContext::default())
+            .await
+            .unwrap();
+        assert_eq!(AccountStatus::Deactivated, deactivated.status);
+        assert!(matches!(
+            service
+                .authenticate(&credentials, &AuditContext::default())
+                .await,
+            Err(AccountsServiceError::AccountNotActive)
+        ));
+        // outstanding tokens were invalidated, and no new ones are sent
+        let result = service
+            .reset_password(
+                &PasswordReset {
+                    token: reset_token,
+                    password: Secret::new(Password::new("velvet-compass-97")),
+                },
+                &AuditContext::default(),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(AccountsServiceError::InvalidPasswordResetToken)
+        ));
+        let sent = service.notifier.sent_messages().len();
+        service
+            .request_password_reset(&account.email)
+            .await
+            .unwrap();
+        assert_eq!(sent, service.notifier.sent_messages().len());
+
+        service
+            .reactivate(&account.id, &AuditContext::default())
+            .await
+            .unwrap();
+        service
+            .authenticate(&credentials, &AuditContext::default())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn delete_scrubs_personal_data() {
+        let audit_store = FakeAuditStore::new();
+        let service = AccountService {
+            store: FakeAccountStore::with_audit_store(audit_store.clone()),
+            ..test_service(&TestClock::new(Utc::now()))
+        };
+        let context = AuditContext {
+            actor: None,
+            source_ip: Some("192.0.2.1".to_string()),
+            user_agent: Some("test-agent/1.0".to_string()),
+        };
+        let account = service
+            .create_account(
+                &NewAccount {
+                    email: "test@test.com".to_string(),
+                    password: Secret::new(Password::new("tangerine-lantern-42")),
+                    display_name: Some("Tester McTester".to_string()),
+                },
+                &context,
+            )
+            .await
+            .unwrap();
+
+        service.delete(&account.id, &context).await.unwrap();
+        let deleted = service
+            .store
+            .load_by_id(&account.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(AccountStatus::Deleted, deleted.status);
+        assert!(!deleted.email.contains("test@test.com"));
+        assert!(deleted.password_hash.is_none());
+        assert!(deleted.display_name.is_none());
+        assert!(service
+            .store
+            .load_by_email(&account.email)
+            .await
+            .unwrap()
+            .is_none());
+        // the events are kept, but not where they came from
+        let events = audit_store
+            .load_for_account(&account.id, None, 50)
+            .await
+            .unwrap();
+        assert_eq!(2, events.len());
+        assert!(events
+            .iter()
+            .all(|event| event.source_ip.is_none() && event.user_agent.is_none()));
+
+        assert!(matches!(
+            service
+                .reactivate(&account.id, &AuditContext::default())
+                .await,
+            Err(AccountsServiceError::AccountNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn records_audit_events() {
+        let audit_store = FakeAuditStore::new();
+        let clock = TestClock::new(Utc::now());
+        let service = AccountService {
+            store: FakeAccountStore::with_audit_store(audit_store.clone()),
+            ..test_service(&clock)
+        };
+        let context = AuditContext {
+            actor: None,
+            source_ip: Some("192.0.2.1".to_string()),
+            user_agent: Some("test-agent".to_string()),
+        };
+        let account = create_test_account(&service).await;
+
+        clock.advance(TimeDelta::seconds(1));
+        let wrong_password = AccountCredentials {
+            email: account.email.clone(),
+            password: Secret::new(Password::new("wrong-password")),
+            recovery_code: None,
+        };
+        assert!(service
+            .authenticate(&wrong_password, &context)
+            .await
+            .is_err());
+        clock.advance(TimeDelta::seconds(1));
+        let credentials = AccountCredentials {
+            email: account.email.clone(),
+            password: Secret::new(Password::new("tangerine-lantern-42")),
+            recovery_code: None,
+        };
+        service.authenticate(&credentials, &context).await.unwrap();
+        clock.advance(TimeDelta::seconds(1));
+        service
+            .deactivate(
+                &account.id,
+                &AuditContext {
+                    actor: Some("acct_support".to_string()),
+                    ..context.clone()
+                },
+            )
+            .await
+            .unwrap();
+
+        let events = audit_store
+            .load_for_account(&account.id, None, 10)
+            .await
+            .unwrap();
+        let kinds: Vec<AuditEventKind> = events.iter().map(|event| event.kind).collect();
+        assert_eq!(
+            vec![
+                AuditEventKind::Deactivated,
+                AuditEventKind::Authenticated,
+                AuditEventKind::AuthenticationFailed,
+                AuditEventKind::AccountCreated,
+            ],
+            kinds
+        );
+        assert_eq!(Some("acct_support".to_string()), events[0].actor);
+        assert_eq!(Some(account.id.clone()), events[1].actor);
+        assert_eq!(context.source_ip, events[1].source_ip);
+        assert_eq!(context.user_agent, events[1].user_agent);
+        assert_eq!(clock.now(), events[0].occurred_at);
+    }
+
+    #[tokio::test]
+    async fn recovery_codes() {
+        let audit_store = FakeAuditStore::new();
+        let clock = TestClock::new(Utc::now());
+        let service = AccountService {
+            store: FakeAccountStore::with_audit_store(audit_store.clone()),
+            ..test_service(&clock)
+        };
+        let context = AuditContext::default();
+        let account = create_test_account(&service).await;
+        let summary = service.recovery_code_summary(&account.id).await.unwrap();
+        assert_eq!(0, summary.remaining);
+        assert!(summary.generated_at.is_none());
+
+        let codes = service
+            .generate_recovery_codes(&account.id, &context)
+            .await
+            .unwrap();
+        assert_eq!(RECOVERY_CODE_COUNT, codes.len());
+        let summary = service.recovery_code_summary(&account.id).await.unwrap();
+        assert_eq!(RECOVERY_CODE_COUNT, summary.remaining);
+        assert_eq!(Some(clock.now()), summary.generated_at);
+
+        // codes are accepted regardless of case and separators, but only once
+        let recovery_code = Secret::new(Password::new(&codes[0].to_uppercase().replace('-', " ")));
+        service
+            .redeem_recovery_code(&account.id, &recovery_code, &context)
+            .await
+            .unwrap();
+        assert!(matches!(
+            service
+                .redeem_recovery_code(&account.id, &recovery_code, &context)
+                .await,
+            Err(AccountsServiceError::InvalidRecoveryCode)
+        ));
+        // which counts as a failed authentication
+        clock.advance(AUTHENTICATION_FAILURE_DELAY);
+
+        // a code can be presented alongside the password
+        let mut credentials = AccountCredentials {
+            email: account.email.clone(),
+            password: Secret::new(Password::new("tangerine-lantern-42")),
+            recovery_code: Some(Secret::new(Password::new(&codes[1]))),
+        };
+        service.authenticate(&credentials, &context).await.unwrap();
+        assert!(matches!(
+            service.authenticate(&credentials, &context).await,
+            Err(AccountsServiceError::InvalidCredentials)
+        ));
+        let summary = service.recovery_code_summary(&account.id).await.unwrap();
+        assert_eq!(RECOVERY_CODE_COUNT - 2, summary.remaining);
+
+        // regenerating invalidates the old set
+        clock.advance(TimeDelta::seconds(1));
+        let new_codes = service
+            .generate_recovery_codes(&account.id, &context)
+            .await
+            .unwrap();
+        credentials.recovery_code = Some(Secret::new(Password::new(&codes[2])));
+        assert!(service.authenticate(&credentials, &context).await.is_err());
+        // the failure delays the next attempt
+        clock.advance(AUTHENTICATION_FAILURE_DELAY);
+        credentials.recovery_code = Some(Secret::new(Password::new(&new_codes[2])));
+        service.authenticate(&credentials, &context).await.unwrap();
+        let summary = service.recovery_code_summary(&account.id).await.unwrap();
+        assert_eq!(RECOVERY_CODE_COUNT - 1, summary.remaining);
+        assert_eq!(
+            Some(clock.now() - AUTHENTICATION_FAILURE_DELAY),
+            summary.generated_at
+        );
+
+        let events = audit_store
+            .load_for_account(&account.id, None, 20)
+            .await
+            .unwrap();
+        let used = events
+            .iter()
+            .filter(|event| event.kind == AuditEventKind::RecoveryCodeUsed)
+            .count();
+        assert_eq!(3, used);
+    }
+
+    /// Extracts the sign-in code from the last message sent by the notifier.
+    fn sent_sign_in_code(notifier: &FakeNotifier) -> Secret<Password> {
+        let message = notifier.sent_messages().pop().unwrap();
+        Secret::new(Password::new(message.body.lines().nth(4).unwrap()))
+    }
+
+    #[tokio::test]
+    async fn passwordless_sign_in() {
+        let audit_store = FakeAuditStore::new();
+        let clock = TestClock::new(Utc::now());
+        let service = AccountService {
+            store: FakeAccountStore::with_audit_store(audit_store.clone()),
+            ..test_service(&clock)
+        };
+        let context = AuditContext::default();
+        let sign_in_request = SignInRequest {
+            email: "new@test.com".to_string(),
+        };
+
+        // an unregistered email address still gets a code, and using it creates the account
+        service.request_sign_in(&sign_in_request).await.unwrap();
+        assert_eq!("new@test.com", service.notifier.sent_messages()[0].to);
+        let code = sent_sign_in_code(&service.notifier);
+        assert_eq!(
+            SIGN_IN_CODE_LENGTH as usize,
+            code.expose_secret().raw().len()
+        );
+        assert!(matches!(
+            service
+                .sign_in_with_code(
+                    "new@test.com",
+                    &Secret::new(Password::new("wrong")),
+                    &context
+                )
+                .await,
+            Err(AccountsServiceError::InvalidSignInToken)
+        ));
+        let account = service
+            .sign_in_with_code(" new@test.com ", &code, &context)
+            .await
+            .unwrap();
+        assert_eq!("new@test.com", account.email);
+        assert!(account.password_hash.is_none());
+        assert!(account.email_verified());
+        clock.advance(TimeDelta::seconds(1));
+
+        // the code can only be used once
+        assert!(matches!(
+            service
+                .sign_in_with_code("new@test.com", &code, &context)
+                .await,
+            Err(AccountsServiceError::InvalidSignInToken)
+        ));
+
+        // the account has no password to sign in with
+        clock.advance(TimeDelta::seconds(1));
+        assert!(matches!(
+            service
+                .authenticate(
+                    &AccountCredentials {
+                        email: account.email.clone(),
+                        password: Secret::new(Password::new("bogus")),
+                        recovery_code: None,
+                    },
+                    &context,
+                )
+                .await,
+            Err(AccountsServiceError::InvalidCredentials)
+        ));
+
+        // the token can be used instead of the code, and only once
+        service.request_sign_in(&sign_in_request).await.unwrap();
+        let token = sent_token(&service.notifier);
+        clock.advance(TimeDelta::seconds(1));
+        let signed_in = service.sign_in_with_token(&token, &context).await.unwrap();
+        assert_eq!(account.id, signed_in.id);
+        assert!(matches!(
+            service.sign_in_with_token(&token, &context).await,
+            Err(AccountsServiceError::InvalidSignInToken)
+        ));
+
+        let events = audit_store
+            .load_for_account(&account.id, None, 10)
+            .await
+            .unwrap();
+        let mut kinds: Vec<AuditEventKind> = events.iter().map(|event| event.kind).collect();
+        // the account was created and signed in at the same instant, so those
+        // two events could be in either order
+        kinds[2..].sort_by_key(|kind| kind.as_str());
+        assert_eq!(
+            vec![
+                AuditEventKind::Authenticated,
+                AuditEventKind::AuthenticationFailed,
+                AuditEventKind::AccountCreated,
+                AuditEventKind::Authenticated,
+            ],
+            kinds
+        );
+    }
+
+    #[tokio::test]
+    async fn passwordless_sign_in_limits() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let context = AuditContext::default();
+        let account = create_test_account(&service).await;
+        let sign_in_request = SignInRequest {
+            email: account.email.clone(),
+        };
+
+        // signing in with a code verifies the email address of an existing account
+        service.request_sign_in(&sign_in_request).await.unwrap();
+        let code = sent_sign_in_code(&service.notifier);
+        let signed_in = service
+            .sign_in_with_code(&account.email, &code, &context)
+            .await
+            .unwrap();
+        assert_eq!(account.id, signed_in.id);
+        assert!(!account.email_verified());
+        assert!(signed_in.email_verified());
+
+        // incorrect codes count as failed authentications, delaying the next
+        // attempt, and too many of them invalidate the code
+        let wrong_code = Secret::new(Password::new("wrong"));
+        service.request_sign_in(&sign_in_request).await.unwrap();
+        let code = sent_sign_in_code(&service.notifier);
+        assert!(service
+            .sign_in_with_code(&account.email, &wrong_code, &context)
+            .await
+            .is_err());
+        assert!(matches!(
+            service
+                .sign_in_with_code(&account.email, &code, &context)
+                .await,
+            Err(AccountsServiceError::InvalidSignInToken)
+        ));
+        clock.advance(AUTHENTICATION_FAILURE_DELAY);
+        for attempt in 1..MAX_SIGN_IN_CODE_ATTEMPTS {
+            assert!(service
+                .sign_in_with_code(&account.email, &wrong_code, &context)
+                .await
+                .is_err());
+            clock.advance(AUTHENTICATION_FAILURE_DELAY * 2i32.pow(attempt as u32));
+        }
+        service.unlock(&account.id, &context).await.unwrap();
+        assert!(matches!(
+            service
+                .sign_in_with_code(&account.email, &code, &context)
+                .await,
+            Err(AccountsServiceError::InvalidSignInToken)
+        ));
+
+        // concurrent incorrect codes are all counted
+        service.request_sign_in(&sign_in_request).await.unwrap();
+        let code = sent_sign_in_code(&service.notifier);
+        let token = service
+            .store
+            .load_sign_in_token_by_email(&account.email)
+            .await
+            .unwrap()
+            .unwrap();
+        let (first, second) = tokio::join!(
+            service
+                .store
+                .increment_sign_in_token_attempts(&token.token_hash),
+            service
+                .store
+                .increment_sign_in_token_attempts(&token.token_hash)
         );
+        first.unwrap();
+        second.unwrap();
+        let token = service
+            .store
+            .load_sign_in_token_by_email(&account.email)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(2, token.failed_attempts);
+        service
+            .sign_in_with_code(&account.email, &code, &context)
+            .await
+            .unwrap();
+
+        // requesting a new code replaces the old one, and codes expire
+        service.request_sign_in(&sign_in_request).await.unwrap();
+        let old_token = sent_token(&service.notifier);
+        service.request_sign_in(&sign_in_request).await.unwrap();
+        let code = sent_sign_in_code(&service.notifier);
+        assert!(service
+            .sign_in_with_token(&old_token, &context)
+            .await
+            .is_err());
+        clock.advance(SIGN_IN_TOKEN_TTL);
+        assert!(matches!(
+            service
+                .sign_in_with_code(&account.email, &code, &context)
+                .await,
+            Err(AccountsServiceError::InvalidSignInToken)
+        ));
+
+        // nothing is sent to deactivated accounts
+        service.deactivate(&account.id, &context).await.unwrap();
+        let sent = service.notifier.sent_messages().len();
+        service.request_sign_in(&sign_in_request).await.unwrap();
+        assert_eq!(sent, service.notifier.sent_messages().len());
+
+        // invalid email addresses are rejected
+        assert!(matches!(
+            service
+                .request_sign_in(&SignInRequest {
+                    email: "not-an-email".to_string()
+                })
+                .await,
+            Err(AccountsServiceError::ValidationErrors(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn lockout() {
+        let audit_store = FakeAuditStore::new();
+        let clock = TestClock::new(Utc::now());
+        let service = AccountService {
+            store: FakeAccountStore::with_audit_store(audit_store.clone()),
+            ..test_service(&clock)
+        };
+        let context = AuditContext::default();
+        let account = create_test_account(&service).await;
+        let credentials = |password: &str| AccountCredentials {
+            email: account.email.clone(),
+            password: Secret::new(Password::new(password)),
+            recovery_code: None,
+        };
+
+        // each failure delays the next attempt for twice as long, even with the right password
+        for _ in 0..2 {
+            assert!(matches!(
+                service.authenticate(&credentials("wrong"), &context).await,
+                Err(AccountsServiceError::InvalidCredentials)
+            ));
+            clock.advance(AUTHENTICATION_FAILURE_DELAY);
+        }
+        assert!(matches!(
+            service
+                .authenticate(&credentials("tangerine-lantern-42"), &context)
+                .await,
+            Err(AccountsServiceError::AccountLocked)
+        ));
+        clock.advance(AUTHENTICATION_FAILURE_DELAY);
+        service
+            .authenticate(&credentials("tangerine-lantern-42"), &context)
+            .await
+            .unwrap();
+        // the failures are only forgotten once the sign-in is complete
+        assert!(service
+            .store
+            .load_authentication_failures(&account.id)
+            .await
+            .unwrap()
+            .is_some());
+        service
+            .clear_authentication_failures(&account.id)
+            .await
+            .unwrap();
+
+        // too many failures within the window lock the account, until the lockout expires
+        for attempt in 0..MAX_AUTHENTICATION_FAILURES {
+            assert!(matches!(
+                service.authenticate(&credentials("wrong"), &context).await,
+                Err(AccountsServiceError::InvalidCredentials)
+            ));
+            clock.advance(AUTHENTICATION_FAILURE_DELAY * 2i32.pow(attempt as u32));
+        }
+        assert!(matches!(
+            service
+                .authenticate(&credentials("tangerine-lantern-42"), &context)
+                .await,
+            Err(AccountsServiceError::AccountLocked)
+        ));
+        clock.advance(LOCKOUT_DURATION);
+        service
+            .authenticate(&credentials("tangerine-lantern-42"), &context)
+            .await
+            .unwrap();
+
+        // failures outside the window are forgotten
+        for _ in 0..MAX_AUTHENTICATION_FAILURES {
+            assert!(service
+                .authenticate(&credentials("wrong"), &context)
+                .await
+                .is_err());
+            clock.advance(AUTHENTICATION_FAILURE_WINDOW);
+        }
+        service
+            .authenticate(&credentials("tangerine-lantern-42"), &context)
+            .await
+            .unwrap();
+
+        // the account can be unlocked early
+        for attempt in 0..MAX_AUTHENTICATION_FAILURES {
+            assert!(service
+                .authenticate(&credentials("wrong"), &context)
+                .await
+                .is_err());
+            clock.advance(AUTHENTICATION_FAILURE_DELAY * 2i32.pow(attempt as u32));
+        }
+        service.unlock(&account.id, &context).await.unwrap();
+        service
+            .authenticate(&credentials("tangerine-lantern-42"), &context)
+            .await
+            .unwrap();
+        assert!(matches!(
+            service.unlock("acct_unknown", &context).await,
+            Err(AccountsServiceError::AccountNotFound(_))
+        ));
+
+        let events = audit_store
+            .load_for_account(&account.id, None, 50)
+            .await
+            .unwrap();
+        let count = |kind: AuditEventKind| events.iter().filter(|event| event.kind == kind).count();
+        assert_eq!(2, count(AuditEventKind::Locked));
+        assert_eq!(1, count(AuditEventKind::Unlocked));
+    }
+
+    #[tokio::test]
+    async fn concurrent_authentication_failures_are_all_counted() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let context = AuditContext::default();
+        let account = create_test_account(&service).await;
+
+        let (first, second) = tokio::join!(
+            service.record_authentication_failure(&account.id, &context),
+            service.record_authentication_failure(&account.id, &context)
+        );
+        first.unwrap();
+        second.unwrap();
+        let failures = service
+            .store
+            .load_authentication_failures(&account.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(2, failures.failed_attempts);
+        // the longer delay, from the second failure, applies
+        assert_eq!(
+            clock.now() + AUTHENTICATION_FAILURE_DELAY * 2,
+            failures.locked_until
+        );
+    }
+
+    #[tokio::test]
+    async fn reauthentication() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let context = AuditContext::default();
+        let account = create_test_account(&service).await;
+        let password = |password: &str| Secret::new(Password::new(password));
+
+        service
+            .verify_password(&account.id, &password("tangerine-lantern-42"), &context)
+            .await
+            .unwrap();
+        assert!(matches!(
+            service
+                .verify_password(&account.id, &password("wrong"), &context)
+                .await,
+            Err(AccountsServiceError::InvalidCredentials)
+        ));
+        assert!(matches!(
+            service
+                .verify_password("acct_unknown", &password("tangerine-lantern-42"), &context)
+                .await,
+            Err(AccountsServiceError::AccountNotFound(_))
+        ));
+
+        // wrong second factors count towards locking the account too
+        clock.advance(LOCKOUT_DURATION);
+        service.check_can_authenticate(&account.id).await.unwrap();
+        for attempt in 0..MAX_AUTHENTICATION_FAILURES {
+            service
+                .record_failed_authentication(&account.id, &context)
+                .await
+                .unwrap();
+            assert!(matches!(
+                service.check_can_authenticate(&account.id).await,
+                Err(AccountsServiceError::AccountLocked)
+            ));
+            clock.advance(AUTHENTICATION_FAILURE_DELAY * 2i32.pow(attempt as u32));
+        }
+        assert!(matches!(
+            service.check_can_authenticate(&account.id).await,
+            Err(AccountsServiceError::AccountLocked)
+        ));
+        assert!(matches!(
+            service
+                .verify_password(&account.id, &password("tangerine-lantern-42"), &context)
+                .await,
+            Err(AccountsServiceError::AccountLocked)
+        ));
+        clock.advance(LOCKOUT_DURATION);
+        service.check_can_authenticate(&account.id).await.unwrap();
     }
 }
@@ -15,10 +15,78 @@ pub enum AccountsServiceError {
     EmailAlreadyExists(String),
     #[error("The email address or password was incorrect")]
     InvalidCredentials,
+    #[error("The account is not active")]
+    AccountNotActive,
+    #[error("The account is temporarily locked after too many failed sign-in attempts")]
+    AccountLocked,
+    #[error("The account '{0}' was not found")]
+    AccountNotFound(String),
+    #[error("The password reset token is invalid or has expired")]
+    InvalidPasswordResetToken,
+    #[error("The recovery code is incorrect or has already been used")]
+    InvalidRecoveryCode,
+    #[error("The sign-in code or token is incorrect or has expired")]
+    InvalidSignInToken,
+    #[error("The email address '{0}' has already been verified")]
+    EmailAlreadyVerified(String),
+    #[error("The email verification token is invalid or has expired")]
+    InvalidEmailVerificationToken,
     #[error("{0}")]
     ValidationErrors(#[from] validify::ValidationErrors),
 }
 
+/// Returned when parsing an [AccountStatus](super::models::AccountStatus)
+/// from an unrecognized name.
+#[derive(Error, Debug)]
+#[error("'{0}' is not a valid account status")]
+pub struct ParseAccountStatusError(pub String);
+
+/// Returned when parsing a [CharacterClass](super::policy::CharacterClass)
+/// from an unrecognized name.
+#[derive(Error, Debug)]
+#[error("'{0}' is not a valid character class")]
+pub struct ParseCharacterClassError(pub String);
+
+/// Returned when a [PasswordPolicy](super::policy::PasswordPolicy) is
+/// weaker than allowed, or can't be satisfied.
+#[derive(Error, Debug)]
+pub enum PasswordPolicyError {
+    #[error("The minimum password length must be at least {0}")]
+    MinLengthTooShort(usize),
+    #[error("The maximum password length must be at least the minimum length, and at most {0}")]
+    InvalidMaxLength(usize),
+    #[error("The minimum password strength must be at most {0}")]
+    InvalidMinStrength(u8),
+    #[error("The password history size must be at most {0}")]
+    InvalidHistorySize(usize),
+}
+
+/// Returned when [HashingParams](super::hashing::HashingParams) are
+/// weaker than allowed, or not valid for Argon2.
+#[derive(Error, Debug)]
+pub enum HashingParamsError {
+    #[error("The password hashing memory size must be at least {0} KiB")]
+    MemoryTooSmall(u32),
+    #[error("Password hashing with {0} KiB of memory needs at least {1} iterations")]
+    TooFewIterations(u32, u32),
+    #[error("The password hashing parameters are not valid: {0}")]
+    InvalidParams(argon2::Error),
+    #[error("There was an error hashing the password: {0}")]
+    HashingError(argon2::password_hash::errors::Error),
+}
+
+impl From<argon2::Error> for HashingParamsError {
+    fn from(value: argon2::Error) -> Self {
+        HashingParamsError::InvalidParams(value)
+    }
+}
+
+impl From<argon2::password_hash::errors::Error> for HashingParamsError {
+    fn from(value: argon2::password_hash::errors::Error) -> Self {
+        HashingParamsError::HashingError(value)
+    }
+}
+
 impl From<argon2::password_hash::errors::Error> for AccountsServiceError {
     fn from(value: argon2::password_hash::errors::Error) -> Self {
         AccountsServiceError::PasswordHashingError(value)
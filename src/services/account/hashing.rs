@@ -0,0 +1,314 @@
+//! Hashes passwords (and other secrets, such as recovery and sign-in codes)
+//! with Argon2id, using parameters and an optional pepper configured at
+//! startup. Hashes record the parameters they were made with, so they can
+//! still be verified after the parameters change, and [Argon2Hasher::needs_rehash]
+//! reports the ones that should be upgraded.
+
+use argon2::{
+    password_hash::{self, rand_core::OsRng, SaltString},
+    Algorithm, Argon2, KeyId, Params, ParamsBuilder, PasswordHash, PasswordHasher,
+    PasswordVerifier, Version,
+};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+
+use super::{error::HashingParamsError, models::Password};
+
+/// The least memory (in KiB) [HashingParams] can use. This is the smallest
+/// configuration OWASP recommends, which needs at least five iterations.
+pub const MIN_MEMORY_KIB: u32 = 7 * 1024;
+/// The configurations OWASP recommends, as pairs of the memory (in KiB) and
+/// the number of iterations needed with at least that much memory.
+const MIN_ITERATIONS: [(u32, u32); 5] = [
+    (46 * 1024, 1),
+    (19 * 1024, 2),
+    (12 * 1024, 3),
+    (9 * 1024, 4),
+    (MIN_MEMORY_KIB, 5),
+];
+/// Number of bytes of the pepper's SHA-256 hash used as the key ID in hashes.
+/// This is enough to tell peppers apart, without revealing much about them.
+const PEPPER_KEY_ID_LENGTH: usize = 4;
+
+/// The Argon2id cost parameters. The defaults match the argon2 crate's
+/// defaults, which follow OWASP's recommendation of 19 MiB and two iterations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashingParams {
+    /// Memory size, in KiB.
+    pub memory_kib: u32,
+    /// Number of iterations.
+    pub iterations: u32,
+    /// Degree of parallelism.
+    pub parallelism: u32,
+}
+
+impl Default for HashingParams {
+    fn default() -> Self {
+        HashingParams {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// Hashes and verifies secrets with Argon2id. If a pepper is configured, it's
+/// mixed into every new hash as the Argon2 secret, and a short ID derived from
+/// it is recorded in the hash, so that hashes made before the pepper was
+/// introduced can still be verified (and then rehashed).
+pub struct Argon2Hasher {
+    params: Params,
+    pepper: Option<Secret<Vec<u8>>>,
+    /// A hash of a bogus password, made with the same parameters and
+    /// pepper, so that verifying it takes as long as verifying a real one.
+    bogus_hash: String,
+}
+
+impl Argon2Hasher {
+    /// Constructs a new [Argon2Hasher] with the [HashingParams] and optional pepper.
+    pub fn new(
+        params: &HashingParams,
+        pepper: Option<Secret<Vec<u8>>>,
+    ) -> Result<Argon2Hasher, HashingParamsError> {
+        if params.memory_kib < MIN_MEMORY_KIB {
+            return Err(HashingParamsError::MemoryTooSmall(MIN_MEMORY_KIB));
+        }
+        let min_iterations = min_iterations(params.memory_kib);
+        if params.iterations < min_iterations {
+            return Err(HashingParamsError::TooFewIterations(
+                params.memory_kib,
+                min_iterations,
+            ));
+        }
+        let mut builder = ParamsBuilder::new();
+        builder
+            .m_cost(params.memory_kib)
+            .t_cost(params.iterations)
+            .p_cost(params.parallelism);
+        if let Some(pepper) = &pepper {
+            builder.keyid(pepper_key_id(pepper));
+        }
+        let mut hasher = Argon2Hasher {
+            params: builder.build()?,
+            pepper,
+            bogus_hash: String::new(),
+        };
+        hasher.bogus_hash = hasher.hash(&Secret::new(Password::new("bogus")))?;
+        Ok(hasher)
+    }
+
+    /// Hashes the secret, returning the hash in PHC string format.
+    pub fn hash(&self, password: &Secret<Password>) -> Result<String, password_hash::Error> {
+        let salt = SaltString::generate(&mut OsRng);
+        let password_hash = self
+            .argon2()?
+            .hash_password(password.expose_secret().raw().as_bytes(), &salt)?;
+        Ok(password_hash.to_string())
+    }
+
+    /// Verifies the secret against a hash, which may have been made with
+    /// other parameters, or without the pepper. Hashes made with a different
+    /// pepper can't be verified.
+    pub fn verify(
+        &self,
+        password: &Secret<Password>,
+        password_hash: &str,
+    ) -> Result<(), password_hash::Error> {
+        let parsed_hash = PasswordHash::new(password_hash)?;
+        let key_id = Params::try_from(&parsed_hash)?.keyid().to_vec();
+        let argon2 = if key_id.is_empty() {
+            Argon2::default()
+        } else if key_id == self.params.keyid() {
+            self.argon2()?
+        } else {
+            return Err(password_hash::Error::Password);
+        };
+        argon2.verify_password(password.expose_secret().raw().as_bytes(), &parsed_hash)
+    }
+
+    /// Returns true if a hash wasn't made with the current algorithm,
+    /// parameters and pepper, so it should be replaced the next time the
+    /// secret is known. Hashes that can't be parsed can't be upgraded,
+    /// so they're never reported.
+    pub fn needs_rehash(&self, password_hash: &str) -> bool {
+        let Ok(parsed_hash) = PasswordHash::new(password_hash) else {
+            return false;
+        };
+        let Ok(params) = Params::try_from(&parsed_hash) else {
+            return false;
+        };
+        parsed_hash.algorithm != Algorithm::Argon2id.ident()
+            || parsed_hash.version != Some(Version::V0x13.into())
+            || params.m_cost() != self.params.m_cost()
+            || params.t_cost() != self.params.t_cost()
+            || params.p_cost() != self.params.p_cost()
+            || params.keyid() != self.params.keyid()
+    }
+
+    /// To mitigate a timing attack, verifies a bogus password but ignores the
+    /// result, so that the API takes about the same duration as it would if
+    /// there was a real hash to verify against.
+    pub fn verify_bogus(&self) {
+        let _ = self.verify(&Secret::new(Password::new("bogus")), &self.bogus_hash);
+    }
+
+    fn argon2(&self) -> Result<Argon2<'_>, password_hash::Error> {
+        Ok(match &self.pepper {
+            Some(pepper) => Argon2::new_with_secret(
+                pepper.expose_secret(),
+                Algorithm::Argon2id,
+                Version::V0x13,
+                self.params.clone(),
+            )?,
+            None => Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone()),
+        })
+    }
+}
+
+impl Default for Argon2Hasher {
+    fn default() -> Self {
+        Argon2Hasher::new(&HashingParams::default(), None).expect("default params are valid")
+    }
+}
+
+/// Returns the fewest iterations OWASP recommends using with the memory (in KiB).
+fn min_iterations(memory_kib: u32) -> u32 {
+    MIN_ITERATIONS
+        .iter()
+        .find(|(memory, _)| memory_kib >= *memory)
+        .map_or(u32::MAX, |(_, iterations)| *iterations)
+}
+
+/// Derives the ID recorded in hashes made with the pepper.
+fn pepper_key_id(pepper: &Secret<Vec<u8>>) -> KeyId {
+    let digest = Sha256::digest(pepper.expose_secret());
+    KeyId::new(&digest[..PEPPER_KEY_ID_LENGTH]).expect("key ID is short enough")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn password(raw: &str) -> Secret<Password> {
+        Secret::new(Password::new(raw))
+    }
+
+    fn pepper(raw: &str) -> Option<Secret<Vec<u8>>> {
+        Some(Secret::new(raw.as_bytes().to_vec()))
+    }
+
+    #[test]
+    fn hash_and_verify() {
+        let params = HashingParams {
+            memory_kib: MIN_MEMORY_KIB,
+            iterations: 5,
+            parallelism: 2,
+        };
+        let hasher = Argon2Hasher::new(&params, None).unwrap();
+        let hash = hasher.hash(&password("tangerine-lantern-42")).unwrap();
+        assert!(
+            hash.starts_with("$argon2id$v=19$m=7168,t=5,p=2$"),
+            "{}",
+            hash
+        );
+        assert!(hasher
+            .verify(&password("tangerine-lantern-42"), &hash)
+            .is_ok());
+        assert!(hasher
+            .verify(&password("velvet-compass-97"), &hash)
+            .is_err());
+        assert!(!hasher.needs_rehash(&hash));
+        assert!(hasher
+            .bogus_hash
+            .starts_with("$argon2id$v=19$m=7168,t=5,p=2$"));
+
+        // hashes made with other parameters still verify, but need rehashing
+        let default_hasher = Argon2Hasher::default();
+        assert!(default_hasher
+            .verify(&password("tangerine-lantern-42"), &hash)
+            .is_ok());
+        assert!(default_hasher.needs_rehash(&hash));
+        let legacy_hash =
+            "$argon2id$v=19$m=16,t=2,p=1$ZlpXbUc0MUw5eVBBbmcxcQ$r79YwaBmNT2s6MplBZYgUw";
+        assert!(default_hasher.needs_rehash(legacy_hash));
+        assert!(!default_hasher.needs_rehash("not-a-hash"));
+    }
+
+    #[test]
+    fn peppered_hashes() {
+        let unpeppered = Argon2Hasher::default();
+        let peppered = Argon2Hasher::new(&HashingParams::default(), pepper("pepper-1")).unwrap();
+        let other_pepper =
+            Argon2Hasher::new(&HashingParams::default(), pepper("pepper-2")).unwrap();
+
+        let hash = peppered.hash(&password("tangerine-lantern-42")).unwrap();
+        assert!(hash.contains(",keyid="), "{}", hash);
+        assert!(peppered
+            .verify(&password("tangerine-lantern-42"), &hash)
+            .is_ok());
+        assert!(!peppered.needs_rehash(&hash));
+        // the pepper is needed to verify the hash
+        assert!(unpeppered
+            .verify(&password("tangerine-lantern-42"), &hash)
+            .is_err());
+        assert!(other_pepper
+            .verify(&password("tangerine-lantern-42"), &hash)
+            .is_err());
+
+        // hashes made before the pepper was introduced still verify, but need rehashing
+        let unpeppered_hash = unpeppered.hash(&password("tangerine-lantern-42")).unwrap();
+        assert!(peppered
+            .verify(&password("tangerine-lantern-42"), &unpeppered_hash)
+            .is_ok());
+        assert!(peppered.needs_rehash(&unpeppered_hash));
+        assert!(unpeppered.needs_rehash(&hash));
+    }
+
+    #[test]
+    fn invalid_params() {
+        let params = HashingParams {
+            memory_kib: MIN_MEMORY_KIB - 1,
+            ..HashingParams::default()
+        };
+        assert!(matches!(
+            Argon2Hasher::new(&params, None),
+            Err(HashingParamsError::MemoryTooSmall(MIN_MEMORY_KIB))
+        ));
+        let params = HashingParams {
+            parallelism: 0,
+            ..HashingParams::default()
+        };
+        assert!(matches!(
+            Argon2Hasher::new(&params, None),
+            Err(HashingParamsError::InvalidParams(_))
+        ));
+    }
+
+    #[test]
+    fn too_few_iterations() {
+        let hasher = |memory_kib: u32, iterations: u32| {
+            Argon2Hasher::new(
+                &HashingParams {
+                    memory_kib,
+                    iterations,
+                    parallelism: 1,
+                },
+                None,
+            )
+        };
+        assert!(matches!(
+            hasher(19 * 1024, 1),
+            Err(HashingParamsError::TooFewIterations(19456, 2))
+        ));
+        assert!(matches!(
+            hasher(MIN_MEMORY_KIB, 4),
+            Err(HashingParamsError::TooFewIterations(MIN_MEMORY_KIB, 5))
+        ));
+        assert!(matches!(
+            hasher(46 * 1024 - 1, 1),
+            Err(HashingParamsError::TooFewIterations(_, 2))
+        ));
+        assert!(hasher(46 * 1024, 1).is_ok());
+        assert!(hasher(12 * 1024, 3).is_ok());
+    }
+}
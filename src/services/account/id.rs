@@ -5,6 +5,14 @@ use uuid::Uuid;
 #[derive(Debug)]
 pub enum ID {
     Acct,
+    Evt,
+    Rcode,
+    Pwhist,
+    Pkey,
+    Client,
+    Svc,
+    Secret,
+    Ident,
 }
 
 impl ID {
@@ -1,3 +1,5 @@
+use std::str::FromStr;
+
 use chrono::{DateTime, Utc};
 #[cfg(test)]
 use secrecy::SerializableSecret;
@@ -7,6 +9,8 @@ use serde::Deserialize;
 use serde::Serialize;
 use validify::{field_err, Validate, ValidationError};
 
+use super::error::ParseAccountStatusError;
+
 /// Tuple struct wrapper around String so that we can implement [SerializableSecret]
 /// only in the `test` configuration (i.e., during unit tests). Rust doesn't let you
 /// implement an interface on a type defined in another crate, so we can't implement
@@ -38,7 +42,7 @@ impl Zeroize for Password {
 impl CloneableSecret for Password {}
 
 impl DebugSecret for Password {
-    fn debug_secret(f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {        
+    fn debug_secret(f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
         String::debug_secret(f)
     }
 }
@@ -56,6 +60,43 @@ pub struct NewAccount {
     pub display_name: Option<String>,
 }
 
+/// The lifecycle status of an account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountStatus {
+    /// The account can be used to sign in.
+    Active,
+    /// The account has been deactivated, and can't be used to sign in
+    /// until it is reactivated.
+    Deactivated,
+    /// The account has been deleted, and its personal data erased.
+    /// Deleted accounts can't be reactivated.
+    Deleted,
+}
+
+impl AccountStatus {
+    /// Returns the name used to store this status.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountStatus::Active => "active",
+            AccountStatus::Deactivated => "deactivated",
+            AccountStatus::Deleted => "deleted",
+        }
+    }
+}
+
+impl FromStr for AccountStatus {
+    type Err = ParseAccountStatusError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active" => Ok(AccountStatus::Active),
+            "deactivated" => Ok(AccountStatus::Deactivated),
+            "deleted" => Ok(AccountStatus::Deleted),
+            _ => Err(ParseAccountStatusError(s.to_string())),
+        }
+    }
+}
+
 /// Represents a full account record.
 #[derive(Debug, Clone)]
 pub struct Account {
@@ -63,12 +104,32 @@ pub struct Account {
     pub id: String,
     /// Account email address.
     pub email: String,
-    /// Hash of the account's password.
-    pub password_hash: String,
+    /// Hash of the account's password, if it has one. Accounts created by
+    /// signing in with an emailed code don't, until one is set.
+    pub password_hash: Option<String>,
     /// Optional display name suitable for showing on screen.
     pub display_name: Option<String>,
     /// When this account was created.
     pub created_at: DateTime<Utc>,
+    /// When the account holder proved they control `email`, if ever.
+    pub email_verified_at: Option<DateTime<Utc>>,
+    /// A new email address the account holder asked to change to.
+    /// It replaces `email` only once it has been verified.
+    pub pending_email: Option<String>,
+    /// Whether the account is active, deactivated, or deleted.
+    pub status: AccountStatus,
+}
+
+impl Account {
+    /// Returns true if the account's current email address has been verified.
+    pub fn email_verified(&self) -> bool {
+        self.email_verified_at.is_some()
+    }
+
+    /// Returns true if the account can be used to sign in.
+    pub fn is_active(&self) -> bool {
+        self.status == AccountStatus::Active
+    }
 }
 
 /// Represents credentials used to authenticate an account when signing in.
@@ -78,20 +139,151 @@ pub struct AccountCredentials {
     pub email: String,
     /// Account password.
     pub password: Secret<Password>,
+    /// An unused recovery code, if the account holder has lost access to
+    /// their second factor. Only [AccountService::authenticate](super::AccountService::authenticate)
+    /// checks this.
+    pub recovery_code: Option<Secret<Password>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Validate)]
 pub struct NewAccountCredentials {
     /// The new password.
     pub password: Secret<Password>,
-    /// Optional new email address.
+    /// Optional new email address. This must be verified before it
+    /// replaces the account's current email address.
+    #[validate(email)]
     pub email: Option<String>,
 }
 
+/// Represents a request to set a new password using a password reset token.
+#[derive(Debug, Validate)]
+pub struct PasswordReset {
+    /// The password reset token that was sent to the account holder.
+    pub token: String,
+    /// The new password.
+    #[validate(custom(non_empty_password))]
+    pub password: Secret<Password>,
+}
+
+/// Represents a stored password reset token.
+#[derive(Debug, Clone)]
+pub struct PasswordResetToken {
+    /// SHA-256 hash of the token.
+    pub token_hash: String,
+    /// ID of the account the token can reset.
+    pub account_id: String,
+    /// When the token was issued.
+    pub created_at: DateTime<Utc>,
+    /// When the token expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Represents a stored email verification token.
+#[derive(Debug, Clone)]
+pub struct EmailVerificationToken {
+    /// SHA-256 hash of the token.
+    pub token_hash: String,
+    /// ID of the account the token was issued for.
+    pub account_id: String,
+    /// The email address the token was sent to, which it verifies.
+    pub email: String,
+    /// When the token was issued.
+    pub created_at: DateTime<Utc>,
+    /// When the token expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Tracks an account's recent failed authentications, so that repeated
+/// guesses are slowed down and eventually locked out. Each failure delays the
+/// next attempt for exponentially longer, until too many failures within a
+/// window lock the account for a fixed period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthenticationFailures {
+    /// ID of the account that failed to authenticate.
+    pub account_id: String,
+    /// Number of failures since `first_failed_at`.
+    pub failed_attempts: i32,
+    /// When the first of these failures happened.
+    pub first_failed_at: DateTime<Utc>,
+    /// Authentication attempts are refused until this time.
+    pub locked_until: DateTime<Utc>,
+}
+
+/// A password that an account used to have, kept so that it can't be reused.
+#[derive(Debug, Clone)]
+pub struct PasswordHistoryEntry {
+    /// Unique ID.
+    pub id: String,
+    /// ID of the account that used the password.
+    pub account_id: String,
+    /// Argon2 hash of the password.
+    pub password_hash: String,
+    /// When the password was replaced by a new one.
+    pub replaced_at: DateTime<Utc>,
+}
+
+/// Represents a request to sign in without a password, by having a
+/// sign-in code and token emailed to the given address.
+#[derive(Debug, Validate)]
+pub struct SignInRequest {
+    /// The email address to send the sign-in code and token to.
+    #[validate(email)]
+    pub email: String,
+}
+
+/// Represents a stored sign-in token, along with the short code sent with it.
+/// Either one can be exchanged for a session, but only once, and there is at
+/// most one outstanding for each email address.
+#[derive(Debug, Clone)]
+pub struct SignInToken {
+    /// SHA-256 hash of the token.
+    pub token_hash: String,
+    /// The email address the token was sent to. This may not belong to
+    /// an account yet, in which case one is created when the token is used.
+    pub email: String,
+    /// Argon2 hash of the code. Codes are short enough to guess by brute
+    /// force, so they are hashed like passwords rather than like tokens.
+    pub code_hash: String,
+    /// When the token was issued.
+    pub created_at: DateTime<Utc>,
+    /// When the token expires.
+    pub expires_at: DateTime<Utc>,
+    /// Number of incorrect codes presented for this token.
+    pub failed_attempts: i32,
+}
+
+/// Represents a stored recovery code. Like passwords, recovery codes are
+/// hashed with Argon2, and each one can be used only once.
+#[derive(Debug, Clone)]
+pub struct RecoveryCode {
+    /// Unique ID.
+    pub id: String,
+    /// ID of the account the code was generated for.
+    pub account_id: String,
+    /// Argon2 hash of the code.
+    pub code_hash: String,
+    /// When the code was generated.
+    pub created_at: DateTime<Utc>,
+    /// When the code was used, if it has been.
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+/// Summarizes an account's current set of recovery codes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveryCodeSummary {
+    /// Number of codes that have not yet been used.
+    pub remaining: usize,
+    /// When the current set was generated, if one has been.
+    pub generated_at: Option<DateTime<Utc>>,
+}
+
 /// Validates that the contents of the Secret<Password> field are non-empty.
 fn non_empty_password(secret: &Secret<Password>) -> Result<(), ValidationError> {
     if secret.expose_secret().raw().is_empty() {
-        Err(field_err!("empty_password", "The password must be at least one character"))
+        Err(field_err!(
+            "empty_password",
+            "The password must be at least one character"
+        ))
     } else {
         Ok(())
     }
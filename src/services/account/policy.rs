@@ -0,0 +1,563 @@
+//! The rules new passwords must follow. Besides length and character class
+//! requirements, passwords are scored with a simplified version of the
+//! [zxcvbn](https://github.com/dropbox/zxcvbn) strength estimator, which
+//! estimates how many guesses an attacker would need by finding the cheapest
+//! way to build the password out of common passwords, personal information,
+//! repeated characters, sequences, and random characters.
+
+use std::str::FromStr;
+
+use secrecy::{ExposeSecret, Secret};
+use validify::{ValidationError, ValidationErrors};
+
+use super::{
+    error::{ParseCharacterClassError, PasswordPolicyError},
+    models::Password,
+};
+
+/// The shortest minimum length a [PasswordPolicy] can require.
+pub const MIN_PASSWORD_LENGTH: usize = 12;
+/// The longest maximum length a [PasswordPolicy] can allow. Hashing very long
+/// passwords is slow, so allowing them would make it easy to overload the service.
+pub const MAX_PASSWORD_LENGTH: usize = 1024;
+/// The most previous passwords a [PasswordPolicy] can prevent reusing. Each
+/// one has to be checked by verifying its hash, which is deliberately slow.
+pub const MAX_PASSWORD_HISTORY: usize = 24;
+/// The highest strength score.
+pub const MAX_STRENGTH: u8 = 4;
+/// Parts of an email address or display name shorter than this
+/// aren't treated as personal information.
+const MIN_PERSONAL_INFO_LENGTH: usize = 3;
+/// The minimum number of guesses (as powers of two) for each strength score
+/// above zero. These match zxcvbn's thresholds of 10^3, 10^6, 10^8 and 10^10.
+const STRENGTH_THRESHOLDS: [f64; 4] = [9.97, 19.93, 26.58, 33.22];
+/// The minimum length of a run of repeated characters or a sequence.
+const MIN_PATTERN_LENGTH: usize = 3;
+/// Commonly used passwords and words, most common first.
+const COMMON_PASSWORDS: &[&str] = &[
+    "password",
+    "123456",
+    "12345678",
+    "qwerty",
+    "123456789",
+    "12345",
+    "1234",
+    "111111",
+    "1234567",
+    "dragon",
+    "123123",
+    "baseball",
+    "abc123",
+    "football",
+    "monkey",
+    "letmein",
+    "696969",
+    "shadow",
+    "master",
+    "666666",
+    "qwertyuiop",
+    "123321",
+    "mustang",
+    "1234567890",
+    "michael",
+    "654321",
+    "superman",
+    "1qaz2wsx",
+    "7777777",
+    "121212",
+    "000000",
+    "qazwsx",
+    "123qwe",
+    "killer",
+    "trustno1",
+    "jordan",
+    "jennifer",
+    "zxcvbnm",
+    "asdfgh",
+    "hunter",
+    "buster",
+    "soccer",
+    "harley",
+    "batman",
+    "andrew",
+    "tigger",
+    "sunshine",
+    "iloveyou",
+    "charlie",
+    "robert",
+    "thomas",
+    "hockey",
+    "ranger",
+    "daniel",
+    "starwars",
+    "112233",
+    "george",
+    "computer",
+    "michelle",
+    "jessica",
+    "pepper",
+    "zxcvbn",
+    "555555",
+    "11111111",
+    "131313",
+    "freedom",
+    "777777",
+    "maggie",
+    "159753",
+    "aaaaaa",
+    "ginger",
+    "princess",
+    "joshua",
+    "cheese",
+    "amanda",
+    "summer",
+    "love",
+    "ashley",
+    "nicole",
+    "chelsea",
+    "matthew",
+    "access",
+    "yankees",
+    "987654321",
+    "dallas",
+    "austin",
+    "thunder",
+    "taylor",
+    "matrix",
+    "admin",
+    "welcome",
+    "login",
+    "secret",
+    "test",
+    "guest",
+    "changeme",
+    "default",
+    "passw0rd",
+    "password1",
+    "hello",
+    "winter",
+    "spring",
+    "autumn",
+    "football1",
+    "qwerty123",
+    "monday",
+    "friday",
+    "flower",
+    "orange",
+    "banana",
+    "cookie",
+];
+
+/// The kinds of characters a [PasswordPolicy] can require.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharacterClass {
+    Lowercase,
+    Uppercase,
+    Digit,
+    Symbol,
+}
+
+impl CharacterClass {
+    /// Returns the name used to configure this class.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CharacterClass::Lowercase => "lowercase",
+            CharacterClass::Uppercase => "uppercase",
+            CharacterClass::Digit => "digit",
+            CharacterClass::Symbol => "symbol",
+        }
+    }
+
+    /// Returns true if the character belongs to this class. Letters
+    /// without case (e.g., in Chinese) count as symbols.
+    fn contains(&self, c: char) -> bool {
+        match self {
+            CharacterClass::Lowercase => c.is_lowercase(),
+            CharacterClass::Uppercase => c.is_uppercase(),
+            CharacterClass::Digit => c.is_ascii_digit(),
+            CharacterClass::Symbol => !(c.is_lowercase() || c.is_uppercase() || c.is_ascii_digit()),
+        }
+    }
+
+    /// Returns roughly how many different characters belong to this class.
+    fn cardinality(&self) -> u32 {
+        match self {
+            CharacterClass::Lowercase | CharacterClass::Uppercase => 26,
+            CharacterClass::Digit => 10,
+            CharacterClass::Symbol => 33,
+        }
+    }
+}
+
+impl FromStr for CharacterClass {
+    type Err = ParseCharacterClassError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lowercase" => Ok(CharacterClass::Lowercase),
+            "uppercase" => Ok(CharacterClass::Uppercase),
+            "digit" => Ok(CharacterClass::Digit),
+            "symbol" => Ok(CharacterClass::Symbol),
+            _ => Err(ParseCharacterClassError(s.to_string())),
+        }
+    }
+}
+
+const CHARACTER_CLASSES: [CharacterClass; 4] = [
+    CharacterClass::Lowercase,
+    CharacterClass::Uppercase,
+    CharacterClass::Digit,
+    CharacterClass::Symbol,
+];
+
+/// The rules that new passwords must follow. Lengths are counted in characters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasswordPolicy {
+    /// The minimum length, which must be at least [MIN_PASSWORD_LENGTH].
+    pub min_length: usize,
+    /// The maximum length, which can't be more than [MAX_PASSWORD_LENGTH].
+    pub max_length: usize,
+    /// The kinds of characters every password must contain at least one of.
+    pub required_classes: Vec<CharacterClass>,
+    /// Whether passwords may contain the account's email address
+    /// or display name, or parts of them.
+    pub reject_personal_info: bool,
+    /// The minimum strength score, from 0 (guessable in under a thousand
+    /// guesses) to [MAX_STRENGTH] (needs more than ten billion guesses).
+    pub min_strength: u8,
+    /// The number of previous passwords that can't be reused, which can't be
+    /// more than [MAX_PASSWORD_HISTORY]. The current password can never be
+    /// reused, even if this is zero.
+    pub history_size: usize,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: MIN_PASSWORD_LENGTH,
+            max_length: 128,
+            required_classes: Vec::new(),
+            reject_personal_info: true,
+            min_strength: 3,
+            history_size: 5,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Returns an error if the policy is weaker than allowed, or can't be satisfied.
+    pub fn check(&self) -> Result<(), PasswordPolicyError> {
+        if self.min_length < MIN_PASSWORD_LENGTH {
+            return Err(PasswordPolicyError::MinLengthTooShort(MIN_PASSWORD_LENGTH));
+        }
+        if self.max_length < self.min_length || self.max_length > MAX_PASSWORD_LENGTH {
+            return Err(PasswordPolicyError::InvalidMaxLength(MAX_PASSWORD_LENGTH));
+        }
+        if self.min_strength > MAX_STRENGTH {
+            return Err(PasswordPolicyError::InvalidMinStrength(MAX_STRENGTH));
+        }
+        if self.history_size > MAX_PASSWORD_HISTORY {
+            return Err(PasswordPolicyError::InvalidHistorySize(
+                MAX_PASSWORD_HISTORY,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Validates a new password against this policy, given the personal
+    /// information (e.g., the email address and display name) of the account
+    /// it is for. Every rule the password breaks is returned as an error on
+    /// the `password` field.
+    pub fn validate(
+        &self,
+        password: &Secret<Password>,
+        personal_info: &[&str],
+    ) -> Result<(), ValidationErrors> {
+        let password = password.expose_secret().raw();
+        let length = password.chars().count();
+        let mut errors = ValidationErrors::new();
+        if length > self.max_length {
+            // don't spend any more time on passwords that are too long
+            errors.add(
+                password_error("password_too_long")
+                    .with_param("max", &self.max_length)
+                    .with_message(format!(
+                        "The password must be at most {} characters",
+                        self.max_length
+                    )),
+            );
+            return Err(errors);
+        }
+        if length < self.min_length {
+            errors.add(
+                password_error("password_too_short")
+                    .with_param("min", &self.min_length)
+                    .with_message(format!(
+                        "The password must be at least {} characters",
+                        self.min_length
+                    )),
+            );
+        }
+        for class in &self.required_classes {
+            if !password.chars().any(|c| class.contains(c)) {
+                errors.add(
+                    password_error("password_missing_character_class")
+                        .with_param("class", &class.as_str())
+                        .with_message(format!(
+                            "The password must contain at least one {} character",
+                            class.as_str()
+                        )),
+                );
+            }
+        }
+
+        let personal_info = personal_info_parts(personal_info);
+        let lowercase_password = password.to_lowercase();
+        if self.reject_personal_info
+            && personal_info
+                .iter()
+                .any(|part| lowercase_password.contains(part.as_str()))
+        {
+            errors.add(
+                password_error("password_contains_personal_info").with_message(
+                    "The password must not contain the email address or display name".to_string(),
+                ),
+            );
+        }
+        let strength = strength(password, &personal_info);
+        if strength < self.min_strength {
+            errors.add(
+                password_error("password_too_weak")
+                    .with_param("strength", &strength)
+                    .with_param("min", &self.min_strength)
+                    .with_message(
+                        "The password is too easy to guess. Avoid common passwords, \
+                        words, repeated characters and sequences"
+                            .to_string(),
+                    ),
+            );
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Returns a new error on the `password` field with the given code.
+fn password_error(code: &'static str) -> ValidationError {
+    ValidationError::new_field_named("password", code)
+}
+
+/// Splits personal information into the lowercase parts that a password
+/// shouldn't contain: each value as a whole and the words within it. Only
+/// the local part of an email address is used, since the domain is often
+/// shared with many other accounts.
+fn personal_info_parts(personal_info: &[&str]) -> Vec<String> {
+    let mut parts = Vec::new();
+    for info in personal_info {
+        let info = info.trim().to_lowercase();
+        let info = match info.split_once('@') {
+            Some((local_part, _)) => local_part.to_string(),
+            None => info,
+        };
+        parts.extend(
+            info.split(|c: char| !c.is_alphanumeric())
+                .map(str::to_string),
+        );
+        parts.push(info);
+    }
+    parts.retain(|part| part.chars().count() >= MIN_PERSONAL_INFO_LENGTH);
+    parts.sort();
+    parts.dedup();
+    parts
+}
+
+/// Returns the password's strength score, from 0 to [MAX_STRENGTH].
+fn strength(password: &str, personal_info: &[String]) -> u8 {
+    let bits = guesses_log2(password, personal_info);
+    STRENGTH_THRESHOLDS
+        .iter()
+        .filter(|threshold| bits >= **threshold)
+        .count() as u8
+}
+
+/// Estimates the number of guesses needed to find the password, as a power of two.
+/// This finds the cheapest way to cover the password with patterns, where a
+/// common password costs its rank in the list, personal information costs one
+/// guess, runs of repeated characters and sequences cost about as much as
+/// their first character, and every other character costs the number of
+/// characters it could have been.
+fn guesses_log2(password: &str, personal_info: &[String]) -> f64 {
+    let chars: Vec<char> = password.chars().collect();
+    let lowercase: Vec<char> = chars
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    let cardinality: u32 = CHARACTER_CLASSES
+        .iter()
+        .filter(|class| chars.iter().any(|c| class.contains(*c)))
+        .map(CharacterClass::cardinality)
+        .sum();
+    let char_bits = (cardinality.max(1) as f64).log2();
+    let dictionary: Vec<(Vec<char>, f64)> = personal_info
+        .iter()
+        .map(|word| (word.chars().collect(), 0.0))
+        .chain(
+            COMMON_PASSWORDS
+                .iter()
+                .enumerate()
+                .map(|(rank, word)| (word.chars().collect(), ((rank + 1) as f64).log2())),
+        )
+        .collect();
+
+    // best[i] is the cheapest way to guess the first i characters
+    let mut best = vec![f64::INFINITY; chars.len() + 1];
+    best[0] = 0.0;
+    for start in 0..chars.len() {
+        let mut relax = |end: usize, bits: f64| {
+            best[end] = best[end].min(best[start] + bits);
+        };
+        relax(start + 1, char_bits);
+
+        for (word, bits) in &dictionary {
+            if lowercase[start..].starts_with(word) {
+                let end = start + word.len();
+                // trying capitalized variants doubles the guesses
+                let capitalized = chars[start..end].iter().any(|c| c.is_uppercase());
+                relax(end, bits + if capitalized { 1.0 } else { 0.0 });
+            }
+        }
+
+        let repeated = run_length(&chars[start..], |a, b| a == b);
+        let ascending = run_length(&chars[start..], |a, b| b as i64 - a as i64 == 1);
+        let descending = run_length(&chars[start..], |a, b| a as i64 - b as i64 == 1);
+        for (run, extra_bits) in [(repeated, 0.0), (ascending, 0.0), (descending, 1.0)] {
+            for length in MIN_PATTERN_LENGTH..=run {
+                relax(
+                    start + length,
+                    char_bits + (length as f64).log2() + extra_bits,
+                );
+            }
+        }
+    }
+    best[chars.len()]
+}
+
+/// Returns the length of the run at the start of the characters, where
+/// each pair of neighbouring characters satisfies the predicate.
+fn run_length(chars: &[char], follows: impl Fn(char, char) -> bool) -> usize {
+    if chars.is_empty() {
+        return 0;
+    }
+    1 + chars
+        .windows(2)
+        .take_while(|pair| follows(pair[0], pair[1]))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(result: Result<(), ValidationErrors>) -> Vec<String> {
+        result
+            .err()
+            .map(|errors| errors.errors().iter().map(ValidationError::code).collect())
+            .unwrap_or_default()
+    }
+
+    fn password(raw: &str) -> Secret<Password> {
+        Secret::new(Password::new(raw))
+    }
+
+    #[test]
+    fn check_policy() {
+        assert!(PasswordPolicy::default().check().is_ok());
+        for invalid in [
+            PasswordPolicy {
+                min_length: 8,
+                ..Default::default()
+            },
+            PasswordPolicy {
+                min_length: 20,
+                max_length: 16,
+                ..Default::default()
+            },
+            PasswordPolicy {
+                max_length: MAX_PASSWORD_LENGTH + 1,
+                ..Default::default()
+            },
+            PasswordPolicy {
+                min_strength: MAX_STRENGTH + 1,
+                ..Default::default()
+            },
+            PasswordPolicy {
+                history_size: MAX_PASSWORD_HISTORY + 1,
+                ..Default::default()
+            },
+        ] {
+            assert!(invalid.check().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn validate_password() {
+        let policy = PasswordPolicy {
+            required_classes: vec![CharacterClass::Uppercase, CharacterClass::Digit],
+            ..Default::default()
+        };
+        let personal_info = ["jane.doe@example.com", "Jane Doe"];
+        let validate = |raw: &str| codes(policy.validate(&password(raw), &personal_info));
+
+        assert!(validate("Tangerine-lantern-42").is_empty());
+        assert_eq!(
+            vec!["password_too_short", "password_missing_character_class"],
+            validate("tangerine-4")
+        );
+        assert_eq!(vec!["password_too_long"], validate(&"Aa1-".repeat(100)));
+        assert_eq!(
+            vec!["password_contains_personal_info", "password_too_weak"],
+            validate("JaneDoe12345")
+        );
+        assert_eq!(vec!["password_too_weak"], validate("Password1234"));
+
+        // each missing class is reported
+        let error = policy
+            .validate(&password("tangerine-lantern"), &[])
+            .unwrap_err();
+        let classes: Vec<_> = error
+            .errors()
+            .iter()
+            .map(|error| error.params()["class"].clone())
+            .collect();
+        assert_eq!(vec!["uppercase", "digit"], classes);
+
+        // personal information only counts against the password if the policy says so
+        let policy = PasswordPolicy {
+            reject_personal_info: false,
+            ..Default::default()
+        };
+        assert!(
+            codes(policy.validate(&password("jane-vermilion-harbour"), &personal_info)).is_empty()
+        );
+    }
+
+    #[test]
+    fn strength_score() {
+        let strength = |raw: &str| strength(raw, &[]);
+        assert_eq!(0, strength("password"));
+        assert_eq!(0, strength("aaaaaaaaaaaaaaaa"));
+        assert_eq!(1, strength("abcdefghijklmnop1"));
+        assert_eq!(1, strength("Password123!"));
+        assert_eq!(1, strength("qwertyletmein99"));
+        assert_eq!(2, strength("letmeinxkqz"));
+        assert_eq!(3, strength("letmeinxkqzv"));
+        assert_eq!(4, strength("tangerine-lantern-42"));
+        assert_eq!(4, strength("xkqvmtrzwplb"));
+        // personal information is as easy to guess as the most common password
+        assert_eq!(0, super::strength("janedoe", &["janedoe".to_string()]));
+    }
+}
@@ -4,13 +4,136 @@ pub mod fake;
 pub mod postgres;
 
 use axum::async_trait;
+use chrono::{DateTime, Utc};
 use error::AccountStoreError;
 
-use crate::services::account::models::Account;
+use crate::services::{
+    account::models::{
+        Account, AccountStatus, AuthenticationFailures, EmailVerificationToken,
+        PasswordHistoryEntry, PasswordResetToken, RecoveryCode, SignInToken,
+    },
+    audit::models::AuditEvent,
+};
 
+/// Stores accounts and their tokens. Methods that change an account also
+/// take the [AuditEvent] describing the change, which implementations
+/// should record atomically with it (e.g., in the same transaction).
 #[async_trait]
 pub trait AccountStore: Send + Sync + 'static {
-    async fn insert(&self, account: &Account) -> Result<(), AccountStoreError>;
+    async fn insert(&self, account: &Account, event: &AuditEvent) -> Result<(), AccountStoreError>;
+    async fn load_by_id(&self, id: &str) -> Result<Option<Account>, AccountStoreError>;
     async fn load_by_email(&self, email: &str) -> Result<Option<Account>, AccountStoreError>;
-    async fn update(&self, account: &Account) -> Result<(), AccountStoreError>;
+    async fn update(&self, account: &Account, event: &AuditEvent) -> Result<(), AccountStoreError>;
+    async fn update_status(
+        &self,
+        id: &str,
+        status: AccountStatus,
+        event: &AuditEvent,
+    ) -> Result<(), AccountStoreError>;
+    /// Records an event that isn't accompanied by a change to the account,
+    /// such as a failed authentication attempt.
+    async fn insert_event(&self, event: &AuditEvent) -> Result<(), AccountStoreError>;
+    /// Erases the source IP address and user agent recorded in the account's
+    /// audit events, which otherwise never change.
+    async fn erase_event_details(&self, account_id: &str) -> Result<(), AccountStoreError>;
+    async fn load_authentication_failures(
+        &self,
+        account_id: &str,
+    ) -> Result<Option<AuthenticationFailures>, AccountStoreError>;
+    /// Atomically counts another failed authentication for the account, and
+    /// returns its updated [AuthenticationFailures]. The count starts again
+    /// from one at `now` if the previous failures began before `window_start`,
+    /// or had already reached `max_attempts`.
+    async fn increment_authentication_failures(
+        &self,
+        account_id: &str,
+        now: DateTime<Utc>,
+        window_start: DateTime<Utc>,
+        max_attempts: i32,
+    ) -> Result<AuthenticationFailures, AccountStoreError>;
+    /// Refuses authentication attempts for the account until `locked_until`,
+    /// unless it's already locked for longer.
+    async fn extend_lockout(
+        &self,
+        account_id: &str,
+        locked_until: DateTime<Utc>,
+    ) -> Result<(), AccountStoreError>;
+    async fn delete_authentication_failures(
+        &self,
+        account_id: &str,
+    ) -> Result<(), AccountStoreError>;
+    /// Adds a password the account no longer uses to its history, and then
+    /// discards all but the `keep` most recently replaced passwords.
+    async fn add_password_history(
+        &self,
+        entry: &PasswordHistoryEntry,
+        keep: usize,
+    ) -> Result<(), AccountStoreError>;
+    /// Loads the account's password history, most recently replaced first.
+    async fn load_password_history(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<PasswordHistoryEntry>, AccountStoreError>;
+    async fn delete_password_history(&self, account_id: &str) -> Result<(), AccountStoreError>;
+    async fn insert_password_reset_token(
+        &self,
+        token: &PasswordResetToken,
+    ) -> Result<(), AccountStoreError>;
+    async fn load_password_reset_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<PasswordResetToken>, AccountStoreError>;
+    async fn delete_password_reset_tokens(&self, account_id: &str)
+        -> Result<(), AccountStoreError>;
+    async fn insert_email_verification_token(
+        &self,
+        token: &EmailVerificationToken,
+    ) -> Result<(), AccountStoreError>;
+    async fn load_email_verification_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<EmailVerificationToken>, AccountStoreError>;
+    async fn delete_email_verification_tokens(
+        &self,
+        account_id: &str,
+    ) -> Result<(), AccountStoreError>;
+    /// Stores a sign-in token, replacing any that is outstanding
+    /// for the same email address.
+    async fn replace_sign_in_token(&self, token: &SignInToken) -> Result<(), AccountStoreError>;
+    async fn load_sign_in_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<SignInToken>, AccountStoreError>;
+    async fn load_sign_in_token_by_email(
+        &self,
+        email: &str,
+    ) -> Result<Option<SignInToken>, AccountStoreError>;
+    /// Atomically counts another incorrect code for the sign-in token, and
+    /// returns the updated count, or None if the token no longer exists.
+    async fn increment_sign_in_token_attempts(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<i32>, AccountStoreError>;
+    async fn delete_sign_in_tokens(&self, email: &str) -> Result<(), AccountStoreError>;
+    /// Replaces all of the account's recovery codes with the new set.
+    async fn replace_recovery_codes(
+        &self,
+        account_id: &str,
+        codes: &[RecoveryCode],
+        event: &AuditEvent,
+    ) -> Result<(), AccountStoreError>;
+    /// Loads all of the account's recovery codes, including used ones.
+    async fn load_recovery_codes(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<RecoveryCode>, AccountStoreError>;
+    /// Marks the recovery code as used, but only if it hasn't been used already.
+    /// Returns false if the code was already used (or doesn't exist).
+    async fn mark_recovery_code_used(
+        &self,
+        id: &str,
+        used_at: DateTime<Utc>,
+        event: &AuditEvent,
+    ) -> Result<bool, AccountStoreError>;
+    async fn delete_recovery_codes(&self, account_id: &str) -> Result<(), AccountStoreError>;
 }
@@ -4,33 +4,59 @@ use std::{
 };
 
 use axum::async_trait;
+use chrono::{DateTime, Utc};
 
-use crate::services::account::models::Account;
+use crate::services::{
+    account::models::{
+        Account, AccountStatus, AuthenticationFailures, EmailVerificationToken,
+        PasswordHistoryEntry, PasswordResetToken, RecoveryCode, SignInToken,
+    },
+    audit::{
+        models::AuditEvent,
+        stores::{fake::FakeAuditStore, AuditStore},
+    },
+};
 
 use super::{error::AccountStoreError, AccountStore};
 
 /// The "database" for the FakeAccountStore. This is a pair of maps
 /// each of which stores a key related to an Arc<Account>. The first
 /// uses the account ID as the key, and the second uses the account
-/// email as the key, so that we can load by email.
+/// email as the key, so that we can load by email. Authentication
+/// failures and password histories (most recently replaced first) are kept
+/// in maps keyed by account ID. Password reset,
+/// email verification, and sign-in tokens are kept in separate maps
+/// keyed by token hash, and recovery codes in a map keyed by their ID.
 struct Database {
     id_to_account: HashMap<String, Arc<Account>>,
     email_to_account: HashMap<String, Arc<Account>>,
+    authentication_failures: HashMap<String, AuthenticationFailures>,
+    password_history: HashMap<String, Vec<PasswordHistoryEntry>>,
+    password_reset_tokens: HashMap<String, PasswordResetToken>,
+    email_verification_tokens: HashMap<String, EmailVerificationToken>,
+    sign_in_tokens: HashMap<String, SignInToken>,
+    recovery_codes: HashMap<String, RecoveryCode>,
 }
 
 impl Database {
     fn put(&mut self, account: &Account) {
+        // the email may have changed since the account was last put
+        if let Some(existing) = self.id_to_account.get(&account.id) {
+            let existing_email = existing.email.clone();
+            self.email_to_account.remove(&existing_email);
+        }
         let arc = Arc::new(account.clone());
         self.id_to_account.insert(account.id.clone(), arc.clone());
         self.email_to_account
             .insert(account.email.clone(), arc.clone());
     }
 
+    fn by_id(&self, id: &str) -> Option<Account> {
+        self.id_to_account.get(id).map(|arc| (**arc).clone())
+    }
+
     fn by_email(&self, email: &str) -> Option<Account> {
-        match self.email_to_account.get(email) {
-            None => None,
-            Some(arc) => Some((**arc).clone()),
-        }
+        self.email_to_account.get(email).map(|arc| (**arc).clone())
     }
 
     fn contains_email(&self, email: &str) -> bool {
@@ -44,14 +70,29 @@ pub struct FakeAccountStore {
     /// for unit tests, a Mutex is sufficient and easier to reason about
     /// than a RwLock.
     db: Mutex<Database>,
+    /// Where audit events are recorded.
+    audit_store: FakeAuditStore,
 }
 
 impl FakeAccountStore {
     pub fn new() -> FakeAccountStore {
+        Self::with_audit_store(FakeAuditStore::new())
+    }
+
+    /// Constructs a new [FakeAccountStore] that records audit events in the
+    /// provided [FakeAuditStore], so that tests can read them back.
+    pub fn with_audit_store(audit_store: FakeAuditStore) -> FakeAccountStore {
         FakeAccountStore {
+            audit_store,
             db: Mutex::new(Database {
                 id_to_account: HashMap::new(),
                 email_to_account: HashMap::new(),
+                authentication_failures: HashMap::new(),
+                password_history: HashMap::new(),
+                password_reset_tokens: HashMap::new(),
+                email_verification_tokens: HashMap::new(),
+                sign_in_tokens: HashMap::new(),
+                recovery_codes: HashMap::new(),
             }),
         }
     }
@@ -59,22 +100,358 @@ impl FakeAccountStore {
 
 #[async_trait]
 impl AccountStore for FakeAccountStore {
-    async fn insert(&self, account: &Account) -> Result<(), AccountStoreError> {
-        let mut db = self.db.lock().unwrap();
-
-        if db.contains_email(&account.email) {
-            Err(AccountStoreError::EmailAlreadyExists(account.email.clone()))
-        } else {
+    async fn insert(&self, account: &Account, event: &AuditEvent) -> Result<(), AccountStoreError> {
+        {
+            let mut db = self.db.lock().unwrap();
+            if db.contains_email(&account.email) {
+                return Err(AccountStoreError::EmailAlreadyExists(account.email.clone()));
+            }
             db.put(account);
-            Ok(())
         }
+        self.insert_event(event).await
+    }
+
+    async fn load_by_id(&self, id: &str) -> Result<Option<Account>, AccountStoreError> {
+        Ok(self.db.lock().unwrap().by_id(id))
     }
 
     async fn load_by_email(&self, email: &str) -> Result<Option<Account>, AccountStoreError> {
         Ok(self.db.lock().unwrap().by_email(email))
     }
 
-    async fn update(&self, account: &Account) -> Result<(), AccountStoreError> {
-        Ok(self.db.lock().unwrap().put(account))
+    async fn update(&self, account: &Account, event: &AuditEvent) -> Result<(), AccountStoreError> {
+        {
+            let mut db = self.db.lock().unwrap();
+            if db
+                .by_email(&account.email)
+                .is_some_and(|other| other.id != account.id)
+            {
+                return Err(AccountStoreError::EmailAlreadyExists(account.email.clone()));
+            }
+            db.put(account);
+        }
+        self.insert_event(event).await
+    }
+
+    async fn update_status(
+        &self,
+        id: &str,
+        status: AccountStatus,
+        event: &AuditEvent,
+    ) -> Result<(), AccountStoreError> {
+        {
+            let mut db = self.db.lock().unwrap();
+            if let Some(account) = db.by_id(id) {
+                db.put(&Account { status, ..account });
+            }
+        }
+        self.insert_event(event).await
+    }
+
+    async fn insert_event(&self, event: &AuditEvent) -> Result<(), AccountStoreError> {
+        self.audit_store
+            .insert(event)
+            .await
+            .map_err(|err| AccountStoreError::DatabaseError(err.to_string()))
+    }
+
+    async fn erase_event_details(&self, account_id: &str) -> Result<(), AccountStoreError> {
+        self.audit_store.erase_details(account_id);
+        Ok(())
+    }
+
+    async fn load_authentication_failures(
+        &self,
+        account_id: &str,
+    ) -> Result<Option<AuthenticationFailures>, AccountStoreError> {
+        Ok(self
+            .db
+            .lock()
+            .unwrap()
+            .authentication_failures
+            .get(account_id)
+            .cloned())
+    }
+
+    async fn increment_authentication_failures(
+        &self,
+        account_id: &str,
+        now: DateTime<Utc>,
+        window_start: DateTime<Utc>,
+        max_attempts: i32,
+    ) -> Result<AuthenticationFailures, AccountStoreError> {
+        let mut db = self.db.lock().unwrap();
+        let failures = db
+            .authentication_failures
+            .entry(account_id.to_string())
+            .or_insert_with(|| AuthenticationFailures {
+                account_id: account_id.to_string(),
+                failed_attempts: 0,
+                first_failed_at: now,
+                locked_until: now,
+            });
+        if failures.first_failed_at > window_start && failures.failed_attempts < max_attempts {
+            failures.failed_attempts += 1;
+        } else {
+            failures.failed_attempts = 1;
+            failures.first_failed_at = now;
+        }
+        Ok(failures.clone())
+    }
+
+    async fn extend_lockout(
+        &self,
+        account_id: &str,
+        locked_until: DateTime<Utc>,
+    ) -> Result<(), AccountStoreError> {
+        if let Some(failures) = self
+            .db
+            .lock()
+            .unwrap()
+            .authentication_failures
+            .get_mut(account_id)
+        {
+            failures.locked_until = failures.locked_until.max(locked_until);
+        }
+        Ok(())
+    }
+
+    async fn delete_authentication_failures(
+        &self,
+        account_id: &str,
+    ) -> Result<(), AccountStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .authentication_failures
+            .remove(account_id);
+        Ok(())
+    }
+
+    async fn add_password_history(
+        &self,
+        entry: &PasswordHistoryEntry,
+        keep: usize,
+    ) -> Result<(), AccountStoreError> {
+        let mut db = self.db.lock().unwrap();
+        let history = db
+            .password_history
+            .entry(entry.account_id.clone())
+            .or_default();
+        history.insert(0, entry.clone());
+        history.truncate(keep);
+        Ok(())
+    }
+
+    async fn load_password_history(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<PasswordHistoryEntry>, AccountStoreError> {
+        Ok(self
+            .db
+            .lock()
+            .unwrap()
+            .password_history
+            .get(account_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn delete_password_history(&self, account_id: &str) -> Result<(), AccountStoreError> {
+        self.db.lock().unwrap().password_history.remove(account_id);
+        Ok(())
+    }
+
+    async fn insert_password_reset_token(
+        &self,
+        token: &PasswordResetToken,
+    ) -> Result<(), AccountStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .password_reset_tokens
+            .insert(token.token_hash.clone(), token.clone());
+        Ok(())
+    }
+
+    async fn load_password_reset_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<PasswordResetToken>, AccountStoreError> {
+        Ok(self
+            .db
+            .lock()
+            .unwrap()
+            .password_reset_tokens
+            .get(token_hash)
+            .cloned())
+    }
+
+    async fn delete_password_reset_tokens(
+        &self,
+        account_id: &str,
+    ) -> Result<(), AccountStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .password_reset_tokens
+            .retain(|_, token| token.account_id != account_id);
+        Ok(())
+    }
+
+    async fn insert_email_verification_token(
+        &self,
+        token: &EmailVerificationToken,
+    ) -> Result<(), AccountStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .email_verification_tokens
+            .insert(token.token_hash.clone(), token.clone());
+        Ok(())
+    }
+
+    async fn load_email_verification_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<EmailVerificationToken>, AccountStoreError> {
+        Ok(self
+            .db
+            .lock()
+            .unwrap()
+            .email_verification_tokens
+            .get(token_hash)
+            .cloned())
+    }
+
+    async fn delete_email_verification_tokens(
+        &self,
+        account_id: &str,
+    ) -> Result<(), AccountStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .email_verification_tokens
+            .retain(|_, token| token.account_id != account_id);
+        Ok(())
+    }
+
+    async fn replace_sign_in_token(&self, token: &SignInToken) -> Result<(), AccountStoreError> {
+        let mut db = self.db.lock().unwrap();
+        db.sign_in_tokens
+            .retain(|_, existing| existing.email != token.email);
+        db.sign_in_tokens
+            .insert(token.token_hash.clone(), token.clone());
+        Ok(())
+    }
+
+    async fn load_sign_in_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<SignInToken>, AccountStoreError> {
+        Ok(self
+            .db
+            .lock()
+            .unwrap()
+            .sign_in_tokens
+            .get(token_hash)
+            .cloned())
+    }
+
+    async fn load_sign_in_token_by_email(
+        &self,
+        email: &str,
+    ) -> Result<Option<SignInToken>, AccountStoreError> {
+        Ok(self
+            .db
+            .lock()
+            .unwrap()
+            .sign_in_tokens
+            .values()
+            .find(|token| token.email == email)
+            .cloned())
+    }
+
+    async fn increment_sign_in_token_attempts(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<i32>, AccountStoreError> {
+        Ok(self
+            .db
+            .lock()
+            .unwrap()
+            .sign_in_tokens
+            .get_mut(token_hash)
+            .map(|token| {
+                token.failed_attempts += 1;
+                token.failed_attempts
+            }))
+    }
+
+    async fn delete_sign_in_tokens(&self, email: &str) -> Result<(), AccountStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .sign_in_tokens
+            .retain(|_, token| token.email != email);
+        Ok(())
+    }
+
+    async fn replace_recovery_codes(
+        &self,
+        account_id: &str,
+        codes: &[RecoveryCode],
+        event: &AuditEvent,
+    ) -> Result<(), AccountStoreError> {
+        {
+            let mut db = self.db.lock().unwrap();
+            db.recovery_codes
+                .retain(|_, code| code.account_id != account_id);
+            for code in codes {
+                db.recovery_codes.insert(code.id.clone(), code.clone());
+            }
+        }
+        self.insert_event(event).await
+    }
+
+    async fn load_recovery_codes(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<RecoveryCode>, AccountStoreError> {
+        Ok(self
+            .db
+            .lock()
+            .unwrap()
+            .recovery_codes
+            .values()
+            .filter(|code| code.account_id == account_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn mark_recovery_code_used(
+        &self,
+        id: &str,
+        used_at: DateTime<Utc>,
+        event: &AuditEvent,
+    ) -> Result<bool, AccountStoreError> {
+        {
+            let mut db = self.db.lock().unwrap();
+            match db.recovery_codes.get_mut(id) {
+                Some(code) if code.used_at.is_none() => code.used_at = Some(used_at),
+                _ => return Ok(false),
+            }
+        }
+        self.insert_event(event).await?;
+        Ok(true)
+    }
+
+    async fn delete_recovery_codes(&self, account_id: &str) -> Result<(), AccountStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .recovery_codes
+            .retain(|_, code| code.account_id != account_id);
+        Ok(())
     }
 }
@@ -1,12 +1,16 @@
 //! Implements [AccountStore] backed by a PostgreSQL database
 
 use axum::async_trait;
-use sqlx::{
-    postgres::{PgPoolOptions, PgRow},
-    PgPool, Row,
-};
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, PgPool, Row};
 
-use crate::services::account::models::Account;
+use crate::services::{
+    account::models::{
+        Account, AccountStatus, AuthenticationFailures, EmailVerificationToken,
+        PasswordHistoryEntry, PasswordResetToken, RecoveryCode, SignInToken,
+    },
+    audit::{models::AuditEvent, stores::postgres::insert_event},
+};
 
 use super::{error::AccountStoreError, AccountStore};
 
@@ -16,72 +20,549 @@ impl From<sqlx::Error> for AccountStoreError {
     }
 }
 
+/// The account columns selected by the load methods, in the order [to_account] expects.
+const ACCOUNT_COLUMNS: &str =
+    "id,email,password_hash,display_name,created_at,email_verified_at,pending_email,status";
+
+/// Maps a row containing the [ACCOUNT_COLUMNS] to an [Account].
+fn to_account(row: PgRow) -> Result<Account, sqlx::Error> {
+    let status: String = row.get(7);
+    Ok(Account {
+        id: row.get(0),
+        email: row.get(1),
+        password_hash: row.get(2),
+        display_name: row.get(3),
+        created_at: row.get(4),
+        email_verified_at: row.get(5),
+        pending_email: row.get(6),
+        status: status
+            .parse()
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+    })
+}
+
+/// The sign-in token columns selected by the load methods, in the order
+/// [sign_in_token_from_row] expects.
+const SIGN_IN_TOKEN_COLUMNS: &str =
+    "token_hash,email,code_hash,created_at,expires_at,failed_attempts";
+
+/// Maps a row containing the [SIGN_IN_TOKEN_COLUMNS] to a [SignInToken].
+fn sign_in_token_from_row(row: PgRow) -> SignInToken {
+    SignInToken {
+        token_hash: row.get(0),
+        email: row.get(1),
+        code_hash: row.get(2),
+        created_at: row.get(3),
+        expires_at: row.get(4),
+        failed_attempts: row.get(5),
+    }
+}
+
 pub struct PostgresAccountStore {
     pool: PgPool,
 }
 
 impl PostgresAccountStore {
-    pub async fn new(
-        url: &str,
-        max_connections: u32,
-    ) -> Result<PostgresAccountStore, AccountStoreError> {
-        let pool = PgPoolOptions::new()
-            .max_connections(max_connections)
-            .connect(url)
-            .await?;
-
-        Ok(PostgresAccountStore { pool })
+    pub fn new(pool: PgPool) -> PostgresAccountStore {
+        PostgresAccountStore { pool }
     }
 }
 
 #[async_trait]
 impl AccountStore for PostgresAccountStore {
-    async fn insert(&self, account: &Account) -> Result<(), AccountStoreError> {
+    async fn insert(&self, account: &Account, event: &AuditEvent) -> Result<(), AccountStoreError> {
+        let mut tx = self.pool.begin().await?;
         let result = sqlx::query(
-            "insert into accounts(id,email,password_hash,display_name,created_at) \
-            values ($1,$2,$3,$4,$5)",
+            "insert into accounts(id,email,password_hash,display_name,created_at,\
+            email_verified_at,pending_email,status) values ($1,$2,$3,$4,$5,$6,$7,$8)",
         )
         .bind(&account.id)
         .bind(&account.email)
         .bind(&account.password_hash)
         .bind(&account.display_name)
         .bind(account.created_at)
-        .execute(&self.pool)
+        .bind(account.email_verified_at)
+        .bind(&account.pending_email)
+        .bind(account.status.as_str())
+        .execute(&mut *tx)
         .await;
 
-        result
-            .map_err(|err| match err {
-                sqlx::Error::Database(dberr) if dberr.is_unique_violation() => {
-                    AccountStoreError::EmailAlreadyExists(account.email.clone())
-                }
-                _ => AccountStoreError::DatabaseError(err.to_string()),
-            })
-            .map(|_| ())
+        result.map_err(|err| match err {
+            sqlx::Error::Database(dberr) if dberr.is_unique_violation() => {
+                AccountStoreError::EmailAlreadyExists(account.email.clone())
+            }
+            _ => AccountStoreError::DatabaseError(err.to_string()),
+        })?;
+        insert_event(&mut *tx, event).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn load_by_id(&self, id: &str) -> Result<Option<Account>, AccountStoreError> {
+        Ok(sqlx::query(&format!(
+            "select {} from accounts where id=$1",
+            ACCOUNT_COLUMNS
+        ))
+        .bind(id)
+        .try_map(to_account)
+        .fetch_optional(&self.pool)
+        .await?)
     }
 
     async fn load_by_email(&self, email: &str) -> Result<Option<Account>, AccountStoreError> {
+        Ok(sqlx::query(&format!(
+            "select {} from accounts where email=$1",
+            ACCOUNT_COLUMNS
+        ))
+        .bind(email)
+        .try_map(to_account)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn update(&self, account: &Account, event: &AuditEvent) -> Result<(), AccountStoreError> {
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query(
+            "update accounts set email=$1,password_hash=$2,display_name=$3,\
+            email_verified_at=$4,pending_email=$5,status=$6 where id=$7",
+        )
+        .bind(&account.email)
+        .bind(&account.password_hash)
+        .bind(&account.display_name)
+        .bind(account.email_verified_at)
+        .bind(&account.pending_email)
+        .bind(account.status.as_str())
+        .bind(&account.id)
+        .execute(&mut *tx)
+        .await;
+
+        result.map_err(|err| match err {
+            sqlx::Error::Database(dberr) if dberr.is_unique_violation() => {
+                AccountStoreError::EmailAlreadyExists(account.email.clone())
+            }
+            _ => AccountStoreError::DatabaseError(err.to_string()),
+        })?;
+        insert_event(&mut *tx, event).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn update_status(
+        &self,
+        id: &str,
+        status: AccountStatus,
+        event: &AuditEvent,
+    ) -> Result<(), AccountStoreError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("update accounts set status=$1 where id=$2")
+            .bind(status.as_str())
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        insert_event(&mut *tx, event).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn insert_event(&self, event: &AuditEvent) -> Result<(), AccountStoreError> {
+        Ok(insert_event(&self.pool, event).await?)
+    }
+
+    async fn erase_event_details(&self, account_id: &str) -> Result<(), AccountStoreError> {
+        // the audit_events_no_update rule only allows this kind of update
+        sqlx::query("update audit_events set source_ip=null,user_agent=null where account_id=$1")
+            .bind(account_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn load_authentication_failures(
+        &self,
+        account_id: &str,
+    ) -> Result<Option<AuthenticationFailures>, AccountStoreError> {
         Ok(sqlx::query(
-            "select id,email,password_hash,display_name,created_at \
-        from accounts where email=$1",
+            "select account_id,failed_attempts,first_failed_at,locked_until \
+            from authentication_failures where account_id=$1",
         )
-        .bind(email)
-        .map(|row: PgRow| Account {
+        .bind(account_id)
+        .map(|row: PgRow| AuthenticationFailures {
+            account_id: row.get(0),
+            failed_attempts: row.get(1),
+            first_failed_at: row.get(2),
+            locked_until: row.get(3),
+        })
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn increment_authentication_failures(
+        &self,
+        account_id: &str,
+        now: DateTime<Utc>,
+        window_start: DateTime<Utc>,
+        max_attempts: i32,
+    ) -> Result<AuthenticationFailures, AccountStoreError> {
+        Ok(sqlx::query(
+            "insert into authentication_failures as f(account_id,failed_attempts,\
+            first_failed_at,locked_until) values ($1,1,$2,$2) on conflict (account_id) do update \
+            set failed_attempts=case when f.first_failed_at>$3 and f.failed_attempts<$4 \
+            then f.failed_attempts+1 else 1 end,first_failed_at=case when \
+            f.first_failed_at>$3 and f.failed_attempts<$4 then f.first_failed_at else $2 end \
+            returning account_id,failed_attempts,first_failed_at,locked_until",
+        )
+        .bind(account_id)
+        .bind(now)
+        .bind(window_start)
+        .bind(max_attempts)
+        .map(|row: PgRow| AuthenticationFailures {
+            account_id: row.get(0),
+            failed_attempts: row.get(1),
+            first_failed_at: row.get(2),
+            locked_until: row.get(3),
+        })
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    async fn extend_lockout(
+        &self,
+        account_id: &str,
+        locked_until: DateTime<Utc>,
+    ) -> Result<(), AccountStoreError> {
+        sqlx::query(
+            "update authentication_failures set locked_until=greatest(locked_until,$2) \
+            where account_id=$1",
+        )
+        .bind(account_id)
+        .bind(locked_until)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_authentication_failures(
+        &self,
+        account_id: &str,
+    ) -> Result<(), AccountStoreError> {
+        sqlx::query("delete from authentication_failures where account_id=$1")
+            .bind(account_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn add_password_history(
+        &self,
+        entry: &PasswordHistoryEntry,
+        keep: usize,
+    ) -> Result<(), AccountStoreError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "insert into password_history(id,account_id,password_hash,replaced_at) \
+            values ($1,$2,$3,$4)",
+        )
+        .bind(&entry.id)
+        .bind(&entry.account_id)
+        .bind(&entry.password_hash)
+        .bind(entry.replaced_at)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "delete from password_history where account_id=$1 and id not in \
+            (select id from password_history where account_id=$1 \
+            order by replaced_at desc limit $2)",
+        )
+        .bind(&entry.account_id)
+        .bind(keep as i64)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn load_password_history(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<PasswordHistoryEntry>, AccountStoreError> {
+        Ok(sqlx::query(
+            "select id,account_id,password_hash,replaced_at from password_history \
+            where account_id=$1 order by replaced_at desc",
+        )
+        .bind(account_id)
+        .map(|row: PgRow| PasswordHistoryEntry {
             id: row.get(0),
-            email: row.get(1),
+            account_id: row.get(1),
             password_hash: row.get(2),
-            display_name: row.get(3),
-            created_at: row.get(4),
+            replaced_at: row.get(3),
+        })
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn delete_password_history(&self, account_id: &str) -> Result<(), AccountStoreError> {
+        sqlx::query("delete from password_history where account_id=$1")
+            .bind(account_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn insert_password_reset_token(
+        &self,
+        token: &PasswordResetToken,
+    ) -> Result<(), AccountStoreError> {
+        sqlx::query(
+            "insert into password_reset_tokens(token_hash,account_id,created_at,expires_at) \
+            values ($1,$2,$3,$4)",
+        )
+        .bind(&token.token_hash)
+        .bind(&token.account_id)
+        .bind(token.created_at)
+        .bind(token.expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_password_reset_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<PasswordResetToken>, AccountStoreError> {
+        Ok(sqlx::query(
+            "select token_hash,account_id,created_at,expires_at \
+            from password_reset_tokens where token_hash=$1",
+        )
+        .bind(token_hash)
+        .map(|row: PgRow| PasswordResetToken {
+            token_hash: row.get(0),
+            account_id: row.get(1),
+            created_at: row.get(2),
+            expires_at: row.get(3),
         })
         .fetch_optional(&self.pool)
         .await?)
     }
 
-    async fn update(&self, account: &Account) -> Result<(), AccountStoreError> {
-        sqlx::query("update accounts set email=$1,password_hash=$2,display_name=$3 where id=$4")
-            .bind(&account.email)
-            .bind(&account.password_hash)
-            .bind(&account.display_name)
-            .bind(&account.id)
+    async fn delete_password_reset_tokens(
+        &self,
+        account_id: &str,
+    ) -> Result<(), AccountStoreError> {
+        sqlx::query("delete from password_reset_tokens where account_id=$1")
+            .bind(account_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn insert_email_verification_token(
+        &self,
+        token: &EmailVerificationToken,
+    ) -> Result<(), AccountStoreError> {
+        sqlx::query(
+            "insert into email_verification_tokens(token_hash,account_id,email,created_at,\
+            expires_at) values ($1,$2,$3,$4,$5)",
+        )
+        .bind(&token.token_hash)
+        .bind(&token.account_id)
+        .bind(&token.email)
+        .bind(token.created_at)
+        .bind(token.expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_email_verification_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<EmailVerificationToken>, AccountStoreError> {
+        Ok(sqlx::query(
+            "select token_hash,account_id,email,created_at,expires_at \
+            from email_verification_tokens where token_hash=$1",
+        )
+        .bind(token_hash)
+        .map(|row: PgRow| EmailVerificationToken {
+            token_hash: row.get(0),
+            account_id: row.get(1),
+            email: row.get(2),
+            created_at: row.get(3),
+            expires_at: row.get(4),
+        })
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn delete_email_verification_tokens(
+        &self,
+        account_id: &str,
+    ) -> Result<(), AccountStoreError> {
+        sqlx::query("delete from email_verification_tokens where account_id=$1")
+            .bind(account_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn replace_sign_in_token(&self, token: &SignInToken) -> Result<(), AccountStoreError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("delete from sign_in_tokens where email=$1")
+            .bind(&token.email)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "insert into sign_in_tokens(token_hash,email,code_hash,created_at,expires_at,\
+            failed_attempts) values ($1,$2,$3,$4,$5,$6)",
+        )
+        .bind(&token.token_hash)
+        .bind(&token.email)
+        .bind(&token.code_hash)
+        .bind(token.created_at)
+        .bind(token.expires_at)
+        .bind(token.failed_attempts)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn load_sign_in_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<SignInToken>, AccountStoreError> {
+        Ok(sqlx::query(&format!(
+            "select {} from sign_in_tokens where token_hash=$1",
+            SIGN_IN_TOKEN_COLUMNS
+        ))
+        .bind(token_hash)
+        .map(sign_in_token_from_row)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn load_sign_in_token_by_email(
+        &self,
+        email: &str,
+    ) -> Result<Option<SignInToken>, AccountStoreError> {
+        Ok(sqlx::query(&format!(
+            "select {} from sign_in_tokens where email=$1",
+            SIGN_IN_TOKEN_COLUMNS
+        ))
+        .bind(email)
+        .map(sign_in_token_from_row)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn increment_sign_in_token_attempts(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<i32>, AccountStoreError> {
+        Ok(sqlx::query(
+            "update sign_in_tokens set failed_attempts=failed_attempts+1 \
+            where token_hash=$1 returning failed_attempts",
+        )
+        .bind(token_hash)
+        .map(|row: PgRow| row.get(0))
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn delete_sign_in_tokens(&self, email: &str) -> Result<(), AccountStoreError> {
+        sqlx::query("delete from sign_in_tokens where email=$1")
+            .bind(email)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn replace_recovery_codes(
+        &self,
+        account_id: &str,
+        codes: &[RecoveryCode],
+        event: &AuditEvent,
+    ) -> Result<(), AccountStoreError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("delete from recovery_codes where account_id=$1")
+            .bind(account_id)
+            .execute(&mut *tx)
+            .await?;
+        for code in codes {
+            sqlx::query(
+                "insert into recovery_codes(id,account_id,code_hash,created_at,used_at) \
+                values ($1,$2,$3,$4,$5)",
+            )
+            .bind(&code.id)
+            .bind(&code.account_id)
+            .bind(&code.code_hash)
+            .bind(code.created_at)
+            .bind(code.used_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+        insert_event(&mut *tx, event).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn load_recovery_codes(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<RecoveryCode>, AccountStoreError> {
+        Ok(sqlx::query(
+            "select id,account_id,code_hash,created_at,used_at \
+            from recovery_codes where account_id=$1",
+        )
+        .bind(account_id)
+        .map(|row: PgRow| RecoveryCode {
+            id: row.get(0),
+            account_id: row.get(1),
+            code_hash: row.get(2),
+            created_at: row.get(3),
+            used_at: row.get(4),
+        })
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn mark_recovery_code_used(
+        &self,
+        id: &str,
+        used_at: DateTime<Utc>,
+        event: &AuditEvent,
+    ) -> Result<bool, AccountStoreError> {
+        let mut tx = self.pool.begin().await?;
+        let result =
+            sqlx::query("update recovery_codes set used_at=$1 where id=$2 and used_at is null")
+                .bind(used_at)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        if result.rows_affected() != 1 {
+            return Ok(false);
+        }
+        insert_event(&mut *tx, event).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn delete_recovery_codes(&self, account_id: &str) -> Result<(), AccountStoreError> {
+        sqlx::query("delete from recovery_codes where account_id=$1")
+            .bind(account_id)
             .execute(&self.pool)
             .await?;
 
@@ -0,0 +1,169 @@
+//! Renders the messages that the [AccountService](super::AccountService)
+//! sends to account holders.
+
+use chrono::TimeDelta;
+
+use super::models::Account;
+use crate::services::notifier::Message;
+
+/// Returns the greeting line for messages sent to the account,
+/// preferring the display name over the email address.
+fn greeting(account: &Account) -> String {
+    let name = account
+        .display_name
+        .as_deref()
+        .filter(|name| !name.is_empty())
+        .unwrap_or(&account.email);
+    format!("Hi {},", name)
+}
+
+/// Renders the message containing a password reset token.
+/// The token is always on the last line.
+pub fn password_reset(account: &Account, token: &str, ttl: TimeDelta) -> Message {
+    Message {
+        to: account.email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "{}\n\n\
+            We received a request to reset the password for the account {}. \
+            If you didn't make this request, you can ignore this message.\n\n\
+            Use this token to reset your password. It expires in {} minutes.\n\n\
+            {}",
+            greeting(account),
+            account.email,
+            ttl.num_minutes(),
+            token
+        ),
+    }
+}
+
+/// Renders the message containing an email verification token,
+/// which is sent to the address being verified. The token is always
+/// on the last line.
+pub fn email_verification(account: &Account, email: &str, token: &str, ttl: TimeDelta) -> Message {
+    Message {
+        to: email.to_string(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "{}\n\n\
+            Please confirm that {} is your email address. \
+            If you didn't sign up or change your email address, you can ignore this message.\n\n\
+            Use this token to verify your email address. It expires in {} hours.\n\n\
+            {}",
+            greeting(account),
+            email,
+            ttl.num_hours(),
+            token
+        ),
+    }
+}
+
+/// Renders the message containing a sign-in code and token, which is sent
+/// to the email address whether or not it belongs to an account yet (if it
+/// doesn't, one is created when the code or token is used). The code is on
+/// a line of its own after the instructions, and the token is always on the
+/// last line.
+pub fn sign_in(
+    account: Option<&Account>,
+    email: &str,
+    code: &str,
+    token: &str,
+    ttl: TimeDelta,
+) -> Message {
+    Message {
+        to: email.to_string(),
+        subject: "Your sign-in code".to_string(),
+        body: format!(
+            "{}\n\n\
+            Enter this code to sign in as {}. It expires in {} minutes. \
+            If you didn't try to sign in, you can ignore this message.\n\n\
+            {}\n\n\
+            Or, to sign in on the device you asked from, use this token.\n\n\
+            {}",
+            account.map(greeting).unwrap_or_else(|| "Hi,".to_string()),
+            email,
+            ttl.num_minutes(),
+            code,
+            token
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::services::account::models::AccountStatus;
+
+    fn test_account(display_name: Option<&str>) -> Account {
+        Account {
+            id: "acct_test".to_string(),
+            email: "test@test.com".to_string(),
+            password_hash: None,
+            display_name: display_name.map(|v| v.to_string()),
+            created_at: Utc::now(),
+            email_verified_at: None,
+            pending_email: None,
+            status: AccountStatus::Active,
+        }
+    }
+
+    #[test]
+    fn password_reset_message() {
+        let message = password_reset(
+            &test_account(Some("Tester")),
+            "test-token",
+            TimeDelta::hours(1),
+        );
+        assert_eq!("test@test.com", message.to);
+        assert!(message.body.starts_with("Hi Tester,"));
+        assert!(message.body.contains("60 minutes"));
+        assert_eq!(Some("test-token"), message.body.lines().last());
+    }
+
+    #[test]
+    fn email_verification_message() {
+        let message = email_verification(
+            &test_account(None),
+            "new@test.com",
+            "test-token",
+            TimeDelta::hours(24),
+        );
+        assert_eq!("new@test.com", message.to);
+        assert!(message.body.contains("new@test.com is your email address"));
+        assert_eq!(Some("test-token"), message.body.lines().last());
+    }
+
+    #[test]
+    fn sign_in_message() {
+        let message = sign_in(
+            None,
+            "new@test.com",
+            "123456",
+            "test-token",
+            TimeDelta::minutes(15),
+        );
+        assert_eq!("new@test.com", message.to);
+        assert!(message.body.starts_with("Hi,"));
+        assert!(message.body.contains("15 minutes"));
+        assert_eq!(Some("123456"), message.body.lines().nth(4));
+        assert_eq!(Some("test-token"), message.body.lines().last());
+
+        let account = test_account(Some("Tester"));
+        let message = sign_in(
+            Some(&account),
+            &account.email,
+            "123456",
+            "test-token",
+            TimeDelta::minutes(15),
+        );
+        assert!(message.body.starts_with("Hi Tester,"));
+    }
+
+    #[test]
+    fn greeting_falls_back_to_email() {
+        assert_eq!("Hi test@test.com,", greeting(&test_account(None)));
+        assert_eq!("Hi test@test.com,", greeting(&test_account(Some(""))));
+    }
+}
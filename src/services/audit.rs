@@ -0,0 +1,205 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use error::AuditServiceError;
+use models::{AuditContext, AuditCursor, AuditEvent, AuditEventKind, AuditEventPage};
+use stores::AuditStore;
+
+use super::{Clock, SystemClock};
+
+pub mod error;
+pub mod models;
+pub mod stores;
+
+/// Number of events returned per page when the caller doesn't specify.
+const DEFAULT_PAGE_SIZE: u32 = 50;
+/// Maximum number of events returned per page.
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// Provides access to the audit log of account events. Most events are
+/// recorded by the stores of the services that cause them (e.g., the
+/// [AccountStore](super::account::stores::AccountStore)) so that each event
+/// can be written in the same transaction as the change it describes.
+pub struct AuditService<S: AuditStore, C: Clock<Utc>> {
+    store: S,
+    clock: C,
+}
+
+impl<S: AuditStore, C: Clock<Utc>> AuditService<S, C> {
+    /// Constructs a new [AuditService] given the [AuditStore] and [Clock] to use.
+    pub fn new_with_clock(audit_store: S, clock: C) -> Self {
+        Self {
+            store: audit_store,
+            clock,
+        }
+    }
+
+    /// Records an event about the account that is happening now. This is for
+    /// events that aren't recorded by a service's store along with a change.
+    pub async fn record(
+        &self,
+        account_id: &str,
+        kind: AuditEventKind,
+        context: &AuditContext,
+    ) -> Result<(), AuditServiceError> {
+        let event = AuditEvent::new(account_id, kind, context, self.clock.now());
+        Ok(self.store.insert(&event).await?)
+    }
+
+    /// Returns a page of the account's events, newest first. Pass the
+    /// `next_cursor` from the previous page to get the next one.
+    pub async fn list_events(
+        &self,
+        account_id: &str,
+        cursor: Option<&str>,
+        limit: Option<u32>,
+    ) -> Result<AuditEventPage, AuditServiceError> {
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        let after = cursor.map(decode_cursor).transpose()?;
+
+        // load one extra event to find out if there's another page
+        let mut events = self
+            .store
+            .load_for_account(account_id, after.as_ref(), limit + 1)
+            .await?;
+        let next_cursor = if events.len() > limit as usize {
+            events.truncate(limit as usize);
+            events.last().map(encode_cursor)
+        } else {
+            None
+        };
+        Ok(AuditEventPage {
+            events,
+            next_cursor,
+        })
+    }
+}
+
+impl<S: AuditStore> AuditService<S, SystemClock<Utc>> {
+    pub fn new(audit_store: S) -> Self {
+        Self::new_with_clock(audit_store, SystemClock::default())
+    }
+}
+
+/// Encodes the position of an event as an opaque cursor string.
+fn encode_cursor(event: &AuditEvent) -> String {
+    URL_SAFE_NO_PAD.encode(format!(
+        "{}|{}",
+        event
+            .occurred_at
+            .to_rfc3339_opts(SecondsFormat::Nanos, true),
+        event.id
+    ))
+}
+
+/// Decodes a cursor string created by [encode_cursor].
+fn decode_cursor(cursor: &str) -> Result<AuditCursor, AuditServiceError> {
+    let decoded = URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or(AuditServiceError::InvalidCursor)?;
+    let (occurred_at, id) = decoded
+        .split_once('|')
+        .ok_or(AuditServiceError::InvalidCursor)?;
+    let occurred_at = DateTime::parse_from_rfc3339(occurred_at)
+        .map_err(|_| AuditServiceError::InvalidCursor)?
+        .with_timezone(&Utc);
+    Ok(AuditCursor {
+        occurred_at,
+        id: id.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+    use stores::fake::FakeAuditStore;
+
+    use crate::services::TestClock;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn list_events_pages() {
+        let store = FakeAuditStore::new();
+        let now = Utc::now();
+        for minutes in 0..5 {
+            store
+                .insert(&AuditEvent::new(
+                    "acct_test",
+                    AuditEventKind::Authenticated,
+                    &AuditContext::default(),
+                    now + TimeDelta::minutes(minutes),
+                ))
+                .await
+                .unwrap();
+        }
+        store
+            .insert(&AuditEvent::new(
+                "acct_other",
+                AuditEventKind::Authenticated,
+                &AuditContext::default(),
+                now,
+            ))
+            .await
+            .unwrap();
+        let service = AuditService::new_with_clock(store, TestClock::new(now));
+
+        let first = service
+            .list_events("acct_test", None, Some(2))
+            .await
+            .unwrap();
+        assert_eq!(2, first.events.len());
+        assert_eq!(now + TimeDelta::minutes(4), first.events[0].occurred_at);
+
+        let second = service
+            .list_events("acct_test", first.next_cursor.as_deref(), Some(2))
+            .await
+            .unwrap();
+        assert_eq!(now + TimeDelta::minutes(2), second.events[0].occurred_at);
+
+        let last = service
+            .list_events("acct_test", second.next_cursor.as_deref(), Some(2))
+            .await
+            .unwrap();
+        assert_eq!(1, last.events.len());
+        assert_eq!(now, last.events[0].occurred_at);
+        assert!(last.next_cursor.is_none());
+    }
+
+    #[tokio::test]
+    async fn list_events_invalid_cursor() {
+        let service =
+            AuditService::new_with_clock(FakeAuditStore::new(), TestClock::new(Utc::now()));
+        for cursor in ["not a cursor", "bm90IGEgY3Vyc29y"] {
+            assert!(matches!(
+                service.list_events("acct_test", Some(cursor), None).await,
+                Err(AuditServiceError::InvalidCursor)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn record_event() {
+        let now = Utc::now();
+        let service = AuditService::new_with_clock(FakeAuditStore::new(), TestClock::new(now));
+        let context = AuditContext {
+            actor: Some("acct_support".to_string()),
+            source_ip: Some("127.0.0.1".to_string()),
+            user_agent: Some("test".to_string()),
+        };
+        service
+            .record("acct_test", AuditEventKind::SessionsRevoked, &context)
+            .await
+            .unwrap();
+
+        let page = service.list_events("acct_test", None, None).await.unwrap();
+        assert_eq!(1, page.events.len());
+        let event = &page.events[0];
+        assert_eq!(AuditEventKind::SessionsRevoked, event.kind);
+        assert_eq!(context.actor, event.actor);
+        assert_eq!(context.source_ip, event.source_ip);
+        assert_eq!(context.user_agent, event.user_agent);
+        assert_eq!(now, event.occurred_at);
+    }
+}
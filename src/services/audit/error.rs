@@ -0,0 +1,17 @@
+use thiserror::Error;
+
+use super::stores::error::AuditStoreError;
+
+#[derive(Error, Debug)]
+pub enum AuditServiceError {
+    #[error("There was an error interacting with the data store: {0}")]
+    StoreError(#[from] AuditStoreError),
+    #[error("The cursor is not valid")]
+    InvalidCursor,
+}
+
+/// Returned when parsing an [AuditEventKind](super::models::AuditEventKind)
+/// from an unrecognized name.
+#[derive(Error, Debug)]
+#[error("'{0}' is not a valid audit event kind")]
+pub struct ParseAuditEventKindError(pub String);
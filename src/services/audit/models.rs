@@ -0,0 +1,181 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+
+use super::error::ParseAuditEventKindError;
+use crate::services::account::id::ID;
+
+/// The kinds of events recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditEventKind {
+    AccountCreated,
+    Authenticated,
+    AuthenticationFailed,
+    Locked,
+    Unlocked,
+    CredentialsUpdated,
+    PasswordReset,
+    PasswordRehashed,
+    EmailVerified,
+    EmailChanged,
+    Deactivated,
+    Reactivated,
+    Deleted,
+    SessionsRevoked,
+    MfaEnabled,
+    MfaDisabled,
+    RecoveryCodesGenerated,
+    RecoveryCodeUsed,
+    PasskeyRegistered,
+    PasskeyRemoved,
+    OAuthConsentGranted,
+    OAuthConsentRevoked,
+    IdentityLinked,
+    IdentityUnlinked,
+}
+
+impl AuditEventKind {
+    /// Returns the name used to store and report this kind of event.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEventKind::AccountCreated => "account_created",
+            AuditEventKind::Authenticated => "authenticated",
+            AuditEventKind::AuthenticationFailed => "authentication_failed",
+            AuditEventKind::Locked => "locked",
+            AuditEventKind::Unlocked => "unlocked",
+            AuditEventKind::CredentialsUpdated => "credentials_updated",
+            AuditEventKind::PasswordReset => "password_reset",
+            AuditEventKind::PasswordRehashed => "password_rehashed",
+            AuditEventKind::EmailVerified => "email_verified",
+            AuditEventKind::EmailChanged => "email_changed",
+            AuditEventKind::Deactivated => "deactivated",
+            AuditEventKind::Reactivated => "reactivated",
+            AuditEventKind::Deleted => "deleted",
+            AuditEventKind::SessionsRevoked => "sessions_revoked",
+            AuditEventKind::MfaEnabled => "mfa_enabled",
+            AuditEventKind::MfaDisabled => "mfa_disabled",
+            AuditEventKind::RecoveryCodesGenerated => "recovery_codes_generated",
+            AuditEventKind::RecoveryCodeUsed => "recovery_code_used",
+            AuditEventKind::PasskeyRegistered => "passkey_registered",
+            AuditEventKind::PasskeyRemoved => "passkey_removed",
+            AuditEventKind::OAuthConsentGranted => "oauth_consent_granted",
+            AuditEventKind::OAuthConsentRevoked => "oauth_consent_revoked",
+            AuditEventKind::IdentityLinked => "identity_linked",
+            AuditEventKind::IdentityUnlinked => "identity_unlinked",
+        }
+    }
+}
+
+impl FromStr for AuditEventKind {
+    type Err = ParseAuditEventKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "account_created" => Ok(AuditEventKind::AccountCreated),
+            "authenticated" => Ok(AuditEventKind::Authenticated),
+            "authentication_failed" => Ok(AuditEventKind::AuthenticationFailed),
+            "locked" => Ok(AuditEventKind::Locked),
+            "unlocked" => Ok(AuditEventKind::Unlocked),
+            "credentials_updated" => Ok(AuditEventKind::CredentialsUpdated),
+            "password_reset" => Ok(AuditEventKind::PasswordReset),
+            "password_rehashed" => Ok(AuditEventKind::PasswordRehashed),
+            "email_verified" => Ok(AuditEventKind::EmailVerified),
+            "email_changed" => Ok(AuditEventKind::EmailChanged),
+            "deactivated" => Ok(AuditEventKind::Deactivated),
+            "reactivated" => Ok(AuditEventKind::Reactivated),
+            "deleted" => Ok(AuditEventKind::Deleted),
+            "sessions_revoked" => Ok(AuditEventKind::SessionsRevoked),
+            "mfa_enabled" => Ok(AuditEventKind::MfaEnabled),
+            "mfa_disabled" => Ok(AuditEventKind::MfaDisabled),
+            "recovery_codes_generated" => Ok(AuditEventKind::RecoveryCodesGenerated),
+            "recovery_code_used" => Ok(AuditEventKind::RecoveryCodeUsed),
+            "passkey_registered" => Ok(AuditEventKind::PasskeyRegistered),
+            "passkey_removed" => Ok(AuditEventKind::PasskeyRemoved),
+            "oauth_consent_granted" => Ok(AuditEventKind::OAuthConsentGranted),
+            "oauth_consent_revoked" => Ok(AuditEventKind::OAuthConsentRevoked),
+            "identity_linked" => Ok(AuditEventKind::IdentityLinked),
+            "identity_unlinked" => Ok(AuditEventKind::IdentityUnlinked),
+            _ => Err(ParseAuditEventKindError(s.to_string())),
+        }
+    }
+}
+
+/// Describes who made a request and where it came from,
+/// so that it can be recorded with any resulting audit events.
+#[derive(Debug, Clone, Default)]
+pub struct AuditContext {
+    /// ID of the account or operator that made the request, if known.
+    pub actor: Option<String>,
+    /// IP address the request came from, if known.
+    pub source_ip: Option<String>,
+    /// User agent that made the request, if known.
+    pub user_agent: Option<String>,
+}
+
+impl AuditContext {
+    /// Returns a copy of this context with the actor set to `account_id` if
+    /// no actor is known. Used for self-service requests, such as signing in,
+    /// where the account holder is the one acting.
+    pub fn acting_as(&self, account_id: &str) -> AuditContext {
+        AuditContext {
+            actor: self.actor.clone().or_else(|| Some(account_id.to_string())),
+            ..self.clone()
+        }
+    }
+}
+
+/// Represents a recorded audit event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEvent {
+    /// Unique ID.
+    pub id: String,
+    /// ID of the account the event is about.
+    pub account_id: String,
+    /// What happened.
+    pub kind: AuditEventKind,
+    /// ID of the account or operator that caused the event, if known.
+    pub actor: Option<String>,
+    /// IP address of the request that caused the event, if known.
+    pub source_ip: Option<String>,
+    /// User agent of the request that caused the event, if known.
+    pub user_agent: Option<String>,
+    /// When the event happened.
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl AuditEvent {
+    /// Constructs a new [AuditEvent] with a new ID.
+    pub fn new(
+        account_id: &str,
+        kind: AuditEventKind,
+        context: &AuditContext,
+        occurred_at: DateTime<Utc>,
+    ) -> AuditEvent {
+        AuditEvent {
+            id: ID::Evt.create(),
+            account_id: account_id.to_string(),
+            kind,
+            actor: context.actor.clone(),
+            source_ip: context.source_ip.clone(),
+            user_agent: context.user_agent.clone(),
+            occurred_at,
+        }
+    }
+}
+
+/// The position of an event in an account's audit log, newest first.
+/// Events are ordered by when they occurred, and then by ID to break ties.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditCursor {
+    pub occurred_at: DateTime<Utc>,
+    pub id: String,
+}
+
+/// A page of audit events, newest first.
+#[derive(Debug)]
+pub struct AuditEventPage {
+    /// The events on this page.
+    pub events: Vec<AuditEvent>,
+    /// Opaque cursor for the next (older) page, or None if this is the last page.
+    pub next_cursor: Option<String>,
+}
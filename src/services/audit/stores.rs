@@ -0,0 +1,22 @@
+pub mod error;
+#[cfg(test)]
+pub mod fake;
+pub mod postgres;
+
+use axum::async_trait;
+use error::AuditStoreError;
+
+use crate::services::audit::models::{AuditCursor, AuditEvent};
+
+#[async_trait]
+pub trait AuditStore: Send + Sync + 'static {
+    async fn insert(&self, event: &AuditEvent) -> Result<(), AuditStoreError>;
+    /// Loads up to `limit` of the account's events, newest first,
+    /// starting after the `after` cursor if provided.
+    async fn load_for_account(
+        &self,
+        account_id: &str,
+        after: Option<&AuditCursor>,
+        limit: u32,
+    ) -> Result<Vec<AuditEvent>, AuditStoreError>;
+}
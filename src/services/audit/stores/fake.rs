@@ -0,0 +1,67 @@
+use std::sync::{Arc, Mutex};
+
+use axum::async_trait;
+
+use crate::services::audit::models::{AuditCursor, AuditEvent};
+
+use super::{error::AuditStoreError, AuditStore};
+
+/// A fake implementation of [AuditStore] that can be used in unit tests.
+/// Clones share the same events, so the same store can be given to the
+/// [FakeAccountStore](crate::services::account::stores::fake::FakeAccountStore),
+/// which records events, and the [AuditService](crate::services::audit::AuditService),
+/// which reads them.
+#[derive(Clone)]
+pub struct FakeAuditStore {
+    events: Arc<Mutex<Vec<AuditEvent>>>,
+}
+
+impl FakeAuditStore {
+    pub fn new() -> FakeAuditStore {
+        FakeAuditStore {
+            events: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Erases the source IP address and user agent of the account's events.
+    pub fn erase_details(&self, account_id: &str) {
+        for event in self.events.lock().unwrap().iter_mut() {
+            if event.account_id == account_id {
+                event.source_ip = None;
+                event.user_agent = None;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AuditStore for FakeAuditStore {
+    async fn insert(&self, event: &AuditEvent) -> Result<(), AuditStoreError> {
+        self.events.lock().unwrap().push(event.clone());
+        Ok(())
+    }
+
+    async fn load_for_account(
+        &self,
+        account_id: &str,
+        after: Option<&AuditCursor>,
+        limit: u32,
+    ) -> Result<Vec<AuditEvent>, AuditStoreError> {
+        let mut events: Vec<AuditEvent> = self
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| event.account_id == account_id)
+            .filter(|event| {
+                after.is_none_or(|cursor| {
+                    (event.occurred_at, &event.id) < (cursor.occurred_at, &cursor.id)
+                })
+            })
+            .cloned()
+            .collect();
+        events.sort_by(|a, b| (b.occurred_at, &b.id).cmp(&(a.occurred_at, &a.id)));
+        events.truncate(limit as usize);
+        Ok(events)
+    }
+}
@@ -0,0 +1,92 @@
+//! Implements [AuditStore] backed by a PostgreSQL database
+
+use axum::async_trait;
+use sqlx::{postgres::PgRow, PgExecutor, PgPool, Row};
+
+use crate::services::audit::models::{AuditCursor, AuditEvent};
+
+use super::{error::AuditStoreError, AuditStore};
+
+impl From<sqlx::Error> for AuditStoreError {
+    fn from(value: sqlx::Error) -> Self {
+        AuditStoreError::DatabaseError(value.to_string())
+    }
+}
+
+/// Inserts an audit event using the given executor. Other Postgres stores
+/// use this to record events in the same transaction as the changes
+/// they describe.
+pub async fn insert_event<'e, E: PgExecutor<'e>>(
+    executor: E,
+    event: &AuditEvent,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "insert into audit_events(id,account_id,kind,actor,source_ip,user_agent,occurred_at) \
+        values ($1,$2,$3,$4,$5,$6,$7)",
+    )
+    .bind(&event.id)
+    .bind(&event.account_id)
+    .bind(event.kind.as_str())
+    .bind(&event.actor)
+    .bind(&event.source_ip)
+    .bind(&event.user_agent)
+    .bind(event.occurred_at)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Maps an audit_events row to an [AuditEvent].
+fn to_event(row: PgRow) -> Result<AuditEvent, sqlx::Error> {
+    let kind: String = row.get(2);
+    Ok(AuditEvent {
+        id: row.get(0),
+        account_id: row.get(1),
+        kind: kind.parse().map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+        actor: row.get(3),
+        source_ip: row.get(4),
+        user_agent: row.get(5),
+        occurred_at: row.get(6),
+    })
+}
+
+pub struct PostgresAuditStore {
+    pool: PgPool,
+}
+
+impl PostgresAuditStore {
+    pub fn new(pool: PgPool) -> PostgresAuditStore {
+        PostgresAuditStore { pool }
+    }
+}
+
+#[async_trait]
+impl AuditStore for PostgresAuditStore {
+    async fn insert(&self, event: &AuditEvent) -> Result<(), AuditStoreError> {
+        Ok(insert_event(&self.pool, event).await?)
+    }
+
+    async fn load_for_account(
+        &self,
+        account_id: &str,
+        after: Option<&AuditCursor>,
+        limit: u32,
+    ) -> Result<Vec<AuditEvent>, AuditStoreError> {
+        let sql = format!(
+            "select id,account_id,kind,actor,source_ip,user_agent,occurred_at \
+            from audit_events where account_id=$1 {} \
+            order by occurred_at desc, id desc limit $2",
+            if after.is_some() {
+                "and (occurred_at,id) < ($3,$4)"
+            } else {
+                ""
+            }
+        );
+        let mut query = sqlx::query(&sql).bind(account_id).bind(i64::from(limit));
+        if let Some(cursor) = after {
+            query = query.bind(cursor.occurred_at).bind(&cursor.id);
+        }
+        Ok(query.try_map(to_event).fetch_all(&self.pool).await?)
+    }
+}
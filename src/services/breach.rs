@@ -0,0 +1,106 @@
+//! Defines the [BreachedPasswordChecker] trait, which services use to reject
+//! passwords that are known to have been exposed in data breaches, along with
+//! its implementations. These work with the SHA-1 hashes published by
+//! [Pwned Passwords](https://haveibeenpwned.com/Passwords).
+
+pub mod bloom;
+pub mod error;
+#[cfg(test)]
+pub mod fake;
+pub mod range_api;
+
+use axum::async_trait;
+use error::BreachCheckError;
+use secrecy::{ExposeSecret, Secret};
+use sha1::{Digest, Sha1};
+
+use super::account::models::Password;
+
+#[async_trait]
+pub trait BreachedPasswordChecker: Send + Sync + 'static {
+    /// Returns true if the password is known to have appeared in a data breach.
+    async fn is_breached(&self, password: &Secret<Password>) -> Result<bool, BreachCheckError>;
+}
+
+/// Allows the implementation to be chosen at runtime
+/// (e.g., based on configuration).
+#[async_trait]
+impl BreachedPasswordChecker for Box<dyn BreachedPasswordChecker> {
+    async fn is_breached(&self, password: &Secret<Password>) -> Result<bool, BreachCheckError> {
+        self.as_ref().is_breached(password).await
+    }
+}
+
+/// A [BreachedPasswordChecker] that doesn't check anything, for when
+/// no breached password dataset is configured.
+pub struct DisabledChecker;
+
+#[async_trait]
+impl BreachedPasswordChecker for DisabledChecker {
+    async fn is_breached(&self, _password: &Secret<Password>) -> Result<bool, BreachCheckError> {
+        Ok(false)
+    }
+}
+
+/// Returns the SHA-1 hash of the password, as it appears in Pwned Passwords.
+fn password_sha1(password: &Secret<Password>) -> [u8; 20] {
+    Sha1::digest(password.expose_secret().raw().as_bytes()).into()
+}
+
+/// Encodes a SHA-1 hash as uppercase hex, as used by Pwned Passwords.
+fn encode_sha1(hash: &[u8; 20]) -> String {
+    hash.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Decodes a SHA-1 hash from hex, returning `None` if it isn't valid.
+fn decode_sha1(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 || !hex.is_ascii() {
+        return None;
+    }
+    let mut hash = [0u8; 20];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(hash)
+}
+
+/// Parses a line of a Pwned Passwords dataset, which is a hash (or, in range
+/// API responses, the rest of a hash after its prefix) and the number of times
+/// it has been seen, separated by a colon (e.g., `5BAA6...68FD8:9659365`).
+/// The count is optional, since it isn't needed to build a dataset.
+fn parse_line(line: &str) -> Option<(&str, u64)> {
+    let line = line.trim();
+    match line.split_once(':') {
+        Some((hash, count)) => Some((hash, count.parse().ok()?)),
+        None => Some((line, 1)),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// A small Pwned Passwords dataset, in the format of the full downloads.
+    pub const TEST_DATASET: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/services/breach/testdata/pwned-passwords.txt"
+    );
+    /// A password that is in [TEST_DATASET] but passes the default
+    /// [PasswordPolicy](super::super::account::policy::PasswordPolicy).
+    pub const BREACHED_PASSWORD: &str = "correct-horse-battery-staple";
+
+    #[test]
+    fn sha1_encoding() {
+        let hash = password_sha1(&Secret::new(Password::new("password")));
+        let hex = encode_sha1(&hash);
+        assert_eq!("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", hex);
+        assert_eq!(Some(hash), decode_sha1(&hex));
+        assert_eq!(Some(hash), decode_sha1(&hex.to_lowercase()));
+        assert_eq!(None, decode_sha1(&hex[1..]));
+        assert_eq!(None, decode_sha1(&hex.replace('5', "X")));
+
+        assert_eq!(Some((hex.as_str(), 1)), parse_line(&hex));
+        assert_eq!(Some(("ABC", 42)), parse_line("ABC:42\r"));
+        assert_eq!(None, parse_line("ABC:many"));
+    }
+}
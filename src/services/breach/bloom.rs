@@ -0,0 +1,157 @@
+//! Implements [BreachedPasswordChecker] using a bloom filter built from a
+//! locally downloaded Pwned Passwords dataset, so that no password hashes
+//! (or parts of them) ever leave the service.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use axum::async_trait;
+use secrecy::Secret;
+
+use super::{
+    decode_sha1, error::BreachCheckError, parse_line, password_sha1, BreachedPasswordChecker,
+};
+use crate::services::account::models::Password;
+
+/// The proportion of passwords that the filter wrongly reports as breached.
+/// At this rate, the filter takes about 14.4 bits per hash in the dataset.
+const FALSE_POSITIVE_RATE: f64 = 0.001;
+
+/// A [BreachedPasswordChecker] backed by a bloom filter of SHA-1 hashes. The
+/// filter is much smaller than the dataset it was built from, at the cost of
+/// occasionally treating a password that isn't in the dataset as breached.
+/// It never misses a password that is in the dataset.
+pub struct BloomFilterChecker {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+}
+
+impl BloomFilterChecker {
+    /// Builds a filter from a Pwned Passwords dataset file (the SHA-1 version,
+    /// ordered by hash or by count), which has one hash per line, optionally
+    /// followed by a colon and the number of times it has been seen. The whole
+    /// file is read twice: once to size the filter and once to fill it.
+    pub fn load(path: &Path) -> Result<BloomFilterChecker, BreachCheckError> {
+        let mut count = 0;
+        Self::read_hashes(path, |_| count += 1)?;
+        let mut filter = Self::with_capacity(count);
+        Self::read_hashes(path, |hash| filter.insert(&hash))?;
+        Ok(filter)
+    }
+
+    /// Constructs an empty filter sized for the number of hashes.
+    fn with_capacity(count: usize) -> BloomFilterChecker {
+        let count = count.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-count * FALSE_POSITIVE_RATE.ln() / (ln2 * ln2)).ceil() as u64;
+        let num_hashes = ((num_bits as f64 / count) * ln2).round().max(1.0) as u32;
+        BloomFilterChecker {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes,
+        }
+    }
+
+    /// Calls `f` with each hash in the dataset file, skipping blank lines
+    /// and hashes that have been seen zero times.
+    fn read_hashes(path: &Path, mut f: impl FnMut([u8; 20])) -> Result<(), BreachCheckError> {
+        let file = File::open(path).map_err(|e| BreachCheckError::DatasetError(e.to_string()))?;
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| BreachCheckError::DatasetError(e.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (hash, count) =
+                parse_line(&line).ok_or(BreachCheckError::InvalidDataset(index + 1))?;
+            let hash = decode_sha1(hash).ok_or(BreachCheckError::InvalidDataset(index + 1))?;
+            if count > 0 {
+                f(hash);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the positions of the bits for the hash. SHA-1 hashes are
+    /// already uniformly distributed, so rather than hashing them again,
+    /// this combines two 64-bit parts of the hash (double hashing).
+    fn positions(&self, hash: &[u8; 20]) -> impl Iterator<Item = u64> {
+        let h1 = u64::from_be_bytes(hash[0..8].try_into().unwrap());
+        let h2 = u64::from_be_bytes(hash[8..16].try_into().unwrap()) | 1;
+        let num_bits = self.num_bits;
+        (0..self.num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+
+    fn insert(&mut self, hash: &[u8; 20]) {
+        for position in self.positions(hash).collect::<Vec<_>>() {
+            self.bits[(position / 64) as usize] |= 1 << (position % 64);
+        }
+    }
+
+    fn contains(&self, hash: &[u8; 20]) -> bool {
+        self.positions(hash)
+            .all(|position| self.bits[(position / 64) as usize] & (1 << (position % 64)) != 0)
+    }
+}
+
+#[async_trait]
+impl BreachedPasswordChecker for BloomFilterChecker {
+    async fn is_breached(&self, password: &Secret<Password>) -> Result<bool, BreachCheckError> {
+        Ok(self.contains(&password_sha1(password)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::services::breach::tests::{BREACHED_PASSWORD, TEST_DATASET};
+
+    use super::*;
+
+    fn password(raw: &str) -> Secret<Password> {
+        Secret::new(Password::new(raw))
+    }
+
+    #[tokio::test]
+    async fn checks_dataset() {
+        let checker = BloomFilterChecker::load(Path::new(TEST_DATASET)).unwrap();
+        for breached in [BREACHED_PASSWORD, "password", "Tr0ub4dor&3"] {
+            assert!(checker.is_breached(&password(breached)).await.unwrap());
+        }
+        for not_breached in ["tangerine-lantern-42", "Password", "correct-horse-battery"] {
+            assert!(!checker.is_breached(&password(not_breached)).await.unwrap());
+        }
+    }
+
+    #[test]
+    fn false_positive_rate() {
+        let mut filter = BloomFilterChecker::with_capacity(10_000);
+        for i in 0..10_000 {
+            filter.insert(&password_sha1(&password(&format!("breached-{}", i))));
+        }
+        let false_positives = (0..10_000)
+            .filter(|i| filter.contains(&password_sha1(&password(&format!("other-{}", i)))))
+            .count();
+        // about 10 are expected
+        assert!(false_positives < 30, "{}", false_positives);
+    }
+
+    #[test]
+    fn invalid_dataset() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3").unwrap();
+        writeln!(file, "not-a-hash:3").unwrap();
+        assert!(matches!(
+            BloomFilterChecker::load(file.path()),
+            Err(BreachCheckError::InvalidDataset(2))
+        ));
+        assert!(matches!(
+            BloomFilterChecker::load(Path::new("does-not-exist.txt")),
+            Err(BreachCheckError::DatasetError(_))
+        ));
+    }
+}
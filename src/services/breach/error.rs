@@ -0,0 +1,13 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum BreachCheckError {
+    #[error("error reading the breached password dataset: {0}")]
+    DatasetError(String),
+    #[error("the breached password dataset is not valid at line {0}")]
+    InvalidDataset(usize),
+    #[error("error querying the breached password API: {0}")]
+    RequestError(String),
+    #[error("invalid response from the breached password API: {0}")]
+    InvalidResponse(String),
+}
@@ -0,0 +1,37 @@
+use std::collections::HashSet;
+
+use axum::async_trait;
+use secrecy::Secret;
+
+use super::{error::BreachCheckError, password_sha1, BreachedPasswordChecker};
+use crate::services::account::models::Password;
+
+/// A fake implementation of [BreachedPasswordChecker] that can be used in
+/// unit tests. It treats only the passwords it was constructed with as breached.
+#[derive(Clone, Default)]
+pub struct FakeBreachedPasswordChecker {
+    breached: HashSet<[u8; 20]>,
+}
+
+impl FakeBreachedPasswordChecker {
+    pub fn new() -> FakeBreachedPasswordChecker {
+        FakeBreachedPasswordChecker::default()
+    }
+
+    /// Constructs a checker that treats the given passwords as breached.
+    pub fn with_breached(passwords: &[&str]) -> FakeBreachedPasswordChecker {
+        FakeBreachedPasswordChecker {
+            breached: passwords
+                .iter()
+                .map(|password| password_sha1(&Secret::new(Password::new(password))))
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl BreachedPasswordChecker for FakeBreachedPasswordChecker {
+    async fn is_breached(&self, password: &Secret<Password>) -> Result<bool, BreachCheckError> {
+        Ok(self.breached.contains(&password_sha1(password)))
+    }
+}
@@ -0,0 +1,141 @@
+//! Implements [BreachedPasswordChecker] using the Pwned Passwords
+//! [range API](https://haveibeenpwned.com/API/v3#SearchingPwnedPasswordsByRange),
+//! or a local stand-in that serves the same API.
+
+use std::time::Duration;
+
+use axum::async_trait;
+use reqwest::Client;
+use secrecy::Secret;
+
+use super::{
+    encode_sha1, error::BreachCheckError, parse_line, password_sha1, BreachedPasswordChecker,
+};
+use crate::services::account::models::Password;
+
+/// Number of hex characters of the hash sent to the API.
+const PREFIX_LENGTH: usize = 5;
+/// How long to wait for the API to respond.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A [BreachedPasswordChecker] that queries the range API using k-anonymity:
+/// only the first five hex characters of the password's SHA-1 hash are sent,
+/// and the API returns the rest of every breached hash with that prefix, so
+/// neither the password nor its full hash leave the service.
+pub struct RangeApiChecker {
+    client: Client,
+    base_url: String,
+}
+
+impl RangeApiChecker {
+    /// Constructs a new [RangeApiChecker] that queries the API at `base_url`
+    /// (e.g., `https://api.pwnedpasswords.com`).
+    pub fn new(base_url: &str) -> Result<RangeApiChecker, BreachCheckError> {
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent("identity-service")
+            .build()
+            .map_err(|e| BreachCheckError::RequestError(e.to_string()))?;
+        Ok(RangeApiChecker {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl BreachedPasswordChecker for RangeApiChecker {
+    async fn is_breached(&self, password: &Secret<Password>) -> Result<bool, BreachCheckError> {
+        let hash = encode_sha1(&password_sha1(password));
+        let (prefix, suffix) = hash.split_at(PREFIX_LENGTH);
+        let response = self
+            .client
+            .get(format!("{}/range/{}", self.base_url, prefix))
+            // pads the response with fake hashes, so its size doesn't reveal the prefix
+            .header("Add-Padding", "true")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| BreachCheckError::RequestError(e.to_string()))?;
+        let body = response
+            .text()
+            .await
+            .map_err(|e| BreachCheckError::RequestError(e.to_string()))?;
+
+        for line in body.lines().filter(|line| !line.trim().is_empty()) {
+            let (candidate, count) = parse_line(line)
+                .ok_or_else(|| BreachCheckError::InvalidResponse(line.to_string()))?;
+            // padding entries have a count of zero
+            if count > 0 && candidate.eq_ignore_ascii_case(suffix) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{extract::Path, http::HeaderMap, routing::get, Router};
+
+    use crate::services::breach::tests::{BREACHED_PASSWORD, TEST_DATASET};
+
+    use super::*;
+
+    fn password(raw: &str) -> Secret<Password> {
+        Secret::new(Password::new(raw))
+    }
+
+    /// A password that isn't breached, but whose hash the stand-in API
+    /// returns as padding (with a count of zero).
+    const PADDED_PASSWORD: &str = "tangerine-lantern-42";
+
+    /// Serves the range API from [TEST_DATASET] on a local port, returning its base URL.
+    async fn stand_in_api() -> String {
+        async fn range(Path(prefix): Path<String>, headers: HeaderMap) -> String {
+            let mut dataset = std::fs::read_to_string(TEST_DATASET).unwrap();
+            if headers.contains_key("add-padding") {
+                let padding = encode_sha1(&password_sha1(&password(PADDED_PASSWORD)));
+                dataset.push_str(&format!("{}:0\r\n", padding));
+            }
+            dataset
+                .lines()
+                .filter_map(|line| line.strip_prefix(&prefix))
+                .collect::<Vec<_>>()
+                .join("\r\n")
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().route("/range/:prefix", get(range)))
+                .await
+                .unwrap();
+        });
+        format!("http://{}/", addr)
+    }
+
+    #[tokio::test]
+    async fn queries_range_api() {
+        let checker = RangeApiChecker::new(&stand_in_api().await).unwrap();
+        for breached in [BREACHED_PASSWORD, "password"] {
+            assert!(checker.is_breached(&password(breached)).await.unwrap());
+        }
+        assert!(!checker
+            .is_breached(&password(PADDED_PASSWORD))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn unavailable_api() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let checker = RangeApiChecker::new(&format!("http://{}", addr)).unwrap();
+        assert!(matches!(
+            checker.is_breached(&password(BREACHED_PASSWORD)).await,
+            Err(BreachCheckError::RequestError(_))
+        ));
+    }
+}
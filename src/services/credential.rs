@@ -0,0 +1,600 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{TimeDelta, Utc};
+use error::CredentialServiceError;
+use models::{
+    AssertionCredential, AuthenticationOptions, Ceremony, Passkey, RegistrationCredential,
+    RegistrationOptions, RelyingParty, WebAuthnChallenge,
+};
+use sha2::{Digest, Sha256};
+use stores::CredentialStore;
+use webauthn::{AttestationObject, AuthenticatorData, ClientData, CoseKey};
+
+use super::{account::id::ID, hash_token, random_token, Clock, SystemClock};
+
+pub mod error;
+pub mod models;
+pub mod stores;
+pub mod webauthn;
+
+/// How long the browser has to complete a ceremony after it is started.
+const WEBAUTHN_CHALLENGE_TTL: TimeDelta = TimeDelta::minutes(5);
+/// The longest name an account holder can give a passkey.
+const MAX_PASSKEY_NAME_LENGTH: usize = 64;
+/// The public key algorithms accepted for new passkeys, in order of preference.
+const SUPPORTED_ALGORITHMS: [i64; 3] = [
+    webauthn::COSE_ALG_ES256,
+    webauthn::COSE_ALG_EDDSA,
+    webauthn::COSE_ALG_RS256,
+];
+
+/// Manages the passkeys (WebAuthn credentials) registered to accounts, and
+/// runs the WebAuthn registration and authentication ceremonies. Each ceremony
+/// starts by issuing a single-use challenge, which the authenticator signs.
+pub struct CredentialService<S: CredentialStore, C: Clock<Utc>> {
+    store: S,
+    relying_party: RelyingParty,
+    clock: C,
+}
+
+impl<S: CredentialStore, C: Clock<Utc>> CredentialService<S, C> {
+    /// Constructs a new [CredentialService] given the [CredentialStore],
+    /// [RelyingParty] and [Clock] to use.
+    pub fn new_with_clock(credential_store: S, relying_party: RelyingParty, clock: C) -> Self {
+        Self {
+            store: credential_store,
+            relying_party,
+            clock,
+        }
+    }
+
+    /// Starts registering a new passkey for the account, returning the options
+    /// to pass to the browser. The account's user handle is its ID, so that
+    /// passkeys can later be used without entering an email address.
+    pub async fn start_registration(
+        &self,
+        account_id: &str,
+        account_name: &str,
+    ) -> Result<RegistrationOptions, CredentialServiceError> {
+        let challenge = self
+            .issue_challenge(Ceremony::Registration, Some(account_id))
+            .await?;
+        let exclude_credentials = self
+            .store
+            .load_passkeys(account_id)
+            .await?
+            .into_iter()
+            .map(|passkey| passkey.credential_id)
+            .collect();
+        Ok(RegistrationOptions {
+            challenge: challenge.0,
+            rp_id: self.relying_party.id.clone(),
+            rp_name: self.relying_party.name.clone(),
+            user_id: URL_SAFE_NO_PAD.encode(account_id),
+            user_name: account_name.to_string(),
+            algorithms: SUPPORTED_ALGORITHMS.to_vec(),
+            exclude_credentials,
+            expires_at: challenge.1.expires_at,
+        })
+    }
+
+    /// Completes a registration started by [CredentialService::start_registration],
+    /// verifying the authenticator's attestation and storing the new passkey.
+    pub async fn finish_registration(
+        &self,
+        account_id: &str,
+        name: &str,
+        credential: &RegistrationCredential,
+    ) -> Result<Passkey, CredentialServiceError> {
+        let name = validate_passkey_name(name)?;
+        let client_data = ClientData::parse(&credential.client_data_json)?;
+        let challenge = self
+            .take_challenge(&client_data, Ceremony::Registration)
+            .await?;
+        if challenge.account_id.as_deref() != Some(account_id) {
+            return Err(CredentialServiceError::InvalidChallenge);
+        }
+
+        let attestation = AttestationObject::parse(&credential.attestation_object)?;
+        let auth_data = AuthenticatorData::parse(&attestation.auth_data)?;
+        auth_data.verify(&self.relying_party.id)?;
+        let attested = auth_data.attested_credential.as_ref().ok_or_else(|| {
+            CredentialServiceError::InvalidAuthenticatorData(
+                "missing attested credential".to_string(),
+            )
+        })?;
+        let public_key = CoseKey::parse(&attested.public_key)?;
+        attestation.verify(
+            &auth_data,
+            &public_key,
+            &Sha256::digest(&credential.client_data_json),
+        )?;
+
+        if self
+            .store
+            .load_passkey_by_credential_id(&attested.credential_id)
+            .await?
+            .is_some()
+        {
+            return Err(CredentialServiceError::PasskeyAlreadyRegistered);
+        }
+        let passkey = Passkey {
+            id: ID::Pkey.create(),
+            account_id: account_id.to_string(),
+            name,
+            credential_id: attested.credential_id.clone(),
+            public_key: attested.public_key.clone(),
+            sign_count: auth_data.sign_count,
+            aaguid: attested.aaguid.clone(),
+            attestation_format: attestation.format,
+            created_at: self.clock.now(),
+            last_used_at: None,
+        };
+        self.store.insert_passkey(&passkey).await?;
+        Ok(passkey)
+    }
+
+    /// Starts signing in with a passkey, returning the options to pass to the
+    /// browser. The account isn't known yet, so the authenticator offers any
+    /// passkey it has for this relying party, and the passkey identifies the account.
+    pub async fn start_authentication(
+        &self,
+    ) -> Result<AuthenticationOptions, CredentialServiceError> {
+        let challenge = self.issue_challenge(Ceremony::Authentication, None).await?;
+        Ok(AuthenticationOptions {
+            challenge: challenge.0,
+            rp_id: self.relying_party.id.clone(),
+            expires_at: challenge.1.expires_at,
+        })
+    }
+
+    /// Completes a sign-in started by [CredentialService::start_authentication],
+    /// verifying the authenticator's signature and returning the passkey that
+    /// was used. Passkeys whose signature counter doesn't increase are rejected,
+    /// since that suggests the authenticator was cloned.
+    pub async fn finish_authentication(
+        &self,
+        credential: &AssertionCredential,
+    ) -> Result<Passkey, CredentialServiceError> {
+        let client_data = ClientData::parse(&credential.client_data_json)?;
+        self.take_challenge(&client_data, Ceremony::Authentication)
+            .await?;
+        let passkey = self
+            .store
+            .load_passkey_by_credential_id(&credential.credential_id)
+            .await?
+            .ok_or(CredentialServiceError::InvalidSignature)?;
+        // the user handle is the account ID the passkey was registered for
+        if credential
+            .user_handle
+            .as_ref()
+            .is_some_and(|handle| handle != passkey.account_id.as_bytes())
+        {
+            return Err(CredentialServiceError::InvalidSignature);
+        }
+
+        let auth_data = AuthenticatorData::parse(&credential.authenticator_data)?;
+        auth_data.verify(&self.relying_party.id)?;
+        let signed = [
+            credential.authenticator_data.as_slice(),
+            Sha256::digest(&credential.client_data_json).as_slice(),
+        ]
+        .concat();
+        CoseKey::parse(&passkey.public_key)?.verify(&signed, &credential.signature)?;
+
+        // authenticators that don't implement a counter always report zero
+        if (auth_data.sign_count != 0 || passkey.sign_count != 0)
+            && auth_data.sign_count <= passkey.sign_count
+        {
+            return Err(CredentialServiceError::SignCountNotIncreased);
+        }
+        let now = self.clock.now();
+        if !self
+            .store
+            .record_passkey_use(&passkey.id, passkey.sign_count, auth_data.sign_count, now)
+            .await?
+        {
+            return Err(CredentialServiceError::SignCountNotIncreased);
+        }
+        Ok(Passkey {
+            sign_count: auth_data.sign_count,
+            last_used_at: Some(now),
+            ..passkey
+        })
+    }
+
+    /// Returns the account's passkeys, oldest first.
+    pub async fn list_passkeys(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<Passkey>, CredentialServiceError> {
+        Ok(self.store.load_passkeys(account_id).await?)
+    }
+
+    /// Changes the name of one of the account's passkeys.
+    pub async fn rename_passkey(
+        &self,
+        account_id: &str,
+        passkey_id: &str,
+        name: &str,
+    ) -> Result<Passkey, CredentialServiceError> {
+        let name = validate_passkey_name(name)?;
+        let passkey = self.get_passkey(account_id, passkey_id).await?;
+        self.store.update_passkey_name(&passkey.id, &name).await?;
+        Ok(Passkey { name, ..passkey })
+    }
+
+    /// Removes one of the account's passkeys.
+    pub async fn delete_passkey(
+        &self,
+        account_id: &str,
+        passkey_id: &str,
+    ) -> Result<(), CredentialServiceError> {
+        let passkey = self.get_passkey(account_id, passkey_id).await?;
+        Ok(self.store.delete_passkey(&passkey.id).await?)
+    }
+
+    /// Removes all of the account's passkeys (e.g., when the account is deleted).
+    pub async fn delete_passkeys(&self, account_id: &str) -> Result<(), CredentialServiceError> {
+        Ok(self.store.delete_passkeys(account_id).await?)
+    }
+
+    /// Loads a passkey, making sure it belongs to the account.
+    async fn get_passkey(
+        &self,
+        account_id: &str,
+        passkey_id: &str,
+    ) -> Result<Passkey, CredentialServiceError> {
+        self.store
+            .load_passkey(passkey_id)
+            .await?
+            .filter(|passkey| passkey.account_id == account_id)
+            .ok_or_else(|| CredentialServiceError::PasskeyNotFound(passkey_id.to_string()))
+    }
+
+    /// Stores a new challenge for the ceremony, returning it along with the
+    /// stored record (which contains only its hash).
+    async fn issue_challenge(
+        &self,
+        ceremony: Ceremony,
+        account_id: Option<&str>,
+    ) -> Result<(String, WebAuthnChallenge), CredentialServiceError> {
+        let challenge = random_token();
+        let now = self.clock.now();
+        let stored = WebAuthnChallenge {
+            token_hash: hash_token(&challenge),
+            ceremony,
+            account_id: account_id.map(str::to_string),
+            created_at: now,
+            expires_at: now + WEBAUTHN_CHALLENGE_TTL,
+        };
+        self.store.insert_challenge(&stored).await?;
+        Ok((challenge, stored))
+    }
+
+    /// Consumes the challenge the client data was signed for, making sure
+    /// it was issued for the ceremony, hasn't expired, and that the client
+    /// data came from the relying party's origin.
+    async fn take_challenge(
+        &self,
+        client_data: &ClientData,
+        ceremony: Ceremony,
+    ) -> Result<WebAuthnChallenge, CredentialServiceError> {
+        if client_data.ceremony_type != ceremony.client_data_type() {
+            return Err(CredentialServiceError::InvalidClientData(format!(
+                "expected type {}",
+                ceremony.client_data_type()
+            )));
+        }
+        if client_data.origin != self.relying_party.origin {
+            return Err(CredentialServiceError::InvalidClientData(format!(
+                "unexpected origin {}",
+                client_data.origin
+            )));
+        }
+        self.store
+            .take_challenge(&hash_token(&client_data.challenge))
+            .await?
+            .filter(|challenge| {
+                challenge.ceremony == ceremony && self.clock.now() < challenge.expires_at
+            })
+            .ok_or(CredentialServiceError::InvalidChallenge)
+    }
+}
+
+impl<S: CredentialStore> CredentialService<S, SystemClock<Utc>> {
+    pub fn new(credential_store: S, relying_party: RelyingParty) -> Self {
+        Self::new_with_clock(credential_store, relying_party, SystemClock::default())
+    }
+}
+
+/// Trims the name, and makes sure it isn't empty or too long.
+fn validate_passkey_name(name: &str) -> Result<String, CredentialServiceError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_PASSKEY_NAME_LENGTH {
+        return Err(CredentialServiceError::InvalidPasskeyName(
+            MAX_PASSKEY_NAME_LENGTH,
+        ));
+    }
+    Ok(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use stores::fake::FakeCredentialStore;
+    use webauthn::tests::{SoftwareAuthenticator, TEST_ORIGIN, TEST_RP_ID};
+
+    use crate::services::TestClock;
+
+    use super::*;
+
+    fn test_service(
+        clock: &TestClock<Utc>,
+    ) -> CredentialService<FakeCredentialStore, TestClock<Utc>> {
+        CredentialService::new_with_clock(
+            FakeCredentialStore::new(),
+            RelyingParty {
+                id: TEST_RP_ID.to_string(),
+                name: "Test".to_string(),
+                origin: TEST_ORIGIN.to_string(),
+            },
+            clock.clone(),
+        )
+    }
+
+    async fn register(
+        service: &CredentialService<FakeCredentialStore, TestClock<Utc>>,
+        authenticator: &SoftwareAuthenticator,
+        name: &str,
+    ) -> Passkey {
+        let options = service
+            .start_registration("acct_test", "test@test.com")
+            .await
+            .unwrap();
+        service
+            .finish_registration(
+                "acct_test",
+                name,
+                &authenticator.register(&options.challenge, "none"),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn register_passkeys() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let options = service
+            .start_registration("acct_test", "test@test.com")
+            .await
+            .unwrap();
+        assert_eq!(TEST_RP_ID, options.rp_id);
+        assert_eq!(URL_SAFE_NO_PAD.encode("acct_test"), options.user_id);
+        assert!(options.exclude_credentials.is_empty());
+
+        let authenticator = SoftwareAuthenticator::new();
+        let credential = authenticator.register(&options.challenge, "packed");
+        let passkey = service
+            .finish_registration("acct_test", " Laptop ", &credential)
+            .await
+            .unwrap();
+        assert_eq!("Laptop", passkey.name);
+        assert_eq!(authenticator.credential_id, passkey.credential_id);
+        assert_eq!("packed", passkey.attestation_format);
+
+        // the challenge can only be used once
+        assert!(matches!(
+            service
+                .finish_registration("acct_test", "Laptop", &credential)
+                .await,
+            Err(CredentialServiceError::InvalidChallenge)
+        ));
+
+        // an account can have several passkeys, but each only once
+        let options = service
+            .start_registration("acct_test", "test@test.com")
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![authenticator.credential_id.clone()],
+            options.exclude_credentials
+        );
+        assert!(matches!(
+            service
+                .finish_registration(
+                    "acct_test",
+                    "Again",
+                    &authenticator.register(&options.challenge, "none")
+                )
+                .await,
+            Err(CredentialServiceError::PasskeyAlreadyRegistered)
+        ));
+        clock.advance(TimeDelta::seconds(1));
+        register(&service, &SoftwareAuthenticator::new(), "Phone").await;
+        let names: Vec<String> = service
+            .list_passkeys("acct_test")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|passkey| passkey.name)
+            .collect();
+        assert_eq!(vec!["Laptop", "Phone"], names);
+    }
+
+    #[tokio::test]
+    async fn registration_rejects_invalid_responses() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let mut authenticator = SoftwareAuthenticator::new();
+
+        // challenge issued to another account
+        let options = service
+            .start_registration("acct_other", "other@test.com")
+            .await
+            .unwrap();
+        let credential = authenticator.register(&options.challenge, "none");
+        assert!(matches!(
+            service
+                .finish_registration("acct_test", "Laptop", &credential)
+                .await,
+            Err(CredentialServiceError::InvalidChallenge)
+        ));
+
+        // expired challenge
+        let options = service
+            .start_registration("acct_test", "test@test.com")
+            .await
+            .unwrap();
+        clock.advance(WEBAUTHN_CHALLENGE_TTL);
+        let credential = authenticator.register(&options.challenge, "none");
+        assert!(matches!(
+            service
+                .finish_registration("acct_test", "Laptop", &credential)
+                .await,
+            Err(CredentialServiceError::InvalidChallenge)
+        ));
+
+        // wrong origin
+        let options = service
+            .start_registration("acct_test", "test@test.com")
+            .await
+            .unwrap();
+        authenticator.origin = "https://evil.example.com".to_string();
+        let credential = authenticator.register(&options.challenge, "none");
+        assert!(matches!(
+            service
+                .finish_registration("acct_test", "Laptop", &credential)
+                .await,
+            Err(CredentialServiceError::InvalidClientData(_))
+        ));
+        authenticator.origin = TEST_ORIGIN.to_string();
+
+        // tampered client data
+        let options = service
+            .start_registration("acct_test", "test@test.com")
+            .await
+            .unwrap();
+        let mut credential = authenticator.register(&options.challenge, "packed");
+        credential.client_data_json.push(b' ');
+        assert!(matches!(
+            service
+                .finish_registration("acct_test", "Laptop", &credential)
+                .await,
+            Err(CredentialServiceError::InvalidSignature)
+        ));
+
+        assert!(matches!(
+            service
+                .finish_registration("acct_test", " ", &credential)
+                .await,
+            Err(CredentialServiceError::InvalidPasskeyName(_))
+        ));
+        assert!(service.list_passkeys("acct_test").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn authenticate_with_passkey() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let mut authenticator = SoftwareAuthenticator::new();
+        let passkey = register(&service, &authenticator, "Laptop").await;
+
+        let options = service.start_authentication().await.unwrap();
+        assert_eq!(TEST_RP_ID, options.rp_id);
+        let assertion = authenticator.assert(&options.challenge, Some("acct_test"));
+        let used = service.finish_authentication(&assertion).await.unwrap();
+        assert_eq!(passkey.id, used.id);
+        assert_eq!("acct_test", used.account_id);
+        assert_eq!(Some(clock.now()), used.last_used_at);
+        assert!(matches!(
+            service.finish_authentication(&assertion).await,
+            Err(CredentialServiceError::InvalidChallenge)
+        ));
+
+        // authenticators don't always return the user handle, but it must match if they do
+        let options = service.start_authentication().await.unwrap();
+        let assertion = authenticator.assert(&options.challenge, None);
+        service.finish_authentication(&assertion).await.unwrap();
+        let options = service.start_authentication().await.unwrap();
+        let assertion = authenticator.assert(&options.challenge, Some("acct_other"));
+        assert!(service.finish_authentication(&assertion).await.is_err());
+
+        // a registration challenge can't be used to sign in
+        let options = service
+            .start_registration("acct_test", "test@test.com")
+            .await
+            .unwrap();
+        let assertion = authenticator.assert(&options.challenge, None);
+        assert!(matches!(
+            service.finish_authentication(&assertion).await,
+            Err(CredentialServiceError::InvalidChallenge)
+        ));
+
+        // a bad signature
+        let options = service.start_authentication().await.unwrap();
+        let mut assertion = authenticator.assert(&options.challenge, None);
+        assertion.signature[10] ^= 0xff;
+        assert!(matches!(
+            service.finish_authentication(&assertion).await,
+            Err(CredentialServiceError::InvalidSignature)
+        ));
+    }
+
+    #[tokio::test]
+    async fn authentication_detects_cloned_authenticators() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let mut authenticator = SoftwareAuthenticator::new();
+        register(&service, &authenticator, "Laptop").await;
+
+        authenticator.sign_count = 5;
+        let options = service.start_authentication().await.unwrap();
+        let assertion = authenticator.assert(&options.challenge, None);
+        assert_eq!(
+            6,
+            service
+                .finish_authentication(&assertion)
+                .await
+                .unwrap()
+                .sign_count
+        );
+
+        // a clone would report a counter that has already been used
+        authenticator.sign_count = 3;
+        let options = service.start_authentication().await.unwrap();
+        let assertion = authenticator.assert(&options.challenge, None);
+        assert!(matches!(
+            service.finish_authentication(&assertion).await,
+            Err(CredentialServiceError::SignCountNotIncreased)
+        ));
+    }
+
+    #[tokio::test]
+    async fn manage_passkeys() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let passkey = register(&service, &SoftwareAuthenticator::new(), "Laptop").await;
+
+        let renamed = service
+            .rename_passkey("acct_test", &passkey.id, "Work laptop")
+            .await
+            .unwrap();
+        assert_eq!("Work laptop", renamed.name);
+        assert!(matches!(
+            service
+                .rename_passkey("acct_other", &passkey.id, "Mine now")
+                .await,
+            Err(CredentialServiceError::PasskeyNotFound(_))
+        ));
+        assert!(matches!(
+            service.delete_passkey("acct_other", &passkey.id).await,
+            Err(CredentialServiceError::PasskeyNotFound(_))
+        ));
+
+        service
+            .delete_passkey("acct_test", &passkey.id)
+            .await
+            .unwrap();
+        assert!(service.list_passkeys("acct_test").await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,33 @@
+use thiserror::Error;
+
+use super::stores::error::CredentialStoreError;
+
+#[derive(Error, Debug)]
+pub enum CredentialServiceError {
+    #[error("There was an error interacting with the data store: {0}")]
+    StoreError(#[from] CredentialStoreError),
+    #[error("The WebAuthn challenge is invalid or has expired")]
+    InvalidChallenge,
+    #[error("The client data is invalid: {0}")]
+    InvalidClientData(String),
+    #[error("The authenticator data is invalid: {0}")]
+    InvalidAuthenticatorData(String),
+    #[error("The attestation is invalid: {0}")]
+    InvalidAttestation(String),
+    #[error("The attestation format '{0}' is not supported")]
+    UnsupportedAttestationFormat(String),
+    #[error("The public key is invalid or uses an unsupported algorithm")]
+    UnsupportedPublicKey,
+    #[error("The authenticator did not verify the user")]
+    UserNotVerified,
+    #[error("The signature is invalid")]
+    InvalidSignature,
+    #[error("The signature counter did not increase, so the passkey may have been cloned")]
+    SignCountNotIncreased,
+    #[error("The passkey is already registered")]
+    PasskeyAlreadyRegistered,
+    #[error("The passkey name must be between 1 and {0} characters")]
+    InvalidPasskeyName(usize),
+    #[error("Passkey '{0}' was not found")]
+    PasskeyNotFound(String),
+}
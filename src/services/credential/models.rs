@@ -0,0 +1,157 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+
+use super::stores::error::CredentialStoreError;
+
+/// Identifies this service to authenticators as a WebAuthn relying party.
+/// Passkeys are scoped to the relying party ID, so changing it makes all
+/// existing passkeys unusable.
+#[derive(Debug, Clone)]
+pub struct RelyingParty {
+    /// The relying party ID: the domain name of the site (e.g., `example.com`).
+    pub id: String,
+    /// The name shown by the browser when creating a passkey.
+    pub name: String,
+    /// The origin of the pages that run the ceremonies (e.g., `https://example.com`).
+    pub origin: String,
+}
+
+/// Represents a passkey (a WebAuthn public key credential) registered to an account.
+/// An account can have several, each with a name chosen by the account holder
+/// (e.g., "Work laptop").
+#[derive(Debug, Clone)]
+pub struct Passkey {
+    /// Unique ID
+    pub id: String,
+    /// ID of the account this passkey belongs to.
+    pub account_id: String,
+    /// Name that identifies the passkey to the account holder.
+    pub name: String,
+    /// The credential ID chosen by the authenticator.
+    pub credential_id: Vec<u8>,
+    /// The credential's public key, as a COSE_Key.
+    pub public_key: Vec<u8>,
+    /// The last signature counter value reported by the authenticator.
+    pub sign_count: u32,
+    /// The authenticator model's AAGUID (all zeros if not disclosed).
+    pub aaguid: Vec<u8>,
+    /// The attestation statement format used when the passkey was registered.
+    pub attestation_format: String,
+    /// When this passkey was registered.
+    pub created_at: DateTime<Utc>,
+    /// When this passkey was last used to sign in, if it has been.
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// The kinds of WebAuthn ceremonies a challenge can be issued for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ceremony {
+    Registration,
+    Authentication,
+}
+
+impl Ceremony {
+    /// Returns the name used to store this ceremony.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Ceremony::Registration => "registration",
+            Ceremony::Authentication => "authentication",
+        }
+    }
+
+    /// Returns the `type` the client data must have for this ceremony.
+    pub fn client_data_type(&self) -> &'static str {
+        match self {
+            Ceremony::Registration => "webauthn.create",
+            Ceremony::Authentication => "webauthn.get",
+        }
+    }
+}
+
+impl FromStr for Ceremony {
+    type Err = CredentialStoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "registration" => Ok(Ceremony::Registration),
+            "authentication" => Ok(Ceremony::Authentication),
+            _ => Err(CredentialStoreError::UnknownCeremony(s.to_string())),
+        }
+    }
+}
+
+/// Represents a stored WebAuthn challenge, issued at the start of a ceremony
+/// and consumed when the authenticator's response is verified.
+#[derive(Debug, Clone)]
+pub struct WebAuthnChallenge {
+    /// SHA-256 hash of the challenge.
+    pub token_hash: String,
+    /// The ceremony the challenge was issued for.
+    pub ceremony: Ceremony,
+    /// ID of the account the challenge was issued to. This is always set for
+    /// registrations, but not for sign-ins where the account isn't known yet.
+    pub account_id: Option<String>,
+    /// When this challenge was created.
+    pub created_at: DateTime<Utc>,
+    /// When this challenge expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The options passed to the browser to create a new passkey.
+#[derive(Debug, Clone)]
+pub struct RegistrationOptions {
+    /// The challenge, base64url-encoded. Only a hash of this is stored,
+    /// so this is the only time the actual value is available.
+    pub challenge: String,
+    /// The relying party ID (a domain name).
+    pub rp_id: String,
+    /// The relying party name shown by the browser.
+    pub rp_name: String,
+    /// The user handle stored with the passkey, base64url-encoded.
+    pub user_id: String,
+    /// The account name shown by the browser (e.g., the email address).
+    pub user_name: String,
+    /// COSE algorithm identifiers of the supported public key types, in order of preference.
+    pub algorithms: Vec<i64>,
+    /// IDs of the account's existing passkeys, so the same authenticator isn't registered twice.
+    pub exclude_credentials: Vec<Vec<u8>>,
+    /// When the challenge expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The options passed to the browser to sign in with a passkey.
+#[derive(Debug, Clone)]
+pub struct AuthenticationOptions {
+    /// The challenge, base64url-encoded. Only a hash of this is stored,
+    /// so this is the only time the actual value is available.
+    pub challenge: String,
+    /// The relying party ID (a domain name).
+    pub rp_id: String,
+    /// When the challenge expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The authenticator's response to a registration ceremony.
+#[derive(Debug, Clone)]
+pub struct RegistrationCredential {
+    /// The JSON-encoded client data, exactly as provided by the browser.
+    pub client_data_json: Vec<u8>,
+    /// The CBOR-encoded attestation object.
+    pub attestation_object: Vec<u8>,
+}
+
+/// The authenticator's response to an authentication ceremony.
+#[derive(Debug, Clone)]
+pub struct AssertionCredential {
+    /// The ID of the passkey that was used.
+    pub credential_id: Vec<u8>,
+    /// The JSON-encoded client data, exactly as provided by the browser.
+    pub client_data_json: Vec<u8>,
+    /// The authenticator data that was signed.
+    pub authenticator_data: Vec<u8>,
+    /// The signature over the authenticator data and client data hash.
+    pub signature: Vec<u8>,
+    /// The user handle stored with the passkey, if the authenticator returned one.
+    pub user_handle: Option<Vec<u8>>,
+}
@@ -0,0 +1,44 @@
+pub mod error;
+#[cfg(test)]
+pub mod fake;
+pub mod postgres;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use error::CredentialStoreError;
+
+use crate::services::credential::models::{Passkey, WebAuthnChallenge};
+
+#[async_trait]
+pub trait CredentialStore: Send + Sync + 'static {
+    async fn insert_passkey(&self, passkey: &Passkey) -> Result<(), CredentialStoreError>;
+    async fn load_passkey(&self, id: &str) -> Result<Option<Passkey>, CredentialStoreError>;
+    async fn load_passkey_by_credential_id(
+        &self,
+        credential_id: &[u8],
+    ) -> Result<Option<Passkey>, CredentialStoreError>;
+    /// Returns the account's passkeys, oldest first.
+    async fn load_passkeys(&self, account_id: &str) -> Result<Vec<Passkey>, CredentialStoreError>;
+    async fn update_passkey_name(&self, id: &str, name: &str) -> Result<(), CredentialStoreError>;
+    /// Records that the passkey was used, but only if its signature counter is
+    /// still `previous_sign_count`. Returns false if it isn't (or the passkey
+    /// doesn't exist), so concurrent requests can't both use the same counter value.
+    async fn record_passkey_use(
+        &self,
+        id: &str,
+        previous_sign_count: u32,
+        sign_count: u32,
+        used_at: DateTime<Utc>,
+    ) -> Result<bool, CredentialStoreError>;
+    async fn delete_passkey(&self, id: &str) -> Result<(), CredentialStoreError>;
+    async fn delete_passkeys(&self, account_id: &str) -> Result<(), CredentialStoreError>;
+    async fn insert_challenge(
+        &self,
+        challenge: &WebAuthnChallenge,
+    ) -> Result<(), CredentialStoreError>;
+    /// Deletes and returns the challenge, so that it can only be used once.
+    async fn take_challenge(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<WebAuthnChallenge>, CredentialStoreError>;
+}
@@ -0,0 +1,9 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CredentialStoreError {
+    #[error("database error: {0}")]
+    DatabaseError(String),
+    #[error("unknown WebAuthn ceremony '{0}'")]
+    UnknownCeremony(String),
+}
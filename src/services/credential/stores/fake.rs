@@ -0,0 +1,134 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::services::credential::models::{Passkey, WebAuthnChallenge};
+
+use super::{error::CredentialStoreError, CredentialStore};
+
+/// The "database" for the FakeCredentialStore.
+struct Database {
+    /// Passkeys keyed by ID.
+    passkeys: HashMap<String, Passkey>,
+    /// WebAuthn challenges keyed by token hash.
+    challenges: HashMap<String, WebAuthnChallenge>,
+}
+
+/// A fake implementation of [CredentialStore] that can be used in unit tests.
+pub struct FakeCredentialStore {
+    db: Mutex<Database>,
+}
+
+impl FakeCredentialStore {
+    pub fn new() -> FakeCredentialStore {
+        FakeCredentialStore {
+            db: Mutex::new(Database {
+                passkeys: HashMap::new(),
+                challenges: HashMap::new(),
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl CredentialStore for FakeCredentialStore {
+    async fn insert_passkey(&self, passkey: &Passkey) -> Result<(), CredentialStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .passkeys
+            .insert(passkey.id.clone(), passkey.clone());
+        Ok(())
+    }
+
+    async fn load_passkey(&self, id: &str) -> Result<Option<Passkey>, CredentialStoreError> {
+        Ok(self.db.lock().unwrap().passkeys.get(id).cloned())
+    }
+
+    async fn load_passkey_by_credential_id(
+        &self,
+        credential_id: &[u8],
+    ) -> Result<Option<Passkey>, CredentialStoreError> {
+        Ok(self
+            .db
+            .lock()
+            .unwrap()
+            .passkeys
+            .values()
+            .find(|passkey| passkey.credential_id == credential_id)
+            .cloned())
+    }
+
+    async fn load_passkeys(&self, account_id: &str) -> Result<Vec<Passkey>, CredentialStoreError> {
+        let mut passkeys: Vec<Passkey> = self
+            .db
+            .lock()
+            .unwrap()
+            .passkeys
+            .values()
+            .filter(|passkey| passkey.account_id == account_id)
+            .cloned()
+            .collect();
+        passkeys.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)));
+        Ok(passkeys)
+    }
+
+    async fn update_passkey_name(&self, id: &str, name: &str) -> Result<(), CredentialStoreError> {
+        if let Some(passkey) = self.db.lock().unwrap().passkeys.get_mut(id) {
+            passkey.name = name.to_string();
+        }
+        Ok(())
+    }
+
+    async fn record_passkey_use(
+        &self,
+        id: &str,
+        previous_sign_count: u32,
+        sign_count: u32,
+        used_at: DateTime<Utc>,
+    ) -> Result<bool, CredentialStoreError> {
+        let mut db = self.db.lock().unwrap();
+        match db.passkeys.get_mut(id) {
+            Some(passkey) if passkey.sign_count == previous_sign_count => {
+                passkey.sign_count = sign_count;
+                passkey.last_used_at = Some(used_at);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete_passkey(&self, id: &str) -> Result<(), CredentialStoreError> {
+        self.db.lock().unwrap().passkeys.remove(id);
+        Ok(())
+    }
+
+    async fn delete_passkeys(&self, account_id: &str) -> Result<(), CredentialStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .passkeys
+            .retain(|_, passkey| passkey.account_id != account_id);
+        Ok(())
+    }
+
+    async fn insert_challenge(
+        &self,
+        challenge: &WebAuthnChallenge,
+    ) -> Result<(), CredentialStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .challenges
+            .insert(challenge.token_hash.clone(), challenge.clone());
+        Ok(())
+    }
+
+    async fn take_challenge(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<WebAuthnChallenge>, CredentialStoreError> {
+        Ok(self.db.lock().unwrap().challenges.remove(token_hash))
+    }
+}
@@ -0,0 +1,196 @@
+//! Implements [CredentialStore] backed by a PostgreSQL database
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, PgPool, Row};
+
+use crate::services::credential::models::{Passkey, WebAuthnChallenge};
+
+use super::{error::CredentialStoreError, CredentialStore};
+
+const PASSKEY_COLUMNS: &str = "id,account_id,name,credential_id,public_key,sign_count,aaguid,\
+    attestation_format,created_at,last_used_at";
+
+impl From<sqlx::Error> for CredentialStoreError {
+    fn from(value: sqlx::Error) -> Self {
+        CredentialStoreError::DatabaseError(value.to_string())
+    }
+}
+
+pub struct PostgresCredentialStore {
+    pool: PgPool,
+}
+
+impl PostgresCredentialStore {
+    pub fn new(pool: PgPool) -> PostgresCredentialStore {
+        PostgresCredentialStore { pool }
+    }
+}
+
+/// Maps a row selected using [PASSKEY_COLUMNS] to a [Passkey]. The signature
+/// counter is an unsigned 32-bit value, so it is stored as a bigint.
+fn passkey_from_row(row: PgRow) -> Passkey {
+    Passkey {
+        id: row.get(0),
+        account_id: row.get(1),
+        name: row.get(2),
+        credential_id: row.get(3),
+        public_key: row.get(4),
+        sign_count: row.get::<i64, _>(5) as u32,
+        aaguid: row.get(6),
+        attestation_format: row.get(7),
+        created_at: row.get(8),
+        last_used_at: row.get(9),
+    }
+}
+
+#[async_trait]
+impl CredentialStore for PostgresCredentialStore {
+    async fn insert_passkey(&self, passkey: &Passkey) -> Result<(), CredentialStoreError> {
+        sqlx::query(&format!(
+            "insert into passkeys({}) values ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10)",
+            PASSKEY_COLUMNS
+        ))
+        .bind(&passkey.id)
+        .bind(&passkey.account_id)
+        .bind(&passkey.name)
+        .bind(&passkey.credential_id)
+        .bind(&passkey.public_key)
+        .bind(passkey.sign_count as i64)
+        .bind(&passkey.aaguid)
+        .bind(&passkey.attestation_format)
+        .bind(passkey.created_at)
+        .bind(passkey.last_used_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_passkey(&self, id: &str) -> Result<Option<Passkey>, CredentialStoreError> {
+        Ok(sqlx::query(&format!(
+            "select {} from passkeys where id=$1",
+            PASSKEY_COLUMNS
+        ))
+        .bind(id)
+        .map(passkey_from_row)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn load_passkey_by_credential_id(
+        &self,
+        credential_id: &[u8],
+    ) -> Result<Option<Passkey>, CredentialStoreError> {
+        Ok(sqlx::query(&format!(
+            "select {} from passkeys where credential_id=$1",
+            PASSKEY_COLUMNS
+        ))
+        .bind(credential_id)
+        .map(passkey_from_row)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn load_passkeys(&self, account_id: &str) -> Result<Vec<Passkey>, CredentialStoreError> {
+        Ok(sqlx::query(&format!(
+            "select {} from passkeys where account_id=$1 order by created_at, id",
+            PASSKEY_COLUMNS
+        ))
+        .bind(account_id)
+        .map(passkey_from_row)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn update_passkey_name(&self, id: &str, name: &str) -> Result<(), CredentialStoreError> {
+        sqlx::query("update passkeys set name=$1 where id=$2")
+            .bind(name)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn record_passkey_use(
+        &self,
+        id: &str,
+        previous_sign_count: u32,
+        sign_count: u32,
+        used_at: DateTime<Utc>,
+    ) -> Result<bool, CredentialStoreError> {
+        let result = sqlx::query(
+            "update passkeys set sign_count=$1, last_used_at=$2 where id=$3 and sign_count=$4",
+        )
+        .bind(sign_count as i64)
+        .bind(used_at)
+        .bind(id)
+        .bind(previous_sign_count as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_passkey(&self, id: &str) -> Result<(), CredentialStoreError> {
+        sqlx::query("delete from passkeys where id=$1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_passkeys(&self, account_id: &str) -> Result<(), CredentialStoreError> {
+        sqlx::query("delete from passkeys where account_id=$1")
+            .bind(account_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn insert_challenge(
+        &self,
+        challenge: &WebAuthnChallenge,
+    ) -> Result<(), CredentialStoreError> {
+        sqlx::query(
+            "insert into webauthn_challenges(token_hash,ceremony,account_id,created_at,expires_at) \
+            values ($1,$2,$3,$4,$5)",
+        )
+        .bind(&challenge.token_hash)
+        .bind(challenge.ceremony.as_str())
+        .bind(&challenge.account_id)
+        .bind(challenge.created_at)
+        .bind(challenge.expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn take_challenge(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<WebAuthnChallenge>, CredentialStoreError> {
+        let row = sqlx::query(
+            "delete from webauthn_challenges where token_hash=$1 \
+            returning token_hash,ceremony,account_id,created_at,expires_at",
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| {
+            Ok(WebAuthnChallenge {
+                token_hash: row.get(0),
+                ceremony: row.get::<String, _>(1).parse()?,
+                account_id: row.get(2),
+                created_at: row.get(3),
+                expires_at: row.get(4),
+            })
+        })
+        .transpose()
+    }
+}
@@ -0,0 +1,600 @@
+//! Parsing and verification of the data structures used in WebAuthn ceremonies,
+//! as defined in the [Web Authentication spec](https://www.w3.org/TR/webauthn-2/).
+//! Only what a relying party needs is implemented: the client data, the
+//! authenticator data, COSE public keys, and the `none` and `packed`
+//! attestation statement formats.
+
+use ciborium::Value;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use x509_cert::{
+    der::{oid::ObjectIdentifier, Decode},
+    Certificate,
+};
+
+use super::error::CredentialServiceError;
+
+/// COSE algorithm identifier for ECDSA using P-256 and SHA-256.
+pub const COSE_ALG_ES256: i64 = -7;
+/// COSE algorithm identifier for EdDSA (always Ed25519 for WebAuthn).
+pub const COSE_ALG_EDDSA: i64 = -8;
+/// COSE algorithm identifier for RSASSA-PKCS1-v1_5 using SHA-256.
+pub const COSE_ALG_RS256: i64 = -257;
+
+/// The user was present (e.g., touched the authenticator).
+const FLAG_USER_PRESENT: u8 = 0x01;
+/// The user was verified (e.g., by PIN or biometric).
+const FLAG_USER_VERIFIED: u8 = 0x04;
+/// The authenticator data includes an attested credential.
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+/// Length of the fixed part of the authenticator data: the RP ID hash,
+/// flags and signature counter.
+const AUTHENTICATOR_DATA_MIN_LENGTH: usize = 37;
+/// Length of an authenticator model's AAGUID.
+const AAGUID_LENGTH: usize = 16;
+/// The longest credential ID allowed by the spec.
+const MAX_CREDENTIAL_ID_LENGTH: usize = 1023;
+/// The X.509 extension in which `packed` attestation certificates include
+/// the AAGUID of the authenticator model.
+const FIDO_GEN_CE_AAGUID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.3.6.1.4.1.45724.1.1.4");
+
+/// The client data collected by the browser (`clientDataJSON`).
+#[derive(Debug, Deserialize)]
+pub struct ClientData {
+    /// Either `webauthn.create` or `webauthn.get`.
+    #[serde(rename = "type")]
+    pub ceremony_type: String,
+    /// The challenge, base64url-encoded.
+    pub challenge: String,
+    /// The origin of the page that started the ceremony.
+    pub origin: String,
+}
+
+impl ClientData {
+    pub fn parse(client_data_json: &[u8]) -> Result<ClientData, CredentialServiceError> {
+        serde_json::from_slice(client_data_json)
+            .map_err(|e| CredentialServiceError::InvalidClientData(e.to_string()))
+    }
+}
+
+/// The data an authenticator returns and signs during each ceremony.
+#[derive(Debug)]
+pub struct AuthenticatorData {
+    /// SHA-256 hash of the relying party ID the credential is scoped to.
+    pub rp_id_hash: Vec<u8>,
+    /// Bit flags describing the ceremony.
+    pub flags: u8,
+    /// The signature counter, or zero if the authenticator doesn't have one.
+    pub sign_count: u32,
+    /// The new credential, included only during registration.
+    pub attested_credential: Option<AttestedCredential>,
+}
+
+/// A newly-created credential included in the [AuthenticatorData].
+#[derive(Debug)]
+pub struct AttestedCredential {
+    /// The authenticator model's AAGUID.
+    pub aaguid: Vec<u8>,
+    /// The credential ID.
+    pub credential_id: Vec<u8>,
+    /// The credential's public key, as a COSE_Key.
+    pub public_key: Vec<u8>,
+}
+
+impl AuthenticatorData {
+    pub fn parse(data: &[u8]) -> Result<AuthenticatorData, CredentialServiceError> {
+        if data.len() < AUTHENTICATOR_DATA_MIN_LENGTH {
+            return Err(invalid_authenticator_data("too short"));
+        }
+        let flags = data[32];
+        let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL_DATA != 0 {
+            Some(AttestedCredential::parse(
+                &data[AUTHENTICATOR_DATA_MIN_LENGTH..],
+            )?)
+        } else {
+            None
+        };
+        Ok(AuthenticatorData {
+            rp_id_hash: data[..32].to_vec(),
+            flags,
+            sign_count: u32::from_be_bytes([data[33], data[34], data[35], data[36]]),
+            attested_credential,
+        })
+    }
+
+    /// Returns an error unless the data is scoped to the relying party ID,
+    /// and the authenticator verified the user (which implies they were present).
+    pub fn verify(&self, rp_id: &str) -> Result<(), CredentialServiceError> {
+        if self.rp_id_hash != Sha256::digest(rp_id.as_bytes()).as_slice() {
+            return Err(invalid_authenticator_data("wrong relying party ID"));
+        }
+        let required = FLAG_USER_PRESENT | FLAG_USER_VERIFIED;
+        if self.flags & required != required {
+            return Err(CredentialServiceError::UserNotVerified);
+        }
+        Ok(())
+    }
+}
+
+impl AttestedCredential {
+    fn parse(data: &[u8]) -> Result<AttestedCredential, CredentialServiceError> {
+        if data.len() < AAGUID_LENGTH + 2 {
+            return Err(invalid_authenticator_data(
+                "attested credential is too short",
+            ));
+        }
+        let (aaguid, rest) = data.split_at(AAGUID_LENGTH);
+        let id_length = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+        let rest = &rest[2..];
+        if id_length > MAX_CREDENTIAL_ID_LENGTH || rest.len() < id_length {
+            return Err(invalid_authenticator_data("invalid credential ID length"));
+        }
+        let (credential_id, rest) = rest.split_at(id_length);
+
+        // the public key is followed by any extensions, so decode it to find its length
+        let mut remaining = rest;
+        let _: Value = ciborium::de::from_reader(&mut remaining)
+            .map_err(|_| invalid_authenticator_data("invalid credential public key"))?;
+        let public_key = &rest[..rest.len() - remaining.len()];
+
+        Ok(AttestedCredential {
+            aaguid: aaguid.to_vec(),
+            credential_id: credential_id.to_vec(),
+            public_key: public_key.to_vec(),
+        })
+    }
+}
+
+/// A credential public key, decoded from a COSE_Key (RFC 9053).
+#[derive(Debug)]
+pub enum CoseKey {
+    /// An uncompressed P-256 point.
+    Es256(Vec<u8>),
+    /// An Ed25519 public key.
+    EdDsa(Vec<u8>),
+    /// An RSA public key.
+    Rs256 { n: Vec<u8>, e: Vec<u8> },
+}
+
+impl CoseKey {
+    pub fn parse(cose_key: &[u8]) -> Result<CoseKey, CredentialServiceError> {
+        let value: Value = ciborium::de::from_reader(cose_key)
+            .map_err(|_| CredentialServiceError::UnsupportedPublicKey)?;
+        let map = value
+            .as_map()
+            .ok_or(CredentialServiceError::UnsupportedPublicKey)?;
+        let int = |label: i64| map_get(map, label).and_then(value_to_i64);
+        let bytes = |label: i64| {
+            map_get(map, label)
+                .and_then(Value::as_bytes)
+                .cloned()
+                .ok_or(CredentialServiceError::UnsupportedPublicKey)
+        };
+        // labels: 1 = key type, 3 = algorithm, -1 = curve (or RSA modulus), and
+        // -2/-3 = coordinates (or RSA exponent)
+        match (int(1), int(3)) {
+            (Some(2), Some(COSE_ALG_ES256)) if int(-1) == Some(1) => {
+                let (x, y) = (bytes(-2)?, bytes(-3)?);
+                if x.len() != 32 || y.len() != 32 {
+                    return Err(CredentialServiceError::UnsupportedPublicKey);
+                }
+                Ok(CoseKey::Es256(
+                    [&[0x04], x.as_slice(), y.as_slice()].concat(),
+                ))
+            }
+            (Some(1), Some(COSE_ALG_EDDSA)) if int(-1) == Some(6) => {
+                let x = bytes(-2)?;
+                if x.len() != 32 {
+                    return Err(CredentialServiceError::UnsupportedPublicKey);
+                }
+                Ok(CoseKey::EdDsa(x))
+            }
+            (Some(3), Some(COSE_ALG_RS256)) => Ok(CoseKey::Rs256 {
+                n: bytes(-1)?,
+                e: bytes(-2)?,
+            }),
+            _ => Err(CredentialServiceError::UnsupportedPublicKey),
+        }
+    }
+
+    /// Returns the COSE algorithm identifier of the key.
+    pub fn algorithm(&self) -> i64 {
+        match self {
+            CoseKey::Es256(_) => COSE_ALG_ES256,
+            CoseKey::EdDsa(_) => COSE_ALG_EDDSA,
+            CoseKey::Rs256 { .. } => COSE_ALG_RS256,
+        }
+    }
+
+    /// Verifies a signature made with the corresponding private key.
+    pub fn verify(&self, message: &[u8], sig: &[u8]) -> Result<(), CredentialServiceError> {
+        let result = match self {
+            CoseKey::Es256(point) => {
+                UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_ASN1, point)
+                    .verify(message, sig)
+            }
+            CoseKey::EdDsa(x) => {
+                UnparsedPublicKey::new(&signature::ED25519, x).verify(message, sig)
+            }
+            CoseKey::Rs256 { n, e } => RsaPublicKeyComponents { n, e }.verify(
+                &signature::RSA_PKCS1_2048_8192_SHA256,
+                message,
+                sig,
+            ),
+        };
+        result.map_err(|_| CredentialServiceError::InvalidSignature)
+    }
+}
+
+/// The attestation object returned by the authenticator during registration.
+#[derive(Debug)]
+pub struct AttestationObject {
+    /// The attestation statement format (e.g., `none` or `packed`).
+    pub format: String,
+    /// The format-specific attestation statement.
+    pub statement: Value,
+    /// The raw authenticator data, which includes the new credential.
+    pub auth_data: Vec<u8>,
+}
+
+impl AttestationObject {
+    pub fn parse(attestation_object: &[u8]) -> Result<AttestationObject, CredentialServiceError> {
+        let value: Value = ciborium::de::from_reader(attestation_object)
+            .map_err(|e| invalid_attestation(&e.to_string()))?;
+        let map = value
+            .as_map()
+            .ok_or_else(|| invalid_attestation("not a map"))?;
+        let field = |name: &str| {
+            map.iter()
+                .find(|(key, _)| key.as_text() == Some(name))
+                .map(|(_, value)| value)
+                .ok_or_else(|| invalid_attestation(&format!("missing {}", name)))
+        };
+        Ok(AttestationObject {
+            format: field("fmt")?
+                .as_text()
+                .ok_or_else(|| invalid_attestation("fmt is not text"))?
+                .to_string(),
+            statement: field("attStmt")?.clone(),
+            auth_data: field("authData")?
+                .as_bytes()
+                .ok_or_else(|| invalid_attestation("authData is not bytes"))?
+                .clone(),
+        })
+    }
+
+    /// Verifies the attestation statement over the authenticator data and
+    /// the hash of the client data. The attestation certificate, if any, is
+    /// not checked against a list of trusted authenticator vendors: the
+    /// statement proves the credential was created in response to this
+    /// ceremony, not which authenticator created it.
+    pub fn verify(
+        &self,
+        auth_data: &AuthenticatorData,
+        credential_key: &CoseKey,
+        client_data_hash: &[u8],
+    ) -> Result<(), CredentialServiceError> {
+        let statement = self
+            .statement
+            .as_map()
+            .ok_or_else(|| invalid_attestation("attStmt is not a map"))?;
+        match self.format.as_str() {
+            "none" if statement.is_empty() => Ok(()),
+            "none" => Err(invalid_attestation("attStmt must be empty")),
+            "packed" => {
+                let field = |name: &str| {
+                    statement
+                        .iter()
+                        .find(|(key, _)| key.as_text() == Some(name))
+                        .map(|(_, value)| value)
+                };
+                let alg = field("alg")
+                    .and_then(value_to_i64)
+                    .ok_or_else(|| invalid_attestation("missing alg"))?;
+                let sig = field("sig")
+                    .and_then(Value::as_bytes)
+                    .ok_or_else(|| invalid_attestation("missing sig"))?;
+                let signed = [self.auth_data.as_slice(), client_data_hash].concat();
+                match field("x5c").and_then(Value::as_array) {
+                    Some(x5c) => {
+                        let certificate = x5c
+                            .first()
+                            .and_then(Value::as_bytes)
+                            .ok_or_else(|| invalid_attestation("empty x5c"))?;
+                        verify_packed_certificate(certificate, alg, &signed, sig, auth_data)
+                    }
+                    // self attestation, signed by the credential itself
+                    None if alg == credential_key.algorithm() => {
+                        credential_key.verify(&signed, sig)
+                    }
+                    None => Err(invalid_attestation("alg doesn't match the credential")),
+                }
+            }
+            format => Err(CredentialServiceError::UnsupportedAttestationFormat(
+                format.to_string(),
+            )),
+        }
+    }
+}
+
+/// Verifies a `packed` attestation signature made by the attestation certificate.
+fn verify_packed_certificate(
+    certificate: &[u8],
+    alg: i64,
+    signed: &[u8],
+    sig: &[u8],
+    auth_data: &AuthenticatorData,
+) -> Result<(), CredentialServiceError> {
+    let certificate = Certificate::from_der(certificate)
+        .map_err(|e| invalid_attestation(&format!("invalid certificate: {}", e)))?;
+    let tbs = &certificate.tbs_certificate;
+
+    // if the certificate names the authenticator model, it must match the authenticator data
+    let aaguid_extension = tbs
+        .extensions
+        .iter()
+        .flatten()
+        .find(|extension| extension.extn_id == FIDO_GEN_CE_AAGUID);
+    if let (Some(extension), Some(credential)) = (aaguid_extension, &auth_data.attested_credential)
+    {
+        // the value is a DER octet string wrapped in another octet string
+        let value = extension.extn_value.as_bytes();
+        if value.len() != AAGUID_LENGTH + 2 || value[2..] != credential.aaguid[..] {
+            return Err(invalid_attestation("certificate AAGUID doesn't match"));
+        }
+    }
+
+    let public_key = tbs.subject_public_key_info.subject_public_key.raw_bytes();
+    let algorithm: &dyn signature::VerificationAlgorithm = match alg {
+        COSE_ALG_ES256 => &signature::ECDSA_P256_SHA256_ASN1,
+        COSE_ALG_RS256 => &signature::RSA_PKCS1_2048_8192_SHA256,
+        _ => return Err(invalid_attestation("unsupported alg")),
+    };
+    UnparsedPublicKey::new(algorithm, public_key)
+        .verify(signed, sig)
+        .map_err(|_| CredentialServiceError::InvalidSignature)
+}
+
+/// Returns the value for an integer label in a CBOR map.
+fn map_get(map: &[(Value, Value)], label: i64) -> Option<&Value> {
+    map.iter()
+        .find(|(key, _)| value_to_i64(key) == Some(label))
+        .map(|(_, value)| value)
+}
+
+fn value_to_i64(value: &Value) -> Option<i64> {
+    value
+        .as_integer()
+        .and_then(|integer| i64::try_from(integer).ok())
+}
+
+fn invalid_authenticator_data(reason: &str) -> CredentialServiceError {
+    CredentialServiceError::InvalidAuthenticatorData(reason.to_string())
+}
+
+fn invalid_attestation(reason: &str) -> CredentialServiceError {
+    CredentialServiceError::InvalidAttestation(reason.to_string())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+    };
+
+    use crate::services::credential::models::{AssertionCredential, RegistrationCredential};
+
+    use super::*;
+
+    pub const TEST_RP_ID: &str = "localhost";
+    pub const TEST_ORIGIN: &str = "http://localhost:3000";
+
+    /// A software authenticator with a single ES256 credential, which other
+    /// tests use to produce the responses a real authenticator would.
+    pub struct SoftwareAuthenticator {
+        key_pair: EcdsaKeyPair,
+        rng: SystemRandom,
+        pub credential_id: Vec<u8>,
+        pub aaguid: [u8; AAGUID_LENGTH],
+        /// The signature counter, incremented before each assertion.
+        /// Tests can reset this to simulate a cloned authenticator.
+        pub sign_count: u32,
+        /// The authenticator data flags to report.
+        pub flags: u8,
+        /// The origin the browser reports.
+        pub origin: String,
+    }
+
+    impl SoftwareAuthenticator {
+        pub fn new() -> SoftwareAuthenticator {
+            let rng = SystemRandom::new();
+            let pkcs8 =
+                EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+            let key_pair =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+                    .unwrap();
+            let credential_id = Sha256::digest(key_pair.public_key().as_ref())[..16].to_vec();
+            SoftwareAuthenticator {
+                key_pair,
+                rng,
+                credential_id,
+                aaguid: [0x42; AAGUID_LENGTH],
+                sign_count: 0,
+                flags: FLAG_USER_PRESENT | FLAG_USER_VERIFIED,
+                origin: TEST_ORIGIN.to_string(),
+            }
+        }
+
+        /// Returns the credential's public key as a COSE_Key.
+        pub fn cose_key(&self) -> Vec<u8> {
+            let point = self.key_pair.public_key().as_ref();
+            let key = Value::Map(vec![
+                (Value::from(1), Value::from(2)),
+                (Value::from(3), Value::from(COSE_ALG_ES256)),
+                (Value::from(-1), Value::from(1)),
+                (Value::from(-2), Value::Bytes(point[1..33].to_vec())),
+                (Value::from(-3), Value::Bytes(point[33..].to_vec())),
+            ]);
+            let mut encoded = Vec::new();
+            ciborium::ser::into_writer(&key, &mut encoded).unwrap();
+            encoded
+        }
+
+        /// Creates the credential in response to a registration challenge,
+        /// attesting it using the given format (`none` or `packed`).
+        pub fn register(&self, challenge: &str, format: &str) -> RegistrationCredential {
+            let client_data_json = self.client_data_json("webauthn.create", challenge);
+            let mut attested = self.aaguid.to_vec();
+            attested.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+            attested.extend_from_slice(&self.credential_id);
+            attested.extend_from_slice(&self.cose_key());
+            let auth_data =
+                self.authenticator_data(self.flags | FLAG_ATTESTED_CREDENTIAL_DATA, &attested);
+
+            let statement = match format {
+                "packed" => Value::Map(vec![
+                    (Value::from("alg"), Value::from(COSE_ALG_ES256)),
+                    (
+                        Value::from("sig"),
+                        Value::Bytes(self.sign(&auth_data, &client_data_json)),
+                    ),
+                ]),
+                _ => Value::Map(vec![]),
+            };
+            let attestation_object = Value::Map(vec![
+                (Value::from("fmt"), Value::from(format)),
+                (Value::from("attStmt"), statement),
+                (Value::from("authData"), Value::Bytes(auth_data)),
+            ]);
+            let mut encoded = Vec::new();
+            ciborium::ser::into_writer(&attestation_object, &mut encoded).unwrap();
+            RegistrationCredential {
+                client_data_json,
+                attestation_object: encoded,
+            }
+        }
+
+        /// Signs in response to an authentication challenge, incrementing the counter.
+        pub fn assert(
+            &mut self,
+            challenge: &str,
+            user_handle: Option<&str>,
+        ) -> AssertionCredential {
+            self.sign_count += 1;
+            let client_data_json = self.client_data_json("webauthn.get", challenge);
+            let authenticator_data = self.authenticator_data(self.flags, &[]);
+            AssertionCredential {
+                credential_id: self.credential_id.clone(),
+                signature: self.sign(&authenticator_data, &client_data_json),
+                client_data_json,
+                authenticator_data,
+                user_handle: user_handle.map(|handle| handle.as_bytes().to_vec()),
+            }
+        }
+
+        fn client_data_json(&self, ceremony_type: &str, challenge: &str) -> Vec<u8> {
+            serde_json::to_vec(&serde_json::json!({
+                "type": ceremony_type,
+                "challenge": challenge,
+                "origin": self.origin,
+                "crossOrigin": false,
+            }))
+            .unwrap()
+        }
+
+        fn authenticator_data(&self, flags: u8, attested: &[u8]) -> Vec<u8> {
+            let mut data = Sha256::digest(TEST_RP_ID.as_bytes()).to_vec();
+            data.push(flags);
+            data.extend_from_slice(&self.sign_count.to_be_bytes());
+            data.extend_from_slice(attested);
+            data
+        }
+
+        fn sign(&self, auth_data: &[u8], client_data_json: &[u8]) -> Vec<u8> {
+            let signed = [auth_data, Sha256::digest(client_data_json).as_slice()].concat();
+            self.key_pair
+                .sign(&self.rng, &signed)
+                .unwrap()
+                .as_ref()
+                .to_vec()
+        }
+    }
+
+    #[test]
+    fn parse_registration() {
+        let authenticator = SoftwareAuthenticator::new();
+        let challenge = URL_SAFE_NO_PAD.encode(b"challenge");
+        let credential = authenticator.register(&challenge, "packed");
+
+        let client_data = ClientData::parse(&credential.client_data_json).unwrap();
+        assert_eq!("webauthn.create", client_data.ceremony_type);
+        assert_eq!(challenge, client_data.challenge);
+        assert_eq!(TEST_ORIGIN, client_data.origin);
+
+        let attestation = AttestationObject::parse(&credential.attestation_object).unwrap();
+        assert_eq!("packed", attestation.format);
+        let auth_data = AuthenticatorData::parse(&attestation.auth_data).unwrap();
+        auth_data.verify(TEST_RP_ID).unwrap();
+        assert!(auth_data.verify("example.com").is_err());
+        let attested = auth_data.attested_credential.as_ref().unwrap();
+        assert_eq!(authenticator.credential_id, attested.credential_id);
+        assert_eq!(authenticator.aaguid.to_vec(), attested.aaguid);
+        assert_eq!(authenticator.cose_key(), attested.public_key);
+
+        let key = CoseKey::parse(&attested.public_key).unwrap();
+        assert_eq!(COSE_ALG_ES256, key.algorithm());
+        let client_data_hash = Sha256::digest(&credential.client_data_json);
+        attestation
+            .verify(&auth_data, &key, &client_data_hash)
+            .unwrap();
+        // the statement covers the client data
+        assert!(attestation
+            .verify(&auth_data, &key, &Sha256::digest(b"other"))
+            .is_err());
+    }
+
+    #[test]
+    fn verify_none_attestation() {
+        let authenticator = SoftwareAuthenticator::new();
+        let credential = authenticator.register("challenge", "none");
+        let attestation = AttestationObject::parse(&credential.attestation_object).unwrap();
+        let auth_data = AuthenticatorData::parse(&attestation.auth_data).unwrap();
+        let key = CoseKey::parse(&authenticator.cose_key()).unwrap();
+        attestation.verify(&auth_data, &key, &[0u8; 32]).unwrap();
+
+        let credential = authenticator.register("challenge", "fido-u2f");
+        let attestation = AttestationObject::parse(&credential.attestation_object).unwrap();
+        assert!(matches!(
+            attestation.verify(&auth_data, &key, &[0u8; 32]),
+            Err(CredentialServiceError::UnsupportedAttestationFormat(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_data() {
+        assert!(ClientData::parse(b"not json").is_err());
+        assert!(AuthenticatorData::parse(&[0u8; 36]).is_err());
+        // claims an attested credential that isn't there
+        let mut data = [0u8; 37];
+        data[32] = FLAG_ATTESTED_CREDENTIAL_DATA;
+        assert!(AuthenticatorData::parse(&data).is_err());
+        assert!(AttestationObject::parse(b"not cbor").is_err());
+        assert!(CoseKey::parse(&[0xa0]).is_err());
+    }
+
+    #[test]
+    fn verify_requires_user_verification() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        authenticator.flags = FLAG_USER_PRESENT;
+        let assertion = authenticator.assert("challenge", None);
+        let auth_data = AuthenticatorData::parse(&assertion.authenticator_data).unwrap();
+        assert!(matches!(
+            auth_data.verify(TEST_RP_ID),
+            Err(CredentialServiceError::UserNotVerified)
+        ));
+    }
+}
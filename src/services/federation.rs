@@ -0,0 +1,454 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{TimeDelta, Utc};
+use error::FederationServiceError;
+use models::{
+    FederatedAuthorization, FederatedAuthorizationUrl, FederatedCallback, IdentityProvider,
+    LinkedIdentity, UpstreamIdentity,
+};
+use oidc::{JsonWebKeySet, OidcClient, ProviderMetadata};
+use sha2::{Digest, Sha256};
+use stores::LinkedIdentityStore;
+use url::Url;
+
+use super::{account::id::ID, hash_token, random_token, Clock, SystemClock};
+
+pub mod error;
+pub mod models;
+pub mod oidc;
+pub mod stores;
+
+/// How long the account holder has to sign in at the provider
+/// after the authorization request is started.
+const FEDERATED_AUTHORIZATION_TTL: TimeDelta = TimeDelta::minutes(10);
+
+/// A provider's discovery document and keys, which are fetched when
+/// first needed, and kept until the provider starts using a new key.
+#[derive(Clone)]
+struct Discovered {
+    metadata: ProviderMetadata,
+    keys: JsonWebKeySet,
+}
+
+/// Signs account holders in using upstream OpenID Connect providers (e.g., Google),
+/// and manages the identities at those providers that are linked to accounts.
+/// Each authorization request sent to a provider is recorded with a single-use
+/// `state`, along with the `nonce` and PKCE code verifier that tie the provider's
+/// response to it.
+pub struct FederationService<S: LinkedIdentityStore, C: Clock<Utc>> {
+    store: S,
+    providers: Vec<IdentityProvider>,
+    redirect_url: String,
+    client: OidcClient,
+    discovered: Mutex<HashMap<String, Discovered>>,
+    clock: C,
+}
+
+impl<S: LinkedIdentityStore, C: Clock<Utc>> FederationService<S, C> {
+    /// Constructs a new [FederationService] given the [LinkedIdentityStore], the
+    /// providers account holders can sign in with, the URL they redirect back to,
+    /// and the [Clock] to use.
+    pub fn new_with_clock(
+        linked_identity_store: S,
+        providers: Vec<IdentityProvider>,
+        redirect_url: &str,
+        clock: C,
+    ) -> Result<Self, FederationServiceError> {
+        Ok(Self {
+            store: linked_identity_store,
+            providers,
+            redirect_url: redirect_url.to_string(),
+            client: OidcClient::new()?,
+            discovered: Mutex::new(HashMap::new()),
+            clock,
+        })
+    }
+
+    /// Returns the providers account holders can sign in with.
+    pub fn providers(&self) -> &[IdentityProvider] {
+        &self.providers
+    }
+
+    /// Starts an authorization request to the provider, returning the URL to send
+    /// the browser to. If `account_id` is set, the identity the account holder
+    /// signs in with will be linked to that account, rather than signing in with it.
+    pub async fn start_authorization(
+        &self,
+        provider: &str,
+        account_id: Option<&str>,
+    ) -> Result<FederatedAuthorizationUrl, FederationServiceError> {
+        let provider = self.provider(provider)?;
+        let discovered = self.discover(provider).await?;
+        let state = random_token();
+        let nonce = random_token();
+        let code_verifier = random_token();
+        let now = self.clock.now();
+        let authorization = FederatedAuthorization {
+            state_hash: hash_token(&state),
+            provider: provider.name.clone(),
+            nonce: nonce.clone(),
+            code_verifier: code_verifier.clone(),
+            account_id: account_id.map(str::to_string),
+            created_at: now,
+            expires_at: now + FEDERATED_AUTHORIZATION_TTL,
+        };
+
+        let mut url = Url::parse(&discovered.metadata.authorization_endpoint)
+            .map_err(|e| FederationServiceError::UpstreamError(e.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &provider.client_id)
+            .append_pair("redirect_uri", &self.redirect_url)
+            .append_pair("scope", &provider.scopes.join(" "))
+            .append_pair("state", &state)
+            .append_pair("nonce", &nonce)
+            .append_pair(
+                "code_challenge",
+                &URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes())),
+            )
+            .append_pair("code_challenge_method", "S256");
+        self.store.insert_authorization(&authorization).await?;
+        Ok(FederatedAuthorizationUrl {
+            authorization_url: url.to_string(),
+            expires_at: authorization.expires_at,
+        })
+    }
+
+    /// Completes a sign-in started by [FederationService::start_authorization],
+    /// returning the linked identity the account holder signed in with. Identities
+    /// must be linked to an account first: they are never matched to accounts by
+    /// email address, since the provider may not have verified it, and whoever
+    /// controls an address at a provider doesn't necessarily own the account.
+    pub async fn finish_sign_in(
+        &self,
+        callback: &FederatedCallback,
+    ) -> Result<LinkedIdentity, FederationServiceError> {
+        let (authorization, upstream) = self.complete(callback).await?;
+        if authorization.account_id.is_some() {
+            return Err(FederationServiceError::InvalidState);
+        }
+        let identity = self
+            .store
+            .load_identity_by_subject(&authorization.provider, &upstream.subject)
+            .await?
+            .ok_or(FederationServiceError::IdentityNotLinked)?;
+        let now = self.clock.now();
+        self.store
+            .record_identity_use(&identity.id, upstream.email.as_deref(), now)
+            .await?;
+        Ok(LinkedIdentity {
+            email: upstream.email,
+            last_used_at: Some(now),
+            ..identity
+        })
+    }
+
+    /// Completes a request started by [FederationService::start_authorization] for
+    /// the account, linking the identity the account holder signed in with to it.
+    pub async fn link_identity(
+        &self,
+        account_id: &str,
+        callback: &FederatedCallback,
+    ) -> Result<LinkedIdentity, FederationServiceError> {
+        let (authorization, upstream) = self.complete(callback).await?;
+        if authorization.account_id.as_deref() != Some(account_id) {
+            return Err(FederationServiceError::InvalidState);
+        }
+        let identity = LinkedIdentity {
+            id: ID::Ident.create(),
+            account_id: account_id.to_string(),
+            provider: authorization.provider,
+            subject: upstream.subject,
+            email: upstream.email,
+            created_at: self.clock.now(),
+            last_used_at: None,
+        };
+        if !self.store.insert_identity(&identity).await? {
+            return Err(FederationServiceError::IdentityAlreadyLinked);
+        }
+        Ok(identity)
+    }
+
+    /// Returns the identities linked to the account, oldest first.
+    pub async fn list_identities(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<LinkedIdentity>, FederationServiceError> {
+        Ok(self.store.load_identities(account_id).await?)
+    }
+
+    /// Unlinks one of the account's identities, so it can no longer be used to sign in.
+    pub async fn unlink_identity(
+        &self,
+        account_id: &str,
+        identity_id: &str,
+    ) -> Result<(), FederationServiceError> {
+        let identity = self
+            .store
+            .load_identity(identity_id)
+            .await?
+            .filter(|identity| identity.account_id == account_id)
+            .ok_or_else(|| FederationServiceError::IdentityNotFound(identity_id.to_string()))?;
+        Ok(self.store.delete_identity(&identity.id).await?)
+    }
+
+    /// Unlinks all of the account's identities (e.g., when the account is deleted).
+    pub async fn delete_identities(&self, account_id: &str) -> Result<(), FederationServiceError> {
+        Ok(self.store.delete_identities(account_id).await?)
+    }
+
+    /// Consumes the authorization request the callback is for, exchanges the
+    /// code for an ID token, and verifies it, returning the identity it asserts.
+    async fn complete(
+        &self,
+        callback: &FederatedCallback,
+    ) -> Result<(FederatedAuthorization, UpstreamIdentity), FederationServiceError> {
+        let authorization = self
+            .store
+            .take_authorization(&hash_token(&callback.state))
+            .await?
+            .filter(|authorization| self.clock.now() < authorization.expires_at)
+            .ok_or(FederationServiceError::InvalidState)?;
+        let provider = self.provider(&authorization.provider)?;
+        let mut discovered = self.discover(provider).await?;
+        let id_token = self
+            .client
+            .exchange_code(
+                provider,
+                &discovered.metadata,
+                &callback.code,
+                &self.redirect_url,
+                &authorization.code_verifier,
+            )
+            .await?;
+        // the provider may have started signing with a new key since its keys were fetched
+        if !id_token.has_key_in(&discovered.keys) {
+            discovered = self.refresh_keys(provider, discovered).await?;
+        }
+        let upstream = id_token.verify(
+            &discovered.keys,
+            provider,
+            &authorization.nonce,
+            self.clock.now(),
+        )?;
+        Ok((authorization, upstream))
+    }
+
+    /// Returns the configured provider with the name.
+    fn provider(&self, name: &str) -> Result<&IdentityProvider, FederationServiceError> {
+        self.providers
+            .iter()
+            .find(|provider| provider.name == name)
+            .ok_or_else(|| FederationServiceError::UnknownProvider(name.to_string()))
+    }
+
+    /// Returns the provider's discovery document and keys,
+    /// fetching them if they haven't been already.
+    async fn discover(
+        &self,
+        provider: &IdentityProvider,
+    ) -> Result<Discovered, FederationServiceError> {
+        if let Some(discovered) = self.discovered.lock().unwrap().get(&provider.name) {
+            return Ok(discovered.clone());
+        }
+        let metadata = self.client.discover(provider).await?;
+        let keys = self.client.fetch_keys(&metadata).await?;
+        let discovered = Discovered { metadata, keys };
+        self.discovered
+            .lock()
+            .unwrap()
+            .insert(provider.name.clone(), discovered.clone());
+        Ok(discovered)
+    }
+
+    /// Fetches the provider's current keys.
+    async fn refresh_keys(
+        &self,
+        provider: &IdentityProvider,
+        discovered: Discovered,
+    ) -> Result<Discovered, FederationServiceError> {
+        let keys = self.client.fetch_keys(&discovered.metadata).await?;
+        let discovered = Discovered { keys, ..discovered };
+        self.discovered
+            .lock()
+            .unwrap()
+            .insert(provider.name.clone(), discovered.clone());
+        Ok(discovered)
+    }
+}
+
+impl<S: LinkedIdentityStore> FederationService<S, SystemClock<Utc>> {
+    pub fn new(
+        linked_identity_store: S,
+        providers: Vec<IdentityProvider>,
+        redirect_url: &str,
+    ) -> Result<Self, FederationServiceError> {
+        Self::new_with_clock(
+            linked_identity_store,
+            providers,
+            redirect_url,
+            SystemClock::default(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use oidc::tests::{MockIdentityProvider, TEST_REDIRECT_URL};
+    use stores::fake::FakeLinkedIdentityStore;
+
+    use crate::services::TestClock;
+
+    use super::*;
+
+    type TestService = FederationService<FakeLinkedIdentityStore, TestClock<Utc>>;
+
+    fn test_service(provider: &MockIdentityProvider, clock: &TestClock<Utc>) -> TestService {
+        FederationService::new_with_clock(
+            FakeLinkedIdentityStore::new(),
+            vec![provider.provider("mock")],
+            TEST_REDIRECT_URL,
+            clock.clone(),
+        )
+        .unwrap()
+    }
+
+    /// Starts an authorization request and signs in to the mock provider as `subject`.
+    async fn authorize(
+        service: &TestService,
+        provider: &MockIdentityProvider,
+        account_id: Option<&str>,
+        subject: &str,
+    ) -> FederatedCallback {
+        let authorization = service
+            .start_authorization("mock", account_id)
+            .await
+            .unwrap();
+        provider.authorize(&authorization.authorization_url, subject, "test@test.com")
+    }
+
+    #[tokio::test]
+    async fn start_authorization() {
+        let provider = MockIdentityProvider::start().await;
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&provider, &clock);
+        let authorization = service.start_authorization("mock", None).await.unwrap();
+        assert_eq!(
+            clock.now() + FEDERATED_AUTHORIZATION_TTL,
+            authorization.expires_at
+        );
+        let url = Url::parse(&authorization.authorization_url).unwrap();
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        assert_eq!(TEST_REDIRECT_URL, params["redirect_uri"]);
+        assert_eq!("openid email", params["scope"]);
+        assert!(!params["state"].is_empty());
+        assert!(!params["nonce"].is_empty());
+
+        assert!(matches!(
+            service.start_authorization("unknown", None).await,
+            Err(FederationServiceError::UnknownProvider(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn link_identity_and_sign_in() {
+        let provider = MockIdentityProvider::start().await;
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&provider, &clock);
+
+        // identities must be linked before they can be used to sign in
+        let callback = authorize(&service, &provider, None, "upstream-subject").await;
+        assert!(matches!(
+            service.finish_sign_in(&callback).await,
+            Err(FederationServiceError::IdentityNotLinked)
+        ));
+
+        let callback = authorize(&service, &provider, Some("acct_test"), "upstream-subject").await;
+        let linked = service.link_identity("acct_test", &callback).await.unwrap();
+        assert_eq!("mock", linked.provider);
+        assert_eq!("upstream-subject", linked.subject);
+        assert_eq!(Some("test@test.com".to_string()), linked.email);
+        assert!(linked.id.starts_with("ident_"));
+
+        let callback = authorize(&service, &provider, None, "upstream-subject").await;
+        let identity = service.finish_sign_in(&callback).await.unwrap();
+        assert_eq!("acct_test", identity.account_id);
+        assert_eq!(Some(clock.now()), identity.last_used_at);
+        // the state can only be used once
+        assert!(matches!(
+            service.finish_sign_in(&callback).await,
+            Err(FederationServiceError::InvalidState)
+        ));
+
+        // an identity can only be linked to one account
+        let callback = authorize(&service, &provider, Some("acct_other"), "upstream-subject").await;
+        assert!(matches!(
+            service.link_identity("acct_other", &callback).await,
+            Err(FederationServiceError::IdentityAlreadyLinked)
+        ));
+
+        let identities = service.list_identities("acct_test").await.unwrap();
+        assert_eq!(1, identities.len());
+        assert!(matches!(
+            service.unlink_identity("acct_other", &linked.id).await,
+            Err(FederationServiceError::IdentityNotFound(_))
+        ));
+        service
+            .unlink_identity("acct_test", &linked.id)
+            .await
+            .unwrap();
+        assert!(service
+            .list_identities("acct_test")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn state_must_match_request() {
+        let provider = MockIdentityProvider::start().await;
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&provider, &clock);
+
+        // a request to link an identity can't be used to sign in, or vice versa
+        let callback = authorize(&service, &provider, Some("acct_test"), "upstream-subject").await;
+        assert!(matches!(
+            service.finish_sign_in(&callback).await,
+            Err(FederationServiceError::InvalidState)
+        ));
+        let callback = authorize(&service, &provider, None, "upstream-subject").await;
+        assert!(matches!(
+            service.link_identity("acct_test", &callback).await,
+            Err(FederationServiceError::InvalidState)
+        ));
+        // or to link the identity to another account
+        let callback = authorize(&service, &provider, Some("acct_test"), "upstream-subject").await;
+        assert!(matches!(
+            service.link_identity("acct_other", &callback).await,
+            Err(FederationServiceError::InvalidState)
+        ));
+
+        let callback = authorize(&service, &provider, Some("acct_test"), "upstream-subject").await;
+        clock.advance(FEDERATED_AUTHORIZATION_TTL);
+        assert!(matches!(
+            service.link_identity("acct_test", &callback).await,
+            Err(FederationServiceError::InvalidState)
+        ));
+    }
+
+    #[tokio::test]
+    async fn refetches_keys_after_rotation() {
+        let provider = MockIdentityProvider::start().await;
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&provider, &clock);
+        let callback = authorize(&service, &provider, Some("acct_test"), "upstream-subject").await;
+        service.link_identity("acct_test", &callback).await.unwrap();
+
+        // the keys were fetched when the first request was started
+        provider.rotate_key("ec-1", "ES256");
+        let callback = authorize(&service, &provider, None, "upstream-subject").await;
+        let identity = service.finish_sign_in(&callback).await.unwrap();
+        assert_eq!("acct_test", identity.account_id);
+    }
+}
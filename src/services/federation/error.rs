@@ -0,0 +1,23 @@
+use thiserror::Error;
+
+use super::stores::error::LinkedIdentityStoreError;
+
+#[derive(Error, Debug)]
+pub enum FederationServiceError {
+    #[error("There was an error interacting with the data store: {0}")]
+    StoreError(#[from] LinkedIdentityStoreError),
+    #[error("Identity provider '{0}' is not configured")]
+    UnknownProvider(String),
+    #[error("The state is invalid or has expired")]
+    InvalidState,
+    #[error("The identity provider returned an error: {0}")]
+    UpstreamError(String),
+    #[error("The identity provider's ID token is invalid: {0}")]
+    InvalidIdToken(String),
+    #[error("This identity is not linked to an account")]
+    IdentityNotLinked,
+    #[error("This identity is already linked to an account")]
+    IdentityAlreadyLinked,
+    #[error("Linked identity '{0}' was not found")]
+    IdentityNotFound(String),
+}
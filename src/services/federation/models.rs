@@ -0,0 +1,92 @@
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+
+/// An upstream OpenID Connect provider that account holders can sign in with
+/// (e.g., Google). Its endpoints and keys are found using OpenID Connect
+/// Discovery, so only the issuer and the client registration are configured.
+#[derive(Debug, Clone)]
+pub struct IdentityProvider {
+    /// Short name that identifies the provider in requests (e.g., `google`).
+    pub name: String,
+    /// The provider's issuer identifier (e.g., `https://accounts.google.com`).
+    pub issuer: String,
+    /// The client ID this service was registered with at the provider.
+    pub client_id: String,
+    /// The client secret this service was registered with at the provider.
+    pub client_secret: Secret<String>,
+    /// The scopes to request, which must include `openid`.
+    pub scopes: Vec<String>,
+}
+
+/// Represents a stored authorization request sent to an upstream provider,
+/// which is consumed when the provider redirects back with a code.
+#[derive(Debug, Clone)]
+pub struct FederatedAuthorization {
+    /// SHA-256 hash of the `state` parameter sent to the provider.
+    pub state_hash: String,
+    /// Name of the provider the request was sent to.
+    pub provider: String,
+    /// The `nonce` the provider must include in the ID token.
+    pub nonce: String,
+    /// The PKCE code verifier for the code challenge sent to the provider.
+    pub code_verifier: String,
+    /// ID of the account the identity is being linked to, or None
+    /// if the account holder is signing in.
+    pub account_id: Option<String>,
+    /// When this authorization was created.
+    pub created_at: DateTime<Utc>,
+    /// When this authorization expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Where to send the browser to sign in with an upstream provider.
+#[derive(Debug, Clone)]
+pub struct FederatedAuthorizationUrl {
+    /// The provider's authorization endpoint, with the request's parameters.
+    pub authorization_url: String,
+    /// When the request expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The parameters an upstream provider redirects back with.
+#[derive(Debug, Clone)]
+pub struct FederatedCallback {
+    /// The `state` sent with the authorization request.
+    pub state: String,
+    /// The authorization code to exchange for tokens.
+    pub code: String,
+}
+
+/// The identity asserted by an upstream provider's ID token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpstreamIdentity {
+    /// The provider's identifier for the account holder, which never changes.
+    pub subject: String,
+    /// The email address the provider has for the account holder, if released.
+    pub email: Option<String>,
+    /// Whether the provider has verified the email address.
+    pub email_verified: bool,
+    /// The account holder's name, if released.
+    pub name: Option<String>,
+}
+
+/// Represents an identity at an upstream provider that is linked to an
+/// account, so the account holder can sign in with it. Each identity
+/// can only be linked to one account.
+#[derive(Debug, Clone)]
+pub struct LinkedIdentity {
+    /// Unique ID
+    pub id: String,
+    /// ID of the account this identity is linked to.
+    pub account_id: String,
+    /// Name of the provider that asserts the identity.
+    pub provider: String,
+    /// The provider's identifier for the account holder.
+    pub subject: String,
+    /// The email address the provider had when the identity was last used.
+    pub email: Option<String>,
+    /// When this identity was linked.
+    pub created_at: DateTime<Utc>,
+    /// When this identity was last used to sign in, if it has been.
+    pub last_used_at: Option<DateTime<Utc>>,
+}
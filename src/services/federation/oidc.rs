@@ -0,0 +1,897 @@
+//! A client for upstream OpenID Connect providers: discovery, exchanging
+//! authorization codes for ID tokens, and verifying those ID tokens against
+//! the provider's published keys.
+
+use std::time::Duration;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use secrecy::ExposeSecret;
+use serde::{de::DeserializeOwned, Deserialize};
+
+use super::{
+    error::FederationServiceError,
+    models::{IdentityProvider, UpstreamIdentity},
+};
+
+/// How long to wait for a provider to respond.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How many seconds a provider's clock can be ahead of ours
+/// before the ID tokens it issues are considered expired.
+const CLOCK_SKEW_LEEWAY: i64 = 60;
+/// The ID token signature algorithms that are accepted. `none` and the
+/// HMAC algorithms (which use the client secret as the key) are not.
+const SUPPORTED_ALGORITHMS: [&str; 3] = ["RS256", "ES256", "EdDSA"];
+
+/// The parts of a provider's discovery document that are used
+/// (OpenID Connect Discovery section 3).
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// A provider's published signing keys (RFC 7517 section 5).
+#[derive(Debug, Clone, Deserialize)]
+pub struct JsonWebKeySet {
+    pub keys: Vec<JsonWebKey>,
+}
+
+impl JsonWebKeySet {
+    /// Returns the keys that could have made a signature using the algorithm:
+    /// the one with the key ID, or if there's no key ID, all of them.
+    pub fn find(&self, kid: Option<&str>, alg: &str) -> Vec<&JsonWebKey> {
+        self.keys
+            .iter()
+            .filter(|key| kid.is_none() || key.kid.as_deref() == kid)
+            .filter(|key| key.alg.as_deref().is_none_or(|key_alg| key_alg == alg))
+            .filter(|key| {
+                key.key_use
+                    .as_deref()
+                    .is_none_or(|key_use| key_use == "sig")
+            })
+            .collect()
+    }
+}
+
+/// A public key in JWK format (RFC 7517). Only the members needed
+/// to verify RS256, ES256 and EdDSA signatures are kept.
+#[derive(Debug, Clone, Deserialize)]
+pub struct JsonWebKey {
+    pub kty: String,
+    #[serde(default)]
+    pub kid: Option<String>,
+    #[serde(default)]
+    pub alg: Option<String>,
+    #[serde(rename = "use", default)]
+    pub key_use: Option<String>,
+    #[serde(default)]
+    pub crv: Option<String>,
+    #[serde(default)]
+    pub n: Option<String>,
+    #[serde(default)]
+    pub e: Option<String>,
+    #[serde(default)]
+    pub x: Option<String>,
+    #[serde(default)]
+    pub y: Option<String>,
+}
+
+impl JsonWebKey {
+    /// Returns true if `sig` is a valid signature of `message` by this key
+    /// using the algorithm.
+    fn verify(&self, alg: &str, message: &[u8], sig: &[u8]) -> bool {
+        let decode = |member: &Option<String>| {
+            member
+                .as_deref()
+                .and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())
+        };
+        match (alg, self.kty.as_str(), self.crv.as_deref()) {
+            ("RS256", "RSA", _) => match (decode(&self.n), decode(&self.e)) {
+                (Some(n), Some(e)) => RsaPublicKeyComponents { n, e }
+                    .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, sig)
+                    .is_ok(),
+                _ => false,
+            },
+            // JWS uses the fixed-length encoding of ECDSA signatures, not ASN.1
+            ("ES256", "EC", Some("P-256")) => match (decode(&self.x), decode(&self.y)) {
+                (Some(x), Some(y)) => {
+                    let point = [&[0x04], x.as_slice(), y.as_slice()].concat();
+                    UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                        .verify(message, sig)
+                        .is_ok()
+                }
+                _ => false,
+            },
+            ("EdDSA", "OKP", Some("Ed25519")) => match decode(&self.x) {
+                Some(x) => UnparsedPublicKey::new(&signature::ED25519, x)
+                    .verify(message, sig)
+                    .is_ok(),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+/// The header of an ID token.
+#[derive(Debug, Clone, Deserialize)]
+struct IdTokenHeader {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+/// The claims in an ID token that are checked or used (OpenID Connect Core section 2).
+#[derive(Debug, Clone, Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    sub: String,
+    aud: Audience,
+    #[serde(default)]
+    azp: Option<String>,
+    exp: i64,
+    #[serde(default)]
+    nonce: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: Option<Flag>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// The `aud` claim, which can be a single client ID or several.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn as_slice(&self) -> &[String] {
+        match self {
+            Audience::One(client_id) => std::slice::from_ref(client_id),
+            Audience::Many(client_ids) => client_ids,
+        }
+    }
+}
+
+/// A boolean claim, which some providers send as a string.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Flag {
+    Bool(bool),
+    Text(String),
+}
+
+impl Flag {
+    fn is_true(&self) -> bool {
+        match self {
+            Flag::Bool(value) => *value,
+            Flag::Text(value) => value == "true",
+        }
+    }
+}
+
+/// An ID token whose signature hasn't been verified yet.
+#[derive(Debug, Clone)]
+pub struct IdToken {
+    header: IdTokenHeader,
+    signing_input: String,
+    claims: String,
+    signature: Vec<u8>,
+}
+
+impl IdToken {
+    /// Splits a compact JWS into its parts, decoding the header.
+    pub fn parse(token: &str) -> Result<IdToken, FederationServiceError> {
+        let invalid = || FederationServiceError::InvalidIdToken("malformed token".to_string());
+        let parts: Vec<&str> = token.split('.').collect();
+        let [header, claims, signature] = parts[..] else {
+            return Err(invalid());
+        };
+        Ok(IdToken {
+            header: decode_part(header).ok_or_else(invalid)?,
+            signing_input: format!("{}.{}", header, claims),
+            claims: claims.to_string(),
+            signature: URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?,
+        })
+    }
+
+    /// Returns the ID of the key that signed the token, if the header says.
+    pub fn kid(&self) -> Option<&str> {
+        self.header.kid.as_deref()
+    }
+
+    /// Returns true if the set contains a key that could have signed this token.
+    pub fn has_key_in(&self, keys: &JsonWebKeySet) -> bool {
+        !keys.find(self.kid(), &self.header.alg).is_empty()
+    }
+
+    /// Verifies the token's signature using the provider's keys, and checks
+    /// that it was issued by the provider, to this service, for the
+    /// authorization request with the `nonce`, and hasn't expired
+    /// (OpenID Connect Core section 3.1.3.7). Returns the identity it asserts.
+    pub fn verify(
+        &self,
+        keys: &JsonWebKeySet,
+        provider: &IdentityProvider,
+        nonce: &str,
+        now: DateTime<Utc>,
+    ) -> Result<UpstreamIdentity, FederationServiceError> {
+        let invalid = |reason: &str| FederationServiceError::InvalidIdToken(reason.to_string());
+        let alg = self.header.alg.as_str();
+        if !SUPPORTED_ALGORITHMS.contains(&alg) {
+            return Err(invalid("unsupported signature algorithm"));
+        }
+        if !keys
+            .find(self.kid(), alg)
+            .into_iter()
+            .any(|key| key.verify(alg, self.signing_input.as_bytes(), &self.signature))
+        {
+            return Err(invalid("invalid signature"));
+        }
+
+        let claims: IdTokenClaims =
+            decode_part(&self.claims).ok_or_else(|| invalid("malformed claims"))?;
+        if claims.iss != provider.issuer {
+            return Err(invalid("unexpected issuer"));
+        }
+        let audience = claims.aud.as_slice();
+        if !audience.contains(&provider.client_id) {
+            return Err(invalid("unexpected audience"));
+        }
+        // with several audiences, the authorized party must be this service
+        if (audience.len() > 1 || claims.azp.is_some())
+            && claims.azp.as_ref() != Some(&provider.client_id)
+        {
+            return Err(invalid("unexpected authorized party"));
+        }
+        if claims.exp + CLOCK_SKEW_LEEWAY <= now.timestamp() {
+            return Err(invalid("expired"));
+        }
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(invalid("unexpected nonce"));
+        }
+        Ok(UpstreamIdentity {
+            subject: claims.sub,
+            email: claims.email,
+            email_verified: claims.email_verified.is_some_and(|flag| flag.is_true()),
+            name: claims.name,
+        })
+    }
+}
+
+/// The parts of a successful token response that are used.
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    #[serde(default)]
+    id_token: Option<String>,
+}
+
+/// An error response from a provider's token endpoint (RFC 6749 section 5.2).
+#[derive(Debug, Deserialize)]
+struct TokenErrorResponse {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+/// Makes requests to upstream providers.
+pub struct OidcClient {
+    client: Client,
+}
+
+impl OidcClient {
+    pub fn new() -> Result<OidcClient, FederationServiceError> {
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent("identity-service")
+            .build()
+            .map_err(|e| FederationServiceError::UpstreamError(e.to_string()))?;
+        Ok(OidcClient { client })
+    }
+
+    /// Fetches the provider's discovery document, making sure it's for the
+    /// provider's issuer (OpenID Connect Discovery section 4.3).
+    pub async fn discover(
+        &self,
+        provider: &IdentityProvider,
+    ) -> Result<ProviderMetadata, FederationServiceError> {
+        let metadata: ProviderMetadata = self
+            .get_json(&format!(
+                "{}/.well-known/openid-configuration",
+                provider.issuer.trim_end_matches('/')
+            ))
+            .await?;
+        if metadata.issuer != provider.issuer {
+            return Err(FederationServiceError::UpstreamError(format!(
+                "discovery document is for issuer {}",
+                metadata.issuer
+            )));
+        }
+        Ok(metadata)
+    }
+
+    /// Fetches the provider's signing keys.
+    pub async fn fetch_keys(
+        &self,
+        metadata: &ProviderMetadata,
+    ) -> Result<JsonWebKeySet, FederationServiceError> {
+        self.get_json(&metadata.jwks_uri).await
+    }
+
+    /// Exchanges an authorization code at the provider's token endpoint,
+    /// authenticating with the client secret (`client_secret_post`),
+    /// and returns the ID token.
+    pub async fn exchange_code(
+        &self,
+        provider: &IdentityProvider,
+        metadata: &ProviderMetadata,
+        code: &str,
+        redirect_uri: &str,
+        code_verifier: &str,
+    ) -> Result<IdToken, FederationServiceError> {
+        let response = self
+            .client
+            .post(&metadata.token_endpoint)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", redirect_uri),
+                ("client_id", &provider.client_id),
+                ("client_secret", provider.client_secret.expose_secret()),
+                ("code_verifier", code_verifier),
+            ])
+            .send()
+            .await
+            .map_err(|e| FederationServiceError::UpstreamError(e.to_string()))?;
+        let status = response.status();
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| FederationServiceError::UpstreamError(e.to_string()))?;
+        if !status.is_success() {
+            let message = match serde_json::from_slice::<TokenErrorResponse>(&body) {
+                Ok(error) => match error.error_description {
+                    Some(description) => format!("{}: {}", error.error, description),
+                    None => error.error,
+                },
+                Err(_) => format!("token endpoint responded with {}", status),
+            };
+            return Err(FederationServiceError::UpstreamError(message));
+        }
+        let token_response: TokenResponse = serde_json::from_slice(&body)
+            .map_err(|e| FederationServiceError::UpstreamError(e.to_string()))?;
+        let id_token = token_response.id_token.ok_or_else(|| {
+            FederationServiceError::UpstreamError("no ID token was issued".to_string())
+        })?;
+        IdToken::parse(&id_token)
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, FederationServiceError> {
+        let body = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| FederationServiceError::UpstreamError(e.to_string()))?
+            .bytes()
+            .await
+            .map_err(|e| FederationServiceError::UpstreamError(e.to_string()))?;
+        serde_json::from_slice(&body)
+            .map_err(|e| FederationServiceError::UpstreamError(format!("{}: {}", url, e)))
+    }
+}
+
+/// Decodes a base64url-encoded JSON part of a token.
+fn decode_part<T: DeserializeOwned>(part: &str) -> Option<T> {
+    URL_SAFE_NO_PAD
+        .decode(part)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use argon2::password_hash::rand_core::OsRng;
+    use axum::{
+        extract::State,
+        http::StatusCode,
+        response::{IntoResponse, Response},
+        routing::{get, post},
+        Form, Json, Router,
+    };
+    use ed25519_dalek::{Signer, SigningKey};
+    use ring::{
+        rand::SystemRandom,
+        signature::{
+            EcdsaKeyPair, KeyPair, RsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING, RSA_PKCS1_SHA256,
+        },
+    };
+    use secrecy::Secret;
+    use serde_json::{json, Value};
+    use sha2::{Digest, Sha256};
+    use url::Url;
+
+    use crate::services::{federation::models::FederatedCallback, random_token};
+
+    use super::*;
+
+    pub const TEST_CLIENT_ID: &str = "identity-service";
+    pub const TEST_CLIENT_SECRET: &str = "mock-idp-client-secret";
+    pub const TEST_REDIRECT_URL: &str = "http://localhost:3000/federated-callback";
+    /// A 2048-bit RSA key in PKCS#1 DER format, since ring can't generate
+    /// RSA keys.
+    const TEST_RSA_KEY: &[u8] = include_bytes!("testdata/rsa_key.der");
+
+    /// A key the mock provider can sign ID tokens with.
+    enum MockKey {
+        Rsa(RsaKeyPair),
+        Ec(EcdsaKeyPair),
+        Ed(SigningKey),
+    }
+
+    impl MockKey {
+        fn generate(alg: &str) -> MockKey {
+            match alg {
+                "RS256" => MockKey::Rsa(RsaKeyPair::from_der(TEST_RSA_KEY).unwrap()),
+                "ES256" => {
+                    let rng = SystemRandom::new();
+                    let pkcs8 =
+                        EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+                            .unwrap();
+                    MockKey::Ec(
+                        EcdsaKeyPair::from_pkcs8(
+                            &ECDSA_P256_SHA256_FIXED_SIGNING,
+                            pkcs8.as_ref(),
+                            &rng,
+                        )
+                        .unwrap(),
+                    )
+                }
+                "EdDSA" => MockKey::Ed(SigningKey::generate(&mut OsRng)),
+                _ => panic!("unsupported algorithm {}", alg),
+            }
+        }
+
+        fn sign(&self, message: &[u8]) -> Vec<u8> {
+            match self {
+                MockKey::Rsa(key_pair) => {
+                    let mut signature = vec![0; key_pair.public().modulus_len()];
+                    key_pair
+                        .sign(
+                            &RSA_PKCS1_SHA256,
+                            &SystemRandom::new(),
+                            message,
+                            &mut signature,
+                        )
+                        .unwrap();
+                    signature
+                }
+                MockKey::Ec(key_pair) => key_pair
+                    .sign(&SystemRandom::new(), message)
+                    .unwrap()
+                    .as_ref()
+                    .to_vec(),
+                MockKey::Ed(signing_key) => signing_key.sign(message).to_bytes().to_vec(),
+            }
+        }
+
+        fn jwk(&self, kid: &str, alg: &str) -> Value {
+            let encode = |bytes: &[u8]| URL_SAFE_NO_PAD.encode(bytes);
+            match self {
+                MockKey::Rsa(key_pair) => {
+                    let public = RsaPublicKeyComponents::<Vec<u8>>::from(key_pair.public());
+                    json!({"kty": "RSA", "kid": kid, "alg": alg, "use": "sig",
+                        "n": encode(&public.n), "e": encode(&public.e)})
+                }
+                MockKey::Ec(key_pair) => {
+                    let point = key_pair.public_key().as_ref();
+                    json!({"kty": "EC", "kid": kid, "alg": alg, "use": "sig", "crv": "P-256",
+                        "x": encode(&point[1..33]), "y": encode(&point[33..])})
+                }
+                MockKey::Ed(signing_key) => {
+                    json!({"kty": "OKP", "kid": kid, "alg": alg, "use": "sig", "crv": "Ed25519",
+                        "x": encode(signing_key.verifying_key().as_bytes())})
+                }
+            }
+        }
+    }
+
+    /// An authorization code issued by the mock provider.
+    struct IssuedCode {
+        redirect_uri: String,
+        code_challenge: String,
+        nonce: String,
+        subject: String,
+        email: String,
+    }
+
+    struct MockState {
+        issuer: String,
+        /// The current signing key, along with its key ID and algorithm.
+        key: Mutex<(String, String, MockKey)>,
+        codes: Mutex<HashMap<String, IssuedCode>>,
+    }
+
+    /// An OpenID Connect provider that runs in-process on a local port, serving
+    /// discovery, its keys and a token endpoint. The authorization endpoint
+    /// is skipped: [MockIdentityProvider::authorize] plays the part of the
+    /// browser, returning the parameters the provider would redirect with.
+    pub struct MockIdentityProvider {
+        state: Arc<MockState>,
+    }
+
+    impl MockIdentityProvider {
+        /// Starts a provider that signs ID tokens with an RS256 key.
+        pub async fn start() -> MockIdentityProvider {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let state = Arc::new(MockState {
+                issuer: format!("http://{}", listener.local_addr().unwrap()),
+                key: Mutex::new((
+                    "rsa-1".to_string(),
+                    "RS256".to_string(),
+                    MockKey::generate("RS256"),
+                )),
+                codes: Mutex::new(HashMap::new()),
+            });
+            let router = Router::new()
+                .route("/.well-known/openid-configuration", get(discovery))
+                .route("/jwks", get(jwks))
+                .route("/token", post(token))
+                .with_state(state.clone());
+            tokio::spawn(async move {
+                axum::serve(listener, router).await.unwrap();
+            });
+            MockIdentityProvider { state }
+        }
+
+        pub fn issuer(&self) -> &str {
+            &self.state.issuer
+        }
+
+        /// Returns the configuration for this provider, registered as `name`.
+        pub fn provider(&self, name: &str) -> IdentityProvider {
+            IdentityProvider {
+                name: name.to_string(),
+                issuer: self.state.issuer.clone(),
+                client_id: TEST_CLIENT_ID.to_string(),
+                client_secret: Secret::new(TEST_CLIENT_SECRET.to_string()),
+                scopes: vec!["openid".to_string(), "email".to_string()],
+            }
+        }
+
+        /// Replaces the signing key with a new one that uses the algorithm.
+        /// Only the new key is published, as if the old one was compromised.
+        pub fn rotate_key(&self, kid: &str, alg: &str) {
+            *self.state.key.lock().unwrap() =
+                (kid.to_string(), alg.to_string(), MockKey::generate(alg));
+        }
+
+        /// Signs the account holder in to the provider using the authorization
+        /// URL, returning the parameters it redirects back with.
+        pub fn authorize(
+            &self,
+            authorization_url: &str,
+            subject: &str,
+            email: &str,
+        ) -> FederatedCallback {
+            let url = Url::parse(authorization_url).unwrap();
+            assert!(authorization_url.starts_with(&format!("{}/authorize?", self.state.issuer)));
+            let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+            assert_eq!("code", params["response_type"]);
+            assert_eq!(TEST_CLIENT_ID, params["client_id"]);
+            assert_eq!("S256", params["code_challenge_method"]);
+            let code = random_token();
+            self.state.codes.lock().unwrap().insert(
+                code.clone(),
+                IssuedCode {
+                    redirect_uri: params["redirect_uri"].clone(),
+                    code_challenge: params["code_challenge"].clone(),
+                    nonce: params["nonce"].clone(),
+                    subject: subject.to_string(),
+                    email: email.to_string(),
+                },
+            );
+            FederatedCallback {
+                state: params["state"].clone(),
+                code,
+            }
+        }
+
+        /// Returns the claims of a valid ID token issued to this service.
+        pub fn claims(&self, subject: &str, email: &str, nonce: &str) -> Value {
+            let now = Utc::now().timestamp();
+            json!({
+                "iss": self.state.issuer,
+                "sub": subject,
+                "aud": TEST_CLIENT_ID,
+                "iat": now,
+                "exp": now + 3600,
+                "nonce": nonce,
+                "email": email,
+                "email_verified": true,
+            })
+        }
+
+        /// Signs the claims with the current key.
+        pub fn sign(&self, claims: &Value) -> String {
+            let key = self.state.key.lock().unwrap();
+            let header = json!({"alg": key.1, "kid": key.0, "typ": "JWT"});
+            let signing_input = format!(
+                "{}.{}",
+                URL_SAFE_NO_PAD.encode(header.to_string()),
+                URL_SAFE_NO_PAD.encode(claims.to_string())
+            );
+            let signature = key.2.sign(signing_input.as_bytes());
+            format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature))
+        }
+
+        /// Returns the provider's current keys.
+        pub fn keys(&self) -> JsonWebKeySet {
+            serde_json::from_value(published_keys(&self.state)).unwrap()
+        }
+    }
+
+    fn published_keys(state: &MockState) -> Value {
+        let key = state.key.lock().unwrap();
+        json!({"keys": [key.2.jwk(&key.0, &key.1)]})
+    }
+
+    async fn discovery(State(state): State<Arc<MockState>>) -> Json<Value> {
+        Json(json!({
+            "issuer": state.issuer,
+            "authorization_endpoint": format!("{}/authorize", state.issuer),
+            "token_endpoint": format!("{}/token", state.issuer),
+            "jwks_uri": format!("{}/jwks", state.issuer),
+            "response_types_supported": ["code"],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": ["RS256", "ES256", "EdDSA"],
+        }))
+    }
+
+    async fn jwks(State(state): State<Arc<MockState>>) -> Json<Value> {
+        Json(published_keys(&state))
+    }
+
+    async fn token(
+        State(state): State<Arc<MockState>>,
+        Form(params): Form<HashMap<String, String>>,
+    ) -> Response {
+        let param = |name: &str| params.get(name).map(String::as_str).unwrap_or_default();
+        let error =
+            |code: &str| (StatusCode::BAD_REQUEST, Json(json!({"error": code}))).into_response();
+        if param("grant_type") != "authorization_code" {
+            return error("unsupported_grant_type");
+        }
+        if param("client_id") != TEST_CLIENT_ID || param("client_secret") != TEST_CLIENT_SECRET {
+            return error("invalid_client");
+        }
+        let Some(code) = state.codes.lock().unwrap().remove(param("code")) else {
+            return error("invalid_grant");
+        };
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(param("code_verifier")));
+        if code.redirect_uri != param("redirect_uri") || code.code_challenge != challenge {
+            return error("invalid_grant");
+        }
+        let provider = MockIdentityProvider { state };
+        let id_token = provider.sign(&provider.claims(&code.subject, &code.email, &code.nonce));
+        Json(json!({
+            "access_token": random_token(),
+            "token_type": "Bearer",
+            "expires_in": 3600,
+            "id_token": id_token,
+        }))
+        .into_response()
+    }
+
+    fn verify(
+        provider: &MockIdentityProvider,
+        claims: &Value,
+    ) -> Result<UpstreamIdentity, FederationServiceError> {
+        IdToken::parse(&provider.sign(claims))?.verify(
+            &provider.keys(),
+            &provider.provider("mock"),
+            "test-nonce",
+            Utc::now(),
+        )
+    }
+
+    #[tokio::test]
+    async fn verify_id_tokens() {
+        let provider = MockIdentityProvider::start().await;
+        for (kid, alg) in [("rsa-1", "RS256"), ("ec-1", "ES256"), ("ed-1", "EdDSA")] {
+            provider.rotate_key(kid, alg);
+            let identity = verify(
+                &provider,
+                &provider.claims("upstream-subject", "test@test.com", "test-nonce"),
+            )
+            .unwrap();
+            assert_eq!(
+                UpstreamIdentity {
+                    subject: "upstream-subject".to_string(),
+                    email: Some("test@test.com".to_string()),
+                    email_verified: true,
+                    name: None,
+                },
+                identity
+            );
+        }
+
+        // some providers send email_verified as a string
+        let mut claims = provider.claims("upstream-subject", "test@test.com", "test-nonce");
+        claims["email_verified"] = json!("false");
+        assert!(!verify(&provider, &claims).unwrap().email_verified);
+        claims["email_verified"] = json!("true");
+        claims["aud"] = json!([TEST_CLIENT_ID]);
+        assert!(verify(&provider, &claims).unwrap().email_verified);
+    }
+
+    #[tokio::test]
+    async fn verify_id_token_rejects_invalid_claims() {
+        let provider = MockIdentityProvider::start().await;
+        let now = Utc::now().timestamp();
+        let invalid_claims = [
+            ("iss", json!("https://attacker.example")),
+            ("aud", json!("another-client")),
+            ("aud", json!([TEST_CLIENT_ID, "another-client"])),
+            ("azp", json!("another-client")),
+            ("exp", json!(now - CLOCK_SKEW_LEEWAY - 1)),
+            ("nonce", json!("another-nonce")),
+            ("nonce", Value::Null),
+        ];
+        for (claim, value) in invalid_claims {
+            let mut claims = provider.claims("upstream-subject", "test@test.com", "test-nonce");
+            claims[claim] = value;
+            assert!(
+                matches!(
+                    verify(&provider, &claims),
+                    Err(FederationServiceError::InvalidIdToken(_))
+                ),
+                "{} should be rejected",
+                claim
+            );
+        }
+
+        // a slightly expired token is accepted, in case the clocks differ
+        let mut claims = provider.claims("upstream-subject", "test@test.com", "test-nonce");
+        claims["exp"] = json!(now - 5);
+        assert!(verify(&provider, &claims).is_ok());
+        // several audiences are accepted if this service is the authorized party
+        claims["aud"] = json!([TEST_CLIENT_ID, "another-client"]);
+        claims["azp"] = json!(TEST_CLIENT_ID);
+        assert!(verify(&provider, &claims).is_ok());
+    }
+
+    #[tokio::test]
+    async fn verify_id_token_rejects_invalid_signatures() {
+        let provider = MockIdentityProvider::start().await;
+        let claims = provider.claims("upstream-subject", "test@test.com", "test-nonce");
+        let keys = provider.keys();
+        let mock = provider.provider("mock");
+        let check = |token: &str| {
+            IdToken::parse(token)
+                .and_then(|id_token| id_token.verify(&keys, &mock, "test-nonce", Utc::now()))
+        };
+
+        // a token whose claims were changed after signing
+        let token = provider.sign(&claims);
+        let parts: Vec<&str> = token.split('.').collect();
+        let mut forged_claims = claims.clone();
+        forged_claims["sub"] = json!("another-subject");
+        let forged = format!(
+            "{}.{}.{}",
+            parts[0],
+            URL_SAFE_NO_PAD.encode(forged_claims.to_string()),
+            parts[2]
+        );
+        assert!(check(&forged).is_err());
+
+        // unsigned tokens, and tokens signed with the client secret
+        for alg in ["none", "HS256"] {
+            let header = json!({"alg": alg, "kid": "rsa-1"});
+            let unsigned = format!(
+                "{}.{}.",
+                URL_SAFE_NO_PAD.encode(header.to_string()),
+                URL_SAFE_NO_PAD.encode(claims.to_string())
+            );
+            assert!(check(&unsigned).is_err());
+        }
+
+        // a token signed by a key that isn't the provider's
+        provider.rotate_key("rsa-1", "ES256");
+        assert!(check(&provider.sign(&claims)).is_err());
+        assert!(check("not-a-token").is_err());
+    }
+
+    #[tokio::test]
+    async fn exchange_code() {
+        let provider = MockIdentityProvider::start().await;
+        let mock = provider.provider("mock");
+        let client = OidcClient::new().unwrap();
+        let metadata = client.discover(&mock).await.unwrap();
+        assert_eq!(
+            format!("{}/token", provider.issuer()),
+            metadata.token_endpoint
+        );
+
+        let verifier = random_token();
+        let authorization_url = format!(
+            "{}?response_type=code&client_id={}&redirect_uri={}&state=test-state&nonce=test-nonce\
+            &code_challenge={}&code_challenge_method=S256",
+            metadata.authorization_endpoint,
+            TEST_CLIENT_ID,
+            TEST_REDIRECT_URL,
+            URL_SAFE_NO_PAD.encode(Sha256::digest(&verifier))
+        );
+        let callback = provider.authorize(&authorization_url, "upstream-subject", "test@test.com");
+        assert_eq!("test-state", callback.state);
+
+        // the code verifier must match
+        assert!(matches!(
+            client
+                .exchange_code(
+                    &mock,
+                    &metadata,
+                    &callback.code,
+                    TEST_REDIRECT_URL,
+                    &random_token()
+                )
+                .await,
+            Err(FederationServiceError::UpstreamError(_))
+        ));
+        let callback = provider.authorize(&authorization_url, "upstream-subject", "test@test.com");
+        let id_token = client
+            .exchange_code(
+                &mock,
+                &metadata,
+                &callback.code,
+                TEST_REDIRECT_URL,
+                &verifier,
+            )
+            .await
+            .unwrap();
+        let keys = client.fetch_keys(&metadata).await.unwrap();
+        assert!(id_token.has_key_in(&keys));
+        let identity = id_token
+            .verify(&keys, &mock, "test-nonce", Utc::now())
+            .unwrap();
+        assert_eq!("upstream-subject", identity.subject);
+
+        // codes can only be used once
+        assert!(client
+            .exchange_code(
+                &mock,
+                &metadata,
+                &callback.code,
+                TEST_REDIRECT_URL,
+                &verifier
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn discovery_must_match_issuer() {
+        let provider = MockIdentityProvider::start().await;
+        let mut mock = provider.provider("mock");
+        mock.issuer = format!("{}/", provider.issuer());
+        assert!(matches!(
+            OidcClient::new().unwrap().discover(&mock).await,
+            Err(FederationServiceError::UpstreamError(_))
+        ));
+    }
+}
@@ -0,0 +1,53 @@
+pub mod error;
+#[cfg(test)]
+pub mod fake;
+pub mod postgres;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use error::LinkedIdentityStoreError;
+
+use crate::services::federation::models::{FederatedAuthorization, LinkedIdentity};
+
+#[async_trait]
+pub trait LinkedIdentityStore: Send + Sync + 'static {
+    /// Inserts a new linked identity. Returns false without inserting it if
+    /// the provider's subject is already linked to an account.
+    async fn insert_identity(
+        &self,
+        identity: &LinkedIdentity,
+    ) -> Result<bool, LinkedIdentityStoreError>;
+    async fn load_identity(
+        &self,
+        id: &str,
+    ) -> Result<Option<LinkedIdentity>, LinkedIdentityStoreError>;
+    async fn load_identity_by_subject(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<LinkedIdentity>, LinkedIdentityStoreError>;
+    /// Returns the account's linked identities, oldest first.
+    async fn load_identities(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<LinkedIdentity>, LinkedIdentityStoreError>;
+    /// Records that the identity was used to sign in, along with the
+    /// email address the provider had for it at the time.
+    async fn record_identity_use(
+        &self,
+        id: &str,
+        email: Option<&str>,
+        used_at: DateTime<Utc>,
+    ) -> Result<(), LinkedIdentityStoreError>;
+    async fn delete_identity(&self, id: &str) -> Result<(), LinkedIdentityStoreError>;
+    async fn delete_identities(&self, account_id: &str) -> Result<(), LinkedIdentityStoreError>;
+    async fn insert_authorization(
+        &self,
+        authorization: &FederatedAuthorization,
+    ) -> Result<(), LinkedIdentityStoreError>;
+    /// Deletes and returns the authorization, so that it can only be used once.
+    async fn take_authorization(
+        &self,
+        state_hash: &str,
+    ) -> Result<Option<FederatedAuthorization>, LinkedIdentityStoreError>;
+}
@@ -0,0 +1,7 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum LinkedIdentityStoreError {
+    #[error("database error: {0}")]
+    DatabaseError(String),
+}
@@ -0,0 +1,134 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::services::federation::models::{FederatedAuthorization, LinkedIdentity};
+
+use super::{error::LinkedIdentityStoreError, LinkedIdentityStore};
+
+/// The "database" for the FakeLinkedIdentityStore.
+struct Database {
+    /// Linked identities keyed by ID.
+    identities: HashMap<String, LinkedIdentity>,
+    /// Pending authorizations keyed by state hash.
+    authorizations: HashMap<String, FederatedAuthorization>,
+}
+
+/// A fake implementation of [LinkedIdentityStore] that can be used in unit tests.
+pub struct FakeLinkedIdentityStore {
+    db: Mutex<Database>,
+}
+
+impl FakeLinkedIdentityStore {
+    pub fn new() -> FakeLinkedIdentityStore {
+        FakeLinkedIdentityStore {
+            db: Mutex::new(Database {
+                identities: HashMap::new(),
+                authorizations: HashMap::new(),
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl LinkedIdentityStore for FakeLinkedIdentityStore {
+    async fn insert_identity(
+        &self,
+        identity: &LinkedIdentity,
+    ) -> Result<bool, LinkedIdentityStoreError> {
+        let mut db = self.db.lock().unwrap();
+        if db.identities.values().any(|existing| {
+            existing.provider == identity.provider && existing.subject == identity.subject
+        }) {
+            return Ok(false);
+        }
+        db.identities.insert(identity.id.clone(), identity.clone());
+        Ok(true)
+    }
+
+    async fn load_identity(
+        &self,
+        id: &str,
+    ) -> Result<Option<LinkedIdentity>, LinkedIdentityStoreError> {
+        Ok(self.db.lock().unwrap().identities.get(id).cloned())
+    }
+
+    async fn load_identity_by_subject(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<LinkedIdentity>, LinkedIdentityStoreError> {
+        Ok(self
+            .db
+            .lock()
+            .unwrap()
+            .identities
+            .values()
+            .find(|identity| identity.provider == provider && identity.subject == subject)
+            .cloned())
+    }
+
+    async fn load_identities(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<LinkedIdentity>, LinkedIdentityStoreError> {
+        let mut identities: Vec<LinkedIdentity> = self
+            .db
+            .lock()
+            .unwrap()
+            .identities
+            .values()
+            .filter(|identity| identity.account_id == account_id)
+            .cloned()
+            .collect();
+        identities.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)));
+        Ok(identities)
+    }
+
+    async fn record_identity_use(
+        &self,
+        id: &str,
+        email: Option<&str>,
+        used_at: DateTime<Utc>,
+    ) -> Result<(), LinkedIdentityStoreError> {
+        if let Some(identity) = self.db.lock().unwrap().identities.get_mut(id) {
+            identity.email = email.map(str::to_string);
+            identity.last_used_at = Some(used_at);
+        }
+        Ok(())
+    }
+
+    async fn delete_identity(&self, id: &str) -> Result<(), LinkedIdentityStoreError> {
+        self.db.lock().unwrap().identities.remove(id);
+        Ok(())
+    }
+
+    async fn delete_identities(&self, account_id: &str) -> Result<(), LinkedIdentityStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .identities
+            .retain(|_, identity| identity.account_id != account_id);
+        Ok(())
+    }
+
+    async fn insert_authorization(
+        &self,
+        authorization: &FederatedAuthorization,
+    ) -> Result<(), LinkedIdentityStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .authorizations
+            .insert(authorization.state_hash.clone(), authorization.clone());
+        Ok(())
+    }
+
+    async fn take_authorization(
+        &self,
+        state_hash: &str,
+    ) -> Result<Option<FederatedAuthorization>, LinkedIdentityStoreError> {
+        Ok(self.db.lock().unwrap().authorizations.remove(state_hash))
+    }
+}
@@ -0,0 +1,186 @@
+//! Implements [LinkedIdentityStore] backed by a PostgreSQL database
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, PgPool, Row};
+
+use crate::services::federation::models::{FederatedAuthorization, LinkedIdentity};
+
+use super::{error::LinkedIdentityStoreError, LinkedIdentityStore};
+
+const IDENTITY_COLUMNS: &str = "id,account_id,provider,subject,email,created_at,last_used_at";
+
+impl From<sqlx::Error> for LinkedIdentityStoreError {
+    fn from(value: sqlx::Error) -> Self {
+        LinkedIdentityStoreError::DatabaseError(value.to_string())
+    }
+}
+
+pub struct PostgresLinkedIdentityStore {
+    pool: PgPool,
+}
+
+impl PostgresLinkedIdentityStore {
+    pub fn new(pool: PgPool) -> PostgresLinkedIdentityStore {
+        PostgresLinkedIdentityStore { pool }
+    }
+}
+
+/// Maps a row selected using [IDENTITY_COLUMNS] to a [LinkedIdentity].
+fn identity_from_row(row: PgRow) -> LinkedIdentity {
+    LinkedIdentity {
+        id: row.get(0),
+        account_id: row.get(1),
+        provider: row.get(2),
+        subject: row.get(3),
+        email: row.get(4),
+        created_at: row.get(5),
+        last_used_at: row.get(6),
+    }
+}
+
+#[async_trait]
+impl LinkedIdentityStore for PostgresLinkedIdentityStore {
+    async fn insert_identity(
+        &self,
+        identity: &LinkedIdentity,
+    ) -> Result<bool, LinkedIdentityStoreError> {
+        let result = sqlx::query(&format!(
+            "insert into linked_identities({}) values ($1,$2,$3,$4,$5,$6,$7) \
+            on conflict (provider,subject) do nothing",
+            IDENTITY_COLUMNS
+        ))
+        .bind(&identity.id)
+        .bind(&identity.account_id)
+        .bind(&identity.provider)
+        .bind(&identity.subject)
+        .bind(&identity.email)
+        .bind(identity.created_at)
+        .bind(identity.last_used_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn load_identity(
+        &self,
+        id: &str,
+    ) -> Result<Option<LinkedIdentity>, LinkedIdentityStoreError> {
+        Ok(sqlx::query(&format!(
+            "select {} from linked_identities where id=$1",
+            IDENTITY_COLUMNS
+        ))
+        .bind(id)
+        .map(identity_from_row)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn load_identity_by_subject(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<LinkedIdentity>, LinkedIdentityStoreError> {
+        Ok(sqlx::query(&format!(
+            "select {} from linked_identities where provider=$1 and subject=$2",
+            IDENTITY_COLUMNS
+        ))
+        .bind(provider)
+        .bind(subject)
+        .map(identity_from_row)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn load_identities(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<LinkedIdentity>, LinkedIdentityStoreError> {
+        Ok(sqlx::query(&format!(
+            "select {} from linked_identities where account_id=$1 order by created_at, id",
+            IDENTITY_COLUMNS
+        ))
+        .bind(account_id)
+        .map(identity_from_row)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn record_identity_use(
+        &self,
+        id: &str,
+        email: Option<&str>,
+        used_at: DateTime<Utc>,
+    ) -> Result<(), LinkedIdentityStoreError> {
+        sqlx::query("update linked_identities set email=$1, last_used_at=$2 where id=$3")
+            .bind(email)
+            .bind(used_at)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_identity(&self, id: &str) -> Result<(), LinkedIdentityStoreError> {
+        sqlx::query("delete from linked_identities where id=$1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_identities(&self, account_id: &str) -> Result<(), LinkedIdentityStoreError> {
+        sqlx::query("delete from linked_identities where account_id=$1")
+            .bind(account_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn insert_authorization(
+        &self,
+        authorization: &FederatedAuthorization,
+    ) -> Result<(), LinkedIdentityStoreError> {
+        sqlx::query(
+            "insert into federated_authorizations(state_hash,provider,nonce,code_verifier,\
+            account_id,created_at,expires_at) values ($1,$2,$3,$4,$5,$6,$7)",
+        )
+        .bind(&authorization.state_hash)
+        .bind(&authorization.provider)
+        .bind(&authorization.nonce)
+        .bind(&authorization.code_verifier)
+        .bind(&authorization.account_id)
+        .bind(authorization.created_at)
+        .bind(authorization.expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn take_authorization(
+        &self,
+        state_hash: &str,
+    ) -> Result<Option<FederatedAuthorization>, LinkedIdentityStoreError> {
+        Ok(sqlx::query(
+            "delete from federated_authorizations where state_hash=$1 \
+            returning state_hash,provider,nonce,code_verifier,account_id,created_at,expires_at",
+        )
+        .bind(state_hash)
+        .map(|row: PgRow| FederatedAuthorization {
+            state_hash: row.get(0),
+            provider: row.get(1),
+            nonce: row.get(2),
+            code_verifier: row.get(3),
+            account_id: row.get(4),
+            created_at: row.get(5),
+            expires_at: row.get(6),
+        })
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+}
@@ -0,0 +1,495 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{TimeDelta, Utc};
+use cipher::SecretCipher;
+use error::MfaServiceError;
+use models::{IssuedMfaChallenge, MfaChallenge, TotpEnrollment, TotpFactor};
+use stores::MfaStore;
+
+use super::{hash_token, random_token, Clock, SystemClock};
+
+pub mod cipher;
+pub mod error;
+pub mod models;
+pub mod stores;
+pub mod totp;
+
+/// The issuer shown next to the account in authenticator apps.
+const DEFAULT_ISSUER: &str = "Identity Service";
+/// Number of random bytes in a TOTP secret (the size of an HMAC-SHA1 key).
+const TOTP_SECRET_BYTES: usize = 20;
+/// How long an account holder has to provide a second factor after
+/// presenting the correct password.
+const MFA_CHALLENGE_TTL: TimeDelta = TimeDelta::minutes(5);
+/// Number of incorrect codes allowed per challenge. There are only a million
+/// possible codes, so the challenge is revoked after this many guesses, and
+/// the account holder must present their password again.
+const MAX_CHALLENGE_ATTEMPTS: i32 = 5;
+
+/// Manages the second factors used for multi-factor authentication, and the
+/// challenges issued to accounts that have presented the correct password
+/// but have yet to provide a second factor. TOTP secrets are encrypted by the
+/// [SecretCipher] before they are given to the [MfaStore].
+pub struct MfaService<S: MfaStore, C: Clock<Utc>> {
+    store: S,
+    cipher: SecretCipher,
+    clock: C,
+    issuer: String,
+}
+
+impl<S: MfaStore, C: Clock<Utc>> MfaService<S, C> {
+    /// Constructs a new [MfaService] given the [MfaStore], [SecretCipher] and [Clock] to use.
+    pub fn new_with_clock(mfa_store: S, cipher: SecretCipher, clock: C) -> Self {
+        Self {
+            store: mfa_store,
+            cipher,
+            clock,
+            issuer: DEFAULT_ISSUER.to_string(),
+        }
+    }
+
+    /// Starts enrolling a TOTP factor for the account, returning the secret
+    /// to add to an authenticator app. The factor isn't used until it is
+    /// confirmed by [MfaService::confirm_totp]. Starting again replaces any
+    /// unconfirmed factor (e.g., if the account holder lost the first QR code).
+    pub async fn enroll_totp(
+        &self,
+        account_id: &str,
+        account_name: &str,
+    ) -> Result<TotpEnrollment, MfaServiceError> {
+        if self.mfa_enabled(account_id).await? {
+            return Err(MfaServiceError::MfaAlreadyEnabled);
+        }
+
+        let mut secret = [0u8; TOTP_SECRET_BYTES];
+        OsRng.fill_bytes(&mut secret);
+        self.store
+            .upsert_totp_factor(&TotpFactor {
+                account_id: account_id.to_string(),
+                encrypted_secret: self.cipher.encrypt(account_id, &secret)?,
+                created_at: self.clock.now(),
+                confirmed_at: None,
+                last_used_step: None,
+            })
+            .await?;
+
+        Ok(TotpEnrollment {
+            secret: totp::encode_secret(&secret),
+            provisioning_uri: totp::provisioning_uri(&secret, &self.issuer, account_name),
+        })
+    }
+
+    /// Confirms a pending TOTP enrollment using the first code from the
+    /// authenticator app, after which sign-ins require a second factor.
+    pub async fn confirm_totp(&self, account_id: &str, code: &str) -> Result<(), MfaServiceError> {
+        let factor = self
+            .store
+            .load_totp_factor(account_id)
+            .await?
+            .ok_or(MfaServiceError::TotpNotEnrolled)?;
+        if factor.confirmed() {
+            return Err(MfaServiceError::MfaAlreadyEnabled);
+        }
+
+        let step = self.verify_code(&factor, code).await?;
+        self.store
+            .update_totp_factor(&TotpFactor {
+                confirmed_at: Some(self.clock.now()),
+                last_used_step: Some(step),
+                ..factor
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Verifies a code from the account's authenticator app outside of a
+    /// challenge (e.g., to prove who the account holder is before disabling MFA).
+    /// The factor must have been confirmed.
+    pub async fn verify_totp(&self, account_id: &str, code: &str) -> Result<(), MfaServiceError> {
+        let factor = self
+            .store
+            .load_totp_factor(account_id)
+            .await?
+            .filter(|factor| factor.confirmed())
+            .ok_or(MfaServiceError::TotpNotEnrolled)?;
+        self.verify_code(&factor, code).await?;
+        Ok(())
+    }
+
+    /// Removes the account's TOTP factor, whether or not it was confirmed.
+    pub async fn disable_totp(&self, account_id: &str) -> Result<(), MfaServiceError> {
+        if self.store.load_totp_factor(account_id).await?.is_none() {
+            return Err(MfaServiceError::TotpNotEnrolled);
+        }
+        Ok(self.store.delete_totp_factor(account_id).await?)
+    }
+
+    /// Removes all of the account's factors (e.g., when the account is deleted).
+    /// Any outstanding challenges can then no longer be completed.
+    pub async fn delete_factors(&self, account_id: &str) -> Result<(), MfaServiceError> {
+        Ok(self.store.delete_totp_factor(account_id).await?)
+    }
+
+    /// Returns true if the account must provide a second factor to sign in.
+    pub async fn mfa_enabled(&self, account_id: &str) -> Result<bool, MfaServiceError> {
+        Ok(self
+            .store
+            .load_totp_factor(account_id)
+            .await?
+            .is_some_and(|factor| factor.confirmed()))
+    }
+
+    /// Creates a challenge for an account that has presented the correct
+    /// password, returning the handle to present along with the second factor.
+    pub async fn create_challenge(
+        &self,
+        account_id: &str,
+    ) -> Result<IssuedMfaChallenge, MfaServiceError> {
+        let handle = random_token();
+        let now = self.clock.now();
+        let challenge = MfaChallenge {
+            token_hash: hash_token(&handle),
+            account_id: account_id.to_string(),
+            created_at: now,
+            expires_at: now + MFA_CHALLENGE_TTL,
+            failed_attempts: 0,
+        };
+        self.store.insert_challenge(&challenge).await?;
+        Ok(IssuedMfaChallenge {
+            handle,
+            expires_at: challenge.expires_at,
+        })
+    }
+
+    /// Completes a challenge using a code from the account's authenticator
+    /// app, returning the ID of the account that can now be signed in.
+    /// Each challenge can be completed only once.
+    pub async fn complete_challenge(
+        &self,
+        handle: &str,
+        code: &str,
+    ) -> Result<String, MfaServiceError> {
+        let challenge = self.load_challenge(handle).await?;
+        let factor = self
+            .store
+            .load_totp_factor(&challenge.account_id)
+            .await?
+            .filter(|factor| factor.confirmed())
+            .ok_or(MfaServiceError::InvalidMfaChallenge)?;
+
+        if let Err(err) = self.verify_code(&factor, code).await {
+            self.record_failed_attempt(&challenge).await?;
+            return Err(err);
+        }
+
+        self.close_challenge(&challenge).await?;
+        Ok(challenge.account_id)
+    }
+
+    /// Loads an unexpired challenge by its handle. This is used when the
+    /// second factor is checked by another service (e.g., a recovery code),
+    /// which must then either [record a failed attempt](Self::record_failed_attempt)
+    /// or [close](Self::close_challenge) the challenge.
+    pub async fn load_challenge(&self, handle: &str) -> Result<MfaChallenge, MfaServiceError> {
+        let challenge = self
+            .store
+            .load_challenge(&hash_token(handle))
+            .await?
+            .ok_or(MfaServiceError::InvalidMfaChallenge)?;
+        if self.clock.now() >= challenge.expires_at {
+            self.store.delete_challenge(&challenge.token_hash).await?;
+            return Err(MfaServiceError::InvalidMfaChallenge);
+        }
+        Ok(challenge)
+    }
+
+    /// Counts a failed attempt to complete the challenge, deleting it once
+    /// too many attempts have failed. The count is incremented in the store,
+    /// so concurrent guesses can't each see the same number of attempts.
+    pub async fn record_failed_attempt(
+        &self,
+        challenge: &MfaChallenge,
+    ) -> Result<(), MfaServiceError> {
+        let failed_attempts = self
+            .store
+            .increment_challenge_attempts(&challenge.token_hash)
+            .await?;
+        if failed_attempts.is_some_and(|attempts| attempts >= MAX_CHALLENGE_ATTEMPTS) {
+            self.store.delete_challenge(&challenge.token_hash).await?;
+        }
+        Ok(())
+    }
+
+    /// Deletes a completed challenge so that it can't be used again.
+    pub async fn close_challenge(&self, challenge: &MfaChallenge) -> Result<(), MfaServiceError> {
+        self.store.delete_challenge(&challenge.token_hash).await?;
+        Ok(())
+    }
+
+    /// Verifies the code against the factor's secret, and records the time
+    /// step it matched so the same code can't be used again. Returns that step.
+    async fn verify_code(&self, factor: &TotpFactor, code: &str) -> Result<i64, MfaServiceError> {
+        let secret = self
+            .cipher
+            .decrypt(&factor.account_id, &factor.encrypted_secret)?;
+        let step = totp::verify(&secret, code, self.clock.now(), factor.last_used_step)
+            .ok_or(MfaServiceError::InvalidCode)?;
+        if !self
+            .store
+            .mark_totp_step_used(&factor.account_id, step)
+            .await?
+        {
+            return Err(MfaServiceError::InvalidCode);
+        }
+        Ok(step)
+    }
+}
+
+impl<S: MfaStore> MfaService<S, SystemClock<Utc>> {
+    pub fn new(mfa_store: S, cipher: SecretCipher) -> Self {
+        Self::new_with_clock(mfa_store, cipher, SystemClock::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use stores::fake::FakeMfaStore;
+
+    use crate::services::TestClock;
+
+    use super::*;
+
+    fn test_service(clock: &TestClock<Utc>) -> MfaService<FakeMfaStore, TestClock<Utc>> {
+        MfaService::new_with_clock(
+            FakeMfaStore::new(),
+            SecretCipher::new(&[7u8; 32]).unwrap(),
+            clock.clone(),
+        )
+    }
+
+    /// Returns the code an authenticator app would show at the clock's current time.
+    fn current_code(enrollment: &TotpEnrollment, clock: &TestClock<Utc>) -> String {
+        code_at_step(enrollment, totp::time_step(clock.now()))
+    }
+
+    fn code_at_step(enrollment: &TotpEnrollment, step: i64) -> String {
+        let secret = totp::tests::decode_secret(&enrollment.secret);
+        totp::generate(&secret, step)
+    }
+
+    /// Returns a code that isn't valid for any time step near the clock's current time.
+    fn wrong_code(enrollment: &TotpEnrollment, clock: &TestClock<Utc>) -> String {
+        let step = totp::time_step(clock.now());
+        let valid: Vec<String> = (step - 1..=step + 1)
+            .map(|step| code_at_step(enrollment, step))
+            .collect();
+        (0..)
+            .map(|n| format!("{:06}", n))
+            .find(|code| !valid.contains(code))
+            .unwrap()
+    }
+
+    async fn enroll_and_confirm(
+        service: &MfaService<FakeMfaStore, TestClock<Utc>>,
+        clock: &TestClock<Utc>,
+    ) -> TotpEnrollment {
+        let enrollment = service
+            .enroll_totp("acct_test", "test@test.com")
+            .await
+            .unwrap();
+        service
+            .confirm_totp("acct_test", &current_code(&enrollment, clock))
+            .await
+            .unwrap();
+        enrollment
+    }
+
+    #[tokio::test]
+    async fn enroll_and_confirm_totp() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let enrollment = service
+            .enroll_totp("acct_test", "test@test.com")
+            .await
+            .unwrap();
+        assert!(enrollment
+            .provisioning_uri
+            .contains(&format!("secret={}", enrollment.secret)));
+        assert!(!service.mfa_enabled("acct_test").await.unwrap());
+
+        // the secret is encrypted at rest
+        let factor = service
+            .store
+            .load_totp_factor("acct_test")
+            .await
+            .unwrap()
+            .unwrap();
+        let secret = totp::tests::decode_secret(&enrollment.secret);
+        assert!(!factor
+            .encrypted_secret
+            .windows(secret.len())
+            .any(|window| window == secret));
+
+        assert!(matches!(
+            service
+                .confirm_totp("acct_test", &wrong_code(&enrollment, &clock))
+                .await,
+            Err(MfaServiceError::InvalidCode)
+        ));
+        service
+            .confirm_totp("acct_test", &current_code(&enrollment, &clock))
+            .await
+            .unwrap();
+        assert!(service.mfa_enabled("acct_test").await.unwrap());
+
+        // enrolling again requires disabling first
+        assert!(matches!(
+            service.enroll_totp("acct_test", "test@test.com").await,
+            Err(MfaServiceError::MfaAlreadyEnabled)
+        ));
+        service.disable_totp("acct_test").await.unwrap();
+        assert!(!service.mfa_enabled("acct_test").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn challenge_allows_clock_drift() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let enrollment = enroll_and_confirm(&service, &clock).await;
+
+        // a code from one step behind (e.g., a slow phone clock) is accepted
+        clock.advance(TimeDelta::seconds(totp::PERIOD_SECONDS * 2));
+        let step = totp::time_step(clock.now());
+        let challenge = service.create_challenge("acct_test").await.unwrap();
+        let account_id = service
+            .complete_challenge(&challenge.handle, &code_at_step(&enrollment, step - 1))
+            .await
+            .unwrap();
+        assert_eq!("acct_test", account_id);
+
+        // as is one from a step ahead, but not from two steps behind
+        clock.advance(TimeDelta::seconds(totp::PERIOD_SECONDS * 3));
+        let step = totp::time_step(clock.now());
+        let challenge = service.create_challenge("acct_test").await.unwrap();
+        assert!(matches!(
+            service
+                .complete_challenge(&challenge.handle, &code_at_step(&enrollment, step - 2))
+                .await,
+            Err(MfaServiceError::InvalidCode)
+        ));
+        service
+            .complete_challenge(&challenge.handle, &code_at_step(&enrollment, step + 1))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn challenge_codes_cannot_be_replayed() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let enrollment = enroll_and_confirm(&service, &clock).await;
+        clock.advance(TimeDelta::seconds(totp::PERIOD_SECONDS));
+
+        let code = current_code(&enrollment, &clock);
+        let first = service.create_challenge("acct_test").await.unwrap();
+        service
+            .complete_challenge(&first.handle, &code)
+            .await
+            .unwrap();
+        // each challenge can be completed only once
+        assert!(matches!(
+            service.complete_challenge(&first.handle, &code).await,
+            Err(MfaServiceError::InvalidMfaChallenge)
+        ));
+
+        let second = service.create_challenge("acct_test").await.unwrap();
+        assert!(matches!(
+            service.complete_challenge(&second.handle, &code).await,
+            Err(MfaServiceError::InvalidCode)
+        ));
+    }
+
+    #[tokio::test]
+    async fn challenge_expires_and_limits_attempts() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let enrollment = enroll_and_confirm(&service, &clock).await;
+
+        let challenge = service.create_challenge("acct_test").await.unwrap();
+        clock.advance(MFA_CHALLENGE_TTL);
+        assert!(matches!(
+            service
+                .complete_challenge(&challenge.handle, &current_code(&enrollment, &clock))
+                .await,
+            Err(MfaServiceError::InvalidMfaChallenge)
+        ));
+
+        let challenge = service.create_challenge("acct_test").await.unwrap();
+        let wrong_code = wrong_code(&enrollment, &clock);
+        for _ in 0..MAX_CHALLENGE_ATTEMPTS {
+            assert!(matches!(
+                service
+                    .complete_challenge(&challenge.handle, &wrong_code)
+                    .await,
+                Err(MfaServiceError::InvalidCode)
+            ));
+        }
+        assert!(matches!(
+            service
+                .complete_challenge(&challenge.handle, &current_code(&enrollment, &clock))
+                .await,
+            Err(MfaServiceError::InvalidMfaChallenge)
+        ));
+    }
+
+    #[tokio::test]
+    async fn concurrent_failed_attempts_are_all_counted() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let enrollment = enroll_and_confirm(&service, &clock).await;
+        let challenge = service.create_challenge("acct_test").await.unwrap();
+
+        // concurrent guesses each load the challenge before any failure is recorded
+        let loaded = service.load_challenge(&challenge.handle).await.unwrap();
+        for _ in 0..MAX_CHALLENGE_ATTEMPTS {
+            service.record_failed_attempt(&loaded).await.unwrap();
+        }
+        assert!(matches!(
+            service
+                .complete_challenge(&challenge.handle, &current_code(&enrollment, &clock))
+                .await,
+            Err(MfaServiceError::InvalidMfaChallenge)
+        ));
+    }
+
+    #[tokio::test]
+    async fn verify_totp() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let enrollment = service
+            .enroll_totp("acct_test", "test@test.com")
+            .await
+            .unwrap();
+        // codes from an unconfirmed enrollment aren't accepted
+        assert!(matches!(
+            service
+                .verify_totp("acct_test", &current_code(&enrollment, &clock))
+                .await,
+            Err(MfaServiceError::TotpNotEnrolled)
+        ));
+
+        let enrollment = enroll_and_confirm(&service, &clock).await;
+        clock.advance(TimeDelta::seconds(totp::PERIOD_SECONDS * 2));
+        let code = current_code(&enrollment, &clock);
+        service.verify_totp("acct_test", &code).await.unwrap();
+        assert!(matches!(
+            service.verify_totp("acct_test", &code).await,
+            Err(MfaServiceError::InvalidCode)
+        ));
+        assert!(matches!(
+            service
+                .verify_totp("acct_test", &wrong_code(&enrollment, &clock))
+                .await,
+            Err(MfaServiceError::InvalidCode)
+        ));
+    }
+}
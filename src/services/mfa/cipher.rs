@@ -0,0 +1,107 @@
+//! Encrypts MFA secrets before they are stored, so that a copy of the
+//! database alone isn't enough to generate codes for every account.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+
+use super::error::MfaServiceError;
+
+/// Encrypts and decrypts secrets using AES-256-GCM. Each ciphertext is
+/// bound to the ID of the account it belongs to, so a ciphertext copied
+/// to another account's row will fail to decrypt.
+pub struct SecretCipher {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl SecretCipher {
+    /// Constructs a [SecretCipher] from a 256-bit key.
+    pub fn new(key: &[u8]) -> Result<SecretCipher, MfaServiceError> {
+        let key = UnboundKey::new(&AES_256_GCM, key).map_err(|_| {
+            MfaServiceError::InvalidEncryptionKey(format!(
+                "expected {} bytes but got {}",
+                AES_256_GCM.key_len(),
+                key.len()
+            ))
+        })?;
+        Ok(SecretCipher {
+            key: LessSafeKey::new(key),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Constructs a [SecretCipher] from a base64-encoded 256-bit key,
+    /// such as one generated by `openssl rand -base64 32`.
+    pub fn from_base64(key: &str) -> Result<SecretCipher, MfaServiceError> {
+        let key = STANDARD
+            .decode(key.trim())
+            .map_err(|e| MfaServiceError::InvalidEncryptionKey(e.to_string()))?;
+        Self::new(&key)
+    }
+
+    /// Encrypts the secret, returning the random nonce followed by the ciphertext.
+    pub fn encrypt(&self, account_id: &str, secret: &[u8]) -> Result<Vec<u8>, MfaServiceError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| MfaServiceError::EncryptionError)?;
+        let mut in_out = secret.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(account_id.as_bytes()),
+                &mut in_out,
+            )
+            .map_err(|_| MfaServiceError::EncryptionError)?;
+        let mut encrypted = nonce.to_vec();
+        encrypted.append(&mut in_out);
+        Ok(encrypted)
+    }
+
+    /// Decrypts a secret encrypted by [SecretCipher::encrypt].
+    pub fn decrypt(&self, account_id: &str, encrypted: &[u8]) -> Result<Vec<u8>, MfaServiceError> {
+        if encrypted.len() < NONCE_LEN {
+            return Err(MfaServiceError::EncryptionError);
+        }
+        let (nonce, ciphertext) = encrypted.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| MfaServiceError::EncryptionError)?;
+        let mut in_out = ciphertext.to_vec();
+        let secret = self
+            .key
+            .open_in_place(nonce, Aad::from(account_id.as_bytes()), &mut in_out)
+            .map_err(|_| MfaServiceError::EncryptionError)?;
+        Ok(secret.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_decrypt() {
+        let cipher = SecretCipher::new(&[7u8; 32]).unwrap();
+        let encrypted = cipher.encrypt("acct_test", b"secret").unwrap();
+        assert!(!encrypted.windows(6).any(|window| window == b"secret"));
+        assert_eq!(
+            b"secret".to_vec(),
+            cipher.decrypt("acct_test", &encrypted).unwrap()
+        );
+
+        // the ciphertext only decrypts for the same account and key
+        assert!(cipher.decrypt("acct_other", &encrypted).is_err());
+        let other_cipher = SecretCipher::new(&[8u8; 32]).unwrap();
+        assert!(other_cipher.decrypt("acct_test", &encrypted).is_err());
+    }
+
+    #[test]
+    fn invalid_keys() {
+        assert!(SecretCipher::new(&[0u8; 16]).is_err());
+        assert!(SecretCipher::from_base64("not base64!").is_err());
+        assert!(SecretCipher::from_base64(&STANDARD.encode([0u8; 32])).is_ok());
+    }
+}
@@ -0,0 +1,21 @@
+use thiserror::Error;
+
+use super::stores::error::MfaStoreError;
+
+#[derive(Error, Debug)]
+pub enum MfaServiceError {
+    #[error("There was an error interacting with the data store: {0}")]
+    StoreError(#[from] MfaStoreError),
+    #[error("The MFA encryption key is invalid: {0}")]
+    InvalidEncryptionKey(String),
+    #[error("The MFA secret could not be encrypted or decrypted")]
+    EncryptionError,
+    #[error("Multi-factor authentication is already enabled for this account")]
+    MfaAlreadyEnabled,
+    #[error("There is no pending TOTP enrollment for this account")]
+    TotpNotEnrolled,
+    #[error("The code was incorrect")]
+    InvalidCode,
+    #[error("The MFA challenge is invalid or has expired")]
+    InvalidMfaChallenge,
+}
@@ -0,0 +1,61 @@
+use chrono::{DateTime, Utc};
+
+/// Represents an account's TOTP authenticator. A factor is created when the
+/// account holder starts enrollment, but isn't used to challenge sign-ins
+/// until it has been confirmed with a first code.
+#[derive(Debug, Clone)]
+pub struct TotpFactor {
+    /// ID of the account this factor belongs to.
+    pub account_id: String,
+    /// The shared secret, encrypted by the [SecretCipher](super::cipher::SecretCipher).
+    pub encrypted_secret: Vec<u8>,
+    /// When enrollment started.
+    pub created_at: DateTime<Utc>,
+    /// When enrollment was confirmed, if it has been.
+    pub confirmed_at: Option<DateTime<Utc>>,
+    /// The time step of the last code that was accepted, so that
+    /// codes can't be replayed.
+    pub last_used_step: Option<i64>,
+}
+
+impl TotpFactor {
+    pub fn confirmed(&self) -> bool {
+        self.confirmed_at.is_some()
+    }
+}
+
+/// The details an account holder needs to add a TOTP factor to their
+/// authenticator app. This is the only time the secret is available.
+#[derive(Debug, Clone)]
+pub struct TotpEnrollment {
+    /// The shared secret, base32-encoded, for manual entry.
+    pub secret: String,
+    /// The `otpauth://` URI to render as a QR code.
+    pub provisioning_uri: String,
+}
+
+/// Represents a stored MFA challenge: an account that has presented the
+/// correct password, but has yet to provide a second factor.
+#[derive(Debug, Clone)]
+pub struct MfaChallenge {
+    /// SHA-256 hash of the challenge handle.
+    pub token_hash: String,
+    /// ID of the account being signed in.
+    pub account_id: String,
+    /// When this challenge was created.
+    pub created_at: DateTime<Utc>,
+    /// When this challenge expires.
+    pub expires_at: DateTime<Utc>,
+    /// Number of incorrect codes presented for this challenge.
+    pub failed_attempts: i32,
+}
+
+/// A newly-created MFA challenge.
+#[derive(Debug, Clone)]
+pub struct IssuedMfaChallenge {
+    /// The challenge handle. Only a hash of this is stored, so this is
+    /// the only time the actual value is available.
+    pub handle: String,
+    /// When the challenge expires.
+    pub expires_at: DateTime<Utc>,
+}
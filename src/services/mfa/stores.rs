@@ -0,0 +1,35 @@
+pub mod error;
+#[cfg(test)]
+pub mod fake;
+pub mod postgres;
+
+use axum::async_trait;
+use error::MfaStoreError;
+
+use crate::services::mfa::models::{MfaChallenge, TotpFactor};
+
+#[async_trait]
+pub trait MfaStore: Send + Sync + 'static {
+    /// Inserts the TOTP factor, replacing any existing factor for the account.
+    async fn upsert_totp_factor(&self, factor: &TotpFactor) -> Result<(), MfaStoreError>;
+    async fn load_totp_factor(&self, account_id: &str)
+        -> Result<Option<TotpFactor>, MfaStoreError>;
+    async fn update_totp_factor(&self, factor: &TotpFactor) -> Result<(), MfaStoreError>;
+    /// Records that a code for the time step was accepted, but only if no code
+    /// for that step or a later one was accepted already. Returns false if one
+    /// was (or the factor doesn't exist), so concurrent requests can't both
+    /// use the same code.
+    async fn mark_totp_step_used(&self, account_id: &str, step: i64)
+        -> Result<bool, MfaStoreError>;
+    async fn delete_totp_factor(&self, account_id: &str) -> Result<(), MfaStoreError>;
+    async fn insert_challenge(&self, challenge: &MfaChallenge) -> Result<(), MfaStoreError>;
+    async fn load_challenge(&self, token_hash: &str)
+        -> Result<Option<MfaChallenge>, MfaStoreError>;
+    /// Atomically increments the challenge's failed attempts, returning the
+    /// new count, or None if the challenge no longer exists.
+    async fn increment_challenge_attempts(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<i32>, MfaStoreError>;
+    async fn delete_challenge(&self, token_hash: &str) -> Result<(), MfaStoreError>;
+}
@@ -0,0 +1,7 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MfaStoreError {
+    #[error("database error: {0}")]
+    DatabaseError(String),
+}
@@ -0,0 +1,121 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use axum::async_trait;
+
+use crate::services::mfa::models::{MfaChallenge, TotpFactor};
+
+use super::{error::MfaStoreError, MfaStore};
+
+/// The "database" for the FakeMfaStore.
+struct Database {
+    /// TOTP factors keyed by account ID.
+    totp_factors: HashMap<String, TotpFactor>,
+    /// MFA challenges keyed by token hash.
+    challenges: HashMap<String, MfaChallenge>,
+}
+
+/// A fake implementation of [MfaStore] that can be used in unit tests.
+pub struct FakeMfaStore {
+    db: Mutex<Database>,
+}
+
+impl FakeMfaStore {
+    pub fn new() -> FakeMfaStore {
+        FakeMfaStore {
+            db: Mutex::new(Database {
+                totp_factors: HashMap::new(),
+                challenges: HashMap::new(),
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl MfaStore for FakeMfaStore {
+    async fn upsert_totp_factor(&self, factor: &TotpFactor) -> Result<(), MfaStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .totp_factors
+            .insert(factor.account_id.clone(), factor.clone());
+        Ok(())
+    }
+
+    async fn load_totp_factor(
+        &self,
+        account_id: &str,
+    ) -> Result<Option<TotpFactor>, MfaStoreError> {
+        Ok(self
+            .db
+            .lock()
+            .unwrap()
+            .totp_factors
+            .get(account_id)
+            .cloned())
+    }
+
+    async fn update_totp_factor(&self, factor: &TotpFactor) -> Result<(), MfaStoreError> {
+        let mut db = self.db.lock().unwrap();
+        if let Some(existing) = db.totp_factors.get_mut(&factor.account_id) {
+            *existing = factor.clone();
+        }
+        Ok(())
+    }
+
+    async fn mark_totp_step_used(
+        &self,
+        account_id: &str,
+        step: i64,
+    ) -> Result<bool, MfaStoreError> {
+        let mut db = self.db.lock().unwrap();
+        match db.totp_factors.get_mut(account_id) {
+            Some(factor) if factor.last_used_step.is_none_or(|used| used < step) => {
+                factor.last_used_step = Some(step);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete_totp_factor(&self, account_id: &str) -> Result<(), MfaStoreError> {
+        self.db.lock().unwrap().totp_factors.remove(account_id);
+        Ok(())
+    }
+
+    async fn insert_challenge(&self, challenge: &MfaChallenge) -> Result<(), MfaStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .challenges
+            .insert(challenge.token_hash.clone(), challenge.clone());
+        Ok(())
+    }
+
+    async fn load_challenge(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<MfaChallenge>, MfaStoreError> {
+        Ok(self.db.lock().unwrap().challenges.get(token_hash).cloned())
+    }
+
+    async fn increment_challenge_attempts(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<i32>, MfaStoreError> {
+        Ok(self
+            .db
+            .lock()
+            .unwrap()
+            .challenges
+            .get_mut(token_hash)
+            .map(|challenge| {
+                challenge.failed_attempts += 1;
+                challenge.failed_attempts
+            }))
+    }
+
+    async fn delete_challenge(&self, token_hash: &str) -> Result<(), MfaStoreError> {
+        self.db.lock().unwrap().challenges.remove(token_hash);
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+//! Implements [MfaStore] backed by a PostgreSQL database
+
+use axum::async_trait;
+use sqlx::{postgres::PgRow, PgPool, Row};
+
+use crate::services::mfa::models::{MfaChallenge, TotpFactor};
+
+use super::{error::MfaStoreError, MfaStore};
+
+impl From<sqlx::Error> for MfaStoreError {
+    fn from(value: sqlx::Error) -> Self {
+        MfaStoreError::DatabaseError(value.to_string())
+    }
+}
+
+pub struct PostgresMfaStore {
+    pool: PgPool,
+}
+
+impl PostgresMfaStore {
+    pub fn new(pool: PgPool) -> PostgresMfaStore {
+        PostgresMfaStore { pool }
+    }
+}
+
+#[async_trait]
+impl MfaStore for PostgresMfaStore {
+    async fn upsert_totp_factor(&self, factor: &TotpFactor) -> Result<(), MfaStoreError> {
+        sqlx::query(
+            "insert into totp_factors(account_id,encrypted_secret,created_at,confirmed_at,last_used_step) \
+            values ($1,$2,$3,$4,$5) \
+            on conflict (account_id) do update set encrypted_secret=excluded.encrypted_secret, \
+            created_at=excluded.created_at, confirmed_at=excluded.confirmed_at, \
+            last_used_step=excluded.last_used_step",
+        )
+        .bind(&factor.account_id)
+        .bind(&factor.encrypted_secret)
+        .bind(factor.created_at)
+        .bind(factor.confirmed_at)
+        .bind(factor.last_used_step)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_totp_factor(
+        &self,
+        account_id: &str,
+    ) -> Result<Option<TotpFactor>, MfaStoreError> {
+        Ok(sqlx::query(
+            "select account_id,encrypted_secret,created_at,confirmed_at,last_used_step \
+            from totp_factors where account_id=$1",
+        )
+        .bind(account_id)
+        .map(|row: PgRow| TotpFactor {
+            account_id: row.get(0),
+            encrypted_secret: row.get(1),
+            created_at: row.get(2),
+            confirmed_at: row.get(3),
+            last_used_step: row.get(4),
+        })
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn update_totp_factor(&self, factor: &TotpFactor) -> Result<(), MfaStoreError> {
+        sqlx::query(
+            "update totp_factors set encrypted_secret=$1, confirmed_at=$2, last_used_step=$3 \
+            where account_id=$4",
+        )
+        .bind(&factor.encrypted_secret)
+        .bind(factor.confirmed_at)
+        .bind(factor.last_used_step)
+        .bind(&factor.account_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn mark_totp_step_used(
+        &self,
+        account_id: &str,
+        step: i64,
+    ) -> Result<bool, MfaStoreError> {
+        let result = sqlx::query(
+            "update totp_factors set last_used_step=$1 \
+            where account_id=$2 and (last_used_step is null or last_used_step < $1)",
+        )
+        .bind(step)
+        .bind(account_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_totp_factor(&self, account_id: &str) -> Result<(), MfaStoreError> {
+        sqlx::query("delete from totp_factors where account_id=$1")
+            .bind(account_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn insert_challenge(&self, challenge: &MfaChallenge) -> Result<(), MfaStoreError> {
+        sqlx::query(
+            "insert into mfa_challenges(token_hash,account_id,created_at,expires_at,failed_attempts) \
+            values ($1,$2,$3,$4,$5)",
+        )
+        .bind(&challenge.token_hash)
+        .bind(&challenge.account_id)
+        .bind(challenge.created_at)
+        .bind(challenge.expires_at)
+        .bind(challenge.failed_attempts)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_challenge(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<MfaChallenge>, MfaStoreError> {
+        Ok(sqlx::query(
+            "select token_hash,account_id,created_at,expires_at,failed_attempts \
+            from mfa_challenges where token_hash=$1",
+        )
+        .bind(token_hash)
+        .map(|row: PgRow| MfaChallenge {
+            token_hash: row.get(0),
+            account_id: row.get(1),
+            created_at: row.get(2),
+            expires_at: row.get(3),
+            failed_attempts: row.get(4),
+        })
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn increment_challenge_attempts(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<i32>, MfaStoreError> {
+        Ok(sqlx::query(
+            "update mfa_challenges set failed_attempts=failed_attempts+1 \
+            where token_hash=$1 returning failed_attempts",
+        )
+        .bind(token_hash)
+        .map(|row: PgRow| row.get(0))
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn delete_challenge(&self, token_hash: &str) -> Result<(), MfaStoreError> {
+        sqlx::query("delete from mfa_challenges where token_hash=$1")
+            .bind(token_hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+//! Time-based one-time passwords, as defined in RFC 6238. These use the
+//! default parameters (HMAC-SHA1, 6 digits, 30 second steps) since those
+//! are the only ones that all authenticator apps support.
+
+use chrono::{DateTime, Utc};
+use ring::hmac;
+use url::Url;
+
+/// Number of digits in each code.
+pub const DIGITS: u32 = 6;
+/// Number of seconds in each time step.
+pub const PERIOD_SECONDS: i64 = 30;
+/// Number of time steps before and after the current one for which codes
+/// are still accepted, to allow for clock drift on the user's device.
+const ALLOWED_DRIFT_STEPS: i64 = 1;
+/// The RFC 4648 base32 alphabet used to encode secrets.
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Returns the time step that contains the given time.
+pub fn time_step(time: DateTime<Utc>) -> i64 {
+    time.timestamp().div_euclid(PERIOD_SECONDS)
+}
+
+/// Generates the code for the given secret and time step (RFC 4226 HOTP).
+pub fn generate(secret: &[u8], step: i64) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let digest = hmac::sign(&key, &step.to_be_bytes());
+    let digest = digest.as_ref();
+    // dynamic truncation
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        value % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// Verifies a code against the secret at the given time, allowing for some
+/// clock drift. Codes for time steps at or before `last_used_step` are
+/// rejected so that each code can only be used once. Returns the time step
+/// that matched, if any.
+pub fn verify(
+    secret: &[u8],
+    code: &str,
+    now: DateTime<Utc>,
+    last_used_step: Option<i64>,
+) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let current = time_step(now);
+    (current - ALLOWED_DRIFT_STEPS..=current + ALLOWED_DRIFT_STEPS)
+        .filter(|step| last_used_step.is_none_or(|used| *step > used))
+        .find(|step| constant_time_eq(generate(secret, *step).as_bytes(), code.as_bytes()))
+}
+
+/// Compares the slices in constant time, so that response times don't
+/// reveal how many digits of a guessed code were correct.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Encodes the secret as unpadded base32, which is how authenticator apps
+/// expect it to be entered or embedded in a provisioning URI.
+pub fn encode_secret(secret: &[u8]) -> String {
+    let mut encoded = String::with_capacity((secret.len() * 8).div_ceil(5));
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in secret {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+/// Returns the `otpauth://` URI that authenticator apps use to add an
+/// account, typically by scanning it as a QR code.
+pub fn provisioning_uri(secret: &[u8], issuer: &str, account_name: &str) -> String {
+    let mut uri = Url::parse("otpauth://totp").expect("the base URI is valid");
+    uri.path_segments_mut()
+        .expect("the base URI has a path")
+        .push(&format!("{issuer}:{account_name}"));
+    uri.query_pairs_mut()
+        .append_pair("secret", &encode_secret(secret))
+        .append_pair("issuer", issuer)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &PERIOD_SECONDS.to_string());
+    uri.into()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /// The secret used by the test vectors in RFC 6238 appendix B.
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    /// Decodes a secret encoded by [encode_secret], as an authenticator app
+    /// would. Other tests use this to generate codes.
+    pub fn decode_secret(encoded: &str) -> Vec<u8> {
+        let mut secret = Vec::new();
+        let mut buffer: u32 = 0;
+        let mut bits = 0;
+        for c in encoded.bytes() {
+            let value = BASE32_ALPHABET.iter().position(|b| *b == c).unwrap();
+            buffer = (buffer << 5) | value as u32;
+            bits += 5;
+            if bits >= 8 {
+                bits -= 8;
+                secret.push((buffer >> bits) as u8);
+            }
+        }
+        secret
+    }
+
+    #[test]
+    fn rfc_6238_test_vectors() {
+        // the RFC lists 8 digit codes, so these are the last 6 digits
+        for (timestamp, code) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+        ] {
+            let time = Utc.timestamp_opt(timestamp, 0).unwrap();
+            assert_eq!(code, generate(RFC_SECRET, time_step(time)));
+        }
+    }
+
+    #[test]
+    fn verify_allows_drift() {
+        let now = Utc.timestamp_opt(1111111111, 0).unwrap();
+        let step = time_step(now);
+        for drift in -1..=1 {
+            let code = generate(RFC_SECRET, step + drift);
+            assert_eq!(Some(step + drift), verify(RFC_SECRET, &code, now, None));
+        }
+        for drift in [-2, 2] {
+            let code = generate(RFC_SECRET, step + drift);
+            assert_eq!(None, verify(RFC_SECRET, &code, now, None));
+        }
+        assert_eq!(None, verify(RFC_SECRET, "12345", now, None));
+        assert_eq!(None, verify(RFC_SECRET, "abcdef", now, None));
+    }
+
+    #[test]
+    fn verify_rejects_used_steps() {
+        let now = Utc.timestamp_opt(1111111111, 0).unwrap();
+        let step = time_step(now);
+        let code = generate(RFC_SECRET, step);
+        assert_eq!(None, verify(RFC_SECRET, &code, now, Some(step)));
+        let next = generate(RFC_SECRET, step + 1);
+        assert_eq!(Some(step + 1), verify(RFC_SECRET, &next, now, Some(step)));
+    }
+
+    #[test]
+    fn encode_secret_base32() {
+        // test vectors from RFC 4648 section 10
+        assert_eq!("", encode_secret(b""));
+        assert_eq!("MY", encode_secret(b"f"));
+        assert_eq!("MZXQ", encode_secret(b"fo"));
+        assert_eq!("MZXW6", encode_secret(b"foo"));
+        assert_eq!("MZXW6YQ", encode_secret(b"foob"));
+        assert_eq!("MZXW6YTB", encode_secret(b"fooba"));
+        assert_eq!("MZXW6YTBOI", encode_secret(b"foobar"));
+        assert_eq!(RFC_SECRET, decode_secret(&encode_secret(RFC_SECRET)));
+    }
+
+    #[test]
+    fn provisioning_uri_escapes_labels() {
+        assert_eq!(
+            "otpauth://totp/Identity%20Service:test@test.com?secret=MZXW6&issuer=Identity+Service&algorithm=SHA1&digits=6&period=30",
+            provisioning_uri(b"foo", "Identity Service", "test@test.com")
+        );
+    }
+}
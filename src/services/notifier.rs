@@ -0,0 +1,56 @@
+//! Defines the [Notifier] trait, which services use to send messages
+//! (e.g., emails) to account holders, along with its implementations.
+
+pub mod directory;
+pub mod error;
+#[cfg(test)]
+pub mod fake;
+pub mod smtp;
+
+use axum::async_trait;
+use error::NotifierError;
+use lettre::message::{header::ContentType, Mailbox};
+
+/// A message to send to an account holder.
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// Recipient address (e.g., an email address).
+    pub to: String,
+    /// Message subject.
+    pub subject: String,
+    /// Plain-text message body.
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync + 'static {
+    async fn send(&self, message: &Message) -> Result<(), NotifierError>;
+}
+
+/// Allows the implementation to be chosen at runtime
+/// (e.g., based on configuration).
+#[async_trait]
+impl Notifier for Box<dyn Notifier> {
+    async fn send(&self, message: &Message) -> Result<(), NotifierError> {
+        self.as_ref().send(message).await
+    }
+}
+
+/// Parses an email address, which may include a display name
+/// (e.g., `Identity Service <identity@example.com>`).
+fn parse_mailbox(address: &str) -> Result<Mailbox, NotifierError> {
+    address
+        .parse()
+        .map_err(|_| NotifierError::InvalidAddress(address.to_string()))
+}
+
+/// Builds a plain-text email from a [Message], for the notifiers that send email.
+fn build_email(from: &Mailbox, message: &Message) -> Result<lettre::Message, NotifierError> {
+    lettre::Message::builder()
+        .from(from.clone())
+        .to(parse_mailbox(&message.to)?)
+        .subject(&message.subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(message.body.clone())
+        .map_err(|e| NotifierError::InvalidMessage(e.to_string()))
+}
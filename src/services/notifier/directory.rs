@@ -0,0 +1,84 @@
+//! Implements [Notifier] by writing email to a local directory.
+
+use std::path::Path;
+
+use axum::async_trait;
+use lettre::{message::Mailbox, AsyncFileTransport, AsyncTransport, Tokio1Executor};
+
+use super::{build_email, error::NotifierError, parse_mailbox, Message, Notifier};
+
+/// A [Notifier] that writes each message as an `.eml` file in a directory
+/// instead of delivering it. This is meant for local development: the
+/// files can be opened with most email clients.
+pub struct DirectoryNotifier {
+    transport: AsyncFileTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl DirectoryNotifier {
+    /// Constructs a new [DirectoryNotifier] that writes email from the `from`
+    /// address to `directory`, which is created if it doesn't exist.
+    pub fn new(directory: &Path, from: &str) -> Result<DirectoryNotifier, NotifierError> {
+        std::fs::create_dir_all(directory)
+            .map_err(|e| NotifierError::InvalidConfiguration(e.to_string()))?;
+        Ok(DirectoryNotifier {
+            transport: AsyncFileTransport::new(directory),
+            from: parse_mailbox(from)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for DirectoryNotifier {
+    async fn send(&self, message: &Message) -> Result<(), NotifierError> {
+        let email = build_email(&self.from, message)?;
+        self.transport
+            .send(email)
+            .await
+            .map_err(|e| NotifierError::DeliveryError(e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn writes_eml_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("mail");
+        let notifier = DirectoryNotifier::new(&path, "identity@test.com").unwrap();
+        notifier
+            .send(&Message {
+                to: "test@test.com".to_string(),
+                subject: "Test subject".to_string(),
+                body: "Test body".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&path)
+            .unwrap()
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(1, files.len());
+        assert_eq!("eml", files[0].path().extension().unwrap());
+        let contents = std::fs::read_to_string(files[0].path()).unwrap();
+        assert!(contents.contains("To: test@test.com"));
+        assert!(contents.contains("Subject: Test subject"));
+        assert!(contents.contains("Test body"));
+
+        // an invalid recipient is reported rather than written
+        assert!(matches!(
+            notifier
+                .send(&Message {
+                    to: "invalid".to_string(),
+                    subject: "Test subject".to_string(),
+                    body: "Test body".to_string(),
+                })
+                .await,
+            Err(NotifierError::InvalidAddress(_))
+        ));
+    }
+}
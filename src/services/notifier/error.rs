@@ -0,0 +1,13 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum NotifierError {
+    #[error("'{0}' is not a valid address")]
+    InvalidAddress(String),
+    #[error("invalid message: {0}")]
+    InvalidMessage(String),
+    #[error("invalid notifier configuration: {0}")]
+    InvalidConfiguration(String),
+    #[error("error delivering message: {0}")]
+    DeliveryError(String),
+}
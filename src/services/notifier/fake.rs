@@ -0,0 +1,35 @@
+use std::sync::{Arc, Mutex};
+
+use axum::async_trait;
+
+use super::{error::NotifierError, Message, Notifier};
+
+/// A fake implementation of [Notifier] that can be used in unit tests.
+/// It records each message instead of sending it. Clones share the same
+/// list of messages, so a test can keep a clone of the notifier it passed
+/// to a service and inspect what was sent.
+#[derive(Clone)]
+pub struct FakeNotifier {
+    sent: Arc<Mutex<Vec<Message>>>,
+}
+
+impl FakeNotifier {
+    pub fn new() -> FakeNotifier {
+        FakeNotifier {
+            sent: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Returns all messages sent so far, oldest first.
+    pub fn sent_messages(&self) -> Vec<Message> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Notifier for FakeNotifier {
+    async fn send(&self, message: &Message) -> Result<(), NotifierError> {
+        self.sent.lock().unwrap().push(message.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,1608 @@
+use std::net::IpAddr;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{TimeDelta, Utc};
+use error::OAuthServiceError;
+use models::{
+    Authorization, AuthorizationCode, AuthorizationRedirect, AuthorizationRequest,
+    ClientAuthentication, ClientCredentialsExchange, ClientCredentialsGrant, CodeExchange,
+    IssuedSecret, NewOAuthClient, NewServiceAccount, OAuthClient, OAuthGrant, OAuthRefreshToken,
+    RefreshExchange, ServiceAccount, ServiceAccountSecret, TokenGrant,
+};
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
+use stores::{AuthorizationCodeStore, ClientStore, GrantStore};
+use url::{Host, Url};
+
+use super::{
+    account::{hashing::Argon2Hasher, id::ID, models::Password},
+    hash_token, random_token, Clock, SystemClock,
+};
+
+pub mod error;
+pub mod models;
+pub mod stores;
+
+/// How long an authorization code can be exchanged for tokens. Codes are
+/// exchanged by the client immediately after the redirect, so this is short.
+const AUTHORIZATION_CODE_TTL: TimeDelta = TimeDelta::minutes(5);
+/// How long a refresh token issued to a client remains valid. Each refresh
+/// issues a new token, so a client that keeps refreshing stays signed in.
+const REFRESH_TOKEN_TTL: TimeDelta = TimeDelta::days(30);
+/// How many secrets a service account can have at once: the current one,
+/// and the one it replaced, which keeps working until it's revoked or
+/// the secret is rotated again.
+const MAX_SERVICE_ACCOUNT_SECRETS: usize = 2;
+/// Maximum length of a client or service account name.
+const MAX_CLIENT_NAME_LENGTH: usize = 64;
+/// The only supported PKCE code challenge method. The `plain` method
+/// offers no protection if the authorization request is observed.
+const CODE_CHALLENGE_METHOD: &str = "S256";
+/// Length of an `S256` code challenge: a base64url-encoded SHA-256 hash.
+const CODE_CHALLENGE_LENGTH: usize = 43;
+/// Minimum and maximum length of a PKCE code verifier (RFC 7636 section 4.1).
+const MIN_CODE_VERIFIER_LENGTH: usize = 43;
+const MAX_CODE_VERIFIER_LENGTH: usize = 128;
+/// Maximum length of an OpenID Connect `nonce`.
+const MAX_NONCE_LENGTH: usize = 255;
+
+pub struct OAuthService<CS: ClientStore, AS: AuthorizationCodeStore, GS: GrantStore, C: Clock<Utc>>
+{
+    clients: CS,
+    codes: AS,
+    grants: GS,
+    login_url: Url,
+    hasher: Argon2Hasher,
+    clock: C,
+}
+
+impl<CS: ClientStore, AS: AuthorizationCodeStore, GS: GrantStore, C: Clock<Utc>>
+    OAuthService<CS, AS, GS, C>
+{
+    /// Constructs a new [OAuthService] given the stores and [Clock] to use. Account
+    /// holders are sent to the `login_url` to sign in and consent to a client's
+    /// access, with the parameters of the authorization request in its query.
+    /// Service account secrets are hashed with the `hasher`.
+    pub fn new_with_clock(
+        client_store: CS,
+        code_store: AS,
+        grant_store: GS,
+        login_url: Url,
+        hasher: Argon2Hasher,
+        clock: C,
+    ) -> Self {
+        Self {
+            clients: client_store,
+            codes: code_store,
+            grants: grant_store,
+            login_url,
+            hasher,
+            clock,
+        }
+    }
+
+    /// Registers a new client.
+    pub async fn create_client(
+        &self,
+        new_client: &NewOAuthClient,
+    ) -> Result<OAuthClient, OAuthServiceError> {
+        let name = check_name(&new_client.name)?;
+        if new_client.redirect_uris.is_empty() {
+            return Err(OAuthServiceError::InvalidClientMetadata(
+                "at least one redirect URI is required".to_string(),
+            ));
+        }
+        if let Some(uri) = new_client
+            .redirect_uris
+            .iter()
+            .find(|uri| !is_valid_redirect_uri(uri))
+        {
+            return Err(OAuthServiceError::InvalidClientMetadata(format!(
+                "'{}' is not a valid redirect URI",
+                uri
+            )));
+        }
+        check_scopes(&new_client.scopes)?;
+
+        let client = OAuthClient {
+            id: ID::Client.create(),
+            name,
+            redirect_uris: dedup(new_client.redirect_uris.iter().cloned()),
+            scopes: dedup(new_client.scopes.iter().cloned()),
+            created_at: self.clock.now(),
+        };
+        self.clients.insert_client(&client).await?;
+        Ok(client)
+    }
+
+    pub async fn get_client(&self, id: &str) -> Result<OAuthClient, OAuthServiceError> {
+        self.clients
+            .load_client(id)
+            .await?
+            .ok_or_else(|| OAuthServiceError::ClientNotFound(id.to_string()))
+    }
+
+    /// Deletes a client, along with its authorization codes, grants and refresh tokens.
+    pub async fn delete_client(&self, id: &str) -> Result<(), OAuthServiceError> {
+        if !self.clients.delete_client(id).await? {
+            return Err(OAuthServiceError::ClientNotFound(id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Checks the client and redirect URI of an authorization request. If these
+    /// aren't valid, the error must be shown to the account holder rather than
+    /// redirected, since the redirect URI might not belong to the client
+    /// (RFC 6749 section 4.1.2.1). Otherwise, the returned redirect is where any
+    /// further response to the request is sent.
+    pub async fn check_redirect(
+        &self,
+        request: &AuthorizationRequest,
+    ) -> Result<AuthorizationRedirect, OAuthServiceError> {
+        let client = match &request.client_id {
+            Some(client_id) => self.clients.load_client(client_id).await?,
+            None => None,
+        }
+        .ok_or_else(|| {
+            OAuthServiceError::InvalidRequest("client_id is missing or unknown".to_string())
+        })?;
+        let redirect_uri = request
+            .redirect_uri
+            .as_deref()
+            .filter(|uri| {
+                client
+                    .redirect_uris
+                    .iter()
+                    .any(|registered| redirect_uri_matches(registered, uri))
+            })
+            .and_then(|uri| Url::parse(uri).ok())
+            .ok_or(OAuthServiceError::InvalidRedirectUri)?;
+        Ok(AuthorizationRedirect {
+            client,
+            redirect_uri,
+            state: request.state.clone(),
+        })
+    }
+
+    /// Checks the rest of an authorization request, once [OAuthService::check_redirect]
+    /// has succeeded. Errors should be sent to the client using the redirect.
+    pub fn check_authorization(
+        &self,
+        redirect: &AuthorizationRedirect,
+        request: &AuthorizationRequest,
+    ) -> Result<Authorization, OAuthServiceError> {
+        match request.response_type.as_deref() {
+            Some("code") => {}
+            Some(response_type) => {
+                return Err(OAuthServiceError::UnsupportedResponseType(
+                    response_type.to_string(),
+                ))
+            }
+            None => {
+                return Err(OAuthServiceError::InvalidRequest(
+                    "response_type is required".to_string(),
+                ))
+            }
+        }
+
+        let code_challenge = request
+            .code_challenge
+            .as_deref()
+            .filter(|challenge| is_valid_code_challenge(challenge))
+            .ok_or_else(|| {
+                OAuthServiceError::InvalidRequest("a valid code_challenge is required".to_string())
+            })?;
+        if request.code_challenge_method.as_deref() != Some(CODE_CHALLENGE_METHOD) {
+            return Err(OAuthServiceError::InvalidRequest(format!(
+                "code_challenge_method must be {}",
+                CODE_CHALLENGE_METHOD
+            )));
+        }
+
+        let scopes = match &request.scope {
+            Some(scope) => parse_scope(scope, &redirect.client.scopes)?,
+            None => redirect.client.scopes.clone(),
+        };
+        if request
+            .nonce
+            .as_ref()
+            .is_some_and(|nonce| nonce.is_empty() || nonce.chars().count() > MAX_NONCE_LENGTH)
+        {
+            return Err(OAuthServiceError::InvalidRequest(format!(
+                "the nonce must be between 1 and {} characters",
+                MAX_NONCE_LENGTH
+            )));
+        }
+
+        Ok(Authorization {
+            client_id: redirect.client.id.clone(),
+            // kept exactly as requested, since the token request must match it
+            redirect_uri: request
+                .redirect_uri
+                .clone()
+                .unwrap_or_else(|| redirect.redirect_uri.to_string()),
+            scopes,
+            code_challenge: code_challenge.to_string(),
+            nonce: request.nonce.clone(),
+        })
+    }
+
+    /// Returns the URL of the login page for an authorization request,
+    /// which carries the request's parameters so that the page can
+    /// submit them along with the account holder's credentials.
+    pub fn login_redirect(&self, request: &AuthorizationRequest) -> String {
+        let mut url = self.login_url.clone();
+        {
+            let mut query = url.query_pairs_mut();
+            for (name, value) in [
+                ("response_type", &request.response_type),
+                ("client_id", &request.client_id),
+                ("redirect_uri", &request.redirect_uri),
+                ("scope", &request.scope),
+                ("state", &request.state),
+                ("code_challenge", &request.code_challenge),
+                ("code_challenge_method", &request.code_challenge_method),
+                ("nonce", &request.nonce),
+            ] {
+                if let Some(value) = value {
+                    query.append_pair(name, value);
+                }
+            }
+        }
+        url.to_string()
+    }
+
+    /// Returns true if the account holder hasn't yet consented
+    /// to all of the scopes in the authorization.
+    pub async fn needs_consent(
+        &self,
+        account_id: &str,
+        authorization: &Authorization,
+    ) -> Result<bool, OAuthServiceError> {
+        let grant = self
+            .grants
+            .load_grant(account_id, &authorization.client_id)
+            .await?;
+        Ok(!grant.is_some_and(|grant| {
+            authorization
+                .scopes
+                .iter()
+                .all(|scope| grant.scopes.contains(scope))
+        }))
+    }
+
+    /// Issues an authorization code for an account holder who has signed in.
+    /// If they haven't yet consented to all of the requested scopes, `consent`
+    /// must be true, and the grant is extended to cover them.
+    pub async fn authorize(
+        &self,
+        account_id: &str,
+        authorization: &Authorization,
+        consent: bool,
+    ) -> Result<String, OAuthServiceError> {
+        let now = self.clock.now();
+        let grant = self
+            .grants
+            .load_grant(account_id, &authorization.client_id)
+            .await?;
+        let granted = grant.as_ref().is_some_and(|grant| {
+            authorization
+                .scopes
+                .iter()
+                .all(|scope| grant.scopes.contains(scope))
+        });
+        if !granted {
+            if !consent {
+                return Err(OAuthServiceError::ConsentRequired);
+            }
+            let grant = match grant {
+                Some(grant) => OAuthGrant {
+                    scopes: dedup(grant.scopes.into_iter().chain(authorization.scopes.clone())),
+                    updated_at: now,
+                    ..grant
+                },
+                None => OAuthGrant {
+                    account_id: account_id.to_string(),
+                    client_id: authorization.client_id.clone(),
+                    scopes: authorization.scopes.clone(),
+                    created_at: now,
+                    updated_at: now,
+                },
+            };
+            self.grants.save_grant(&grant).await?;
+        }
+
+        self.codes.delete_codes_expired_before(now).await?;
+        let code = random_token();
+        self.codes
+            .insert_code(&AuthorizationCode {
+                code_hash: hash_token(&code),
+                client_id: authorization.client_id.clone(),
+                account_id: account_id.to_string(),
+                redirect_uri: authorization.redirect_uri.clone(),
+                scopes: authorization.scopes.clone(),
+                code_challenge: authorization.code_challenge.clone(),
+                nonce: authorization.nonce.clone(),
+                created_at: now,
+                expires_at: now + AUTHORIZATION_CODE_TTL,
+                used_at: None,
+            })
+            .await?;
+        Ok(code)
+    }
+
+    /// Exchanges an authorization code for a refresh token (RFC 6749 section 4.1.3),
+    /// returning what an access token should be issued for. Each code can be used
+    /// only once: if a code is presented again, it has likely been intercepted,
+    /// so the refresh tokens issued with it are revoked (RFC 6749 section 4.1.2).
+    pub async fn exchange_code(
+        &self,
+        exchange: &CodeExchange,
+    ) -> Result<TokenGrant, OAuthServiceError> {
+        self.clients
+            .load_client(&exchange.client_id)
+            .await?
+            .ok_or(OAuthServiceError::InvalidClient)?;
+        let code_hash = hash_token(&exchange.code);
+        let code = self
+            .codes
+            .load_code(&code_hash)
+            .await?
+            .filter(|code| code.client_id == exchange.client_id)
+            .ok_or(OAuthServiceError::InvalidGrant)?;
+
+        let now = self.clock.now();
+        if !self.codes.mark_code_used(&code_hash, now).await? {
+            tracing::warn!(
+                "Authorization code reuse detected; revoking refresh tokens of client {} for account {}",
+                code.client_id,
+                code.account_id
+            );
+            self.grants
+                .delete_refresh_tokens(&code.account_id, &code.client_id)
+                .await?;
+            return Err(OAuthServiceError::InvalidGrant);
+        }
+        if now >= code.expires_at
+            || code.redirect_uri != exchange.redirect_uri
+            || !verify_code_challenge(&code.code_challenge, &exchange.code_verifier)
+        {
+            return Err(OAuthServiceError::InvalidGrant);
+        }
+        // the account holder may have revoked the grant since the code was issued
+        self.grants
+            .load_grant(&code.account_id, &code.client_id)
+            .await?
+            .ok_or(OAuthServiceError::InvalidGrant)?;
+
+        let refresh_token = self
+            .issue_refresh_token(&code.account_id, &code.client_id, &code.scopes)
+            .await?;
+        Ok(TokenGrant {
+            account_id: code.account_id,
+            client_id: code.client_id,
+            scopes: code.scopes,
+            refresh_token,
+            nonce: code.nonce,
+        })
+    }
+
+    /// Exchanges a refresh token for a new one (RFC 6749 section 6), returning
+    /// what an access token should be issued for. Like session refresh tokens,
+    /// each can be used only once, and reuse revokes all of the refresh tokens
+    /// issued to the client for the account.
+    pub async fn refresh(
+        &self,
+        exchange: &RefreshExchange,
+    ) -> Result<TokenGrant, OAuthServiceError> {
+        self.clients
+            .load_client(&exchange.client_id)
+            .await?
+            .ok_or(OAuthServiceError::InvalidClient)?;
+        let token_hash = hash_token(&exchange.refresh_token);
+        let token = self
+            .grants
+            .load_refresh_token(&token_hash)
+            .await?
+            .filter(|token| token.client_id == exchange.client_id)
+            .ok_or(OAuthServiceError::InvalidGrant)?;
+
+        let now = self.clock.now();
+        if !self
+            .grants
+            .mark_refresh_token_used(&token_hash, now)
+            .await?
+        {
+            tracing::warn!(
+                "OAuth refresh token reuse detected; revoking refresh tokens of client {} for account {}",
+                token.client_id,
+                token.account_id
+            );
+            self.grants
+                .delete_refresh_tokens(&token.account_id, &token.client_id)
+                .await?;
+            return Err(OAuthServiceError::InvalidGrant);
+        }
+        if now >= token.expires_at {
+            return Err(OAuthServiceError::InvalidGrant);
+        }
+
+        // the client can ask for fewer scopes, but the new refresh token keeps them all
+        let scopes = match &exchange.scope {
+            Some(scope) => parse_scope(scope, &token.scopes)?,
+            None => token.scopes.clone(),
+        };
+        let refresh_token = self
+            .issue_refresh_token(&token.account_id, &token.client_id, &token.scopes)
+            .await?;
+        Ok(TokenGrant {
+            account_id: token.account_id,
+            client_id: token.client_id,
+            scopes,
+            refresh_token,
+            nonce: None,
+        })
+    }
+
+    /// Returns the account's grants, oldest first.
+    pub async fn list_grants(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<OAuthGrant>, OAuthServiceError> {
+        Ok(self.grants.load_grants(account_id).await?)
+    }
+
+    /// Revokes the account holder's consent for a client, along with the
+    /// refresh tokens issued to it. Access tokens remain valid until they expire.
+    pub async fn revoke_grant(
+        &self,
+        account_id: &str,
+        client_id: &str,
+    ) -> Result<(), OAuthServiceError> {
+        if !self.grants.delete_grant(account_id, client_id).await? {
+            return Err(OAuthServiceError::ClientNotFound(client_id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Deletes all of the account's grants (e.g., when the account is deleted).
+    pub async fn delete_grants(&self, account_id: &str) -> Result<(), OAuthServiceError> {
+        Ok(self.grants.delete_grants(account_id).await?)
+    }
+
+    /// Creates a new service account, along with its first secret.
+    pub async fn create_service_account(
+        &self,
+        new_service_account: &NewServiceAccount,
+    ) -> Result<(ServiceAccount, IssuedSecret), OAuthServiceError> {
+        let name = check_name(&new_service_account.name)?;
+        check_scopes(&new_service_account.scopes)?;
+        let service_account = ServiceAccount {
+            id: ID::Svc.create(),
+            name,
+            scopes: dedup(new_service_account.scopes.iter().cloned()),
+            created_at: self.clock.now(),
+        };
+        self.clients
+            .insert_service_account(&service_account)
+            .await?;
+        let secret = self.issue_secret(&service_account.id).await?;
+        Ok((service_account, secret))
+    }
+
+    pub async fn get_service_account(&self, id: &str) -> Result<ServiceAccount, OAuthServiceError> {
+        self.clients
+            .load_service_account(id)
+            .await?
+            .ok_or_else(|| OAuthServiceError::ClientNotFound(id.to_string()))
+    }
+
+    /// Deletes a service account and its secrets. Access tokens
+    /// already issued to it remain valid until they expire.
+    pub async fn delete_service_account(&self, id: &str) -> Result<(), OAuthServiceError> {
+        if !self.clients.delete_service_account(id).await? {
+            return Err(OAuthServiceError::ClientNotFound(id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Returns the service account's secrets (without the secrets themselves), oldest first.
+    pub async fn list_secrets(
+        &self,
+        id: &str,
+    ) -> Result<Vec<ServiceAccountSecret>, OAuthServiceError> {
+        self.get_service_account(id).await?;
+        Ok(self.clients.load_secrets(id).await?)
+    }
+
+    /// Issues a new secret for the service account. The previous secret keeps
+    /// working until it's revoked, so that the new one can be rolled out first,
+    /// but any older secrets are revoked, so that only two are active at once.
+    pub async fn rotate_secret(&self, id: &str) -> Result<IssuedSecret, OAuthServiceError> {
+        self.get_service_account(id).await?;
+        let issued = self.issue_secret(id).await?;
+        let secrets = self.clients.load_secrets(id).await?;
+        let excess = secrets.len().saturating_sub(MAX_SERVICE_ACCOUNT_SECRETS);
+        for secret in secrets
+            .iter()
+            .filter(|secret| secret.id != issued.id)
+            .take(excess)
+        {
+            self.clients.delete_secret(id, &secret.id).await?;
+        }
+        Ok(issued)
+    }
+
+    /// Revokes one of the service account's secrets (e.g., the
+    /// previous secret, once the new one has been rolled out).
+    pub async fn revoke_secret(&self, id: &str, secret_id: &str) -> Result<(), OAuthServiceError> {
+        self.get_service_account(id).await?;
+        if !self.clients.delete_secret(id, secret_id).await? {
+            return Err(OAuthServiceError::SecretNotFound(secret_id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Authenticates a service account using its client ID and one of its
+    /// secrets (RFC 6749 section 4.4), returning what an access token should
+    /// be issued for. No refresh token is issued, since the service can
+    /// authenticate again when the access token expires.
+    pub async fn exchange_client_credentials(
+        &self,
+        exchange: &ClientCredentialsExchange,
+    ) -> Result<ClientCredentialsGrant, OAuthServiceError> {
+        let service_account = self
+            .authenticate_service_account(&exchange.client_id, &exchange.client_secret)
+            .await?;
+        let scopes = match &exchange.scope {
+            Some(scope) => parse_scope(scope, &service_account.scopes)?,
+            None => service_account.scopes.clone(),
+        };
+        Ok(ClientCredentialsGrant {
+            service_account_id: service_account.id,
+            scopes,
+        })
+    }
+
+    /// Authenticates a client to the introspection or revocation endpoint:
+    /// service accounts by one of their secrets, and public OAuth clients,
+    /// which have no secret, just by their ID.
+    pub async fn authenticate_client(
+        &self,
+        authentication: &ClientAuthentication,
+    ) -> Result<(), OAuthServiceError> {
+        match &authentication.client_secret {
+            Some(client_secret) => {
+                self.authenticate_service_account(&authentication.client_id, client_secret)
+                    .await?;
+            }
+            None => {
+                self.clients
+                    .load_client(&authentication.client_id)
+                    .await?
+                    .ok_or(OAuthServiceError::InvalidClient)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Authenticates a service account using its ID and one of its secrets.
+    pub async fn authenticate_service_account(
+        &self,
+        client_id: &str,
+        client_secret: &Secret<Password>,
+    ) -> Result<ServiceAccount, OAuthServiceError> {
+        let Some(service_account) = self.clients.load_service_account(client_id).await? else {
+            // take as long as checking a real secret, so that callers
+            // can't tell which service accounts exist
+            self.hasher.verify_bogus();
+            return Err(OAuthServiceError::InvalidClient);
+        };
+        let secrets = self.clients.load_secrets(&service_account.id).await?;
+        if secrets.is_empty() {
+            self.hasher.verify_bogus();
+        }
+        if !secrets.iter().any(|secret| {
+            self.hasher
+                .verify(client_secret, &secret.secret_hash)
+                .is_ok()
+        }) {
+            return Err(OAuthServiceError::InvalidClient);
+        }
+        Ok(service_account)
+    }
+
+    /// Returns the stored refresh token, if it's active: it was issued by this
+    /// service, hasn't been used or revoked, and hasn't expired.
+    pub async fn introspect_refresh_token(
+        &self,
+        refresh_token: &str,
+    ) -> Result<Option<OAuthRefreshToken>, OAuthServiceError> {
+        let now = self.clock.now();
+        Ok(self
+            .grants
+            .load_refresh_token(&hash_token(refresh_token))
+            .await?
+            .filter(|token| token.used_at.is_none() && now < token.expires_at))
+    }
+
+    /// Revokes a refresh token issued to the client (RFC 7009), along with
+    /// the rest of the refresh tokens issued under the same grant, so that
+    /// the client needs a new authorization code to get more tokens. Returns
+    /// false, without revoking anything, if the token doesn't exist or was
+    /// issued to another client.
+    pub async fn revoke_refresh_token(
+        &self,
+        client_id: &str,
+        refresh_token: &str,
+    ) -> Result<bool, OAuthServiceError> {
+        let Some(token) = self
+            .grants
+            .load_refresh_token(&hash_token(refresh_token))
+            .await?
+            .filter(|token| token.client_id == client_id)
+        else {
+            return Ok(false);
+        };
+        self.grants
+            .delete_refresh_tokens(&token.account_id, &token.client_id)
+            .await?;
+        Ok(true)
+    }
+
+    /// Creates and stores a new secret for the service account,
+    /// returning the secret itself.
+    async fn issue_secret(
+        &self,
+        service_account_id: &str,
+    ) -> Result<IssuedSecret, OAuthServiceError> {
+        let secret = random_token();
+        let record = ServiceAccountSecret {
+            id: ID::Secret.create(),
+            service_account_id: service_account_id.to_string(),
+            secret_hash: self.hasher.hash(&Secret::new(Password::new(&secret)))?,
+            created_at: self.clock.now(),
+        };
+        self.clients.insert_secret(&record).await?;
+        Ok(IssuedSecret {
+            id: record.id,
+            secret,
+            created_at: record.created_at,
+        })
+    }
+
+    /// Creates and stores a new refresh token for the grant, returning the token value.
+    async fn issue_refresh_token(
+        &self,
+        account_id: &str,
+        client_id: &str,
+        scopes: &[String],
+    ) -> Result<String, OAuthServiceError> {
+        let now = self.clock.now();
+        let refresh_token = random_token();
+        self.grants
+            .insert_refresh_token(&OAuthRefreshToken {
+                token_hash: hash_token(&refresh_token),
+                account_id: account_id.to_string(),
+                client_id: client_id.to_string(),
+                scopes: scopes.to_vec(),
+                created_at: now,
+                expires_at: now + REFRESH_TOKEN_TTL,
+                used_at: None,
+            })
+            .await?;
+        Ok(refresh_token)
+    }
+}
+
+impl<CS: ClientStore, AS: AuthorizationCodeStore, GS: GrantStore>
+    OAuthService<CS, AS, GS, SystemClock<Utc>>
+{
+    pub fn new(
+        client_store: CS,
+        code_store: AS,
+        grant_store: GS,
+        login_url: Url,
+        hasher: Argon2Hasher,
+    ) -> Self {
+        Self::new_with_clock(
+            client_store,
+            code_store,
+            grant_store,
+            login_url,
+            hasher,
+            SystemClock::default(),
+        )
+    }
+}
+
+/// Returns the trimmed name of a client or service account, if it's valid.
+fn check_name(name: &str) -> Result<String, OAuthServiceError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_CLIENT_NAME_LENGTH {
+        return Err(OAuthServiceError::InvalidClientMetadata(format!(
+            "the name must be between 1 and {} characters",
+            MAX_CLIENT_NAME_LENGTH
+        )));
+    }
+    Ok(name.to_string())
+}
+
+/// Checks the scopes a client or service account is registered with.
+fn check_scopes(scopes: &[String]) -> Result<(), OAuthServiceError> {
+    if let Some(scope) = scopes.iter().find(|scope| !is_valid_scope_token(scope)) {
+        return Err(OAuthServiceError::InvalidClientMetadata(format!(
+            "'{}' is not a valid scope",
+            scope
+        )));
+    }
+    Ok(())
+}
+
+/// Returns true if the URI can be registered as a redirect URI. It must be
+/// absolute and have no fragment (RFC 6749 section 3.1.2). Web clients must
+/// use `https`, except on the loopback interface, and native apps can also
+/// use a private-use scheme based on a domain name they control (RFC 8252
+/// section 7.1), such as `com.example.app:/callback`.
+fn is_valid_redirect_uri(uri: &str) -> bool {
+    let Ok(url) = Url::parse(uri) else {
+        return false;
+    };
+    if url.fragment().is_some() || url.cannot_be_a_base() {
+        return false;
+    }
+    match url.scheme() {
+        "https" => true,
+        "http" => is_loopback(&url) || url.host() == Some(Host::Domain("localhost")),
+        scheme => scheme.contains('.'),
+    }
+}
+
+/// Returns true if the URL is an `http` URL on a loopback IP address.
+fn is_loopback(url: &Url) -> bool {
+    url.scheme() == "http"
+        && match url.host() {
+            Some(Host::Ipv4(ip)) => IpAddr::V4(ip).is_loopback(),
+            Some(Host::Ipv6(ip)) => IpAddr::V6(ip).is_loopback(),
+            _ => false,
+        }
+}
+
+/// Returns true if the redirect URI in an authorization request matches
+/// a registered one. They must be identical, except that native apps
+/// listening on a loopback IP address can use any port, since they get
+/// whichever one is free when they make the request (RFC 8252 section 7.3).
+fn redirect_uri_matches(registered: &str, requested: &str) -> bool {
+    if registered == requested {
+        return true;
+    }
+    match (Url::parse(registered), Url::parse(requested)) {
+        (Ok(mut registered), Ok(mut requested))
+            if is_loopback(&registered) && is_loopback(&requested) =>
+        {
+            // these can't fail for http URLs
+            let _ = registered.set_port(None);
+            let _ = requested.set_port(None);
+            registered == requested
+        }
+        _ => false,
+    }
+}
+
+/// Returns true if the value only uses the characters allowed in a scope
+/// (RFC 6749 section 3.3).
+fn is_valid_scope_token(scope: &str) -> bool {
+    !scope.is_empty()
+        && scope
+            .bytes()
+            .all(|b| b == 0x21 || (0x23..=0x5B).contains(&b) || (0x5D..=0x7E).contains(&b))
+}
+
+/// Parses a space-separated list of scopes, all of which must be allowed.
+fn parse_scope(scope: &str, allowed: &[String]) -> Result<Vec<String>, OAuthServiceError> {
+    let scopes = dedup(scope.split(' ').filter(|s| !s.is_empty()).map(String::from));
+    if let Some(invalid) = scopes.iter().find(|scope| !allowed.contains(scope)) {
+        return Err(OAuthServiceError::InvalidScope(invalid.clone()));
+    }
+    Ok(scopes)
+}
+
+/// Returns true if the value could be an `S256` code challenge.
+fn is_valid_code_challenge(challenge: &str) -> bool {
+    challenge.len() == CODE_CHALLENGE_LENGTH
+        && challenge
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Returns true if the PKCE code verifier is valid and
+/// matches the `S256` code challenge (RFC 7636 section 4.6).
+fn verify_code_challenge(challenge: &str, verifier: &str) -> bool {
+    (MIN_CODE_VERIFIER_LENGTH..=MAX_CODE_VERIFIER_LENGTH).contains(&verifier.len())
+        && verifier
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-._~".contains(&b))
+        && URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) == challenge
+}
+
+/// Removes duplicates, keeping the first occurrence of each value.
+fn dedup(values: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut unique: Vec<String> = Vec::new();
+    for value in values {
+        if !unique.contains(&value) {
+            unique.push(value);
+        }
+    }
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use stores::fake::{FakeAuthorizationCodeStore, FakeClientStore, FakeGrantStore};
+
+    use crate::services::TestClock;
+
+    use super::*;
+
+    type TestService =
+        OAuthService<FakeClientStore, FakeAuthorizationCodeStore, FakeGrantStore, TestClock<Utc>>;
+
+    const REDIRECT_URI: &str = "https://app.example.com/callback";
+    /// The code verifier and challenge from RFC 7636 appendix B.
+    const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const CODE_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+    fn test_service(clock: &TestClock<Utc>) -> TestService {
+        OAuthService::new_with_clock(
+            FakeClientStore::new(),
+            FakeAuthorizationCodeStore::new(),
+            FakeGrantStore::new(),
+            Url::parse("https://login.example.com/login").unwrap(),
+            Argon2Hasher::default(),
+            clock.clone(),
+        )
+    }
+
+    async fn create_client(service: &TestService) -> OAuthClient {
+        service
+            .create_client(&NewOAuthClient {
+                name: "Example App".to_string(),
+                redirect_uris: vec![
+                    REDIRECT_URI.to_string(),
+                    "http://127.0.0.1/callback".to_string(),
+                ],
+                scopes: vec!["profile".to_string(), "email".to_string()],
+            })
+            .await
+            .unwrap()
+    }
+
+    fn authorization_request(client: &OAuthClient) -> AuthorizationRequest {
+        AuthorizationRequest {
+            response_type: Some("code".to_string()),
+            client_id: Some(client.id.clone()),
+            redirect_uri: Some(REDIRECT_URI.to_string()),
+            scope: Some("profile".to_string()),
+            state: Some("xyz".to_string()),
+            code_challenge: Some(CODE_CHALLENGE.to_string()),
+            code_challenge_method: Some("S256".to_string()),
+            nonce: Some("n-0S6_WzA2Mj".to_string()),
+        }
+    }
+
+    async fn check(
+        service: &TestService,
+        request: &AuthorizationRequest,
+    ) -> Result<Authorization, OAuthServiceError> {
+        let redirect = service.check_redirect(request).await?;
+        service.check_authorization(&redirect, request)
+    }
+
+    /// Runs an authorization request for the client through to an
+    /// authorization code, consenting on the account holder's behalf.
+    async fn authorize(service: &TestService, client: &OAuthClient) -> String {
+        let authorization = check(service, &authorization_request(client))
+            .await
+            .unwrap();
+        service
+            .authorize("acct_test", &authorization, true)
+            .await
+            .unwrap()
+    }
+
+    fn code_exchange(client: &OAuthClient, code: &str) -> CodeExchange {
+        CodeExchange {
+            client_id: client.id.clone(),
+            code: code.to_string(),
+            redirect_uri: REDIRECT_URI.to_string(),
+            code_verifier: CODE_VERIFIER.to_string(),
+        }
+    }
+
+    fn refresh_exchange(client: &OAuthClient, refresh_token: &str) -> RefreshExchange {
+        RefreshExchange {
+            client_id: client.id.clone(),
+            refresh_token: refresh_token.to_string(),
+            scope: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn create_client_validates_metadata() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let client = create_client(&service).await;
+        assert!(client.id.starts_with("client_"));
+        assert_eq!(client, service.get_client(&client.id).await.unwrap());
+
+        for redirect_uri in [
+            "not a url",
+            "/relative/callback",
+            "https://app.example.com/callback#fragment",
+            "http://app.example.com/callback",
+            "myapp:/callback",
+            "mailto:someone@example.com",
+        ] {
+            let result = service
+                .create_client(&NewOAuthClient {
+                    name: "Example App".to_string(),
+                    redirect_uris: vec![redirect_uri.to_string()],
+                    scopes: vec![],
+                })
+                .await;
+            assert!(
+                matches!(result, Err(OAuthServiceError::InvalidClientMetadata(_))),
+                "{} should be rejected",
+                redirect_uri
+            );
+        }
+        for redirect_uri in [
+            "http://localhost:8080/callback",
+            "http://[::1]/callback",
+            "com.example.app:/callback",
+        ] {
+            service
+                .create_client(&NewOAuthClient {
+                    name: "Example App".to_string(),
+                    redirect_uris: vec![redirect_uri.to_string()],
+                    scopes: vec![],
+                })
+                .await
+                .unwrap();
+        }
+
+        let result = service
+            .create_client(&NewOAuthClient {
+                name: "Example App".to_string(),
+                redirect_uris: vec![REDIRECT_URI.to_string()],
+                scopes: vec!["bad\"scope".to_string()],
+            })
+            .await;
+        assert!(matches!(
+            result,
+            Err(OAuthServiceError::InvalidClientMetadata(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn redirect_uri_must_be_registered() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let client = create_client(&service).await;
+
+        for redirect_uri in [
+            None,
+            Some("https://app.example.com/callback/other"),
+            Some("https://evil.example.com/callback"),
+            Some("http://127.0.0.1/other"),
+        ] {
+            let request = AuthorizationRequest {
+                redirect_uri: redirect_uri.map(String::from),
+                ..authorization_request(&client)
+            };
+            assert!(matches!(
+                service.check_redirect(&request).await,
+                Err(OAuthServiceError::InvalidRedirectUri)
+            ));
+        }
+
+        // loopback redirect URIs can use any port
+        let request = AuthorizationRequest {
+            redirect_uri: Some("http://127.0.0.1:51004/callback".to_string()),
+            ..authorization_request(&client)
+        };
+        let redirect = service.check_redirect(&request).await.unwrap();
+        assert_eq!(
+            "http://127.0.0.1:51004/callback?code=abc&state=xyz",
+            redirect.with_code("abc")
+        );
+
+        let request = AuthorizationRequest {
+            client_id: Some("client_unknown".to_string()),
+            ..authorization_request(&client)
+        };
+        assert!(matches!(
+            service.check_redirect(&request).await,
+            Err(OAuthServiceError::InvalidRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn check_authorization_requires_pkce() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let client = create_client(&service).await;
+
+        let authorization = check(&service, &authorization_request(&client))
+            .await
+            .unwrap();
+        assert_eq!(vec!["profile"], authorization.scopes);
+        assert_eq!(CODE_CHALLENGE, authorization.code_challenge);
+
+        for (challenge, method) in [
+            (None, Some("S256")),
+            (Some(CODE_CHALLENGE), None),
+            (Some(CODE_CHALLENGE), Some("plain")),
+            (Some("too-short"), Some("S256")),
+        ] {
+            let request = AuthorizationRequest {
+                code_challenge: challenge.map(String::from),
+                code_challenge_method: method.map(String::from),
+                ..authorization_request(&client)
+            };
+            assert!(matches!(
+                check(&service, &request).await,
+                Err(OAuthServiceError::InvalidRequest(_))
+            ));
+        }
+
+        let request = AuthorizationRequest {
+            response_type: Some("token".to_string()),
+            ..authorization_request(&client)
+        };
+        assert!(matches!(
+            check(&service, &request).await,
+            Err(OAuthServiceError::UnsupportedResponseType(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn check_authorization_scopes() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let client = create_client(&service).await;
+
+        // omitting the scope requests all of the client's scopes
+        let request = AuthorizationRequest {
+            scope: None,
+            ..authorization_request(&client)
+        };
+        let authorization = check(&service, &request).await.unwrap();
+        assert_eq!(vec!["profile", "email"], authorization.scopes);
+
+        let request = AuthorizationRequest {
+            scope: Some("profile admin".to_string()),
+            ..authorization_request(&client)
+        };
+        assert!(matches!(
+            check(&service, &request).await,
+            Err(OAuthServiceError::InvalidScope(scope)) if scope == "admin"
+        ));
+    }
+
+    #[tokio::test]
+    async fn check_authorization_nonce() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let client = create_client(&service).await;
+
+        let authorization = check(&service, &authorization_request(&client))
+            .await
+            .unwrap();
+        assert_eq!(Some("n-0S6_WzA2Mj".to_string()), authorization.nonce);
+
+        for nonce in [String::new(), "n".repeat(MAX_NONCE_LENGTH + 1)] {
+            let request = AuthorizationRequest {
+                nonce: Some(nonce),
+                ..authorization_request(&client)
+            };
+            assert!(matches!(
+                check(&service, &request).await,
+                Err(OAuthServiceError::InvalidRequest(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn authorize_requires_consent() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let client = create_client(&service).await;
+        let authorization = check(&service, &authorization_request(&client))
+            .await
+            .unwrap();
+
+        assert!(service
+            .needs_consent("acct_test", &authorization)
+            .await
+            .unwrap());
+        assert!(matches!(
+            service.authorize("acct_test", &authorization, false).await,
+            Err(OAuthServiceError::ConsentRequired)
+        ));
+
+        service
+            .authorize("acct_test", &authorization, true)
+            .await
+            .unwrap();
+        assert!(!service
+            .needs_consent("acct_test", &authorization)
+            .await
+            .unwrap());
+        // consent is remembered
+        service
+            .authorize("acct_test", &authorization, false)
+            .await
+            .unwrap();
+
+        // requesting more scopes needs consent again, which extends the grant
+        let request = AuthorizationRequest {
+            scope: Some("email".to_string()),
+            ..authorization_request(&client)
+        };
+        let authorization = check(&service, &request).await.unwrap();
+        assert!(service
+            .needs_consent("acct_test", &authorization)
+            .await
+            .unwrap());
+        clock.advance(TimeDelta::minutes(1));
+        service
+            .authorize("acct_test", &authorization, true)
+            .await
+            .unwrap();
+        let grants = service.list_grants("acct_test").await.unwrap();
+        assert_eq!(1, grants.len());
+        assert_eq!(vec!["profile", "email"], grants[0].scopes);
+        assert_eq!(clock.now(), grants[0].updated_at);
+        assert!(grants[0].created_at < grants[0].updated_at);
+    }
+
+    #[tokio::test]
+    async fn exchange_code() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let client = create_client(&service).await;
+        let code = authorize(&service, &client).await;
+
+        let grant = service
+            .exchange_code(&code_exchange(&client, &code))
+            .await
+            .unwrap();
+        assert_eq!("acct_test", grant.account_id);
+        assert_eq!(client.id, grant.client_id);
+        assert_eq!(vec!["profile"], grant.scopes);
+        assert!(!grant.refresh_token.is_empty());
+        assert_eq!(Some("n-0S6_WzA2Mj".to_string()), grant.nonce);
+    }
+
+    #[tokio::test]
+    async fn exchange_code_verifies_request() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let client = create_client(&service).await;
+
+        // each failed attempt uses up the code, so issue a new one each time
+        for exchange in [
+            CodeExchange {
+                code_verifier: "wrong-verifier-wrong-verifier-wrong-verifier".to_string(),
+                ..code_exchange(&client, "")
+            },
+            CodeExchange {
+                redirect_uri: "http://127.0.0.1/callback".to_string(),
+                ..code_exchange(&client, "")
+            },
+        ] {
+            let code = authorize(&service, &client).await;
+            let result = service
+                .exchange_code(&CodeExchange { code, ..exchange })
+                .await;
+            assert!(matches!(result, Err(OAuthServiceError::InvalidGrant)));
+        }
+
+        let code = authorize(&service, &client).await;
+        let result = service
+            .exchange_code(&CodeExchange {
+                client_id: "client_unknown".to_string(),
+                ..code_exchange(&client, &code)
+            })
+            .await;
+        assert!(matches!(result, Err(OAuthServiceError::InvalidClient)));
+
+        let other_client = create_client(&service).await;
+        let result = service
+            .exchange_code(&code_exchange(&other_client, &code))
+            .await;
+        assert!(matches!(result, Err(OAuthServiceError::InvalidGrant)));
+
+        clock.advance(AUTHORIZATION_CODE_TTL);
+        let result = service.exchange_code(&code_exchange(&client, &code)).await;
+        assert!(matches!(result, Err(OAuthServiceError::InvalidGrant)));
+    }
+
+    #[tokio::test]
+    async fn exchange_code_reuse_revokes_tokens() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let client = create_client(&service).await;
+        let code = authorize(&service, &client).await;
+        let grant = service
+            .exchange_code(&code_exchange(&client, &code))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            service.exchange_code(&code_exchange(&client, &code)).await,
+            Err(OAuthServiceError::InvalidGrant)
+        ));
+        assert!(matches!(
+            service
+                .refresh(&refresh_exchange(&client, &grant.refresh_token))
+                .await,
+            Err(OAuthServiceError::InvalidGrant)
+        ));
+    }
+
+    #[tokio::test]
+    async fn refresh_rotates_token() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let client = create_client(&service).await;
+        let code = authorize(&service, &client).await;
+        let first = service
+            .exchange_code(&code_exchange(&client, &code))
+            .await
+            .unwrap();
+
+        clock.advance(TimeDelta::days(1));
+        let second = service
+            .refresh(&refresh_exchange(&client, &first.refresh_token))
+            .await
+            .unwrap();
+        assert_eq!(first.scopes, second.scopes);
+        assert_ne!(first.refresh_token, second.refresh_token);
+        // the nonce only belongs in the ID token issued for the authorization request
+        assert_eq!(None, second.nonce);
+
+        // reusing the first token revokes the second
+        assert!(matches!(
+            service
+                .refresh(&refresh_exchange(&client, &first.refresh_token))
+                .await,
+            Err(OAuthServiceError::InvalidGrant)
+        ));
+        assert!(matches!(
+            service
+                .refresh(&refresh_exchange(&client, &second.refresh_token))
+                .await,
+            Err(OAuthServiceError::InvalidGrant)
+        ));
+    }
+
+    #[tokio::test]
+    async fn refresh_scopes() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let client = create_client(&service).await;
+        let code = authorize(&service, &client).await;
+        let grant = service
+            .exchange_code(&code_exchange(&client, &code))
+            .await
+            .unwrap();
+
+        let result = service
+            .refresh(&RefreshExchange {
+                scope: Some("email".to_string()),
+                ..refresh_exchange(&client, &grant.refresh_token)
+            })
+            .await;
+        assert!(matches!(result, Err(OAuthServiceError::InvalidScope(_))));
+    }
+
+    #[tokio::test]
+    async fn revoke_grant() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let client = create_client(&service).await;
+        let code = authorize(&service, &client).await;
+        let grant = service
+            .exchange_code(&code_exchange(&client, &code))
+            .await
+            .unwrap();
+
+        service.revoke_grant("acct_test", &client.id).await.unwrap();
+        assert!(service.list_grants("acct_test").await.unwrap().is_empty());
+        assert!(matches!(
+            service
+                .refresh(&refresh_exchange(&client, &grant.refresh_token))
+                .await,
+            Err(OAuthServiceError::InvalidGrant)
+        ));
+        assert!(matches!(
+            service.revoke_grant("acct_test", &client.id).await,
+            Err(OAuthServiceError::ClientNotFound(_))
+        ));
+
+        // a code issued before the grant was revoked can't be exchanged
+        let code = authorize(&service, &client).await;
+        service.revoke_grant("acct_test", &client.id).await.unwrap();
+        assert!(matches!(
+            service.exchange_code(&code_exchange(&client, &code)).await,
+            Err(OAuthServiceError::InvalidGrant)
+        ));
+    }
+
+    fn client_credentials(
+        service_account: &ServiceAccount,
+        secret: &IssuedSecret,
+    ) -> ClientCredentialsExchange {
+        ClientCredentialsExchange {
+            client_id: service_account.id.clone(),
+            client_secret: Secret::new(Password::new(&secret.secret)),
+            scope: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn exchange_client_credentials() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let (service_account, secret) = service
+            .create_service_account(&NewServiceAccount {
+                name: " Nightly Export ".to_string(),
+                scopes: vec!["accounts:read".to_string(), "events:read".to_string()],
+            })
+            .await
+            .unwrap();
+        assert!(service_account.id.starts_with("svc_"));
+        assert_eq!("Nightly Export", service_account.name);
+
+        let grant = service
+            .exchange_client_credentials(&client_credentials(&service_account, &secret))
+            .await
+            .unwrap();
+        assert_eq!(service_account.id, grant.service_account_id);
+        assert_eq!(vec!["accounts:read", "events:read"], grant.scopes);
+
+        let exchange = ClientCredentialsExchange {
+            scope: Some("events:read".to_string()),
+            ..client_credentials(&service_account, &secret)
+        };
+        let grant = service
+            .exchange_client_credentials(&exchange)
+            .await
+            .unwrap();
+        assert_eq!(vec!["events:read"], grant.scopes);
+
+        let exchange = ClientCredentialsExchange {
+            scope: Some("accounts:write".to_string()),
+            ..client_credentials(&service_account, &secret)
+        };
+        assert!(matches!(
+            service.exchange_client_credentials(&exchange).await,
+            Err(OAuthServiceError::InvalidScope(_))
+        ));
+
+        for exchange in [
+            ClientCredentialsExchange {
+                client_secret: Secret::new(Password::new("wrong-secret")),
+                ..client_credentials(&service_account, &secret)
+            },
+            ClientCredentialsExchange {
+                client_id: "svc_unknown".to_string(),
+                ..client_credentials(&service_account, &secret)
+            },
+        ] {
+            assert!(matches!(
+                service.exchange_client_credentials(&exchange).await,
+                Err(OAuthServiceError::InvalidClient)
+            ));
+        }
+
+        let invalid = NewServiceAccount {
+            name: "Nightly Export".to_string(),
+            scopes: vec!["bad scope".to_string()],
+        };
+        assert!(matches!(
+            service.create_service_account(&invalid).await,
+            Err(OAuthServiceError::InvalidClientMetadata(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rotate_secret() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let (service_account, first) = service
+            .create_service_account(&NewServiceAccount {
+                name: "Nightly Export".to_string(),
+                scopes: vec![],
+            })
+            .await
+            .unwrap();
+
+        // both secrets work during the cutover
+        clock.advance(TimeDelta::minutes(1));
+        let second = service.rotate_secret(&service_account.id).await.unwrap();
+        assert_ne!(first.secret, second.secret);
+        for secret in [&first, &second] {
+            service
+                .exchange_client_credentials(&client_credentials(&service_account, secret))
+                .await
+                .unwrap();
+        }
+
+        // rotating again revokes the oldest secret
+        clock.advance(TimeDelta::minutes(1));
+        let third = service.rotate_secret(&service_account.id).await.unwrap();
+        let secrets = service.list_secrets(&service_account.id).await.unwrap();
+        assert_eq!(
+            vec![second.id.clone(), third.id.clone()],
+            secrets.into_iter().map(|s| s.id).collect::<Vec<_>>()
+        );
+        assert!(matches!(
+            service
+                .exchange_client_credentials(&client_credentials(&service_account, &first))
+                .await,
+            Err(OAuthServiceError::InvalidClient)
+        ));
+
+        // the previous secret can be revoked once the new one is rolled out
+        service
+            .revoke_secret(&service_account.id, &second.id)
+            .await
+            .unwrap();
+        assert!(matches!(
+            service
+                .exchange_client_credentials(&client_credentials(&service_account, &second))
+                .await,
+            Err(OAuthServiceError::InvalidClient)
+        ));
+        assert!(matches!(
+            service.revoke_secret(&service_account.id, &second.id).await,
+            Err(OAuthServiceError::SecretNotFound(_))
+        ));
+        service
+            .exchange_client_credentials(&client_credentials(&service_account, &third))
+            .await
+            .unwrap();
+
+        service
+            .delete_service_account(&service_account.id)
+            .await
+            .unwrap();
+        assert!(matches!(
+            service
+                .exchange_client_credentials(&client_credentials(&service_account, &third))
+                .await,
+            Err(OAuthServiceError::InvalidClient)
+        ));
+        assert!(matches!(
+            service.rotate_secret(&service_account.id).await,
+            Err(OAuthServiceError::ClientNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn authenticate_client() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let client = create_client(&service).await;
+        let (service_account, secret) = service
+            .create_service_account(&NewServiceAccount {
+                name: "Resource Server".to_string(),
+                scopes: vec![],
+            })
+            .await
+            .unwrap();
+
+        for authentication in [
+            ClientAuthentication {
+                client_id: client.id.clone(),
+                client_secret: None,
+            },
+            ClientAuthentication {
+                client_id: service_account.id.clone(),
+                client_secret: Some(Secret::new(Password::new(&secret.secret))),
+            },
+        ] {
+            service.authenticate_client(&authentication).await.unwrap();
+        }
+
+        // public clients have no secret, and service accounts must send theirs
+        for authentication in [
+            ClientAuthentication {
+                client_id: client.id.clone(),
+                client_secret: Some(Secret::new(Password::new(&secret.secret))),
+            },
+            ClientAuthentication {
+                client_id: service_account.id.clone(),
+                client_secret: None,
+            },
+            ClientAuthentication {
+                client_id: service_account.id.clone(),
+                client_secret: Some(Secret::new(Password::new("wrong-secret"))),
+            },
+            ClientAuthentication {
+                client_id: "client_unknown".to_string(),
+                client_secret: None,
+            },
+        ] {
+            assert!(matches!(
+                service.authenticate_client(&authentication).await,
+                Err(OAuthServiceError::InvalidClient)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn revoke_refresh_token() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let client = create_client(&service).await;
+        let code = authorize(&service, &client).await;
+        let grant = service
+            .exchange_code(&code_exchange(&client, &code))
+            .await
+            .unwrap();
+        let token = service
+            .introspect_refresh_token(&grant.refresh_token)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!("acct_test", token.account_id);
+        assert_eq!(client.id, token.client_id);
+
+        // other clients can't revoke the token
+        assert!(!service
+            .revoke_refresh_token("client_other", &grant.refresh_token)
+            .await
+            .unwrap());
+        assert!(service
+            .introspect_refresh_token(&grant.refresh_token)
+            .await
+            .unwrap()
+            .is_some());
+
+        assert!(service
+            .revoke_refresh_token(&client.id, &grant.refresh_token)
+            .await
+            .unwrap());
+        assert!(service
+            .introspect_refresh_token(&grant.refresh_token)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            service
+                .refresh(&refresh_exchange(&client, &grant.refresh_token))
+                .await,
+            Err(OAuthServiceError::InvalidGrant)
+        ));
+        assert!(!service
+            .revoke_refresh_token(&client.id, &grant.refresh_token)
+            .await
+            .unwrap());
+
+        // used and expired refresh tokens aren't active
+        let code = authorize(&service, &client).await;
+        let grant = service
+            .exchange_code(&code_exchange(&client, &code))
+            .await
+            .unwrap();
+        let refreshed = service
+            .refresh(&refresh_exchange(&client, &grant.refresh_token))
+            .await
+            .unwrap();
+        assert!(service
+            .introspect_refresh_token(&grant.refresh_token)
+            .await
+            .unwrap()
+            .is_none());
+        clock.advance(REFRESH_TOKEN_TTL);
+        assert!(service
+            .introspect_refresh_token(&refreshed.refresh_token)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,70 @@
+use thiserror::Error;
+
+use super::stores::error::OAuthStoreError;
+
+#[derive(Error, Debug)]
+pub enum OAuthServiceError {
+    #[error("There was an error interacting with the data store: {0}")]
+    StoreError(#[from] OAuthStoreError),
+    #[error("The client is unknown")]
+    InvalidClient,
+    #[error("OAuth client '{0}' was not found")]
+    ClientNotFound(String),
+    #[error("The redirect URI is missing or is not registered for the client")]
+    InvalidRedirectUri,
+    #[error("The client registration is invalid: {0}")]
+    InvalidClientMetadata(String),
+    #[error("The request is invalid: {0}")]
+    InvalidRequest(String),
+    #[error("The response type '{0}' is not supported")]
+    UnsupportedResponseType(String),
+    #[error("The grant type '{0}' is not supported")]
+    UnsupportedGrantType(String),
+    #[error("The scope '{0}' is invalid or is not allowed for the client")]
+    InvalidScope(String),
+    #[error("The account holder denied the request")]
+    AccessDenied,
+    #[error("The account holder must consent to the client's access")]
+    ConsentRequired,
+    #[error("The authorization code or refresh token is invalid, has expired, or was issued to another client")]
+    InvalidGrant,
+    #[error("Service account secret '{0}' was not found")]
+    SecretNotFound(String),
+    #[error("There was an error hashing the secret: {0}")]
+    HashingError(argon2::password_hash::errors::Error),
+    #[error("The access token is missing, invalid or has expired")]
+    InvalidToken,
+    #[error("The access token was not granted the '{0}' scope")]
+    InsufficientScope(String),
+}
+
+impl OAuthServiceError {
+    /// Returns the error code to send to the client, as registered in the
+    /// [OAuth Extensions Error Registry](https://www.iana.org/assignments/oauth-parameters/oauth-parameters.xhtml#extensions-error).
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            OAuthServiceError::StoreError(_) | OAuthServiceError::HashingError(_) => "server_error",
+            OAuthServiceError::InvalidClient | OAuthServiceError::ClientNotFound(_) => {
+                "invalid_client"
+            }
+            OAuthServiceError::InvalidRedirectUri => "invalid_redirect_uri",
+            OAuthServiceError::InvalidClientMetadata(_) => "invalid_client_metadata",
+            OAuthServiceError::InvalidRequest(_) => "invalid_request",
+            OAuthServiceError::UnsupportedResponseType(_) => "unsupported_response_type",
+            OAuthServiceError::UnsupportedGrantType(_) => "unsupported_grant_type",
+            OAuthServiceError::InvalidScope(_) => "invalid_scope",
+            OAuthServiceError::AccessDenied => "access_denied",
+            OAuthServiceError::ConsentRequired => "consent_required",
+            OAuthServiceError::InvalidGrant => "invalid_grant",
+            OAuthServiceError::SecretNotFound(_) => "invalid_request",
+            OAuthServiceError::InvalidToken => "invalid_token",
+            OAuthServiceError::InsufficientScope(_) => "insufficient_scope",
+        }
+    }
+}
+
+impl From<argon2::password_hash::errors::Error> for OAuthServiceError {
+    fn from(value: argon2::password_hash::errors::Error) -> Self {
+        OAuthServiceError::HashingError(value)
+    }
+}
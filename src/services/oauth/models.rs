@@ -0,0 +1,267 @@
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use url::Url;
+
+use crate::services::account::models::Password;
+
+use super::error::OAuthServiceError;
+
+/// Represents an application (e.g., a web or mobile app) registered to sign
+/// account holders in through the OAuth authorization code flow. Clients are
+/// public: they can't keep a secret, so they prove they started the flow
+/// using PKCE instead.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OAuthClient {
+    /// Unique ID, which the client sends as its `client_id`.
+    pub id: String,
+    /// Name shown to account holders when they're asked for consent.
+    pub name: String,
+    /// The URIs the client can be redirected back to. Authorization requests
+    /// must use one of these exactly (apart from the port of loopback URIs).
+    pub redirect_uris: Vec<String>,
+    /// The scopes the client can request.
+    pub scopes: Vec<String>,
+    /// When this client was registered.
+    pub created_at: DateTime<Utc>,
+}
+
+/// Represents a request to register a new [OAuthClient].
+#[derive(Debug, Clone)]
+pub struct NewOAuthClient {
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+}
+
+/// Represents a confidential client used by a backend job or service to
+/// authenticate as itself, rather than on behalf of an account holder,
+/// using the client credentials grant (RFC 6749 section 4.4).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceAccount {
+    /// Unique ID, which the service sends as its `client_id`.
+    pub id: String,
+    pub name: String,
+    /// The scopes the service can request.
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Represents a request to create a new [ServiceAccount].
+#[derive(Debug, Clone)]
+pub struct NewServiceAccount {
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+/// A secret that a [ServiceAccount] authenticates with. Only an argon2 hash
+/// is stored. A service account can have two secrets at once, so that a new
+/// secret can be rolled out before the old one stops working.
+#[derive(Debug, Clone)]
+pub struct ServiceAccountSecret {
+    /// Unique ID, so that the secret can be revoked.
+    pub id: String,
+    pub service_account_id: String,
+    /// Argon2 hash of the secret.
+    pub secret_hash: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A newly issued [ServiceAccountSecret], along with the secret itself,
+/// which can't be retrieved again.
+#[derive(Debug, Clone)]
+pub struct IssuedSecret {
+    pub id: String,
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Represents a request for an access token using the client credentials
+/// grant (RFC 6749 section 4.4.2).
+#[derive(Debug, Clone)]
+pub struct ClientCredentialsExchange {
+    pub client_id: String,
+    pub client_secret: Secret<Password>,
+    /// Space-separated list of scopes. Defaults to all of the
+    /// service account's scopes if omitted.
+    pub scope: Option<String>,
+}
+
+/// The credentials a client presents to the introspection and revocation
+/// endpoints. Public OAuth clients only have an ID, while service accounts
+/// also send one of their secrets.
+#[derive(Debug, Clone)]
+pub struct ClientAuthentication {
+    pub client_id: String,
+    pub client_secret: Option<Secret<Password>>,
+}
+
+/// The result of a successful client credentials token request:
+/// what an access token should be issued for.
+#[derive(Debug, Clone)]
+pub struct ClientCredentialsGrant {
+    pub service_account_id: String,
+    pub scopes: Vec<String>,
+}
+
+/// The query parameters of an authorization request (RFC 6749 section 4.1.1),
+/// along with the PKCE code challenge (RFC 7636 section 4.3).
+#[derive(Debug, Clone, Default)]
+pub struct AuthorizationRequest {
+    pub response_type: Option<String>,
+    pub client_id: Option<String>,
+    pub redirect_uri: Option<String>,
+    /// Space-separated list of requested scopes. Defaults to all of the
+    /// client's scopes if omitted.
+    pub scope: Option<String>,
+    /// Opaque value the client uses to tie the response to its request,
+    /// which is returned unchanged.
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    /// Opaque value that OpenID Connect clients use to tie the ID token
+    /// to their request, which is included in the ID token unchanged.
+    pub nonce: Option<String>,
+}
+
+/// Where to send the browser at the end of an authorization request, once the
+/// client and redirect URI have been checked. Any later error is sent to the
+/// client this way, rather than shown to the account holder.
+#[derive(Debug, Clone)]
+pub struct AuthorizationRedirect {
+    /// The client that made the request.
+    pub client: OAuthClient,
+    /// The redirect URI from the request, which the client has registered.
+    pub redirect_uri: Url,
+    /// The `state` from the request, which is returned to the client.
+    pub state: Option<String>,
+}
+
+impl AuthorizationRedirect {
+    /// Returns the URL that sends an authorization code back to the client.
+    pub fn with_code(&self, code: &str) -> String {
+        self.with_params(&[("code", code)])
+    }
+
+    /// Returns the URL that sends an error back to the client
+    /// (RFC 6749 section 4.1.2.1).
+    pub fn with_error(&self, error: &OAuthServiceError) -> String {
+        self.with_params(&[
+            ("error", error.error_code()),
+            ("error_description", &error.to_string()),
+        ])
+    }
+
+    fn with_params(&self, params: &[(&str, &str)]) -> String {
+        let mut url = self.redirect_uri.clone();
+        {
+            let mut query = url.query_pairs_mut();
+            query.extend_pairs(params);
+            if let Some(state) = &self.state {
+                query.append_pair("state", state);
+            }
+        }
+        url.to_string()
+    }
+}
+
+/// An authorization request that has been checked and can be granted
+/// once the account holder has signed in.
+#[derive(Debug, Clone)]
+pub struct Authorization {
+    pub client_id: String,
+    pub redirect_uri: String,
+    /// The scopes to grant.
+    pub scopes: Vec<String>,
+    /// The PKCE code challenge (always using the `S256` method).
+    pub code_challenge: String,
+    /// The `nonce` to include in the ID token.
+    pub nonce: Option<String>,
+}
+
+/// Represents an authorization code issued at the end of an authorization
+/// request, which the client exchanges for tokens. Only a hash of the code is
+/// stored, and it can only be exchanged once.
+#[derive(Debug, Clone)]
+pub struct AuthorizationCode {
+    /// SHA-256 hash of the code.
+    pub code_hash: String,
+    pub client_id: String,
+    /// The account that signed in and granted access.
+    pub account_id: String,
+    /// The redirect URI used in the authorization request, which
+    /// the client must present again when exchanging the code.
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    /// The PKCE code challenge, which the client's code verifier must match.
+    pub code_challenge: String,
+    /// The `nonce` from the authorization request, to include in the ID token.
+    pub nonce: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// When the code was exchanged, if it has been.
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+/// Records an account holder's consent for a client to access the account
+/// with a set of scopes. While the grant covers the scopes a client requests,
+/// the account holder isn't asked for consent again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OAuthGrant {
+    pub account_id: String,
+    pub client_id: String,
+    /// The scopes the account holder has consented to.
+    pub scopes: Vec<String>,
+    /// When consent was first given.
+    pub created_at: DateTime<Utc>,
+    /// When consent was last extended to more scopes.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A refresh token issued to a client under an [OAuthGrant]. Like session
+/// refresh tokens, each can be used only once, and only a hash is stored.
+#[derive(Debug, Clone)]
+pub struct OAuthRefreshToken {
+    /// SHA-256 hash of the token.
+    pub token_hash: String,
+    pub account_id: String,
+    pub client_id: String,
+    /// The scopes that access tokens issued with this token can have.
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// When the token was exchanged, if it has been.
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+/// Represents a request to exchange an authorization code for tokens
+/// (RFC 6749 section 4.1.3), along with the PKCE code verifier.
+#[derive(Debug, Clone)]
+pub struct CodeExchange {
+    pub client_id: String,
+    pub code: String,
+    pub redirect_uri: String,
+    pub code_verifier: String,
+}
+
+/// Represents a request to exchange a refresh token for new tokens
+/// (RFC 6749 section 6), optionally narrowing the scopes.
+#[derive(Debug, Clone)]
+pub struct RefreshExchange {
+    pub client_id: String,
+    pub refresh_token: String,
+    /// Space-separated list of scopes, which must all have been granted.
+    pub scope: Option<String>,
+}
+
+/// The result of a successful token request: what an access token should
+/// be issued for, along with a new refresh token.
+#[derive(Debug, Clone)]
+pub struct TokenGrant {
+    pub account_id: String,
+    pub client_id: String,
+    pub scopes: Vec<String>,
+    pub refresh_token: String,
+    /// The `nonce` to include in the ID token, if the grant came from an
+    /// authorization request that had one.
+    pub nonce: Option<String>,
+}
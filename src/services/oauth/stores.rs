@@ -0,0 +1,103 @@
+pub mod error;
+#[cfg(test)]
+pub mod fake;
+pub mod postgres;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use error::OAuthStoreError;
+
+use crate::services::oauth::models::{
+    AuthorizationCode, OAuthClient, OAuthGrant, OAuthRefreshToken, ServiceAccount,
+    ServiceAccountSecret,
+};
+
+#[async_trait]
+pub trait ClientStore: Send + Sync + 'static {
+    async fn insert_client(&self, client: &OAuthClient) -> Result<(), OAuthStoreError>;
+    async fn load_client(&self, id: &str) -> Result<Option<OAuthClient>, OAuthStoreError>;
+    /// Deletes the client. Returns false if it doesn't exist.
+    async fn delete_client(&self, id: &str) -> Result<bool, OAuthStoreError>;
+    async fn insert_service_account(
+        &self,
+        service_account: &ServiceAccount,
+    ) -> Result<(), OAuthStoreError>;
+    async fn load_service_account(
+        &self,
+        id: &str,
+    ) -> Result<Option<ServiceAccount>, OAuthStoreError>;
+    /// Deletes the service account and its secrets. Returns false if it doesn't exist.
+    async fn delete_service_account(&self, id: &str) -> Result<bool, OAuthStoreError>;
+    async fn insert_secret(&self, secret: &ServiceAccountSecret) -> Result<(), OAuthStoreError>;
+    /// Returns the service account's secrets, oldest first.
+    async fn load_secrets(
+        &self,
+        service_account_id: &str,
+    ) -> Result<Vec<ServiceAccountSecret>, OAuthStoreError>;
+    /// Deletes a secret. Returns false if it doesn't exist.
+    async fn delete_secret(
+        &self,
+        service_account_id: &str,
+        secret_id: &str,
+    ) -> Result<bool, OAuthStoreError>;
+}
+
+#[async_trait]
+pub trait AuthorizationCodeStore: Send + Sync + 'static {
+    async fn insert_code(&self, code: &AuthorizationCode) -> Result<(), OAuthStoreError>;
+    async fn load_code(
+        &self,
+        code_hash: &str,
+    ) -> Result<Option<AuthorizationCode>, OAuthStoreError>;
+    /// Marks the code as used, but only if it hasn't been used already.
+    /// Returns false if the code was already used (or doesn't exist).
+    async fn mark_code_used(
+        &self,
+        code_hash: &str,
+        used_at: DateTime<Utc>,
+    ) -> Result<bool, OAuthStoreError>;
+    /// Deletes codes that expired before the given time.
+    async fn delete_codes_expired_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<(), OAuthStoreError>;
+}
+
+#[async_trait]
+pub trait GrantStore: Send + Sync + 'static {
+    async fn load_grant(
+        &self,
+        account_id: &str,
+        client_id: &str,
+    ) -> Result<Option<OAuthGrant>, OAuthStoreError>;
+    /// Inserts the grant, or replaces the existing grant for the same account and client.
+    async fn save_grant(&self, grant: &OAuthGrant) -> Result<(), OAuthStoreError>;
+    /// Returns the account's grants, oldest first.
+    async fn load_grants(&self, account_id: &str) -> Result<Vec<OAuthGrant>, OAuthStoreError>;
+    /// Deletes the grant and all of its refresh tokens. Returns false if it doesn't exist.
+    async fn delete_grant(
+        &self,
+        account_id: &str,
+        client_id: &str,
+    ) -> Result<bool, OAuthStoreError>;
+    /// Deletes all of the account's grants, and all of their refresh tokens.
+    async fn delete_grants(&self, account_id: &str) -> Result<(), OAuthStoreError>;
+    async fn insert_refresh_token(&self, token: &OAuthRefreshToken) -> Result<(), OAuthStoreError>;
+    async fn load_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<OAuthRefreshToken>, OAuthStoreError>;
+    /// Marks the refresh token as used, but only if it hasn't been used already.
+    /// Returns false if the token was already used (or doesn't exist).
+    async fn mark_refresh_token_used(
+        &self,
+        token_hash: &str,
+        used_at: DateTime<Utc>,
+    ) -> Result<bool, OAuthStoreError>;
+    /// Deletes all refresh tokens issued under the account's grant to the client.
+    async fn delete_refresh_tokens(
+        &self,
+        account_id: &str,
+        client_id: &str,
+    ) -> Result<(), OAuthStoreError>;
+}
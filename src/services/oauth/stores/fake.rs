@@ -0,0 +1,313 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::services::oauth::models::{
+    AuthorizationCode, OAuthClient, OAuthGrant, OAuthRefreshToken, ServiceAccount,
+    ServiceAccountSecret,
+};
+
+use super::{error::OAuthStoreError, AuthorizationCodeStore, ClientStore, GrantStore};
+
+/// A fake implementation of [ClientStore] that can be used in unit tests.
+pub struct FakeClientStore {
+    /// Clients keyed by ID.
+    clients: Mutex<HashMap<String, OAuthClient>>,
+    /// Service accounts keyed by ID.
+    service_accounts: Mutex<HashMap<String, ServiceAccount>>,
+    /// Service account secrets, oldest first.
+    secrets: Mutex<Vec<ServiceAccountSecret>>,
+}
+
+impl FakeClientStore {
+    pub fn new() -> FakeClientStore {
+        FakeClientStore {
+            clients: Mutex::new(HashMap::new()),
+            service_accounts: Mutex::new(HashMap::new()),
+            secrets: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl ClientStore for FakeClientStore {
+    async fn insert_client(&self, client: &OAuthClient) -> Result<(), OAuthStoreError> {
+        self.clients
+            .lock()
+            .unwrap()
+            .insert(client.id.clone(), client.clone());
+        Ok(())
+    }
+
+    async fn load_client(&self, id: &str) -> Result<Option<OAuthClient>, OAuthStoreError> {
+        Ok(self.clients.lock().unwrap().get(id).cloned())
+    }
+
+    async fn delete_client(&self, id: &str) -> Result<bool, OAuthStoreError> {
+        Ok(self.clients.lock().unwrap().remove(id).is_some())
+    }
+
+    async fn insert_service_account(
+        &self,
+        service_account: &ServiceAccount,
+    ) -> Result<(), OAuthStoreError> {
+        self.service_accounts
+            .lock()
+            .unwrap()
+            .insert(service_account.id.clone(), service_account.clone());
+        Ok(())
+    }
+
+    async fn load_service_account(
+        &self,
+        id: &str,
+    ) -> Result<Option<ServiceAccount>, OAuthStoreError> {
+        Ok(self.service_accounts.lock().unwrap().get(id).cloned())
+    }
+
+    async fn delete_service_account(&self, id: &str) -> Result<bool, OAuthStoreError> {
+        self.secrets
+            .lock()
+            .unwrap()
+            .retain(|secret| secret.service_account_id != id);
+        Ok(self.service_accounts.lock().unwrap().remove(id).is_some())
+    }
+
+    async fn insert_secret(&self, secret: &ServiceAccountSecret) -> Result<(), OAuthStoreError> {
+        self.secrets.lock().unwrap().push(secret.clone());
+        Ok(())
+    }
+
+    async fn load_secrets(
+        &self,
+        service_account_id: &str,
+    ) -> Result<Vec<ServiceAccountSecret>, OAuthStoreError> {
+        Ok(self
+            .secrets
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|secret| secret.service_account_id == service_account_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_secret(
+        &self,
+        service_account_id: &str,
+        secret_id: &str,
+    ) -> Result<bool, OAuthStoreError> {
+        let mut secrets = self.secrets.lock().unwrap();
+        let len = secrets.len();
+        secrets.retain(|secret| {
+            secret.service_account_id != service_account_id || secret.id != secret_id
+        });
+        Ok(secrets.len() < len)
+    }
+}
+
+/// A fake implementation of [AuthorizationCodeStore] that can be used in unit tests.
+pub struct FakeAuthorizationCodeStore {
+    /// Authorization codes keyed by code hash.
+    codes: Mutex<HashMap<String, AuthorizationCode>>,
+}
+
+impl FakeAuthorizationCodeStore {
+    pub fn new() -> FakeAuthorizationCodeStore {
+        FakeAuthorizationCodeStore {
+            codes: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthorizationCodeStore for FakeAuthorizationCodeStore {
+    async fn insert_code(&self, code: &AuthorizationCode) -> Result<(), OAuthStoreError> {
+        self.codes
+            .lock()
+            .unwrap()
+            .insert(code.code_hash.clone(), code.clone());
+        Ok(())
+    }
+
+    async fn load_code(
+        &self,
+        code_hash: &str,
+    ) -> Result<Option<AuthorizationCode>, OAuthStoreError> {
+        Ok(self.codes.lock().unwrap().get(code_hash).cloned())
+    }
+
+    async fn mark_code_used(
+        &self,
+        code_hash: &str,
+        used_at: DateTime<Utc>,
+    ) -> Result<bool, OAuthStoreError> {
+        match self.codes.lock().unwrap().get_mut(code_hash) {
+            Some(code) if code.used_at.is_none() => {
+                code.used_at = Some(used_at);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete_codes_expired_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<(), OAuthStoreError> {
+        self.codes
+            .lock()
+            .unwrap()
+            .retain(|_, code| code.expires_at >= before);
+        Ok(())
+    }
+}
+
+/// The "database" for the FakeGrantStore.
+struct Database {
+    /// Grants keyed by account ID and client ID.
+    grants: HashMap<(String, String), OAuthGrant>,
+    /// Refresh tokens keyed by token hash.
+    refresh_tokens: HashMap<String, OAuthRefreshToken>,
+}
+
+impl Database {
+    /// Removes the refresh tokens belonging to grants that no longer exist,
+    /// like the `on delete cascade` foreign key in the real database.
+    fn cascade_deletes(&mut self) {
+        let grants = &self.grants;
+        self.refresh_tokens.retain(|_, token| {
+            grants.contains_key(&(token.account_id.clone(), token.client_id.clone()))
+        });
+    }
+}
+
+/// A fake implementation of [GrantStore] that can be used in unit tests.
+pub struct FakeGrantStore {
+    db: Mutex<Database>,
+}
+
+impl FakeGrantStore {
+    pub fn new() -> FakeGrantStore {
+        FakeGrantStore {
+            db: Mutex::new(Database {
+                grants: HashMap::new(),
+                refresh_tokens: HashMap::new(),
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl GrantStore for FakeGrantStore {
+    async fn load_grant(
+        &self,
+        account_id: &str,
+        client_id: &str,
+    ) -> Result<Option<OAuthGrant>, OAuthStoreError> {
+        Ok(self
+            .db
+            .lock()
+            .unwrap()
+            .grants
+            .get(&(account_id.to_string(), client_id.to_string()))
+            .cloned())
+    }
+
+    async fn save_grant(&self, grant: &OAuthGrant) -> Result<(), OAuthStoreError> {
+        self.db.lock().unwrap().grants.insert(
+            (grant.account_id.clone(), grant.client_id.clone()),
+            grant.clone(),
+        );
+        Ok(())
+    }
+
+    async fn load_grants(&self, account_id: &str) -> Result<Vec<OAuthGrant>, OAuthStoreError> {
+        let mut grants: Vec<OAuthGrant> = self
+            .db
+            .lock()
+            .unwrap()
+            .grants
+            .values()
+            .filter(|grant| grant.account_id == account_id)
+            .cloned()
+            .collect();
+        grants.sort_by(|a, b| {
+            a.created_at
+                .cmp(&b.created_at)
+                .then(a.client_id.cmp(&b.client_id))
+        });
+        Ok(grants)
+    }
+
+    async fn delete_grant(
+        &self,
+        account_id: &str,
+        client_id: &str,
+    ) -> Result<bool, OAuthStoreError> {
+        let mut db = self.db.lock().unwrap();
+        let deleted = db
+            .grants
+            .remove(&(account_id.to_string(), client_id.to_string()))
+            .is_some();
+        db.cascade_deletes();
+        Ok(deleted)
+    }
+
+    async fn delete_grants(&self, account_id: &str) -> Result<(), OAuthStoreError> {
+        let mut db = self.db.lock().unwrap();
+        db.grants.retain(|_, grant| grant.account_id != account_id);
+        db.cascade_deletes();
+        Ok(())
+    }
+
+    async fn insert_refresh_token(&self, token: &OAuthRefreshToken) -> Result<(), OAuthStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .refresh_tokens
+            .insert(token.token_hash.clone(), token.clone());
+        Ok(())
+    }
+
+    async fn load_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<OAuthRefreshToken>, OAuthStoreError> {
+        Ok(self
+            .db
+            .lock()
+            .unwrap()
+            .refresh_tokens
+            .get(token_hash)
+            .cloned())
+    }
+
+    async fn mark_refresh_token_used(
+        &self,
+        token_hash: &str,
+        used_at: DateTime<Utc>,
+    ) -> Result<bool, OAuthStoreError> {
+        match self.db.lock().unwrap().refresh_tokens.get_mut(token_hash) {
+            Some(token) if token.used_at.is_none() => {
+                token.used_at = Some(used_at);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete_refresh_tokens(
+        &self,
+        account_id: &str,
+        client_id: &str,
+    ) -> Result<(), OAuthStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .refresh_tokens
+            .retain(|_, token| token.account_id != account_id || token.client_id != client_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,423 @@
+//! Implements [ClientStore], [AuthorizationCodeStore] and [GrantStore]
+//! backed by a PostgreSQL database
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, PgPool, Row};
+
+use crate::services::oauth::models::{
+    AuthorizationCode, OAuthClient, OAuthGrant, OAuthRefreshToken, ServiceAccount,
+    ServiceAccountSecret,
+};
+
+use super::{error::OAuthStoreError, AuthorizationCodeStore, ClientStore, GrantStore};
+
+impl From<sqlx::Error> for OAuthStoreError {
+    fn from(value: sqlx::Error) -> Self {
+        OAuthStoreError::DatabaseError(value.to_string())
+    }
+}
+
+pub struct PostgresClientStore {
+    pool: PgPool,
+}
+
+impl PostgresClientStore {
+    pub fn new(pool: PgPool) -> PostgresClientStore {
+        PostgresClientStore { pool }
+    }
+}
+
+#[async_trait]
+impl ClientStore for PostgresClientStore {
+    async fn insert_client(&self, client: &OAuthClient) -> Result<(), OAuthStoreError> {
+        sqlx::query(
+            "insert into oauth_clients(id,name,redirect_uris,scopes,created_at) \
+            values ($1,$2,$3,$4,$5)",
+        )
+        .bind(&client.id)
+        .bind(&client.name)
+        .bind(&client.redirect_uris)
+        .bind(&client.scopes)
+        .bind(client.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_client(&self, id: &str) -> Result<Option<OAuthClient>, OAuthStoreError> {
+        Ok(sqlx::query(
+            "select id,name,redirect_uris,scopes,created_at \
+            from oauth_clients where id=$1",
+        )
+        .bind(id)
+        .map(|row: PgRow| OAuthClient {
+            id: row.get(0),
+            name: row.get(1),
+            redirect_uris: row.get(2),
+            scopes: row.get(3),
+            created_at: row.get(4),
+        })
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn delete_client(&self, id: &str) -> Result<bool, OAuthStoreError> {
+        let result = sqlx::query("delete from oauth_clients where id=$1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn insert_service_account(
+        &self,
+        service_account: &ServiceAccount,
+    ) -> Result<(), OAuthStoreError> {
+        sqlx::query(
+            "insert into service_accounts(id,name,scopes,created_at) \
+            values ($1,$2,$3,$4)",
+        )
+        .bind(&service_account.id)
+        .bind(&service_account.name)
+        .bind(&service_account.scopes)
+        .bind(service_account.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_service_account(
+        &self,
+        id: &str,
+    ) -> Result<Option<ServiceAccount>, OAuthStoreError> {
+        Ok(
+            sqlx::query("select id,name,scopes,created_at from service_accounts where id=$1")
+                .bind(id)
+                .map(|row: PgRow| ServiceAccount {
+                    id: row.get(0),
+                    name: row.get(1),
+                    scopes: row.get(2),
+                    created_at: row.get(3),
+                })
+                .fetch_optional(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn delete_service_account(&self, id: &str) -> Result<bool, OAuthStoreError> {
+        // secrets are deleted by the foreign key's cascade
+        let result = sqlx::query("delete from service_accounts where id=$1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn insert_secret(&self, secret: &ServiceAccountSecret) -> Result<(), OAuthStoreError> {
+        sqlx::query(
+            "insert into service_account_secrets(id,service_account_id,secret_hash,created_at) \
+            values ($1,$2,$3,$4)",
+        )
+        .bind(&secret.id)
+        .bind(&secret.service_account_id)
+        .bind(&secret.secret_hash)
+        .bind(secret.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_secrets(
+        &self,
+        service_account_id: &str,
+    ) -> Result<Vec<ServiceAccountSecret>, OAuthStoreError> {
+        Ok(sqlx::query(
+            "select id,service_account_id,secret_hash,created_at \
+            from service_account_secrets where service_account_id=$1 order by created_at, id",
+        )
+        .bind(service_account_id)
+        .map(|row: PgRow| ServiceAccountSecret {
+            id: row.get(0),
+            service_account_id: row.get(1),
+            secret_hash: row.get(2),
+            created_at: row.get(3),
+        })
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn delete_secret(
+        &self,
+        service_account_id: &str,
+        secret_id: &str,
+    ) -> Result<bool, OAuthStoreError> {
+        let result = sqlx::query(
+            "delete from service_account_secrets where service_account_id=$1 and id=$2",
+        )
+        .bind(service_account_id)
+        .bind(secret_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+pub struct PostgresAuthorizationCodeStore {
+    pool: PgPool,
+}
+
+impl PostgresAuthorizationCodeStore {
+    pub fn new(pool: PgPool) -> PostgresAuthorizationCodeStore {
+        PostgresAuthorizationCodeStore { pool }
+    }
+}
+
+#[async_trait]
+impl AuthorizationCodeStore for PostgresAuthorizationCodeStore {
+    async fn insert_code(&self, code: &AuthorizationCode) -> Result<(), OAuthStoreError> {
+        sqlx::query(
+            "insert into oauth_authorization_codes(code_hash,client_id,account_id,redirect_uri,\
+            scopes,code_challenge,nonce,created_at,expires_at,used_at) \
+            values ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10)",
+        )
+        .bind(&code.code_hash)
+        .bind(&code.client_id)
+        .bind(&code.account_id)
+        .bind(&code.redirect_uri)
+        .bind(&code.scopes)
+        .bind(&code.code_challenge)
+        .bind(&code.nonce)
+        .bind(code.created_at)
+        .bind(code.expires_at)
+        .bind(code.used_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_code(
+        &self,
+        code_hash: &str,
+    ) -> Result<Option<AuthorizationCode>, OAuthStoreError> {
+        Ok(sqlx::query(
+            "select code_hash,client_id,account_id,redirect_uri,scopes,code_challenge,\
+            nonce,created_at,expires_at,used_at \
+            from oauth_authorization_codes where code_hash=$1",
+        )
+        .bind(code_hash)
+        .map(|row: PgRow| AuthorizationCode {
+            code_hash: row.get(0),
+            client_id: row.get(1),
+            account_id: row.get(2),
+            redirect_uri: row.get(3),
+            scopes: row.get(4),
+            code_challenge: row.get(5),
+            nonce: row.get(6),
+            created_at: row.get(7),
+            expires_at: row.get(8),
+            used_at: row.get(9),
+        })
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn mark_code_used(
+        &self,
+        code_hash: &str,
+        used_at: DateTime<Utc>,
+    ) -> Result<bool, OAuthStoreError> {
+        let result = sqlx::query(
+            "update oauth_authorization_codes set used_at=$1 \
+            where code_hash=$2 and used_at is null",
+        )
+        .bind(used_at)
+        .bind(code_hash)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_codes_expired_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<(), OAuthStoreError> {
+        sqlx::query("delete from oauth_authorization_codes where expires_at < $1")
+            .bind(before)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+pub struct PostgresGrantStore {
+    pool: PgPool,
+}
+
+impl PostgresGrantStore {
+    pub fn new(pool: PgPool) -> PostgresGrantStore {
+        PostgresGrantStore { pool }
+    }
+}
+
+fn grant_from_row(row: PgRow) -> OAuthGrant {
+    OAuthGrant {
+        account_id: row.get(0),
+        client_id: row.get(1),
+        scopes: row.get(2),
+        created_at: row.get(3),
+        updated_at: row.get(4),
+    }
+}
+
+#[async_trait]
+impl GrantStore for PostgresGrantStore {
+    async fn load_grant(
+        &self,
+        account_id: &str,
+        client_id: &str,
+    ) -> Result<Option<OAuthGrant>, OAuthStoreError> {
+        Ok(sqlx::query(
+            "select account_id,client_id,scopes,created_at,updated_at \
+            from oauth_grants where account_id=$1 and client_id=$2",
+        )
+        .bind(account_id)
+        .bind(client_id)
+        .map(grant_from_row)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn save_grant(&self, grant: &OAuthGrant) -> Result<(), OAuthStoreError> {
+        sqlx::query(
+            "insert into oauth_grants(account_id,client_id,scopes,created_at,updated_at) \
+            values ($1,$2,$3,$4,$5) \
+            on conflict (account_id,client_id) do update \
+            set scopes=excluded.scopes, updated_at=excluded.updated_at",
+        )
+        .bind(&grant.account_id)
+        .bind(&grant.client_id)
+        .bind(&grant.scopes)
+        .bind(grant.created_at)
+        .bind(grant.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_grants(&self, account_id: &str) -> Result<Vec<OAuthGrant>, OAuthStoreError> {
+        Ok(sqlx::query(
+            "select account_id,client_id,scopes,created_at,updated_at \
+            from oauth_grants where account_id=$1 order by created_at, client_id",
+        )
+        .bind(account_id)
+        .map(grant_from_row)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn delete_grant(
+        &self,
+        account_id: &str,
+        client_id: &str,
+    ) -> Result<bool, OAuthStoreError> {
+        let result = sqlx::query("delete from oauth_grants where account_id=$1 and client_id=$2")
+            .bind(account_id)
+            .bind(client_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_grants(&self, account_id: &str) -> Result<(), OAuthStoreError> {
+        sqlx::query("delete from oauth_grants where account_id=$1")
+            .bind(account_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn insert_refresh_token(&self, token: &OAuthRefreshToken) -> Result<(), OAuthStoreError> {
+        sqlx::query(
+            "insert into oauth_refresh_tokens(token_hash,account_id,client_id,scopes,\
+            created_at,expires_at,used_at) \
+            values ($1,$2,$3,$4,$5,$6,$7)",
+        )
+        .bind(&token.token_hash)
+        .bind(&token.account_id)
+        .bind(&token.client_id)
+        .bind(&token.scopes)
+        .bind(token.created_at)
+        .bind(token.expires_at)
+        .bind(token.used_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<OAuthRefreshToken>, OAuthStoreError> {
+        Ok(sqlx::query(
+            "select token_hash,account_id,client_id,scopes,created_at,expires_at,used_at \
+            from oauth_refresh_tokens where token_hash=$1",
+        )
+        .bind(token_hash)
+        .map(|row: PgRow| OAuthRefreshToken {
+            token_hash: row.get(0),
+            account_id: row.get(1),
+            client_id: row.get(2),
+            scopes: row.get(3),
+            created_at: row.get(4),
+            expires_at: row.get(5),
+            used_at: row.get(6),
+        })
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn mark_refresh_token_used(
+        &self,
+        token_hash: &str,
+        used_at: DateTime<Utc>,
+    ) -> Result<bool, OAuthStoreError> {
+        let result = sqlx::query(
+            "update oauth_refresh_tokens set used_at=$1 \
+            where token_hash=$2 and used_at is null",
+        )
+        .bind(used_at)
+        .bind(token_hash)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_refresh_tokens(
+        &self,
+        account_id: &str,
+        client_id: &str,
+    ) -> Result<(), OAuthStoreError> {
+        sqlx::query("delete from oauth_refresh_tokens where account_id=$1 and client_id=$2")
+            .bind(account_id)
+            .bind(client_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,388 @@
+use chrono::{TimeDelta, Utc};
+use error::SessionServiceError;
+use models::{IssuedSession, RefreshToken, Session};
+use stores::SessionStore;
+
+use super::{hash_token, random_token, Clock, SystemClock};
+
+pub mod error;
+pub mod models;
+pub mod stores;
+
+/// How long a session remains valid without being used. Each successful
+/// validation pushes the expiration out by this amount (sliding expiration).
+const DEFAULT_IDLE_TIMEOUT: TimeDelta = TimeDelta::hours(24);
+/// The maximum lifetime of a session, regardless of how often it is used.
+const DEFAULT_ABSOLUTE_TIMEOUT: TimeDelta = TimeDelta::days(30);
+/// How recently a session must have been started to count as proof that the
+/// account holder just signed in (see [SessionService::verify_recent_session]).
+const RECENT_SESSION_AGE: TimeDelta = TimeDelta::minutes(5);
+
+pub struct SessionService<S: SessionStore, C: Clock<Utc>> {
+    store: S,
+    clock: C,
+    idle_timeout: TimeDelta,
+    absolute_timeout: TimeDelta,
+}
+
+impl<S: SessionStore, C: Clock<Utc>> SessionService<S, C> {
+    /// Constructs a new [SessionService] given the [SessionStore] and [Clock] to use.
+    pub fn new_with_clock(session_store: S, clock: C) -> Self {
+        Self {
+            store: session_store,
+            clock,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            absolute_timeout: DEFAULT_ABSOLUTE_TIMEOUT,
+        }
+    }
+
+    /// Creates and persists a new session for an account that has
+    /// already been authenticated, along with its first refresh token.
+    pub async fn create_session(
+        &self,
+        account_id: &str,
+    ) -> Result<IssuedSession, SessionServiceError> {
+        let now = self.clock.now();
+        let session = Session {
+            id: random_token(),
+            account_id: account_id.to_string(),
+            created_at: now,
+            expires_at: now + self.idle_timeout.min(self.absolute_timeout),
+        };
+        self.store.insert(&session).await?;
+        let refresh_token = self.issue_refresh_token(&session).await?;
+        Ok(IssuedSession {
+            session,
+            refresh_token,
+        })
+    }
+
+    /// Exchanges a refresh token for a new one, extending the session like
+    /// [SessionService::validate_session] does. Each refresh token can be used
+    /// only once: if a refresh token that was already used is presented again,
+    /// it has likely been stolen, so every session for the account, along with
+    /// all of their refresh tokens, is revoked.
+    pub async fn refresh_session(
+        &self,
+        refresh_token: &str,
+    ) -> Result<IssuedSession, SessionServiceError> {
+        let token_hash = hash_token(refresh_token);
+        let stored_token = self
+            .store
+            .load_refresh_token(&token_hash)
+            .await?
+            .ok_or(SessionServiceError::InvalidRefreshToken)?;
+
+        let now = self.clock.now();
+        if !self.store.mark_refresh_token_used(&token_hash, now).await? {
+            // the session is gone if the family was already revoked
+            if let Some(session) = self.store.load(&stored_token.session_id).await? {
+                tracing::warn!(
+                    "Refresh token reuse detected; revoking all sessions for account {}",
+                    session.account_id
+                );
+                self.revoke_all_sessions(&session.account_id).await?;
+            }
+            return Err(SessionServiceError::InvalidRefreshToken);
+        }
+        if now >= stored_token.expires_at {
+            return Err(SessionServiceError::InvalidRefreshToken);
+        }
+
+        let session = self
+            .validate_session(&stored_token.session_id)
+            .await
+            .map_err(|err| match err {
+                SessionServiceError::InvalidSession => SessionServiceError::InvalidRefreshToken,
+                _ => err,
+            })?;
+        let refresh_token = self.issue_refresh_token(&session).await?;
+        Ok(IssuedSession {
+            session,
+            refresh_token,
+        })
+    }
+
+    /// Validates that the session exists and has not expired, and returns it.
+    /// Validating a session also extends its expiration by the idle timeout,
+    /// though never beyond the absolute timeout measured from when the
+    /// session was created.
+    pub async fn validate_session(&self, id: &str) -> Result<Session, SessionServiceError> {
+        let session = self
+            .store
+            .load(id)
+            .await?
+            .ok_or(SessionServiceError::InvalidSession)?;
+
+        let now = self.clock.now();
+        if now >= session.expires_at {
+            self.store.delete(id).await?;
+            return Err(SessionServiceError::InvalidSession);
+        }
+
+        let expires_at = (now + self.idle_timeout).min(session.created_at + self.absolute_timeout);
+        self.store.update_expires_at(id, expires_at).await?;
+        Ok(Session {
+            expires_at,
+            ..session
+        })
+    }
+
+    /// Checks that the session belongs to the account, and was started within
+    /// the last [RECENT_SESSION_AGE], so the account holder has just proven who
+    /// they are (e.g., before disabling MFA). Unlike [SessionService::validate_session],
+    /// this doesn't extend the session's expiration.
+    pub async fn verify_recent_session(
+        &self,
+        id: &str,
+        account_id: &str,
+    ) -> Result<(), SessionServiceError> {
+        let now = self.clock.now();
+        match self.store.load(id).await? {
+            Some(session)
+                if session.account_id == account_id
+                    && now < session.expires_at
+                    && now - session.created_at < RECENT_SESSION_AGE =>
+            {
+                Ok(())
+            }
+            _ => Err(SessionServiceError::InvalidSession),
+        }
+    }
+
+    /// Revokes a single session (i.e., signs out). Revoking a session
+    /// that doesn't exist is not an error.
+    pub async fn revoke_session(&self, id: &str) -> Result<(), SessionServiceError> {
+        Ok(self.store.delete(id).await?)
+    }
+
+    /// Revokes all sessions for the given account (i.e., signs out everywhere).
+    pub async fn revoke_all_sessions(&self, account_id: &str) -> Result<(), SessionServiceError> {
+        Ok(self.store.delete_for_account(account_id).await?)
+    }
+
+    /// Creates and stores a new refresh token for the session, returning
+    /// the token value. The token expires when the session would reach its
+    /// absolute timeout.
+    async fn issue_refresh_token(&self, session: &Session) -> Result<String, SessionServiceError> {
+        let refresh_token = random_token();
+        self.store
+            .insert_refresh_token(&RefreshToken {
+                token_hash: hash_token(&refresh_token),
+                session_id: session.id.clone(),
+                created_at: self.clock.now(),
+                expires_at: session.created_at + self.absolute_timeout,
+                used_at: None,
+            })
+            .await?;
+        Ok(refresh_token)
+    }
+}
+
+impl<S: SessionStore> SessionService<S, SystemClock<Utc>> {
+    pub fn new(session_store: S) -> Self {
+        Self::new_with_clock(session_store, SystemClock::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use stores::fake::FakeSessionStore;
+
+    use crate::services::TestClock;
+
+    use super::*;
+
+    fn test_service(clock: &TestClock<Utc>) -> SessionService<FakeSessionStore, TestClock<Utc>> {
+        SessionService::new_with_clock(FakeSessionStore::new(), clock.clone())
+    }
+
+    #[tokio::test]
+    async fn create_session() {
+        let now = Utc::now();
+        let service = test_service(&TestClock::new(now));
+        let session = service.create_session("acct_test").await.unwrap().session;
+
+        assert_eq!("acct_test", session.account_id);
+        assert_eq!(now, session.created_at);
+        assert_eq!(now + DEFAULT_IDLE_TIMEOUT, session.expires_at);
+        assert!(!session.id.is_empty());
+
+        // every session must get a distinct ID
+        let another = service.create_session("acct_test").await.unwrap().session;
+        assert_ne!(session.id, another.id);
+    }
+
+    #[tokio::test]
+    async fn validate_session_slides_expiration() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let session = service.create_session("acct_test").await.unwrap().session;
+
+        // using the session just before it expires should keep it alive
+        clock.advance(DEFAULT_IDLE_TIMEOUT - TimeDelta::minutes(1));
+        let validated = service.validate_session(&session.id).await.unwrap();
+        assert_eq!(clock.now() + DEFAULT_IDLE_TIMEOUT, validated.expires_at);
+
+        clock.advance(DEFAULT_IDLE_TIMEOUT - TimeDelta::minutes(1));
+        service.validate_session(&session.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn validate_session_idle_timeout() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let session = service.create_session("acct_test").await.unwrap().session;
+
+        clock.advance(DEFAULT_IDLE_TIMEOUT);
+        assert!(matches!(
+            service.validate_session(&session.id).await,
+            Err(SessionServiceError::InvalidSession)
+        ));
+    }
+
+    #[tokio::test]
+    async fn validate_session_absolute_timeout() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let session = service.create_session("acct_test").await.unwrap().session;
+
+        // keep using the session often enough that it never idles out
+        let step = DEFAULT_IDLE_TIMEOUT - TimeDelta::minutes(1);
+        while clock.now() + step < session.created_at + DEFAULT_ABSOLUTE_TIMEOUT {
+            clock.advance(step);
+            let validated = service.validate_session(&session.id).await.unwrap();
+            assert!(validated.expires_at <= session.created_at + DEFAULT_ABSOLUTE_TIMEOUT);
+        }
+
+        clock.advance(step);
+        assert!(matches!(
+            service.validate_session(&session.id).await,
+            Err(SessionServiceError::InvalidSession)
+        ));
+    }
+
+    #[tokio::test]
+    async fn verify_recent_session() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let session = service.create_session("acct_test").await.unwrap().session;
+
+        service
+            .verify_recent_session(&session.id, "acct_test")
+            .await
+            .unwrap();
+        assert!(matches!(
+            service
+                .verify_recent_session(&session.id, "acct_other")
+                .await,
+            Err(SessionServiceError::InvalidSession)
+        ));
+        assert!(matches!(
+            service.verify_recent_session("invalid", "acct_test").await,
+            Err(SessionServiceError::InvalidSession)
+        ));
+
+        // the session is still valid, but no longer recent
+        clock.advance(RECENT_SESSION_AGE);
+        service.validate_session(&session.id).await.unwrap();
+        assert!(matches!(
+            service
+                .verify_recent_session(&session.id, "acct_test")
+                .await,
+            Err(SessionServiceError::InvalidSession)
+        ));
+    }
+
+    #[tokio::test]
+    async fn revoke_sessions() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let first = service.create_session("acct_test").await.unwrap().session;
+        let second = service.create_session("acct_test").await.unwrap().session;
+        let other = service.create_session("acct_other").await.unwrap().session;
+
+        service.revoke_session(&first.id).await.unwrap();
+        assert!(service.validate_session(&first.id).await.is_err());
+        assert!(service.validate_session(&second.id).await.is_ok());
+
+        service.revoke_all_sessions("acct_test").await.unwrap();
+        assert!(service.validate_session(&second.id).await.is_err());
+        assert!(service.validate_session(&other.id).await.is_ok());
+    }
+    #[tokio::test]
+    async fn refresh_session_rotates_token() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let issued = service.create_session("acct_test").await.unwrap();
+
+        clock.advance(TimeDelta::hours(1));
+        let refreshed = service
+            .refresh_session(&issued.refresh_token)
+            .await
+            .unwrap();
+        assert_eq!(issued.session.id, refreshed.session.id);
+        assert_ne!(issued.refresh_token, refreshed.refresh_token);
+        assert_eq!(
+            clock.now() + DEFAULT_IDLE_TIMEOUT,
+            refreshed.session.expires_at
+        );
+
+        // the new refresh token can be used in turn
+        service
+            .refresh_session(&refreshed.refresh_token)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn refresh_token_reuse_revokes_family() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let issued = service.create_session("acct_test").await.unwrap();
+        let other = service.create_session("acct_test").await.unwrap();
+        let unrelated = service.create_session("acct_other").await.unwrap();
+        let refreshed = service
+            .refresh_session(&issued.refresh_token)
+            .await
+            .unwrap();
+
+        // replaying the used token should fail and revoke the whole family
+        assert!(matches!(
+            service.refresh_session(&issued.refresh_token).await,
+            Err(SessionServiceError::InvalidRefreshToken)
+        ));
+        assert!(matches!(
+            service.refresh_session(&refreshed.refresh_token).await,
+            Err(SessionServiceError::InvalidRefreshToken)
+        ));
+        assert!(service.validate_session(&issued.session.id).await.is_err());
+
+        // so are the account's other sessions, but not other accounts' sessions
+        assert!(service.validate_session(&other.session.id).await.is_err());
+        assert!(matches!(
+            service.refresh_session(&other.refresh_token).await,
+            Err(SessionServiceError::InvalidRefreshToken)
+        ));
+        assert!(service
+            .validate_session(&unrelated.session.id)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn refresh_expired_session() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let issued = service.create_session("acct_test").await.unwrap();
+
+        clock.advance(DEFAULT_IDLE_TIMEOUT);
+        assert!(matches!(
+            service.refresh_session(&issued.refresh_token).await,
+            Err(SessionServiceError::InvalidRefreshToken)
+        ));
+        assert!(matches!(
+            service.refresh_session("invalid").await,
+            Err(SessionServiceError::InvalidRefreshToken)
+        ));
+    }
+}
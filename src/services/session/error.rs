@@ -0,0 +1,13 @@
+use thiserror::Error;
+
+use super::stores::error::SessionStoreError;
+
+#[derive(Error, Debug)]
+pub enum SessionServiceError {
+    #[error("There was an error interacting with the data store: {0}")]
+    StoreError(#[from] SessionStoreError),
+    #[error("The session does not exist or has expired")]
+    InvalidSession,
+    #[error("The refresh token is invalid or has expired")]
+    InvalidRefreshToken,
+}
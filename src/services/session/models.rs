@@ -0,0 +1,41 @@
+use chrono::{DateTime, Utc};
+
+/// Represents an authenticated session for an account.
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// Opaque, randomly-generated session ID.
+    pub id: String,
+    /// ID of the account that owns this session.
+    pub account_id: String,
+    /// When this session was created.
+    pub created_at: DateTime<Utc>,
+    /// When this session expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A newly-created or refreshed session, along with the refresh token that
+/// can later be exchanged for a new access token and refresh token.
+#[derive(Debug, Clone)]
+pub struct IssuedSession {
+    /// The session.
+    pub session: Session,
+    /// The refresh token. Only a hash of this is stored, so this is the
+    /// only time the actual value is available.
+    pub refresh_token: String,
+}
+
+/// Represents a stored refresh token. Each refresh token belongs to a session,
+/// and all refresh tokens issued for the same session form a token family.
+#[derive(Debug, Clone)]
+pub struct RefreshToken {
+    /// SHA-256 hash of the refresh token.
+    pub token_hash: String,
+    /// ID of the session this token belongs to.
+    pub session_id: String,
+    /// When this token was issued.
+    pub created_at: DateTime<Utc>,
+    /// When this token expires.
+    pub expires_at: DateTime<Utc>,
+    /// When this token was exchanged for a new one, if it has been.
+    pub used_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,37 @@
+pub mod error;
+#[cfg(test)]
+pub mod fake;
+pub mod postgres;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use error::SessionStoreError;
+
+use crate::services::session::models::{RefreshToken, Session};
+
+#[async_trait]
+pub trait SessionStore: Send + Sync + 'static {
+    async fn insert(&self, session: &Session) -> Result<(), SessionStoreError>;
+    async fn load(&self, id: &str) -> Result<Option<Session>, SessionStoreError>;
+    async fn update_expires_at(
+        &self,
+        id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), SessionStoreError>;
+    /// Deletes the session and all of its refresh tokens.
+    async fn delete(&self, id: &str) -> Result<(), SessionStoreError>;
+    /// Deletes all sessions for the account, and all of their refresh tokens.
+    async fn delete_for_account(&self, account_id: &str) -> Result<(), SessionStoreError>;
+    async fn insert_refresh_token(&self, token: &RefreshToken) -> Result<(), SessionStoreError>;
+    async fn load_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<RefreshToken>, SessionStoreError>;
+    /// Marks the refresh token as used, but only if it hasn't been used already.
+    /// Returns false if the token was already used (or doesn't exist).
+    async fn mark_refresh_token_used(
+        &self,
+        token_hash: &str,
+        used_at: DateTime<Utc>,
+    ) -> Result<bool, SessionStoreError>;
+}
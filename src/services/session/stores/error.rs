@@ -0,0 +1,7 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SessionStoreError {
+    #[error("database error: {0}")]
+    DatabaseError(String),
+}
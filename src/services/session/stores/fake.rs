@@ -0,0 +1,120 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::services::session::models::{RefreshToken, Session};
+
+use super::{error::SessionStoreError, SessionStore};
+
+/// The "database" for the FakeSessionStore.
+struct Database {
+    /// Sessions keyed by session ID.
+    sessions: HashMap<String, Session>,
+    /// Refresh tokens keyed by token hash.
+    refresh_tokens: HashMap<String, RefreshToken>,
+}
+
+impl Database {
+    /// Removes the refresh tokens belonging to sessions that no longer exist,
+    /// like the `on delete cascade` foreign key in the real database.
+    fn cascade_deletes(&mut self) {
+        let sessions = &self.sessions;
+        self.refresh_tokens
+            .retain(|_, token| sessions.contains_key(&token.session_id));
+    }
+}
+
+/// A fake implementation of [SessionStore] that can be used in unit tests.
+pub struct FakeSessionStore {
+    db: Mutex<Database>,
+}
+
+impl FakeSessionStore {
+    pub fn new() -> FakeSessionStore {
+        FakeSessionStore {
+            db: Mutex::new(Database {
+                sessions: HashMap::new(),
+                refresh_tokens: HashMap::new(),
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl SessionStore for FakeSessionStore {
+    async fn insert(&self, session: &Session) -> Result<(), SessionStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .sessions
+            .insert(session.id.clone(), session.clone());
+        Ok(())
+    }
+
+    async fn load(&self, id: &str) -> Result<Option<Session>, SessionStoreError> {
+        Ok(self.db.lock().unwrap().sessions.get(id).cloned())
+    }
+
+    async fn update_expires_at(
+        &self,
+        id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), SessionStoreError> {
+        if let Some(session) = self.db.lock().unwrap().sessions.get_mut(id) {
+            session.expires_at = expires_at;
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), SessionStoreError> {
+        let mut db = self.db.lock().unwrap();
+        db.sessions.remove(id);
+        db.cascade_deletes();
+        Ok(())
+    }
+
+    async fn delete_for_account(&self, account_id: &str) -> Result<(), SessionStoreError> {
+        let mut db = self.db.lock().unwrap();
+        db.sessions
+            .retain(|_, session| session.account_id != account_id);
+        db.cascade_deletes();
+        Ok(())
+    }
+
+    async fn insert_refresh_token(&self, token: &RefreshToken) -> Result<(), SessionStoreError> {
+        self.db
+            .lock()
+            .unwrap()
+            .refresh_tokens
+            .insert(token.token_hash.clone(), token.clone());
+        Ok(())
+    }
+
+    async fn load_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<RefreshToken>, SessionStoreError> {
+        Ok(self
+            .db
+            .lock()
+            .unwrap()
+            .refresh_tokens
+            .get(token_hash)
+            .cloned())
+    }
+
+    async fn mark_refresh_token_used(
+        &self,
+        token_hash: &str,
+        used_at: DateTime<Utc>,
+    ) -> Result<bool, SessionStoreError> {
+        match self.db.lock().unwrap().refresh_tokens.get_mut(token_hash) {
+            Some(token) if token.used_at.is_none() => {
+                token.used_at = Some(used_at);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Implements [SessionStore] backed by a PostgreSQL database
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, PgPool, Row};
+
+use crate::services::session::models::{RefreshToken, Session};
+
+use super::{error::SessionStoreError, SessionStore};
+
+impl From<sqlx::Error> for SessionStoreError {
+    fn from(value: sqlx::Error) -> Self {
+        SessionStoreError::DatabaseError(value.to_string())
+    }
+}
+
+pub struct PostgresSessionStore {
+    pool: PgPool,
+}
+
+impl PostgresSessionStore {
+    pub fn new(pool: PgPool) -> PostgresSessionStore {
+        PostgresSessionStore { pool }
+    }
+}
+
+#[async_trait]
+impl SessionStore for PostgresSessionStore {
+    async fn insert(&self, session: &Session) -> Result<(), SessionStoreError> {
+        sqlx::query(
+            "insert into sessions(id,account_id,created_at,expires_at) \
+            values ($1,$2,$3,$4)",
+        )
+        .bind(&session.id)
+        .bind(&session.account_id)
+        .bind(session.created_at)
+        .bind(session.expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+    async fn load(&self, id: &str) -> Result<Option<Session>, SessionStoreError> {
+        Ok(sqlx::query(
+            "select id,account_id,created_at,expires_at \
+            from sessions where id=$1",
+        )
+        .bind(id)
+        .map(|row: PgRow| Session {
+            id: row.get(0),
+            account_id: row.get(1),
+            created_at: row.get(2),
+            expires_at: row.get(3),
+        })
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn update_expires_at(
+        &self,
+        id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), SessionStoreError> {
+        sqlx::query("update sessions set expires_at=$1 where id=$2")
+            .bind(expires_at)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), SessionStoreError> {
+        sqlx::query("delete from sessions where id=$1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_for_account(&self, account_id: &str) -> Result<(), SessionStoreError> {
+        sqlx::query("delete from sessions where account_id=$1")
+            .bind(account_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+    async fn insert_refresh_token(&self, token: &RefreshToken) -> Result<(), SessionStoreError> {
+        sqlx::query(
+            "insert into refresh_tokens(token_hash,session_id,created_at,expires_at,used_at) \
+            values ($1,$2,$3,$4,$5)",
+        )
+        .bind(&token.token_hash)
+        .bind(&token.session_id)
+        .bind(token.created_at)
+        .bind(token.expires_at)
+        .bind(token.used_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<RefreshToken>, SessionStoreError> {
+        Ok(sqlx::query(
+            "select token_hash,session_id,created_at,expires_at,used_at \
+            from refresh_tokens where token_hash=$1",
+        )
+        .bind(token_hash)
+        .map(|row: PgRow| RefreshToken {
+            token_hash: row.get(0),
+            session_id: row.get(1),
+            created_at: row.get(2),
+            expires_at: row.get(3),
+            used_at: row.get(4),
+        })
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn mark_refresh_token_used(
+        &self,
+        token_hash: &str,
+        used_at: DateTime<Utc>,
+    ) -> Result<bool, SessionStoreError> {
+        let result = sqlx::query(
+            "update refresh_tokens set used_at=$1 where token_hash=$2 and used_at is null",
+        )
+        .bind(used_at)
+        .bind(token_hash)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
@@ -0,0 +1,811 @@
+use std::path::Path;
+
+use argon2::password_hash::rand_core::OsRng;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, TimeDelta, Utc};
+use ed25519_dalek::{pkcs8::DecodePrivateKey, Signature, Signer, SigningKey, VerifyingKey};
+use error::TokenServiceError;
+use models::{
+    AccessToken, AccessTokenClaims, IdTokenClaims, IdentityClaims, PublicKey, RevokedToken,
+    SigningKeyRecord,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use stores::{RevokedTokenStore, SigningKeyStore};
+
+use super::{account::models::Account, hash_token, Clock, SystemClock};
+
+pub mod error;
+pub mod models;
+pub mod stores;
+
+/// The JWS algorithm used to sign tokens. Only Ed25519 keys are
+/// currently supported, as they are small, fast, and have no
+/// parameter choices to get wrong.
+pub const SIGNING_ALGORITHM: &str = "EdDSA";
+/// The `typ` header of access tokens (RFC 9068 section 2.1), which
+/// stops other tokens signed by this service being used as them.
+const ACCESS_TOKEN_TYPE: &str = "at+jwt";
+/// The `typ` header of ID tokens.
+const ID_TOKEN_TYPE: &str = "JWT";
+/// How long an access token or ID token remains valid. This is also how
+/// long a retired signing key continues to be published after rotation,
+/// so that tokens it signed can still be verified until they expire.
+const DEFAULT_ACCESS_TOKEN_TTL: TimeDelta = TimeDelta::minutes(15);
+/// How long a signing key is used before [TokenService::rotate_keys_if_due]
+/// replaces it with a new one.
+const DEFAULT_ROTATION_INTERVAL: TimeDelta = TimeDelta::days(30);
+
+/// The JOSE header of a signed token.
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    alg: String,
+    typ: String,
+    kid: String,
+}
+
+/// Mints signed access tokens (JWTs) that downstream services can verify
+/// offline using the public keys returned from [TokenService::public_keys].
+/// Signing keys are kept in a key ring persisted by the [SigningKeyStore],
+/// so all instances of this service share the same keys. Access tokens revoked
+/// before they expire are recorded in the [RevokedTokenStore], which
+/// [TokenService::verify_access_token] consults.
+pub struct TokenService<S: SigningKeyStore, R: RevokedTokenStore, C: Clock<Utc>> {
+    store: S,
+    revoked_tokens: R,
+    issuer: String,
+    clock: C,
+    access_token_ttl: TimeDelta,
+    rotation_interval: TimeDelta,
+}
+
+impl<S: SigningKeyStore, R: RevokedTokenStore, C: Clock<Utc>> TokenService<S, R, C> {
+    /// Constructs a new [TokenService] given the [SigningKeyStore], [RevokedTokenStore]
+    /// and [Clock] to use. The `issuer` is the URL this service is reached at, which
+    /// identifies it in ID tokens (e.g., `https://id.example.com`).
+    pub fn new_with_clock(
+        signing_key_store: S,
+        revoked_token_store: R,
+        issuer: &str,
+        clock: C,
+    ) -> Self {
+        Self {
+            store: signing_key_store,
+            revoked_tokens: revoked_token_store,
+            issuer: issuer.trim_end_matches('/').to_string(),
+            clock,
+            access_token_ttl: DEFAULT_ACCESS_TOKEN_TTL,
+            rotation_interval: DEFAULT_ROTATION_INTERVAL,
+        }
+    }
+
+    /// Returns the issuer identifier of this service, without a trailing slash.
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// Returns how long newly issued access tokens remain valid.
+    pub fn access_token_ttl(&self) -> TimeDelta {
+        self.access_token_ttl
+    }
+
+    /// Issues a signed access token for an account that has already been authenticated.
+    pub async fn issue_access_token(
+        &self,
+        account: &Account,
+    ) -> Result<AccessToken, TokenServiceError> {
+        self.issue(account, None, None).await
+    }
+
+    /// Issues a signed access token to an OAuth client, which
+    /// can access the account with the granted scopes.
+    pub async fn issue_client_access_token(
+        &self,
+        account: &Account,
+        client_id: &str,
+        scopes: &[String],
+    ) -> Result<AccessToken, TokenServiceError> {
+        self.issue(account, Some(client_id.to_string()), Some(scopes.join(" ")))
+            .await
+    }
+
+    async fn issue(
+        &self,
+        account: &Account,
+        client_id: Option<String>,
+        scope: Option<String>,
+    ) -> Result<AccessToken, TokenServiceError> {
+        let now = self.clock.now();
+        let expires_at = now + self.access_token_ttl;
+        let claims = AccessTokenClaims {
+            sub: account.id.clone(),
+            email: Some(account.email.clone()),
+            email_verified: Some(account.email_verified()),
+            name: account.display_name.clone(),
+            iat: now.timestamp(),
+            exp: expires_at.timestamp(),
+            client_id,
+            scope,
+        };
+        self.sign_access_token(&claims, expires_at).await
+    }
+
+    /// Issues a signed access token to a service account, which is both
+    /// the subject and the client, with the granted scopes.
+    pub async fn issue_service_access_token(
+        &self,
+        service_account_id: &str,
+        scopes: &[String],
+    ) -> Result<AccessToken, TokenServiceError> {
+        let now = self.clock.now();
+        let expires_at = now + self.access_token_ttl;
+        let claims = AccessTokenClaims {
+            sub: service_account_id.to_string(),
+            email: None,
+            email_verified: None,
+            name: None,
+            iat: now.timestamp(),
+            exp: expires_at.timestamp(),
+            client_id: Some(service_account_id.to_string()),
+            scope: Some(scopes.join(" ")),
+        };
+        self.sign_access_token(&claims, expires_at).await
+    }
+
+    async fn sign_access_token(
+        &self,
+        claims: &AccessTokenClaims,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AccessToken, TokenServiceError> {
+        let signing_key = self.active_key().await?;
+        Ok(AccessToken {
+            token: Self::sign(&signing_key, ACCESS_TOKEN_TYPE, claims)?,
+            expires_at,
+        })
+    }
+
+    /// Issues a signed ID token to an OpenID Connect client, containing the claims
+    /// about the account covered by the granted scopes, and the `nonce` from the
+    /// authorization request, if there was one.
+    pub async fn issue_id_token(
+        &self,
+        account: &Account,
+        client_id: &str,
+        scopes: &[String],
+        nonce: Option<&str>,
+    ) -> Result<String, TokenServiceError> {
+        let signing_key = self.active_key().await?;
+        let now = self.clock.now();
+        let claims = IdTokenClaims {
+            iss: self.issuer.clone(),
+            aud: client_id.to_string(),
+            identity: IdentityClaims::for_scopes(account, scopes),
+            iat: now.timestamp(),
+            exp: (now + self.access_token_ttl).timestamp(),
+            nonce: nonce.map(str::to_string),
+        };
+        Self::sign(&signing_key, ID_TOKEN_TYPE, &claims)
+    }
+
+    /// Verifies an access token issued by this service, returning its claims.
+    /// Fails if the token is malformed, wasn't signed by a published key,
+    /// isn't an access token, has expired, or has been revoked.
+    pub async fn verify_access_token(
+        &self,
+        token: &str,
+    ) -> Result<AccessTokenClaims, TokenServiceError> {
+        let claims = self.decode_access_token(token).await?;
+        if self.revoked_tokens.contains(&hash_token(token)).await? {
+            return Err(TokenServiceError::InvalidAccessToken);
+        }
+        Ok(claims)
+    }
+
+    /// Revokes an access token issued to the OAuth client (or service account),
+    /// so that [TokenService::verify_access_token] rejects it from now on.
+    /// Returns false, without revoking anything, if the token is already invalid
+    /// or was issued to someone else.
+    pub async fn revoke_access_token(
+        &self,
+        token: &str,
+        client_id: &str,
+    ) -> Result<bool, TokenServiceError> {
+        let claims = match self.decode_access_token(token).await {
+            Ok(claims) => claims,
+            Err(TokenServiceError::InvalidAccessToken) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        if claims.client_id.as_deref() != Some(client_id) {
+            return Ok(false);
+        }
+        let expires_at =
+            DateTime::from_timestamp(claims.exp, 0).ok_or(TokenServiceError::InvalidAccessToken)?;
+        self.revoked_tokens
+            .insert(&RevokedToken {
+                token_hash: hash_token(token),
+                expires_at,
+            })
+            .await?;
+        Ok(true)
+    }
+
+    /// Deletes the records of revoked access tokens that have since
+    /// expired. This is meant to be called periodically.
+    pub async fn delete_expired_revocations(&self) -> Result<(), TokenServiceError> {
+        self.revoked_tokens
+            .delete_expired_before(self.clock.now())
+            .await?;
+        Ok(())
+    }
+
+    /// Checks an access token's signature, type and expiry, returning its claims.
+    async fn decode_access_token(
+        &self,
+        token: &str,
+    ) -> Result<AccessTokenClaims, TokenServiceError> {
+        let parts: Vec<&str> = token.split('.').collect();
+        let [header, claims, signature] = parts[..] else {
+            return Err(TokenServiceError::InvalidAccessToken);
+        };
+        let header: Header = decode_part(header)?;
+        if header.alg != SIGNING_ALGORITHM || header.typ != ACCESS_TOKEN_TYPE {
+            return Err(TokenServiceError::InvalidAccessToken);
+        }
+        let public_key = self
+            .public_keys()
+            .await?
+            .into_iter()
+            .find(|key| key.kid == header.kid)
+            .ok_or(TokenServiceError::InvalidAccessToken)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or(TokenServiceError::InvalidAccessToken)?;
+        public_key
+            .key
+            .verify_strict(format!("{}.{}", parts[0], claims).as_bytes(), &signature)
+            .map_err(|_| TokenServiceError::InvalidAccessToken)?;
+
+        let claims: AccessTokenClaims = decode_part(claims)?;
+        if claims.exp <= self.clock.now().timestamp() {
+            return Err(TokenServiceError::InvalidAccessToken);
+        }
+        Ok(claims)
+    }
+
+    /// Returns the public keys that can be used to verify tokens signed by this service.
+    /// This includes the active key, as well as any retired keys that may have signed
+    /// tokens that have not yet expired.
+    pub async fn public_keys(&self) -> Result<Vec<PublicKey>, TokenServiceError> {
+        let now = self.clock.now();
+        Ok(self
+            .store
+            .load_all()
+            .await?
+            .into_iter()
+            .filter(|key| {
+                key.retired_at
+                    .is_none_or(|retired_at| retired_at + self.access_token_ttl > now)
+            })
+            .map(|key| PublicKey {
+                kid: key.kid,
+                key: key.private_key.verifying_key(),
+            })
+            .collect())
+    }
+
+    /// Generates a new signing key and makes it the active key, retiring the
+    /// previously active key. Returns the public half of the new key.
+    pub async fn rotate_keys(&self) -> Result<PublicKey, TokenServiceError> {
+        let new_key = self.activate(SigningKey::generate(&mut OsRng)).await?;
+        Ok(PublicKey {
+            kid: new_key.kid,
+            key: new_key.private_key.verifying_key(),
+        })
+    }
+
+    /// Rotates the keys if the active key is older than the rotation interval,
+    /// returning true if a rotation occurred. This is meant to be called periodically.
+    pub async fn rotate_keys_if_due(&self) -> Result<bool, TokenServiceError> {
+        let active_key = self.active_key().await?;
+        if active_key.created_at + self.rotation_interval <= self.clock.now() {
+            self.rotate_keys().await?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Adds an externally-generated key to the key ring and makes it the active key.
+    /// Importing a key that is already in the key ring has no effect, so this can be
+    /// safely called each time the service starts.
+    pub async fn import_signing_key(
+        &self,
+        signing_key: SigningKey,
+    ) -> Result<(), TokenServiceError> {
+        let kid = key_id(&signing_key.verifying_key());
+        if !self
+            .store
+            .load_all()
+            .await?
+            .iter()
+            .any(|key| key.kid == kid)
+        {
+            self.activate(signing_key).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the key that should be used for signing, creating
+    /// one if the key ring is empty.
+    async fn active_key(&self) -> Result<SigningKeyRecord, TokenServiceError> {
+        match self
+            .store
+            .load_all()
+            .await?
+            .into_iter()
+            .find(|key| key.retired_at.is_none())
+        {
+            Some(key) => Ok(key),
+            None => self.activate(SigningKey::generate(&mut OsRng)).await,
+        }
+    }
+
+    /// Adds the key to the key ring and retires all other active keys. Keys that
+    /// were retired long enough ago that no unexpired tokens could have been signed
+    /// with them are removed.
+    async fn activate(
+        &self,
+        signing_key: SigningKey,
+    ) -> Result<SigningKeyRecord, TokenServiceError> {
+        let now = self.clock.now();
+        let new_key = SigningKeyRecord {
+            kid: key_id(&signing_key.verifying_key()),
+            private_key: signing_key,
+            created_at: now,
+            retired_at: None,
+        };
+        let existing_keys = self.store.load_all().await?;
+        self.store.insert(&new_key).await?;
+        for key in existing_keys.iter().filter(|key| key.retired_at.is_none()) {
+            self.store.retire(&key.kid, now).await?;
+        }
+        self.store
+            .delete_retired_before(now - self.access_token_ttl)
+            .await?;
+        Ok(new_key)
+    }
+
+    /// Encodes the claims as a compact JWS signed with the given key.
+    fn sign<T: Serialize>(
+        signing_key: &SigningKeyRecord,
+        typ: &str,
+        claims: &T,
+    ) -> Result<String, TokenServiceError> {
+        let header = Header {
+            alg: SIGNING_ALGORITHM.to_string(),
+            typ: typ.to_string(),
+            kid: signing_key.kid.clone(),
+        };
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?)
+        );
+        let signature = signing_key.private_key.sign(signing_input.as_bytes());
+        Ok(format!(
+            "{}.{}",
+            signing_input,
+            URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        ))
+    }
+}
+
+impl<S: SigningKeyStore, R: RevokedTokenStore> TokenService<S, R, SystemClock<Utc>> {
+    pub fn new(signing_key_store: S, revoked_token_store: R, issuer: &str) -> Self {
+        Self::new_with_clock(
+            signing_key_store,
+            revoked_token_store,
+            issuer,
+            SystemClock::default(),
+        )
+    }
+}
+
+/// Decodes a base64url-encoded JSON part of a token.
+fn decode_part<T: DeserializeOwned>(part: &str) -> Result<T, TokenServiceError> {
+    URL_SAFE_NO_PAD
+        .decode(part)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or(TokenServiceError::InvalidAccessToken)
+}
+
+/// Reads an Ed25519 signing key from a PKCS#8 PEM file, such as one
+/// generated by `openssl genpkey -algorithm ed25519`.
+pub fn read_signing_key(path: &Path) -> Result<SigningKey, TokenServiceError> {
+    let pem = std::fs::read_to_string(path)
+        .map_err(|e| TokenServiceError::InvalidSigningKey(format!("{}: {}", path.display(), e)))?;
+    SigningKey::from_pkcs8_pem(&pem)
+        .map_err(|e| TokenServiceError::InvalidSigningKey(format!("{}: {}", path.display(), e)))
+}
+
+/// Returns the RFC 7638 JWK thumbprint of the public key, which is used as its key ID.
+fn key_id(key: &VerifyingKey) -> String {
+    // The thumbprint is the hash of the required JWK members in lexical order
+    // with no whitespace, so this is formatted by hand rather than serialized.
+    let jwk = format!(
+        r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#,
+        URL_SAFE_NO_PAD.encode(key.as_bytes())
+    );
+    URL_SAFE_NO_PAD.encode(Sha256::digest(jwk.as_bytes()))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use ed25519_dalek::{Signature, Verifier};
+    use stores::fake::{FakeRevokedTokenStore, FakeSigningKeyStore};
+
+    use crate::services::{account::models::AccountStatus, TestClock};
+
+    use super::*;
+
+    const TEST_ISSUER: &str = "https://id.example.com/";
+
+    fn test_service(
+        clock: &TestClock<Utc>,
+    ) -> TokenService<FakeSigningKeyStore, FakeRevokedTokenStore, TestClock<Utc>> {
+        TokenService::new_with_clock(
+            FakeSigningKeyStore::new(),
+            FakeRevokedTokenStore::new(),
+            TEST_ISSUER,
+            clock.clone(),
+        )
+    }
+
+    /// Decodes a token after verifying its signature against the provided
+    /// public keys, like a downstream service would.
+    pub fn verify_token(token: &str, public_keys: &[PublicKey]) -> AccessTokenClaims {
+        verify_token_claims(token, public_keys)
+    }
+
+    /// Like [verify_token], but for any type of token.
+    pub fn verify_token_claims<T: DeserializeOwned>(token: &str, public_keys: &[PublicKey]) -> T {
+        let parts: Vec<&str> = token.split('.').collect();
+        assert_eq!(3, parts.len());
+        let header: Header =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[0]).unwrap()).unwrap();
+        assert_eq!(SIGNING_ALGORITHM, header.alg);
+        let public_key = public_keys.iter().find(|k| k.kid == header.kid).unwrap();
+        let signature = Signature::from_slice(&URL_SAFE_NO_PAD.decode(parts[2]).unwrap()).unwrap();
+        public_key
+            .key
+            .verify(format!("{}.{}", parts[0], parts[1]).as_bytes(), &signature)
+            .unwrap();
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[1]).unwrap()).unwrap()
+    }
+
+    fn test_account() -> Account {
+        Account {
+            id: "acct_test".to_string(),
+            email: "test@test.com".to_string(),
+            password_hash: Some("not-a-real-hash".to_string()),
+            display_name: Some("Tester McTester".to_string()),
+            created_at: Utc::now(),
+            email_verified_at: None,
+            pending_email: None,
+            status: AccountStatus::Active,
+        }
+    }
+
+    #[tokio::test]
+    async fn issue_access_token() {
+        let now = Utc::now();
+        let service = test_service(&TestClock::new(now));
+        let account = test_account();
+        let access_token = service.issue_access_token(&account).await.unwrap();
+        assert_eq!(now + DEFAULT_ACCESS_TOKEN_TTL, access_token.expires_at);
+
+        let claims = verify_token(&access_token.token, &service.public_keys().await.unwrap());
+        assert_eq!(account.id, claims.sub);
+        assert_eq!(Some(account.email), claims.email);
+        assert_eq!(Some(false), claims.email_verified);
+        assert_eq!(account.display_name, claims.name);
+        assert_eq!(now.timestamp(), claims.iat);
+        assert_eq!(access_token.expires_at.timestamp(), claims.exp);
+        assert_eq!(None, claims.client_id);
+        assert_eq!(None, claims.scope);
+    }
+
+    #[tokio::test]
+    async fn issue_client_access_token() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let access_token = service
+            .issue_client_access_token(
+                &test_account(),
+                "client_test",
+                &["profile".to_string(), "email".to_string()],
+            )
+            .await
+            .unwrap();
+
+        let claims = verify_token(&access_token.token, &service.public_keys().await.unwrap());
+        assert_eq!("acct_test", claims.sub);
+        assert_eq!(Some("client_test".to_string()), claims.client_id);
+        assert_eq!(Some("profile email".to_string()), claims.scope);
+    }
+
+    #[tokio::test]
+    async fn issue_service_access_token() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let access_token = service
+            .issue_service_access_token("svc_test", &["events:read".to_string()])
+            .await
+            .unwrap();
+
+        let claims = verify_token(&access_token.token, &service.public_keys().await.unwrap());
+        assert_eq!("svc_test", claims.sub);
+        assert_eq!(Some("svc_test".to_string()), claims.client_id);
+        assert_eq!(Some("events:read".to_string()), claims.scope);
+        assert_eq!(None, claims.email);
+        assert_eq!(None, claims.email_verified);
+    }
+
+    #[tokio::test]
+    async fn issue_id_token() {
+        let now = Utc::now();
+        let service = test_service(&TestClock::new(now));
+        let account = test_account();
+
+        let id_token = service
+            .issue_id_token(
+                &account,
+                "client_test",
+                &["openid".to_string()],
+                Some("n-0S6"),
+            )
+            .await
+            .unwrap();
+        let claims: IdTokenClaims =
+            verify_token_claims(&id_token, &service.public_keys().await.unwrap());
+        assert_eq!("https://id.example.com", claims.iss);
+        assert_eq!("client_test", claims.aud);
+        assert_eq!(Some("n-0S6".to_string()), claims.nonce);
+        assert_eq!(now.timestamp(), claims.iat);
+        assert_eq!((now + DEFAULT_ACCESS_TOKEN_TTL).timestamp(), claims.exp);
+        // only the subject is released without the email and profile scopes
+        assert_eq!(
+            IdentityClaims {
+                sub: account.id.clone(),
+                email: None,
+                email_verified: None,
+                name: None,
+            },
+            claims.identity
+        );
+
+        let scopes = ["openid", "email", "profile"].map(str::to_string);
+        let id_token = service
+            .issue_id_token(&account, "client_test", &scopes, None)
+            .await
+            .unwrap();
+        let claims: IdTokenClaims =
+            verify_token_claims(&id_token, &service.public_keys().await.unwrap());
+        assert_eq!(None, claims.nonce);
+        assert_eq!(Some(account.email), claims.identity.email);
+        assert_eq!(Some(false), claims.identity.email_verified);
+        assert_eq!(account.display_name, claims.identity.name);
+    }
+
+    #[tokio::test]
+    async fn verify_access_token() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let account = test_account();
+        let access_token = service.issue_access_token(&account).await.unwrap();
+        let claims = service
+            .verify_access_token(&access_token.token)
+            .await
+            .unwrap();
+        assert_eq!(account.id, claims.sub);
+
+        // ID tokens can't be used as access tokens
+        let id_token = service
+            .issue_id_token(&account, "client_test", &[], None)
+            .await
+            .unwrap();
+        assert!(matches!(
+            service.verify_access_token(&id_token).await,
+            Err(TokenServiceError::InvalidAccessToken)
+        ));
+
+        // tokens signed by another key or with altered claims are rejected
+        let other_token = test_service(&clock)
+            .issue_access_token(&account)
+            .await
+            .unwrap();
+        let forged_claims = AccessTokenClaims {
+            sub: "acct_someone_else".to_string(),
+            ..claims
+        };
+        let parts: Vec<&str> = access_token.token.split('.').collect();
+        for token in [
+            other_token.token,
+            format!(
+                "{}.{}.{}",
+                parts[0],
+                URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged_claims).unwrap()),
+                parts[2]
+            ),
+            "not-a-token".to_string(),
+        ] {
+            assert!(matches!(
+                service.verify_access_token(&token).await,
+                Err(TokenServiceError::InvalidAccessToken)
+            ));
+        }
+
+        clock.advance(DEFAULT_ACCESS_TOKEN_TTL);
+        assert!(matches!(
+            service.verify_access_token(&access_token.token).await,
+            Err(TokenServiceError::InvalidAccessToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn revoke_access_token() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let account = test_account();
+        let scopes = vec!["profile".to_string()];
+        let client_token = service
+            .issue_client_access_token(&account, "client_test", &scopes)
+            .await
+            .unwrap();
+        let session_token = service.issue_access_token(&account).await.unwrap();
+
+        // clients can only revoke their own tokens
+        for (token, client_id) in [
+            (&client_token.token, "client_other"),
+            (&session_token.token, "client_test"),
+            (&"not-a-token".to_string(), "client_test"),
+        ] {
+            assert!(!service.revoke_access_token(token, client_id).await.unwrap());
+        }
+        service
+            .verify_access_token(&client_token.token)
+            .await
+            .unwrap();
+
+        assert!(service
+            .revoke_access_token(&client_token.token, "client_test")
+            .await
+            .unwrap());
+        assert!(matches!(
+            service.verify_access_token(&client_token.token).await,
+            Err(TokenServiceError::InvalidAccessToken)
+        ));
+        service
+            .verify_access_token(&session_token.token)
+            .await
+            .unwrap();
+
+        // the record is kept until the token has expired
+        service.delete_expired_revocations().await.unwrap();
+        assert!(service
+            .revoked_tokens
+            .contains(&hash_token(&client_token.token))
+            .await
+            .unwrap());
+        clock.advance(DEFAULT_ACCESS_TOKEN_TTL + TimeDelta::seconds(1));
+        service.delete_expired_revocations().await.unwrap();
+        assert!(!service
+            .revoked_tokens
+            .contains(&hash_token(&client_token.token))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    #[should_panic]
+    async fn tampered_token_fails_verification() {
+        let service = test_service(&TestClock::new(Utc::now()));
+        let access_token = service.issue_access_token(&test_account()).await.unwrap();
+        let public_keys = service.public_keys().await.unwrap();
+        let forged_claims = AccessTokenClaims {
+            sub: "acct_someone_else".to_string(),
+            ..verify_token(&access_token.token, &public_keys)
+        };
+        let parts: Vec<&str> = access_token.token.split('.').collect();
+        let forged_token = format!(
+            "{}.{}.{}",
+            parts[0],
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged_claims).unwrap()),
+            parts[2]
+        );
+        verify_token(&forged_token, &public_keys);
+    }
+
+    #[tokio::test]
+    async fn rotated_keys_published_until_tokens_expire() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let old_token = service.issue_access_token(&test_account()).await.unwrap();
+        let old_keys = service.public_keys().await.unwrap();
+        assert_eq!(1, old_keys.len());
+
+        clock.advance(TimeDelta::minutes(1));
+        let new_key = service.rotate_keys().await.unwrap();
+        assert_ne!(old_keys[0].kid, new_key.kid);
+
+        // new tokens are signed with the new key, but tokens signed
+        // with the old key can still be verified
+        let new_token = service.issue_access_token(&test_account()).await.unwrap();
+        let public_keys = service.public_keys().await.unwrap();
+        assert_eq!(2, public_keys.len());
+        verify_token(&new_token.token, std::slice::from_ref(&new_key));
+        verify_token(&old_token.token, &public_keys);
+
+        // once all tokens signed by the old key have expired, it is no longer published
+        clock.advance(DEFAULT_ACCESS_TOKEN_TTL);
+        let public_keys = service.public_keys().await.unwrap();
+        assert_eq!(1, public_keys.len());
+        assert_eq!(new_key.kid, public_keys[0].kid);
+    }
+
+    #[tokio::test]
+    async fn rotate_keys_if_due() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        service.issue_access_token(&test_account()).await.unwrap();
+
+        clock.advance(DEFAULT_ROTATION_INTERVAL - TimeDelta::seconds(1));
+        assert!(!service.rotate_keys_if_due().await.unwrap());
+        clock.advance(TimeDelta::seconds(1));
+        assert!(service.rotate_keys_if_due().await.unwrap());
+        assert!(!service.rotate_keys_if_due().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn import_signing_key() {
+        let clock = TestClock::new(Utc::now());
+        let service = test_service(&clock);
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let kid = key_id(&signing_key.verifying_key());
+
+        service
+            .import_signing_key(signing_key.clone())
+            .await
+            .unwrap();
+        let token = service.issue_access_token(&test_account()).await.unwrap();
+        verify_token(
+            &token.token,
+            &[PublicKey {
+                kid: kid.clone(),
+                key: signing_key.verifying_key(),
+            }],
+        );
+
+        // importing the same key again after a rotation should not reactivate it
+        clock.advance(TimeDelta::minutes(1));
+        service.rotate_keys().await.unwrap();
+        service.import_signing_key(signing_key).await.unwrap();
+        let public_keys = service.public_keys().await.unwrap();
+        assert_eq!(2, public_keys.len());
+        assert_ne!(kid, public_keys[0].kid);
+    }
+
+    #[test]
+    fn key_id_is_stable_thumbprint() {
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let kid = key_id(&signing_key.verifying_key());
+        assert_eq!(kid, key_id(&signing_key.verifying_key()));
+        assert_ne!(
+            kid,
+            key_id(&SigningKey::generate(&mut OsRng).verifying_key())
+        );
+        // base64url encoding of a SHA-256 hash without padding
+        assert_eq!(43, kid.len());
+    }
+}
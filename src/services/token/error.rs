@@ -0,0 +1,17 @@
+use thiserror::Error;
+
+use super::stores::error::{RevokedTokenStoreError, SigningKeyStoreError};
+
+#[derive(Error, Debug)]
+pub enum TokenServiceError {
+    #[error("The signing key could not be loaded: {0}")]
+    InvalidSigningKey(String),
+    #[error("The access token is invalid or has expired")]
+    InvalidAccessToken,
+    #[error("There was an error serializing the token: {0}")]
+    SerializationError(#[from] serde_json::Error),
+    #[error("There was an error interacting with the data store: {0}")]
+    StoreError(#[from] SigningKeyStoreError),
+    #[error("There was an error interacting with the revoked token store: {0}")]
+    RevokedTokenStoreError(#[from] RevokedTokenStoreError),
+}
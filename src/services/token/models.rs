@@ -0,0 +1,138 @@
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use crate::services::account::models::Account;
+
+/// The OpenID Connect scope, which a client requests to get an ID token.
+pub const OPENID_SCOPE: &str = "openid";
+/// The scope that releases the `email` and `email_verified` claims.
+pub const EMAIL_SCOPE: &str = "email";
+/// The scope that releases the `name` claim.
+pub const PROFILE_SCOPE: &str = "profile";
+
+/// The claims contained in a signed access token (JWT).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccessTokenClaims {
+    /// Subject: the ID of the account (or service account) the token was issued to.
+    pub sub: String,
+    /// Account email address. Service accounts don't have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// Whether the account holder has proved they control `email`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+    /// Optional display name suitable for showing on screen.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// When the token was issued, in seconds since the Unix epoch.
+    pub iat: i64,
+    /// When the token expires, in seconds since the Unix epoch.
+    pub exp: i64,
+    /// ID of the OAuth client the token was issued to, if it
+    /// wasn't issued to the account holder directly (RFC 9068).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Space-separated list of the scopes granted to the OAuth client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+/// The claims about an account holder that an OpenID Connect client can
+/// receive, in an ID token or from the userinfo endpoint. Claims other than
+/// `sub` are only released if the client was granted the scope that covers
+/// them (OpenID Connect Core section 5.4).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdentityClaims {
+    /// Subject: the ID of the account.
+    pub sub: String,
+    /// Account email address, if the `email` scope was granted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// Whether the account holder has proved they control `email`,
+    /// if the `email` scope was granted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+    /// Display name, if the `profile` scope was granted and the account has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl IdentityClaims {
+    /// Returns the claims about the account covered by the scopes.
+    pub fn for_scopes(account: &Account, scopes: &[String]) -> Self {
+        let has_scope = |scope: &str| scopes.iter().any(|s| s == scope);
+        let email_granted = has_scope(EMAIL_SCOPE);
+        Self {
+            sub: account.id.clone(),
+            email: email_granted.then(|| account.email.clone()),
+            email_verified: email_granted.then(|| account.email_verified()),
+            name: account
+                .display_name
+                .clone()
+                .filter(|_| has_scope(PROFILE_SCOPE)),
+        }
+    }
+}
+
+/// The claims contained in a signed ID token (OpenID Connect Core section 2).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    /// Issuer: the URL of this service.
+    pub iss: String,
+    /// Audience: the ID of the client the token was issued to.
+    pub aud: String,
+    #[serde(flatten)]
+    pub identity: IdentityClaims,
+    /// When the token was issued, in seconds since the Unix epoch.
+    pub iat: i64,
+    /// When the token expires, in seconds since the Unix epoch.
+    pub exp: i64,
+    /// The `nonce` from the authorization request, which
+    /// the client checks to detect replayed tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+}
+
+/// A signed access token.
+#[derive(Debug, Clone)]
+pub struct AccessToken {
+    /// The encoded and signed JWT.
+    pub token: String,
+    /// When the token expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// An access token that was revoked before it expired.
+#[derive(Debug, Clone)]
+pub struct RevokedToken {
+    /// SHA-256 hash of the token.
+    pub token_hash: String,
+    /// When the token expires, after which the record is no longer needed.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A public key that can be used to verify tokens signed by this service.
+#[derive(Debug, Clone)]
+pub struct PublicKey {
+    /// Key ID, which appears in the `kid` header of tokens signed with
+    /// the corresponding private key.
+    pub kid: String,
+    /// The Ed25519 public key.
+    pub key: VerifyingKey,
+}
+
+/// A signing key in the key ring. Only the newest key that has not been
+/// retired is used for signing, but retired keys continue to be published
+/// until all tokens they signed have expired.
+#[derive(Debug, Clone)]
+pub struct SigningKeyRecord {
+    /// Key ID (the RFC 7638 thumbprint of the public key).
+    pub kid: String,
+    /// The Ed25519 private key.
+    pub private_key: SigningKey,
+    /// When this key was added to the key ring.
+    pub created_at: DateTime<Utc>,
+    /// When this key stopped being used for signing, if it has been rotated out.
+    pub retired_at: Option<DateTime<Utc>>,
+}
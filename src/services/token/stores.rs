@@ -0,0 +1,40 @@
+pub mod error;
+#[cfg(test)]
+pub mod fake;
+pub mod postgres;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use error::{RevokedTokenStoreError, SigningKeyStoreError};
+
+use crate::services::token::models::{RevokedToken, SigningKeyRecord};
+
+#[async_trait]
+pub trait SigningKeyStore: Send + Sync + 'static {
+    async fn insert(&self, key: &SigningKeyRecord) -> Result<(), SigningKeyStoreError>;
+    /// Loads all keys, newest first.
+    async fn load_all(&self) -> Result<Vec<SigningKeyRecord>, SigningKeyStoreError>;
+    async fn retire(
+        &self,
+        kid: &str,
+        retired_at: DateTime<Utc>,
+    ) -> Result<(), SigningKeyStoreError>;
+    async fn delete_retired_before(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<(), SigningKeyStoreError>;
+}
+
+#[async_trait]
+pub trait RevokedTokenStore: Send + Sync + 'static {
+    /// Records that the token was revoked. Revoking a token again does nothing.
+    async fn insert(&self, token: &RevokedToken) -> Result<(), RevokedTokenStoreError>;
+    /// Returns true if the token with the hash has been revoked.
+    async fn contains(&self, token_hash: &str) -> Result<bool, RevokedTokenStoreError>;
+    /// Deletes the records of revoked tokens that expired before the cutoff,
+    /// since they would be rejected anyway.
+    async fn delete_expired_before(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<(), RevokedTokenStoreError>;
+}
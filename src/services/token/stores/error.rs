@@ -0,0 +1,15 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SigningKeyStoreError {
+    #[error("database error: {0}")]
+    DatabaseError(String),
+    #[error("the stored signing key '{0}' could not be decoded")]
+    InvalidKey(String),
+}
+
+#[derive(Debug, Error)]
+pub enum RevokedTokenStoreError {
+    #[error("database error: {0}")]
+    DatabaseError(String),
+}
@@ -0,0 +1,104 @@
+use std::{cmp::Reverse, collections::HashMap, sync::Mutex};
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::services::token::models::{RevokedToken, SigningKeyRecord};
+
+use super::{
+    error::{RevokedTokenStoreError, SigningKeyStoreError},
+    RevokedTokenStore, SigningKeyStore,
+};
+
+/// A fake implementation of [SigningKeyStore] that can be used in unit tests.
+pub struct FakeSigningKeyStore {
+    /// Keys in the order they were inserted.
+    keys: Mutex<Vec<SigningKeyRecord>>,
+}
+
+impl FakeSigningKeyStore {
+    pub fn new() -> FakeSigningKeyStore {
+        FakeSigningKeyStore {
+            keys: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl SigningKeyStore for FakeSigningKeyStore {
+    async fn insert(&self, key: &SigningKeyRecord) -> Result<(), SigningKeyStoreError> {
+        self.keys.lock().unwrap().push(key.clone());
+        Ok(())
+    }
+
+    async fn load_all(&self) -> Result<Vec<SigningKeyRecord>, SigningKeyStoreError> {
+        let mut keys = self.keys.lock().unwrap().clone();
+        keys.sort_by_key(|key| Reverse(key.created_at));
+        Ok(keys)
+    }
+
+    async fn retire(
+        &self,
+        kid: &str,
+        retired_at: DateTime<Utc>,
+    ) -> Result<(), SigningKeyStoreError> {
+        for key in self.keys.lock().unwrap().iter_mut() {
+            if key.kid == kid {
+                key.retired_at = Some(retired_at);
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_retired_before(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<(), SigningKeyStoreError> {
+        self.keys
+            .lock()
+            .unwrap()
+            .retain(|key| key.retired_at.is_none_or(|retired_at| retired_at >= cutoff));
+        Ok(())
+    }
+}
+
+/// A fake implementation of [RevokedTokenStore] that can be used in unit tests.
+pub struct FakeRevokedTokenStore {
+    /// Revoked tokens, keyed by token hash.
+    tokens: Mutex<HashMap<String, RevokedToken>>,
+}
+
+impl FakeRevokedTokenStore {
+    pub fn new() -> FakeRevokedTokenStore {
+        FakeRevokedTokenStore {
+            tokens: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl RevokedTokenStore for FakeRevokedTokenStore {
+    async fn insert(&self, token: &RevokedToken) -> Result<(), RevokedTokenStoreError> {
+        self.tokens
+            .lock()
+            .unwrap()
+            .entry(token.token_hash.clone())
+            .or_insert_with(|| token.clone());
+        Ok(())
+    }
+
+    async fn contains(&self, token_hash: &str) -> Result<bool, RevokedTokenStoreError> {
+        Ok(self.tokens.lock().unwrap().contains_key(token_hash))
+    }
+
+    async fn delete_expired_before(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<(), RevokedTokenStoreError> {
+        self.tokens
+            .lock()
+            .unwrap()
+            .retain(|_, token| token.expires_at >= cutoff);
+        Ok(())
+    }
+}
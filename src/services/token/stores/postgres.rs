@@ -0,0 +1,158 @@
+//! Implements [SigningKeyStore] and [RevokedTokenStore] backed by a PostgreSQL database
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{
+    pkcs8::{DecodePrivateKey, EncodePrivateKey},
+    SigningKey,
+};
+use sqlx::{postgres::PgRow, PgPool, Row};
+
+use crate::services::token::models::{RevokedToken, SigningKeyRecord};
+
+use super::{
+    error::{RevokedTokenStoreError, SigningKeyStoreError},
+    RevokedTokenStore, SigningKeyStore,
+};
+
+impl From<sqlx::Error> for SigningKeyStoreError {
+    fn from(value: sqlx::Error) -> Self {
+        SigningKeyStoreError::DatabaseError(value.to_string())
+    }
+}
+
+impl From<sqlx::Error> for RevokedTokenStoreError {
+    fn from(value: sqlx::Error) -> Self {
+        RevokedTokenStoreError::DatabaseError(value.to_string())
+    }
+}
+
+pub struct PostgresSigningKeyStore {
+    pool: PgPool,
+}
+
+impl PostgresSigningKeyStore {
+    pub fn new(pool: PgPool) -> PostgresSigningKeyStore {
+        PostgresSigningKeyStore { pool }
+    }
+}
+
+#[async_trait]
+impl SigningKeyStore for PostgresSigningKeyStore {
+    async fn insert(&self, key: &SigningKeyRecord) -> Result<(), SigningKeyStoreError> {
+        let der = key
+            .private_key
+            .to_pkcs8_der()
+            .map_err(|_| SigningKeyStoreError::InvalidKey(key.kid.clone()))?;
+
+        sqlx::query(
+            "insert into signing_keys(kid,private_key,created_at,retired_at) \
+            values ($1,$2,$3,$4)",
+        )
+        .bind(&key.kid)
+        .bind(der.as_bytes())
+        .bind(key.created_at)
+        .bind(key.retired_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_all(&self) -> Result<Vec<SigningKeyRecord>, SigningKeyStoreError> {
+        let rows = sqlx::query(
+            "select kid,private_key,created_at,retired_at \
+            from signing_keys order by created_at desc",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row: &PgRow| {
+                let kid: String = row.get(0);
+                let der: Vec<u8> = row.get(1);
+                let private_key = SigningKey::from_pkcs8_der(&der)
+                    .map_err(|_| SigningKeyStoreError::InvalidKey(kid.clone()))?;
+                Ok(SigningKeyRecord {
+                    kid,
+                    private_key,
+                    created_at: row.get(2),
+                    retired_at: row.get(3),
+                })
+            })
+            .collect()
+    }
+
+    async fn retire(
+        &self,
+        kid: &str,
+        retired_at: DateTime<Utc>,
+    ) -> Result<(), SigningKeyStoreError> {
+        sqlx::query("update signing_keys set retired_at=$1 where kid=$2")
+            .bind(retired_at)
+            .bind(kid)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_retired_before(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<(), SigningKeyStoreError> {
+        sqlx::query("delete from signing_keys where retired_at < $1")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+pub struct PostgresRevokedTokenStore {
+    pool: PgPool,
+}
+
+impl PostgresRevokedTokenStore {
+    pub fn new(pool: PgPool) -> PostgresRevokedTokenStore {
+        PostgresRevokedTokenStore { pool }
+    }
+}
+
+#[async_trait]
+impl RevokedTokenStore for PostgresRevokedTokenStore {
+    async fn insert(&self, token: &RevokedToken) -> Result<(), RevokedTokenStoreError> {
+        sqlx::query(
+            "insert into revoked_access_tokens(token_hash,expires_at) \
+            values ($1,$2) on conflict (token_hash) do nothing",
+        )
+        .bind(&token.token_hash)
+        .bind(token.expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn contains(&self, token_hash: &str) -> Result<bool, RevokedTokenStoreError> {
+        let row = sqlx::query("select 1 from revoked_access_tokens where token_hash=$1")
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    async fn delete_expired_before(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<(), RevokedTokenStoreError> {
+        sqlx::query("delete from revoked_access_tokens where expires_at < $1")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}